@@ -40,6 +40,30 @@ fn rpds_hash_trie_map_insert_mut(c: &mut Criterion) {
     });
 }
 
+fn rpds_hash_trie_map_from_iter(c: &mut Criterion) {
+    let limit = 5_000;
+
+    c.bench_function("rpds hash trie map from iter", move |b| {
+        b.iter(|| (0..limit).map(|i| (i, -(i as isize))).collect::<HashTrieMap<_, _>>())
+    });
+}
+
+fn rpds_hash_trie_map_extend(c: &mut Criterion) {
+    let limit = 5_000;
+    let map: HashTrieMap<isize, isize> = (0..limit).map(|i| (i, -i)).collect();
+
+    c.bench_function("rpds hash trie map extend", move |b| {
+        b.iter_with_setup(
+            || map.clone(),
+            |mut map| {
+                map.extend((limit..2 * limit).map(|i| (i, -i)));
+
+                map
+            },
+        );
+    });
+}
+
 fn rpds_hash_trie_map_remove(c: &mut Criterion) {
     let limit = 5_000;
 
@@ -107,6 +131,23 @@ fn rpds_hash_trie_map_get(c: &mut Criterion) {
     });
 }
 
+fn rpds_hash_trie_map_get_missing(c: &mut Criterion) {
+    let limit = 10_000;
+    let mut map = HashTrieMap::new();
+
+    for i in 0..limit {
+        map.insert_mut(i, -(i as isize));
+    }
+
+    c.bench_function("rpds hash trie map get missing", move |b| {
+        b.iter(|| {
+            for i in limit..2 * limit {
+                black_box(map.get(&i));
+            }
+        })
+    });
+}
+
 fn rpds_hash_trie_map_iterate(c: &mut Criterion) {
     let limit = 10_000;
     let mut map = HashTrieMap::new();
@@ -124,13 +165,121 @@ fn rpds_hash_trie_map_iterate(c: &mut Criterion) {
     });
 }
 
+fn rpds_hash_trie_map_iterate_for_each(c: &mut Criterion) {
+    let limit = 10_000;
+    let mut map = HashTrieMap::new();
+
+    for i in 0..limit {
+        map.insert_mut(i, -(i as isize));
+    }
+
+    c.bench_function("rpds hash trie map iterate for_each", move |b| {
+        b.iter(|| {
+            map.iter().for_each(|kv| {
+                black_box(kv);
+            });
+        })
+    });
+}
+
+fn rpds_hash_trie_map_small_insert(c: &mut Criterion) {
+    let limit = 1_000;
+    let small_size = 6;
+
+    c.bench_function("rpds hash trie map small insert", move |b| {
+        b.iter(|| {
+            for _ in 0..limit {
+                let mut map = HashTrieMap::new();
+
+                for i in 0..small_size {
+                    map = map.insert(i, -(i as isize));
+                }
+
+                black_box(map);
+            }
+        })
+    });
+}
+
+fn rpds_hash_trie_map_small_remove(c: &mut Criterion) {
+    let limit = 1_000;
+    let small_size = 6;
+    let mut map = HashTrieMap::new();
+
+    for i in 0..small_size {
+        map.insert_mut(i, -(i as isize));
+    }
+
+    c.bench_function("rpds hash trie map small remove", move |b| {
+        b.iter(|| {
+            for _ in 0..limit {
+                let mut m = map.clone();
+
+                for i in 0..small_size {
+                    m = m.remove(&i);
+                }
+
+                black_box(m);
+            }
+        })
+    });
+}
+
+fn rpds_hash_trie_map_small_get(c: &mut Criterion) {
+    let limit = 1_000;
+    let small_size = 6;
+    let mut map = HashTrieMap::new();
+
+    for i in 0..small_size {
+        map.insert_mut(i, -(i as isize));
+    }
+
+    c.bench_function("rpds hash trie map small get", move |b| {
+        b.iter(|| {
+            for _ in 0..limit {
+                for i in 0..small_size {
+                    black_box(map.get(&i));
+                }
+            }
+        })
+    });
+}
+
+fn rpds_hash_trie_map_small_iterate(c: &mut Criterion) {
+    let limit = 1_000;
+    let small_size = 6;
+    let mut map = HashTrieMap::new();
+
+    for i in 0..small_size {
+        map.insert_mut(i, -(i as isize));
+    }
+
+    c.bench_function("rpds hash trie map small iterate", move |b| {
+        b.iter(|| {
+            for _ in 0..limit {
+                for kv in map.iter() {
+                    black_box(kv);
+                }
+            }
+        })
+    });
+}
+
 criterion_group!(
     benches,
     rpds_hash_trie_map_insert,
     rpds_hash_trie_map_insert_mut,
+    rpds_hash_trie_map_from_iter,
+    rpds_hash_trie_map_extend,
     rpds_hash_trie_map_remove,
     rpds_hash_trie_map_remove_mut,
     rpds_hash_trie_map_get,
-    rpds_hash_trie_map_iterate
+    rpds_hash_trie_map_get_missing,
+    rpds_hash_trie_map_iterate,
+    rpds_hash_trie_map_iterate_for_each,
+    rpds_hash_trie_map_small_insert,
+    rpds_hash_trie_map_small_remove,
+    rpds_hash_trie_map_small_get,
+    rpds_hash_trie_map_small_iterate
 );
 criterion_main!(benches);
@@ -0,0 +1,77 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+
+/// Key equivalence, used to look up keys of type `K` with a value of another type.
+///
+/// This is more general than [`Borrow`]: the key does not need to contain a value of the lookup
+/// type.  For instance, a map with `(String, u32)` keys can be queried with a `(&str, u32)`
+/// by implementing `Equivalent<(String, u32)>` for a wrapper of it.
+///
+/// Every type `Q` such that `K: Borrow<Q>` is already `Equivalent<K>`.
+///
+/// When used to look up a hashed collection, an implementation must make a value and its
+/// equivalent keys have the same hash.
+///
+/// # Example
+///
+/// ```
+/// # use rpds::*;
+/// use std::hash::{Hash, Hasher};
+///
+/// #[derive(Hash)]
+/// struct Pair<'a>(&'a str, u32);
+///
+/// impl Equivalent<(String, u32)> for Pair<'_> {
+///     fn equivalent(&self, key: &(String, u32)) -> bool {
+///         self.0 == key.0 && self.1 == key.1
+///     }
+/// }
+///
+/// let map = ht_map![("one".to_owned(), 1) => "uno"];
+///
+/// assert_eq!(map.get_equiv(&Pair("one", 1)), Some(&"uno"));
+/// assert_eq!(map.get_equiv(&Pair("one", 2)), None);
+/// ```
+pub trait Equivalent<K: ?Sized> {
+    /// Returns `true` if `self` is equivalent to `key`.
+    fn equivalent(&self, key: &K) -> bool;
+}
+
+impl<Q: ?Sized, K: ?Sized> Equivalent<K> for Q
+where
+    Q: Eq,
+    K: Borrow<Q>,
+{
+    #[inline]
+    fn equivalent(&self, key: &K) -> bool {
+        *self == *key.borrow()
+    }
+}
+
+/// Key ordering, used to look up keys of type `K` with a value of another type in an ordered
+/// collection.  See [`Equivalent`].
+///
+/// Every type `Q` such that `K: Borrow<Q>` is already `Comparable<K>`.
+///
+/// An implementation must order a value with respect to the keys in the same way as the keys are
+/// ordered among themselves.
+pub trait Comparable<K: ?Sized>: Equivalent<K> {
+    /// Compares `self` with `key`.
+    fn compare(&self, key: &K) -> Ordering;
+}
+
+impl<Q: ?Sized, K: ?Sized> Comparable<K> for Q
+where
+    Q: Ord,
+    K: Borrow<Q>,
+{
+    #[inline]
+    fn compare(&self, key: &K) -> Ordering {
+        Ord::cmp(self, key.borrow())
+    }
+}
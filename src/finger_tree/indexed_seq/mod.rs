@@ -0,0 +1,494 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::{FingerTree, Measured, Monoid};
+use archery::{ArcK, RcK, SharedPointerKind};
+use core::cmp::Ordering;
+use core::fmt::Display;
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+use core::ops::Index;
+
+/// Creates a [`IndexedSeq`](finger_tree/indexed_seq/struct.IndexedSeq.html) containing the given
+/// arguments:
+///
+/// ```
+/// # use rpds::*;
+/// #
+/// let s = IndexedSeq::new()
+///     .push_back(1)
+///     .push_back(2)
+///     .push_back(3);
+///
+/// assert_eq!(indexed_seq![1, 2, 3], s);
+/// ```
+#[macro_export]
+macro_rules! indexed_seq {
+    ($($e:expr),*) => {
+        {
+            #[allow(unused_mut)]
+            let mut s = $crate::IndexedSeq::new();
+            $(
+                s.push_back_mut($e);
+            )*
+            s
+        }
+    };
+}
+
+/// Creates a [`IndexedSeq`](finger_tree/indexed_seq/struct.IndexedSeq.html) that implements
+/// `Sync`, containing the given arguments:
+///
+/// ```
+/// # use rpds::*;
+/// #
+/// let s = IndexedSeq::new_sync()
+///     .push_back(1)
+///     .push_back(2)
+///     .push_back(3);
+///
+/// assert_eq!(indexed_seq_sync![1, 2, 3], s);
+/// ```
+#[macro_export]
+macro_rules! indexed_seq_sync {
+    ($($e:expr),*) => {
+        {
+            #[allow(unused_mut)]
+            let mut s = $crate::IndexedSeq::new_sync();
+            $(
+                s.push_back_mut($e);
+            )*
+            s
+        }
+    };
+}
+
+/// The measure of a [`FingerTree`](../struct.FingerTree.html) that counts its elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Size(pub usize);
+
+impl Monoid for Size {
+    fn empty() -> Size {
+        Size(0)
+    }
+
+    fn combine(&self, other: &Size) -> Size {
+        Size(self.0 + other.0)
+    }
+}
+
+/// An element of the sequence, which is measured as having size one.
+#[derive(Debug)]
+struct Element<T>(T);
+
+impl<T> Measured<Size> for Element<T> {
+    fn measure(&self) -> Size {
+        Size(1)
+    }
+}
+
+/// A persistent sequence with structural sharing that supports efficient random access,
+/// concatenation, and splitting.
+///
+/// # Complexity
+///
+/// Let *n* be the number of elements in the sequence.
+///
+/// ## Temporal complexity
+///
+/// | Operation                           | Average   | Worst case  |
+/// |:----------------------------------- | ---------:| -----------:|
+/// | `new()`                             |      Θ(1) |        Θ(1) |
+/// | `push_front()`/`push_back()`        |      Θ(1) |   Θ(log(n)) |
+/// | `drop_first()`/`drop_last()`        |      Θ(1) |   Θ(log(n)) |
+/// | `first()`/`last()`                  |      Θ(1) |        Θ(1) |
+/// | `get()`                             | Θ(log(n)) |   Θ(log(n)) |
+/// | `set()`                             | Θ(log(n)) |   Θ(log(n)) |
+/// | `split_at()`                        | Θ(log(n)) |   Θ(log(n)) |
+/// | `append()`                          | Θ(log(n)) |   Θ(log(n)) |
+/// | `len()`                             |      Θ(1) |        Θ(1) |
+/// | `clone()`                           |      Θ(1) |        Θ(1) |
+/// | iterator creation                   |      Θ(1) |        Θ(1) |
+/// | iterator step                       |      Θ(1) |   Θ(log(n)) |
+/// | iterator full                       |      Θ(n) |        Θ(n) |
+///
+/// # Implementation details
+///
+/// This is a thin wrapper around a [`FingerTree`](../struct.FingerTree.html) measured by
+/// [`Size`](struct.Size.html).
+#[derive(Debug)]
+pub struct IndexedSeq<T, P = RcK>
+where
+    P: SharedPointerKind,
+{
+    tree: FingerTree<Element<T>, Size, P>,
+}
+
+pub type IndexedSeqSync<T> = IndexedSeq<T, ArcK>;
+
+impl<T> IndexedSeqSync<T> {
+    #[must_use]
+    pub fn new_sync() -> IndexedSeqSync<T> {
+        IndexedSeq::new_with_ptr_kind()
+    }
+}
+
+impl<T> IndexedSeq<T> {
+    #[must_use]
+    pub fn new() -> IndexedSeq<T> {
+        IndexedSeq::new_with_ptr_kind()
+    }
+}
+
+impl<T, P> IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+{
+    #[must_use]
+    pub fn new_with_ptr_kind() -> IndexedSeq<T, P> {
+        IndexedSeq { tree: FingerTree::new_with_ptr_kind() }
+    }
+
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.tree.lookup(|size| size.0 > index).map(|e| &e.0)
+    }
+
+    #[must_use]
+    pub fn first(&self) -> Option<&T> {
+        self.tree.first().map(|e| &e.0)
+    }
+
+    #[must_use]
+    pub fn last(&self) -> Option<&T> {
+        self.tree.last().map(|e| &e.0)
+    }
+
+    #[must_use]
+    pub fn set(&self, index: usize, v: T) -> Option<IndexedSeq<T, P>> {
+        let mut new_seq = self.clone();
+
+        if new_seq.set_mut(index, v) {
+            Some(new_seq)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the operation was successful.
+    pub fn set_mut(&mut self, index: usize, v: T) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+
+        let (mut left, mut right) = self.tree.split(|size| size.0 > index);
+
+        right.drop_first_mut();
+        left.push_back_mut(Element(v));
+        left.append_mut(&right);
+
+        self.tree = left;
+
+        true
+    }
+
+    #[must_use]
+    pub fn push_front(&self, v: T) -> IndexedSeq<T, P> {
+        let mut new_seq = self.clone();
+
+        new_seq.push_front_mut(v);
+
+        new_seq
+    }
+
+    pub fn push_front_mut(&mut self, v: T) {
+        self.tree.push_front_mut(Element(v));
+    }
+
+    #[must_use]
+    pub fn push_back(&self, v: T) -> IndexedSeq<T, P> {
+        let mut new_seq = self.clone();
+
+        new_seq.push_back_mut(v);
+
+        new_seq
+    }
+
+    pub fn push_back_mut(&mut self, v: T) {
+        self.tree.push_back_mut(Element(v));
+    }
+
+    #[must_use]
+    pub fn drop_first(&self) -> Option<IndexedSeq<T, P>> {
+        self.tree.drop_first().map(|tree| IndexedSeq { tree })
+    }
+
+    pub fn drop_first_mut(&mut self) -> bool {
+        self.tree.drop_first_mut()
+    }
+
+    #[must_use]
+    pub fn drop_last(&self) -> Option<IndexedSeq<T, P>> {
+        self.tree.drop_last().map(|tree| IndexedSeq { tree })
+    }
+
+    pub fn drop_last_mut(&mut self) -> bool {
+        self.tree.drop_last_mut()
+    }
+
+    /// Returns the concatenation of `self` with `other`.
+    #[must_use]
+    pub fn append(&self, other: &IndexedSeq<T, P>) -> IndexedSeq<T, P> {
+        IndexedSeq { tree: self.tree.append(&other.tree) }
+    }
+
+    pub fn append_mut(&mut self, other: &IndexedSeq<T, P>) {
+        self.tree.append_mut(&other.tree);
+    }
+
+    /// Splits the sequence in two at the given index.  The first sequence contains the elements
+    /// in `[0, index)` and the second sequence contains the elements in `[index, len)`.
+    ///
+    /// If `index` is greater than the length of the sequence the second sequence will be empty.
+    #[must_use]
+    pub fn split_at(&self, index: usize) -> (IndexedSeq<T, P>, IndexedSeq<T, P>) {
+        let (left, right) = self.tree.split(|size| size.0 > index);
+
+        (IndexedSeq { tree: left }, IndexedSeq { tree: right })
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tree.measure().0
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter { iter: self.tree.iter(), remaining: self.len() }
+    }
+}
+
+impl<T, P> Index<usize> for IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+{
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).unwrap_or_else(|| panic!("index out of bounds {}", index))
+    }
+}
+
+impl<T, P> Default for IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn default() -> IndexedSeq<T, P> {
+        IndexedSeq::new_with_ptr_kind()
+    }
+}
+
+impl<T, P> Clone for IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> IndexedSeq<T, P> {
+        IndexedSeq { tree: self.tree.clone() }
+    }
+}
+
+impl<T: PartialEq, P, PO> PartialEq<IndexedSeq<T, PO>> for IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+    PO: SharedPointerKind,
+{
+    fn eq(&self, other: &IndexedSeq<T, PO>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, P> Eq for IndexedSeq<T, P> where P: SharedPointerKind {}
+
+impl<T: PartialOrd, P, PO> PartialOrd<IndexedSeq<T, PO>> for IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+    PO: SharedPointerKind,
+{
+    fn partial_cmp(&self, other: &IndexedSeq<T, PO>) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord, P> Ord for IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn cmp(&self, other: &IndexedSeq<T, P>) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Hash, P> Hash for IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Add the hash of length so that if two collections are added one after the other it doesn't
+        // hash to the same thing as a single collection with the same elements in the same order.
+        self.len().hash(state);
+
+        for e in self {
+            e.hash(state);
+        }
+    }
+}
+
+impl<T: Display, P> Display for IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut first = true;
+
+        fmt.write_str("[")?;
+
+        for v in self {
+            if !first {
+                fmt.write_str(", ")?;
+            }
+            v.fmt(fmt)?;
+            first = false;
+        }
+
+        fmt.write_str("]")
+    }
+}
+
+impl<'a, T, P> IntoIterator for &'a IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, P> FromIterator<T> for IndexedSeq<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(into_iter: I) -> IndexedSeq<T, P> {
+        IndexedSeq { tree: into_iter.into_iter().map(Element).collect() }
+    }
+}
+
+#[derive(Debug)]
+pub struct Iter<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    iter: super::Iter<'a, Element<T>, Size, P>,
+    remaining: usize,
+}
+
+impl<'a, T, P> Iterator for Iter<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let next = self.iter.next();
+
+        if next.is_some() {
+            self.remaining -= 1;
+        }
+
+        next.map(|e| &e.0)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, P> ExactSizeIterator for Iter<'a, T, P> where P: SharedPointerKind {}
+
+#[cfg(feature = "serde")]
+pub mod serde {
+    use super::*;
+    use ::serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+    use ::serde::ser::{Serialize, Serializer};
+    use core::fmt;
+    use core::marker::PhantomData;
+
+    impl<T, P> Serialize for IndexedSeq<T, P>
+    where
+        T: Serialize,
+        P: SharedPointerKind,
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self)
+        }
+    }
+
+    impl<'de, T, P> Deserialize<'de> for IndexedSeq<T, P>
+    where
+        T: Deserialize<'de>,
+        P: SharedPointerKind,
+    {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<IndexedSeq<T, P>, D::Error> {
+            deserializer.deserialize_seq(IndexedSeqVisitor {
+                _phantom_t: PhantomData,
+                _phantom_p: PhantomData,
+            })
+        }
+    }
+
+    struct IndexedSeqVisitor<T, P> {
+        _phantom_t: PhantomData<T>,
+        _phantom_p: PhantomData<P>,
+    }
+
+    impl<'de, T, P> Visitor<'de> for IndexedSeqVisitor<T, P>
+    where
+        T: Deserialize<'de>,
+        P: SharedPointerKind,
+    {
+        type Value = IndexedSeq<T, P>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("a sequence")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<IndexedSeq<T, P>, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut indexed_seq = IndexedSeq::new_with_ptr_kind();
+
+            while let Some(value) = seq.next_element()? {
+                indexed_seq.push_back_mut(value);
+            }
+
+            Ok(indexed_seq)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
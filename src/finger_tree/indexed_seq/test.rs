@@ -0,0 +1,305 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::*;
+use alloc::vec::Vec;
+use pretty_assertions::assert_eq;
+use static_assertions::assert_impl_all;
+
+assert_impl_all!(IndexedSeqSync<i32>: Send, Sync);
+
+#[allow(dead_code)]
+fn compile_time_macro_indexed_seq_sync_is_send_and_sync() -> impl Send + Sync {
+    indexed_seq_sync!(0)
+}
+
+mod iter {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_iter() {
+        let limit = 1024;
+        let seq: IndexedSeq<usize> = (0..limit).collect();
+        let mut iter = seq.iter();
+
+        for i in 0..limit {
+            assert_eq!(iter.next(), Some(&i));
+        }
+
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_iter_size_hint() {
+        let seq = indexed_seq![0, 1, 2];
+        let mut iterator = seq.iter();
+
+        assert_eq!(iterator.size_hint(), (3, Some(3)));
+
+        iterator.next();
+
+        assert_eq!(iterator.size_hint(), (2, Some(2)));
+
+        iterator.next();
+
+        assert_eq!(iterator.size_hint(), (1, Some(1)));
+
+        iterator.next();
+
+        assert_eq!(iterator.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn test_into_iterator() {
+        let seq = indexed_seq![0, 1, 2, 3];
+        let mut left = 4;
+
+        for (expected, n) in seq.into_iter().enumerate() {
+            left -= 1;
+
+            assert!(left >= 0);
+            assert_eq!(expected, *n);
+        }
+
+        assert_eq!(left, 0);
+    }
+}
+
+#[test]
+fn test_new() {
+    let empty_seq: IndexedSeq<i32> = IndexedSeq::new();
+
+    assert!(empty_seq.is_empty());
+    assert_eq!(empty_seq.len(), 0);
+    assert_eq!(empty_seq.get(0), None);
+}
+
+#[test]
+fn test_macro_indexed_seq() {
+    let seq_1_2_3 = IndexedSeq::new().push_back(1).push_back(2).push_back(3);
+
+    assert_eq!(IndexedSeq::<u32>::new(), indexed_seq![]);
+    assert_eq!(seq_1_2_3, indexed_seq![1, 2, 3]);
+}
+
+#[test]
+fn test_get() {
+    let limit = 1000;
+    let seq: IndexedSeq<usize> = (0..limit).collect();
+
+    for i in 0..limit {
+        assert_eq!(seq.get(i), Some(&i));
+        assert_eq!(seq[i], i);
+    }
+
+    assert_eq!(seq.get(limit), None);
+}
+
+#[test]
+#[should_panic(expected = "index out of bounds")]
+fn test_index_out_of_bounds() {
+    let seq = indexed_seq![0, 1];
+
+    let _ = seq[2];
+}
+
+#[test]
+fn test_set() {
+    let limit = 200;
+    let seq: IndexedSeq<usize> = (0..limit).collect();
+
+    for i in 0..limit {
+        let new_seq = seq.set(i, 1000).unwrap();
+
+        assert_eq!(new_seq.len(), limit);
+        assert_eq!(new_seq[i], 1000);
+        assert!(new_seq.iter().enumerate().all(|(j, v)| j == i || *v == j));
+    }
+
+    assert!(seq.iter().copied().eq(0..limit));
+    assert_eq!(seq.set(limit, 0), None);
+}
+
+#[test]
+fn test_push_front_drop_first() {
+    let limit = 500;
+    let mut seq = IndexedSeq::new();
+
+    for i in 0..limit {
+        seq.push_front_mut(i);
+
+        assert_eq!(seq.len(), i + 1);
+        assert_eq!(seq.first(), Some(&i));
+    }
+
+    for i in (0..limit).rev() {
+        assert_eq!(seq.first(), Some(&i));
+        assert!(seq.drop_first_mut());
+        assert_eq!(seq.len(), i);
+    }
+
+    assert!(!seq.drop_first_mut());
+    assert_eq!(seq.drop_first(), None);
+}
+
+#[test]
+fn test_push_back_drop_last() {
+    let limit = 500;
+    let mut seq = IndexedSeq::new();
+
+    for i in 0..limit {
+        seq = seq.push_back(i);
+
+        assert_eq!(seq.len(), i + 1);
+        assert_eq!(seq.last(), Some(&i));
+    }
+
+    for i in (0..limit).rev() {
+        assert_eq!(seq.last(), Some(&i));
+
+        seq = seq.drop_last().unwrap();
+
+        assert_eq!(seq.len(), i);
+    }
+
+    assert!(!seq.drop_last_mut());
+}
+
+#[test]
+fn test_split_at() {
+    let limit = 100;
+    let seq: IndexedSeq<usize> = (0..limit).collect();
+
+    for i in 0..=limit + 1 {
+        let (left, right) = seq.split_at(i);
+
+        assert_eq!(left.len(), i.min(limit));
+        assert_eq!(right.len(), limit - i.min(limit));
+        assert!(left.iter().copied().eq(0..i.min(limit)));
+        assert!(right.iter().copied().eq(i.min(limit)..limit));
+        assert_eq!(left.append(&right), seq);
+    }
+}
+
+#[test]
+fn test_append() {
+    let mut seq = IndexedSeq::new();
+
+    for i in 0..50 {
+        let other: IndexedSeq<usize> = (i * 7..i * 7 + 7).collect();
+
+        seq.append_mut(&other);
+    }
+
+    assert_eq!(seq.len(), 350);
+    assert!(seq.iter().copied().eq(0..350));
+
+    for i in 0..350 {
+        assert_eq!(seq[i], i);
+    }
+}
+
+#[test]
+fn test_from_iterator() {
+    let vec: Vec<u32> = vec![10, 11, 12, 13];
+    let seq: IndexedSeq<u32> = vec.iter().copied().collect();
+
+    assert!(vec.iter().eq(seq.iter()));
+}
+
+#[test]
+fn test_default() {
+    let seq: IndexedSeq<i32> = IndexedSeq::default();
+
+    assert_eq!(seq.len(), 0);
+}
+
+#[test]
+fn test_display() {
+    let empty_seq: IndexedSeq<i32> = IndexedSeq::new();
+    let singleton_seq = indexed_seq!["hello"];
+    let seq = indexed_seq![0, 1, 2, 3];
+
+    assert_eq!(format!("{}", empty_seq), "[]");
+    assert_eq!(format!("{}", singleton_seq), "[hello]");
+    assert_eq!(format!("{}", seq), "[0, 1, 2, 3]");
+}
+
+#[test]
+fn test_eq() {
+    let seq_1 = indexed_seq!["a", "a"];
+    let seq_1_prime = indexed_seq!["a", "a"];
+    let seq_2 = indexed_seq!["a", "b"];
+
+    assert_ne!(seq_1, seq_2);
+    assert_eq!(seq_1, seq_1);
+    assert_eq!(seq_1, seq_1_prime);
+    assert_eq!(seq_2, seq_2);
+}
+
+#[test]
+fn test_eq_pointer_kind_consistent() {
+    let seq_a = indexed_seq!["a"];
+    let seq_a_sync = indexed_seq_sync!["a"];
+    let seq_b = indexed_seq!["b"];
+    let seq_b_sync = indexed_seq_sync!["b"];
+
+    assert!(seq_a == seq_a_sync);
+    assert!(seq_a != seq_b_sync);
+    assert!(seq_b == seq_b_sync);
+}
+
+#[test]
+fn test_ord() {
+    let seq_1 = indexed_seq!["a"];
+    let seq_1_prime = indexed_seq!["a"];
+    let seq_2 = indexed_seq!["b"];
+
+    assert_eq!(seq_1.partial_cmp(&seq_1_prime), Some(Ordering::Equal));
+    assert_eq!(seq_1.cmp(&seq_1_prime), Ordering::Equal);
+    assert_eq!(seq_1.cmp(&seq_2), Ordering::Less);
+    assert_eq!(seq_2.cmp(&seq_1), Ordering::Greater);
+}
+
+fn hash<T: Hash, P: SharedPointerKind>(seq: &IndexedSeq<T, P>) -> u64 {
+    #[allow(deprecated)]
+    let mut hasher = core::hash::SipHasher::new();
+
+    seq.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[test]
+fn test_hash() {
+    let seq_1 = indexed_seq!["a"];
+    let seq_1_prime = indexed_seq!["a"];
+    let seq_2 = indexed_seq!["a", "b"];
+
+    assert_eq!(hash(&seq_1), hash(&seq_1_prime));
+    assert_ne!(hash(&seq_1), hash(&seq_2));
+    assert_eq!(hash(&seq_1), hash(&indexed_seq_sync!["a"]));
+}
+
+#[test]
+fn test_clone() {
+    let seq = indexed_seq!["hello", "there"];
+    let clone = seq.clone();
+
+    assert!(clone.iter().eq(seq.iter()));
+    assert_eq!(clone.len(), seq.len());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use bincode::{deserialize, serialize};
+    let seq: IndexedSeq<i32> = indexed_seq![5, 6, 7, 8];
+    let encoded = serialize(&seq).unwrap();
+    let decoded: IndexedSeq<i32> = deserialize(&encoded).unwrap();
+
+    assert_eq!(seq, decoded);
+}
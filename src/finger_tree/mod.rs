@@ -0,0 +1,1181 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+pub mod indexed_seq;
+pub mod priority_queue;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use archery::{ArcK, RcK, SharedPointer, SharedPointerKind};
+use core::cmp::Ordering;
+use core::fmt::Display;
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+
+pub use indexed_seq::{IndexedSeq, IndexedSeqSync, Size};
+pub use priority_queue::{Max, PriorityQueue, PriorityQueueSync};
+
+/// Creates a [`FingerTree`](finger_tree/struct.FingerTree.html) containing the given arguments:
+///
+/// ```
+/// # use rpds::*;
+/// # use rpds::finger_tree::{Measured, Monoid};
+/// #
+/// # #[derive(Clone, Debug)]
+/// # struct Sum(u32);
+/// #
+/// # impl Monoid for Sum {
+/// #     fn empty() -> Sum {
+/// #         Sum(0)
+/// #     }
+/// #
+/// #     fn combine(&self, other: &Sum) -> Sum {
+/// #         Sum(self.0 + other.0)
+/// #     }
+/// # }
+/// #
+/// # impl Measured<Sum> for u32 {
+/// #     fn measure(&self) -> Sum {
+/// #         Sum(*self)
+/// #     }
+/// # }
+/// #
+/// let t: FingerTree<u32, Sum> = FingerTree::new()
+///     .push_back(1)
+///     .push_back(2)
+///     .push_back(3);
+///
+/// assert_eq!(finger_tree![1, 2, 3], t);
+/// ```
+#[macro_export]
+macro_rules! finger_tree {
+    ($($e:expr),*) => {
+        {
+            #[allow(unused_mut)]
+            let mut t = $crate::FingerTree::new();
+            $(
+                t.push_back_mut($e);
+            )*
+            t
+        }
+    };
+}
+
+/// Creates a [`FingerTree`](finger_tree/struct.FingerTree.html) that implements `Sync`, containing
+/// the given arguments:
+///
+/// ```
+/// # use rpds::*;
+/// # use rpds::finger_tree::{Measured, Monoid};
+/// #
+/// # #[derive(Clone, Debug)]
+/// # struct Sum(u32);
+/// #
+/// # impl Monoid for Sum {
+/// #     fn empty() -> Sum {
+/// #         Sum(0)
+/// #     }
+/// #
+/// #     fn combine(&self, other: &Sum) -> Sum {
+/// #         Sum(self.0 + other.0)
+/// #     }
+/// # }
+/// #
+/// # impl Measured<Sum> for u32 {
+/// #     fn measure(&self) -> Sum {
+/// #         Sum(*self)
+/// #     }
+/// # }
+/// #
+/// let t: FingerTreeSync<u32, Sum> = FingerTree::new_sync()
+///     .push_back(1)
+///     .push_back(2)
+///     .push_back(3);
+///
+/// assert_eq!(finger_tree_sync![1, 2, 3], t);
+/// ```
+#[macro_export]
+macro_rules! finger_tree_sync {
+    ($($e:expr),*) => {
+        {
+            #[allow(unused_mut)]
+            let mut t = $crate::FingerTree::new_sync();
+            $(
+                t.push_back_mut($e);
+            )*
+            t
+        }
+    };
+}
+
+/// A monoid: a type with an associative `combine()` operation and an identity element.
+///
+/// The measure of a sequence is the combination of the measures of its elements, in order.
+/// `combine()` must be associative, but it does not have to be commutative.
+pub trait Monoid: Clone {
+    /// The identity element, i.e. `empty().combine(m) == m.combine(&empty()) == m`.
+    fn empty() -> Self;
+
+    #[must_use]
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// A type whose values can be measured with a [`Monoid`](trait.Monoid.html) `M`.
+pub trait Measured<M: Monoid> {
+    fn measure(&self) -> M;
+}
+
+/// A persistent sequence with structural sharing, where every subsequence is annotated with a
+/// user defined measure.
+///
+/// The measure lets you split the sequence at the first position where a predicate over the
+/// accumulated measure becomes true.  Depending on the measure this gives you indexing (see
+/// [`IndexedSeq`](indexed_seq/struct.IndexedSeq.html)), priority queues (see
+/// [`PriorityQueue`](priority_queue/struct.PriorityQueue.html)), ordered sequences, and so on.
+///
+/// # Complexity
+///
+/// Let *n* be the number of elements in the tree.
+///
+/// ## Temporal complexity
+///
+/// | Operation                           | Average   | Worst case  |
+/// |:----------------------------------- | ---------:| -----------:|
+/// | `new()`                             |      Θ(1) |        Θ(1) |
+/// | `push_front()`/`push_back()`        |      Θ(1) |   Θ(log(n)) |
+/// | `drop_first()`/`drop_last()`        |      Θ(1) |   Θ(log(n)) |
+/// | `first()`/`last()`                  |      Θ(1) |        Θ(1) |
+/// | `append()`                          | Θ(log(n)) |   Θ(log(n)) |
+/// | `split()`                           | Θ(log(n)) |   Θ(log(n)) |
+/// | `lookup()`                          | Θ(log(n)) |   Θ(log(n)) |
+/// | `measure()`                         |      Θ(1) |        Θ(1) |
+/// | `clone()`                           |      Θ(1) |        Θ(1) |
+/// | iterator creation                   |      Θ(1) |        Θ(1) |
+/// | iterator step                       |      Θ(1) |   Θ(log(n)) |
+/// | iterator full                       |      Θ(n) |        Θ(n) |
+///
+/// The average case of `push_*()` and `drop_*()` is amortized, and assumes the operations are not
+/// repeatedly applied to the same old version of the tree.
+///
+/// For the complexity of `append()` *n* is the size of the smallest of the two trees.  For
+/// `split()` and `lookup()` we assume that computing the measure and the predicate takes Θ(1).
+///
+/// # Implementation details
+///
+/// This is a 2-3 finger tree as described in the paper
+/// [Finger Trees: A Simple General-purpose Data Structure](http://www.staff.city.ac.uk/~ross/papers/FingerTree.pdf)
+/// by Ralf Hinze and Ross Paterson.
+///
+/// The tree is stored as a spine of `Tree`s.  Every level holds up to four nodes on each end (the
+/// "digits") and a pointer to the next level.  The nodes in the digits of the first level are
+/// leaves, the nodes of the second level are 2-3 nodes of leaves, and so on.  Every 2-3 node
+/// caches its measure, as does every deep level of the spine.
+#[derive(Debug)]
+pub struct FingerTree<T, M, P = RcK>
+where
+    P: SharedPointerKind,
+{
+    tree: Tree<T, M, P>,
+}
+
+pub type FingerTreeSync<T, M> = FingerTree<T, M, ArcK>;
+
+type NodePtr<T, M, P> = SharedPointer<Node<T, M, P>, P>;
+
+/// The result of splitting a digit or a tree: what comes before the node, the node, and what
+/// comes after the node.
+type Split<T, M, P, S> = (S, NodePtr<T, M, P>, S);
+
+#[derive(Debug)]
+enum Node<T, M, P>
+where
+    P: SharedPointerKind,
+{
+    Leaf(T),
+    Node2(M, [NodePtr<T, M, P>; 2]),
+    Node3(M, [NodePtr<T, M, P>; 3]),
+}
+
+#[derive(Debug)]
+enum Tree<T, M, P>
+where
+    P: SharedPointerKind,
+{
+    Empty,
+    Single(NodePtr<T, M, P>),
+    Deep(Deep<T, M, P>),
+}
+
+#[derive(Debug)]
+struct Deep<T, M, P>
+where
+    P: SharedPointerKind,
+{
+    measure: M,
+    prefix: Vec<NodePtr<T, M, P>>,
+    middle: SharedPointer<Tree<T, M, P>, P>,
+    suffix: Vec<NodePtr<T, M, P>>,
+}
+
+mod digit_utils {
+    use super::*;
+
+    pub fn measure<T, M, P>(digit: &[NodePtr<T, M, P>]) -> M
+    where
+        T: Measured<M>,
+        M: Monoid,
+        P: SharedPointerKind,
+    {
+        digit.iter().fold(M::empty(), |acc, node| acc.combine(&node.measure()))
+    }
+
+    /// Groups a sequence of at least two nodes into 2-3 nodes, using 3-nodes whenever possible.
+    pub fn nodes<T, M, P>(digit: Vec<NodePtr<T, M, P>>) -> Vec<NodePtr<T, M, P>>
+    where
+        T: Measured<M>,
+        M: Monoid,
+        P: SharedPointerKind,
+    {
+        debug_assert!(digit.len() >= 2);
+
+        let mut result = Vec::with_capacity(digit.len() / 2);
+        let mut iter = digit.into_iter();
+        let mut left = iter.len();
+
+        while left > 0 {
+            let node = match left {
+                2 | 4 => Node::node2(iter.next().unwrap(), iter.next().unwrap()),
+                _ => Node::node3(iter.next().unwrap(), iter.next().unwrap(), iter.next().unwrap()),
+            };
+
+            left = iter.len();
+            result.push(SharedPointer::new(node));
+        }
+
+        result
+    }
+
+    /// Finds the first node of the digit where `predicate` becomes true, when applied to `acc`
+    /// combined with the measure of the prefix of the digit up to and including that node.  If
+    /// that never happens the last node is chosen.
+    ///
+    /// Returns the index of that node and `acc` combined with the measure of the nodes before it.
+    pub fn find<T, M, P, F>(predicate: &F, acc: M, digit: &[NodePtr<T, M, P>]) -> (usize, M)
+    where
+        T: Measured<M>,
+        M: Monoid,
+        P: SharedPointerKind,
+        F: Fn(&M) -> bool,
+    {
+        let mut acc = acc;
+        let mut i = 0;
+
+        while i + 1 < digit.len() {
+            let next_acc = acc.combine(&digit[i].measure());
+
+            if predicate(&next_acc) {
+                break;
+            }
+
+            acc = next_acc;
+            i += 1;
+        }
+
+        (i, acc)
+    }
+
+    /// Splits the digit at the node chosen by [`find()`](fn.find.html).
+    pub fn split<T, M, P, F>(
+        predicate: &F,
+        acc: M,
+        digit: &[NodePtr<T, M, P>],
+    ) -> Split<T, M, P, Vec<NodePtr<T, M, P>>>
+    where
+        T: Measured<M>,
+        M: Monoid,
+        P: SharedPointerKind,
+        F: Fn(&M) -> bool,
+    {
+        let (i, _) = find(predicate, acc, digit);
+
+        (digit[..i].to_vec(), SharedPointer::clone(&digit[i]), digit[i + 1..].to_vec())
+    }
+}
+
+impl<T, M, P> Node<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+    fn node2(a: NodePtr<T, M, P>, b: NodePtr<T, M, P>) -> Node<T, M, P> {
+        Node::Node2(a.measure().combine(&b.measure()), [a, b])
+    }
+
+    fn node3(a: NodePtr<T, M, P>, b: NodePtr<T, M, P>, c: NodePtr<T, M, P>) -> Node<T, M, P> {
+        Node::Node3(a.measure().combine(&b.measure()).combine(&c.measure()), [a, b, c])
+    }
+
+    fn measure(&self) -> M {
+        match self {
+            Node::Leaf(v) => v.measure(),
+            Node::Node2(measure, _) | Node::Node3(measure, _) => measure.clone(),
+        }
+    }
+
+    fn children(&self) -> &[NodePtr<T, M, P>] {
+        match self {
+            Node::Leaf(_) => unreachable!("a leaf has no children"),
+            Node::Node2(_, children) => children,
+            Node::Node3(_, children) => children,
+        }
+    }
+
+    fn value(&self) -> &T {
+        match self {
+            Node::Leaf(v) => v,
+            _ => unreachable!("expected a leaf"),
+        }
+    }
+
+    /// Descends to the leaf where `predicate` becomes true.
+    fn lookup<F: Fn(&M) -> bool>(&self, predicate: &F, acc: M) -> &T {
+        match self {
+            Node::Leaf(v) => v,
+            Node::Node2(_, children) => Node::lookup_children(children, predicate, acc),
+            Node::Node3(_, children) => Node::lookup_children(children, predicate, acc),
+        }
+    }
+
+    fn lookup_children<'a, F: Fn(&M) -> bool>(
+        children: &'a [NodePtr<T, M, P>],
+        predicate: &F,
+        acc: M,
+    ) -> &'a T {
+        let (i, acc) = digit_utils::find(predicate, acc, children);
+
+        children[i].lookup(predicate, acc)
+    }
+}
+
+impl<T, M, P> Tree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+    fn deep(
+        prefix: Vec<NodePtr<T, M, P>>,
+        middle: SharedPointer<Tree<T, M, P>, P>,
+        suffix: Vec<NodePtr<T, M, P>>,
+    ) -> Tree<T, M, P> {
+        debug_assert!(!prefix.is_empty() && prefix.len() <= 4);
+        debug_assert!(!suffix.is_empty() && suffix.len() <= 4);
+
+        let measure = digit_utils::measure(&prefix)
+            .combine(&middle.measure())
+            .combine(&digit_utils::measure(&suffix));
+
+        Tree::Deep(Deep { measure, prefix, middle, suffix })
+    }
+
+    /// Creates a deep tree whose prefix might be empty, in which case we borrow a node from the
+    /// middle tree.
+    fn deep_left(
+        prefix: Vec<NodePtr<T, M, P>>,
+        mut middle: SharedPointer<Tree<T, M, P>, P>,
+        suffix: Vec<NodePtr<T, M, P>>,
+    ) -> Tree<T, M, P> {
+        if !prefix.is_empty() {
+            Tree::deep(prefix, middle, suffix)
+        } else if middle.is_empty() {
+            Tree::from_digit(suffix)
+        } else {
+            let node = SharedPointer::make_mut(&mut middle).pop_front().unwrap();
+
+            Tree::deep(node.children().to_vec(), middle, suffix)
+        }
+    }
+
+    /// Creates a deep tree whose suffix might be empty, in which case we borrow a node from the
+    /// middle tree.
+    fn deep_right(
+        prefix: Vec<NodePtr<T, M, P>>,
+        mut middle: SharedPointer<Tree<T, M, P>, P>,
+        suffix: Vec<NodePtr<T, M, P>>,
+    ) -> Tree<T, M, P> {
+        if !suffix.is_empty() {
+            Tree::deep(prefix, middle, suffix)
+        } else if middle.is_empty() {
+            Tree::from_digit(prefix)
+        } else {
+            let node = SharedPointer::make_mut(&mut middle).pop_back().unwrap();
+
+            Tree::deep(prefix, middle, node.children().to_vec())
+        }
+    }
+
+    fn from_digit(digit: Vec<NodePtr<T, M, P>>) -> Tree<T, M, P> {
+        let mut tree = Tree::Empty;
+
+        for node in digit {
+            tree.push_back(node);
+        }
+
+        tree
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        matches!(self, Tree::Empty)
+    }
+
+    fn measure(&self) -> M {
+        match self {
+            Tree::Empty => M::empty(),
+            Tree::Single(node) => node.measure(),
+            Tree::Deep(deep) => deep.measure.clone(),
+        }
+    }
+
+    fn first(&self) -> Option<&NodePtr<T, M, P>> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(node) => Some(node),
+            Tree::Deep(deep) => deep.prefix.first(),
+        }
+    }
+
+    fn last(&self) -> Option<&NodePtr<T, M, P>> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(node) => Some(node),
+            Tree::Deep(deep) => deep.suffix.last(),
+        }
+    }
+
+    fn push_front(&mut self, node: NodePtr<T, M, P>) {
+        match self {
+            Tree::Empty => *self = Tree::Single(node),
+            Tree::Single(single) => {
+                let single = SharedPointer::clone(single);
+
+                *self = Tree::deep(vec![node], SharedPointer::new(Tree::Empty), vec![single]);
+            }
+            Tree::Deep(deep) => {
+                deep.measure = node.measure().combine(&deep.measure);
+
+                if deep.prefix.len() == 4 {
+                    let mut rest = deep.prefix.split_off(1).into_iter();
+                    let node3 = Node::node3(
+                        rest.next().unwrap(),
+                        rest.next().unwrap(),
+                        rest.next().unwrap(),
+                    );
+
+                    SharedPointer::make_mut(&mut deep.middle).push_front(SharedPointer::new(node3));
+                }
+
+                deep.prefix.insert(0, node);
+            }
+        }
+    }
+
+    fn push_back(&mut self, node: NodePtr<T, M, P>) {
+        match self {
+            Tree::Empty => *self = Tree::Single(node),
+            Tree::Single(single) => {
+                let single = SharedPointer::clone(single);
+
+                *self = Tree::deep(vec![single], SharedPointer::new(Tree::Empty), vec![node]);
+            }
+            Tree::Deep(deep) => {
+                deep.measure = deep.measure.combine(&node.measure());
+
+                if deep.suffix.len() == 4 {
+                    let last = deep.suffix.pop().unwrap();
+                    let mut rest = deep.suffix.drain(..);
+                    let node3 = Node::node3(
+                        rest.next().unwrap(),
+                        rest.next().unwrap(),
+                        rest.next().unwrap(),
+                    );
+
+                    drop(rest);
+
+                    SharedPointer::make_mut(&mut deep.middle).push_back(SharedPointer::new(node3));
+                    deep.suffix.push(last);
+                }
+
+                deep.suffix.push(node);
+            }
+        }
+    }
+
+    fn pop_front(&mut self) -> Option<NodePtr<T, M, P>> {
+        match core::mem::replace(self, Tree::Empty) {
+            Tree::Empty => None,
+            Tree::Single(node) => Some(node),
+            Tree::Deep(Deep { mut prefix, middle, suffix, .. }) => {
+                let node = prefix.remove(0);
+
+                *self = Tree::deep_left(prefix, middle, suffix);
+
+                Some(node)
+            }
+        }
+    }
+
+    fn pop_back(&mut self) -> Option<NodePtr<T, M, P>> {
+        match core::mem::replace(self, Tree::Empty) {
+            Tree::Empty => None,
+            Tree::Single(node) => Some(node),
+            Tree::Deep(Deep { prefix, middle, mut suffix, .. }) => {
+                let node = suffix.pop().unwrap();
+
+                *self = Tree::deep_right(prefix, middle, suffix);
+
+                Some(node)
+            }
+        }
+    }
+
+    /// Concatenates `left`, the nodes in `nodes`, and `right`.
+    fn concat(
+        left: &Tree<T, M, P>,
+        nodes: Vec<NodePtr<T, M, P>>,
+        right: &Tree<T, M, P>,
+    ) -> Tree<T, M, P> {
+        match (left, right) {
+            (Tree::Empty, _) => {
+                let mut tree = right.clone();
+
+                for node in nodes.into_iter().rev() {
+                    tree.push_front(node);
+                }
+
+                tree
+            }
+            (_, Tree::Empty) => {
+                let mut tree = left.clone();
+
+                for node in nodes {
+                    tree.push_back(node);
+                }
+
+                tree
+            }
+            (Tree::Single(single), _) => {
+                let mut tree = right.clone();
+
+                for node in nodes.into_iter().rev() {
+                    tree.push_front(node);
+                }
+
+                tree.push_front(SharedPointer::clone(single));
+
+                tree
+            }
+            (_, Tree::Single(single)) => {
+                let mut tree = left.clone();
+
+                for node in nodes {
+                    tree.push_back(node);
+                }
+
+                tree.push_back(SharedPointer::clone(single));
+
+                tree
+            }
+            (Tree::Deep(left), Tree::Deep(right)) => {
+                let mut middle_nodes: Vec<NodePtr<T, M, P>> =
+                    Vec::with_capacity(left.suffix.len() + nodes.len() + right.prefix.len());
+
+                middle_nodes.extend(left.suffix.iter().cloned());
+                middle_nodes.extend(nodes);
+                middle_nodes.extend(right.prefix.iter().cloned());
+
+                let middle =
+                    Tree::concat(&left.middle, digit_utils::nodes(middle_nodes), &right.middle);
+
+                Tree::deep(left.prefix.clone(), SharedPointer::new(middle), right.suffix.clone())
+            }
+        }
+    }
+
+    /// Splits a non-empty tree at the node where `predicate` becomes true.  The predicate must
+    /// be true for `acc` combined with the measure of the whole tree.
+    ///
+    /// Returns the tree before that node, the node, and the tree after that node.
+    fn split<F: Fn(&M) -> bool>(&self, predicate: &F, acc: M) -> Split<T, M, P, Tree<T, M, P>> {
+        match self {
+            Tree::Empty => unreachable!("cannot split an empty tree"),
+            Tree::Single(node) => (Tree::Empty, SharedPointer::clone(node), Tree::Empty),
+            Tree::Deep(deep) => {
+                let acc_prefix = acc.combine(&digit_utils::measure(&deep.prefix));
+
+                if predicate(&acc_prefix) {
+                    let (left, node, right) = digit_utils::split(predicate, acc, &deep.prefix);
+
+                    return (
+                        Tree::from_digit(left),
+                        node,
+                        Tree::deep_left(
+                            right,
+                            SharedPointer::clone(&deep.middle),
+                            deep.suffix.clone(),
+                        ),
+                    );
+                }
+
+                let acc_middle = acc_prefix.combine(&deep.middle.measure());
+
+                if predicate(&acc_middle) {
+                    let (middle_left, middle_node, middle_right) =
+                        deep.middle.split(predicate, acc_prefix.clone());
+                    let acc = acc_prefix.combine(&middle_left.measure());
+                    let (left, node, right) =
+                        digit_utils::split(predicate, acc, middle_node.children());
+
+                    return (
+                        Tree::deep_right(
+                            deep.prefix.clone(),
+                            SharedPointer::new(middle_left),
+                            left,
+                        ),
+                        node,
+                        Tree::deep_left(
+                            right,
+                            SharedPointer::new(middle_right),
+                            deep.suffix.clone(),
+                        ),
+                    );
+                }
+
+                let (left, node, right) = digit_utils::split(predicate, acc_middle, &deep.suffix);
+
+                (
+                    Tree::deep_right(deep.prefix.clone(), SharedPointer::clone(&deep.middle), left),
+                    node,
+                    Tree::from_digit(right),
+                )
+            }
+        }
+    }
+
+    /// Finds the element where `predicate` becomes true.  The predicate must be true for `acc`
+    /// combined with the measure of the whole tree.
+    fn lookup<F: Fn(&M) -> bool>(&self, predicate: &F, acc: M) -> &T {
+        match self {
+            Tree::Empty => unreachable!("cannot lookup in an empty tree"),
+            Tree::Single(node) => node.lookup(predicate, acc),
+            Tree::Deep(deep) => {
+                let acc_prefix = acc.combine(&digit_utils::measure(&deep.prefix));
+
+                if predicate(&acc_prefix) {
+                    return Node::lookup_children(&deep.prefix, predicate, acc);
+                }
+
+                let acc_middle = acc_prefix.combine(&deep.middle.measure());
+
+                if predicate(&acc_middle) {
+                    return deep.middle.lookup(predicate, acc_prefix);
+                }
+
+                Node::lookup_children(&deep.suffix, predicate, acc_middle)
+            }
+        }
+    }
+}
+
+impl<T, M, P> Clone for Tree<T, M, P>
+where
+    M: Clone,
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> Tree<T, M, P> {
+        match self {
+            Tree::Empty => Tree::Empty,
+            Tree::Single(node) => Tree::Single(SharedPointer::clone(node)),
+            Tree::Deep(deep) => Tree::Deep(Deep {
+                measure: deep.measure.clone(),
+                prefix: deep.prefix.clone(),
+                middle: SharedPointer::clone(&deep.middle),
+                suffix: deep.suffix.clone(),
+            }),
+        }
+    }
+}
+
+impl<T, M> FingerTreeSync<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    #[must_use]
+    pub fn new_sync() -> FingerTreeSync<T, M> {
+        FingerTree::new_with_ptr_kind()
+    }
+}
+
+impl<T, M> FingerTree<T, M>
+where
+    T: Measured<M>,
+    M: Monoid,
+{
+    #[must_use]
+    pub fn new() -> FingerTree<T, M> {
+        FingerTree::new_with_ptr_kind()
+    }
+}
+
+impl<T, M, P> FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+    #[must_use]
+    pub fn new_with_ptr_kind() -> FingerTree<T, M, P> {
+        FingerTree { tree: Tree::Empty }
+    }
+
+    #[must_use]
+    pub fn first(&self) -> Option<&T> {
+        self.tree.first().map(|node| node.value())
+    }
+
+    #[must_use]
+    pub fn last(&self) -> Option<&T> {
+        self.tree.last().map(|node| node.value())
+    }
+
+    #[must_use]
+    pub fn push_front(&self, v: T) -> FingerTree<T, M, P> {
+        let mut new_tree = self.clone();
+
+        new_tree.push_front_mut(v);
+
+        new_tree
+    }
+
+    pub fn push_front_mut(&mut self, v: T) {
+        self.tree.push_front(SharedPointer::new(Node::Leaf(v)));
+    }
+
+    #[must_use]
+    pub fn push_back(&self, v: T) -> FingerTree<T, M, P> {
+        let mut new_tree = self.clone();
+
+        new_tree.push_back_mut(v);
+
+        new_tree
+    }
+
+    pub fn push_back_mut(&mut self, v: T) {
+        self.tree.push_back(SharedPointer::new(Node::Leaf(v)));
+    }
+
+    #[must_use]
+    pub fn drop_first(&self) -> Option<FingerTree<T, M, P>> {
+        let mut new_tree = self.clone();
+
+        if new_tree.drop_first_mut() {
+            Some(new_tree)
+        } else {
+            None
+        }
+    }
+
+    pub fn drop_first_mut(&mut self) -> bool {
+        self.tree.pop_front().is_some()
+    }
+
+    #[must_use]
+    pub fn drop_last(&self) -> Option<FingerTree<T, M, P>> {
+        let mut new_tree = self.clone();
+
+        if new_tree.drop_last_mut() {
+            Some(new_tree)
+        } else {
+            None
+        }
+    }
+
+    pub fn drop_last_mut(&mut self) -> bool {
+        self.tree.pop_back().is_some()
+    }
+
+    /// Returns the concatenation of `self` with `other`.
+    #[must_use]
+    pub fn append(&self, other: &FingerTree<T, M, P>) -> FingerTree<T, M, P> {
+        FingerTree { tree: Tree::concat(&self.tree, Vec::new(), &other.tree) }
+    }
+
+    pub fn append_mut(&mut self, other: &FingerTree<T, M, P>) {
+        self.tree = Tree::concat(&self.tree, Vec::new(), &other.tree);
+    }
+
+    /// Splits the tree in two.  The second tree starts with the first element where `predicate`
+    /// becomes true, when applied to the measure of the prefix of the tree up to and including
+    /// that element.
+    ///
+    /// If `predicate` is not true for the measure of the whole tree the second tree will be empty.
+    ///
+    /// The predicate must be monotonic, i.e. once it becomes true for a prefix it must stay true
+    /// for all longer prefixes.
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// use rpds::finger_tree::{Measured, Monoid};
+    ///
+    /// #[derive(Clone, Debug)]
+    /// struct Sum(u32);
+    ///
+    /// impl Monoid for Sum {
+    ///     fn empty() -> Sum {
+    ///         Sum(0)
+    ///     }
+    ///
+    ///     fn combine(&self, other: &Sum) -> Sum {
+    ///         Sum(self.0 + other.0)
+    ///     }
+    /// }
+    ///
+    /// impl Measured<Sum> for u32 {
+    ///     fn measure(&self) -> Sum {
+    ///         Sum(*self)
+    ///     }
+    /// }
+    ///
+    /// let tree: FingerTree<u32, Sum> = finger_tree![3, 1, 4, 1, 5];
+    /// let (left, right) = tree.split(|sum| sum.0 > 4);
+    ///
+    /// assert_eq!(left, finger_tree![3, 1]);
+    /// assert_eq!(right, finger_tree![4, 1, 5]);
+    /// ```
+    #[must_use]
+    pub fn split<F: Fn(&M) -> bool>(
+        &self,
+        predicate: F,
+    ) -> (FingerTree<T, M, P>, FingerTree<T, M, P>) {
+        if !self.tree.is_empty() && predicate(&self.measure()) {
+            let (left, node, mut right) = self.tree.split(&predicate, M::empty());
+
+            right.push_front(node);
+
+            (FingerTree { tree: left }, FingerTree { tree: right })
+        } else {
+            (self.clone(), FingerTree::new_with_ptr_kind())
+        }
+    }
+
+    /// Returns the first element where `predicate` becomes true, when applied to the measure of
+    /// the prefix of the tree up to and including that element.
+    ///
+    /// The predicate must be monotonic (see [`split()`](#method.split)).
+    #[must_use]
+    pub fn lookup<F: Fn(&M) -> bool>(&self, predicate: F) -> Option<&T> {
+        if !self.tree.is_empty() && predicate(&self.measure()) {
+            Some(self.tree.lookup(&predicate, M::empty()))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the measure of the whole tree.
+    #[must_use]
+    pub fn measure(&self) -> M {
+        self.tree.measure()
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, T, M, P> {
+        Iter::new(self)
+    }
+}
+
+impl<T, M, P> Default for FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+    fn default() -> FingerTree<T, M, P> {
+        FingerTree::new_with_ptr_kind()
+    }
+}
+
+impl<T, M, P> Clone for FingerTree<T, M, P>
+where
+    M: Clone,
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> FingerTree<T, M, P> {
+        FingerTree { tree: self.tree.clone() }
+    }
+}
+
+impl<T: PartialEq, M, P, PO> PartialEq<FingerTree<T, M, PO>> for FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+    PO: SharedPointerKind,
+{
+    fn eq(&self, other: &FingerTree<T, M, PO>) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, M, P> Eq for FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+}
+
+impl<T: PartialOrd, M, P, PO> PartialOrd<FingerTree<T, M, PO>> for FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+    PO: SharedPointerKind,
+{
+    fn partial_cmp(&self, other: &FingerTree<T, M, PO>) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord, M, P> Ord for FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+    fn cmp(&self, other: &FingerTree<T, M, P>) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Hash, M, P> Hash for FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len: usize = 0;
+
+        for e in self {
+            e.hash(state);
+            len += 1;
+        }
+
+        // Add the hash of length so that if two collections are added one after the other it
+        // doesn't hash to the same thing as a single collection with the same elements in the same
+        // order.  We do it at the end since the length of the tree is not stored.
+        len.hash(state);
+    }
+}
+
+impl<T: Display, M, P> Display for FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut first = true;
+
+        fmt.write_str("[")?;
+
+        for v in self {
+            if !first {
+                fmt.write_str(", ")?;
+            }
+            v.fmt(fmt)?;
+            first = false;
+        }
+
+        fmt.write_str("]")
+    }
+}
+
+impl<'a, T, M, P> IntoIterator for &'a FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, M, P>;
+
+    fn into_iter(self) -> Iter<'a, T, M, P> {
+        self.iter()
+    }
+}
+
+impl<T, M, P> FromIterator<T> for FingerTree<T, M, P>
+where
+    T: Measured<M>,
+    M: Monoid,
+    P: SharedPointerKind,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(into_iter: I) -> FingerTree<T, M, P> {
+        let mut tree = FingerTree::new_with_ptr_kind();
+
+        for e in into_iter {
+            tree.push_back_mut(e);
+        }
+
+        tree
+    }
+}
+
+#[derive(Debug)]
+pub struct Iter<'a, T, M, P>
+where
+    P: SharedPointerKind,
+{
+    stack: Vec<IterStackElement<'a, T, M, P>>,
+}
+
+#[derive(Debug)]
+enum IterStackElement<'a, T, M, P>
+where
+    P: SharedPointerKind,
+{
+    Tree(&'a Tree<T, M, P>),
+    Node(&'a Node<T, M, P>),
+}
+
+impl<'a, T, M, P> Iter<'a, T, M, P>
+where
+    P: SharedPointerKind,
+{
+    fn new(tree: &FingerTree<T, M, P>) -> Iter<'_, T, M, P> {
+        Iter { stack: vec![IterStackElement::Tree(&tree.tree)] }
+    }
+}
+
+impl<'a, T, M, P> Iterator for Iter<'a, T, M, P>
+where
+    P: SharedPointerKind,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        while let Some(element) = self.stack.pop() {
+            match element {
+                IterStackElement::Tree(Tree::Empty) => (),
+                IterStackElement::Tree(Tree::Single(node)) => {
+                    self.stack.push(IterStackElement::Node(node));
+                }
+                IterStackElement::Tree(Tree::Deep(deep)) => {
+                    // The stack is LIFO, so we push things in the reverse order of the iteration.
+                    self.stack.extend(deep.suffix.iter().rev().map(|n| IterStackElement::Node(n)));
+                    self.stack.push(IterStackElement::Tree(&deep.middle));
+                    self.stack.extend(deep.prefix.iter().rev().map(|n| IterStackElement::Node(n)));
+                }
+                IterStackElement::Node(Node::Leaf(v)) => return Some(v),
+                IterStackElement::Node(Node::Node2(_, children)) => {
+                    self.stack.extend(children.iter().rev().map(|n| IterStackElement::Node(n)));
+                }
+                IterStackElement::Node(Node::Node3(_, children)) => {
+                    self.stack.extend(children.iter().rev().map(|n| IterStackElement::Node(n)));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(feature = "serde")]
+pub mod serde {
+    use super::*;
+    use ::serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+    use ::serde::ser::{Serialize, SerializeSeq, Serializer};
+    use core::fmt;
+    use core::marker::PhantomData;
+
+    impl<T, M, P> Serialize for FingerTree<T, M, P>
+    where
+        T: Measured<M> + Serialize,
+        M: Monoid,
+        P: SharedPointerKind,
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            // The length of the tree is not stored, but some formats need it upfront.
+            let mut seq = serializer.serialize_seq(Some(self.iter().count()))?;
+
+            for e in self {
+                seq.serialize_element(e)?;
+            }
+
+            seq.end()
+        }
+    }
+
+    impl<'de, T, M, P> Deserialize<'de> for FingerTree<T, M, P>
+    where
+        T: Measured<M> + Deserialize<'de>,
+        M: Monoid,
+        P: SharedPointerKind,
+    {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<FingerTree<T, M, P>, D::Error> {
+            deserializer.deserialize_seq(FingerTreeVisitor {
+                _phantom_t: PhantomData,
+                _phantom_m: PhantomData,
+                _phantom_p: PhantomData,
+            })
+        }
+    }
+
+    struct FingerTreeVisitor<T, M, P> {
+        _phantom_t: PhantomData<T>,
+        _phantom_m: PhantomData<M>,
+        _phantom_p: PhantomData<P>,
+    }
+
+    impl<'de, T, M, P> Visitor<'de> for FingerTreeVisitor<T, M, P>
+    where
+        T: Measured<M> + Deserialize<'de>,
+        M: Monoid,
+        P: SharedPointerKind,
+    {
+        type Value = FingerTree<T, M, P>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("a sequence")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<FingerTree<T, M, P>, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut tree = FingerTree::new_with_ptr_kind();
+
+            while let Some(value) = seq.next_element()? {
+                tree.push_back_mut(value);
+            }
+
+            Ok(tree)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
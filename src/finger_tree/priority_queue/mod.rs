@@ -0,0 +1,342 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::{FingerTree, Measured, Monoid};
+use archery::{ArcK, RcK, SharedPointerKind};
+use core::iter::FromIterator;
+
+/// The measure of a [`FingerTree`](../struct.FingerTree.html) that keeps track of its maximum
+/// element, or `None` if the tree is empty.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Max<T>(pub Option<T>);
+
+impl<T: Ord + Clone> Monoid for Max<T> {
+    fn empty() -> Max<T> {
+        Max(None)
+    }
+
+    fn combine(&self, other: &Max<T>) -> Max<T> {
+        if other.0 > self.0 {
+            other.clone()
+        } else {
+            self.clone()
+        }
+    }
+}
+
+/// An element of the queue, which is measured as its own value.
+#[derive(Debug)]
+struct Element<T>(T);
+
+impl<T: Ord + Clone> Measured<Max<T>> for Element<T> {
+    fn measure(&self) -> Max<T> {
+        Max(Some(self.0.clone()))
+    }
+}
+
+/// A persistent max-priority queue with structural sharing, which supports efficient merging.
+///
+/// Elements that compare as equal are popped in the order they were pushed.  To get a
+/// min-priority queue wrap the elements in [`Reverse`](https://doc.rust-lang.org/std/cmp/struct.Reverse.html).
+///
+/// # Complexity
+///
+/// Let *n* be the number of elements in the queue.
+///
+/// ## Temporal complexity
+///
+/// | Operation                  | Average   | Worst case  |
+/// |:-------------------------- | ---------:| -----------:|
+/// | `new()`                    |      Θ(1) |        Θ(1) |
+/// | `push()`                   |      Θ(1) |   Θ(log(n)) |
+/// | `peek()`                   | Θ(log(n)) |   Θ(log(n)) |
+/// | `pop()`                    | Θ(log(n)) |   Θ(log(n)) |
+/// | `append()`                 | Θ(log(n)) |   Θ(log(n)) |
+/// | `len()`                    |      Θ(1) |        Θ(1) |
+/// | `clone()`                  |      Θ(1) |        Θ(1) |
+/// | iterator creation          |      Θ(1) |        Θ(1) |
+/// | iterator step              |      Θ(1) |   Θ(log(n)) |
+/// | iterator full              |      Θ(n) |        Θ(n) |
+///
+/// # Implementation details
+///
+/// This is a [`FingerTree`](../struct.FingerTree.html) measured by [`Max`](struct.Max.html),
+/// where the elements are kept in insertion order.  To find the maximum we look for the first
+/// element where the maximum of the prefix equals the maximum of the whole queue.
+#[derive(Debug)]
+pub struct PriorityQueue<T, P = RcK>
+where
+    T: Ord + Clone,
+    P: SharedPointerKind,
+{
+    tree: FingerTree<Element<T>, Max<T>, P>,
+    length: usize,
+}
+
+pub type PriorityQueueSync<T> = PriorityQueue<T, ArcK>;
+
+impl<T> PriorityQueueSync<T>
+where
+    T: Ord + Clone,
+{
+    #[must_use]
+    pub fn new_sync() -> PriorityQueueSync<T> {
+        PriorityQueue::new_with_ptr_kind()
+    }
+}
+
+impl<T> PriorityQueue<T>
+where
+    T: Ord + Clone,
+{
+    #[must_use]
+    pub fn new() -> PriorityQueue<T> {
+        PriorityQueue::new_with_ptr_kind()
+    }
+}
+
+impl<T, P> PriorityQueue<T, P>
+where
+    T: Ord + Clone,
+    P: SharedPointerKind,
+{
+    #[must_use]
+    pub fn new_with_ptr_kind() -> PriorityQueue<T, P> {
+        PriorityQueue { tree: FingerTree::new_with_ptr_kind(), length: 0 }
+    }
+
+    /// Returns the greatest element of the queue.
+    #[must_use]
+    pub fn peek(&self) -> Option<&T> {
+        let max = self.tree.measure();
+
+        self.tree.lookup(|m| *m >= max).map(|e| &e.0)
+    }
+
+    #[must_use]
+    pub fn push(&self, v: T) -> PriorityQueue<T, P> {
+        let mut new_queue = self.clone();
+
+        new_queue.push_mut(v);
+
+        new_queue
+    }
+
+    pub fn push_mut(&mut self, v: T) {
+        self.tree.push_back_mut(Element(v));
+        self.length += 1;
+    }
+
+    /// Returns the queue without its greatest element.
+    #[must_use]
+    pub fn pop(&self) -> Option<PriorityQueue<T, P>> {
+        let mut new_queue = self.clone();
+
+        if new_queue.pop_mut() {
+            Some(new_queue)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the operation was successful.
+    pub fn pop_mut(&mut self) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let max = self.tree.measure();
+        let (mut left, mut right) = self.tree.split(|m| *m >= max);
+
+        right.drop_first_mut();
+        left.append_mut(&right);
+
+        self.tree = left;
+        self.length -= 1;
+
+        true
+    }
+
+    /// Returns a queue with the elements of both `self` and `other`.
+    #[must_use]
+    pub fn append(&self, other: &PriorityQueue<T, P>) -> PriorityQueue<T, P> {
+        let mut new_queue = self.clone();
+
+        new_queue.append_mut(other);
+
+        new_queue
+    }
+
+    pub fn append_mut(&mut self, other: &PriorityQueue<T, P>) {
+        self.tree.append_mut(&other.tree);
+        self.length += other.length;
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the elements of the queue in an unspecified order.
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter { iter: self.tree.iter(), remaining: self.len() }
+    }
+}
+
+impl<T, P> Default for PriorityQueue<T, P>
+where
+    T: Ord + Clone,
+    P: SharedPointerKind,
+{
+    fn default() -> PriorityQueue<T, P> {
+        PriorityQueue::new_with_ptr_kind()
+    }
+}
+
+impl<T, P> Clone for PriorityQueue<T, P>
+where
+    T: Ord + Clone,
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> PriorityQueue<T, P> {
+        PriorityQueue { tree: self.tree.clone(), length: self.length }
+    }
+}
+
+impl<'a, T, P> IntoIterator for &'a PriorityQueue<T, P>
+where
+    T: Ord + Clone,
+    P: SharedPointerKind,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, P> FromIterator<T> for PriorityQueue<T, P>
+where
+    T: Ord + Clone,
+    P: SharedPointerKind,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(into_iter: I) -> PriorityQueue<T, P> {
+        let mut queue = PriorityQueue::new_with_ptr_kind();
+
+        for e in into_iter {
+            queue.push_mut(e);
+        }
+
+        queue
+    }
+}
+
+#[derive(Debug)]
+pub struct Iter<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    iter: super::Iter<'a, Element<T>, Max<T>, P>,
+    remaining: usize,
+}
+
+impl<'a, T, P> Iterator for Iter<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let next = self.iter.next();
+
+        if next.is_some() {
+            self.remaining -= 1;
+        }
+
+        next.map(|e| &e.0)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, P> ExactSizeIterator for Iter<'a, T, P> where P: SharedPointerKind {}
+
+#[cfg(feature = "serde")]
+pub mod serde {
+    use super::*;
+    use ::serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+    use ::serde::ser::{Serialize, Serializer};
+    use core::fmt;
+    use core::marker::PhantomData;
+
+    impl<T, P> Serialize for PriorityQueue<T, P>
+    where
+        T: Ord + Clone + Serialize,
+        P: SharedPointerKind,
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self)
+        }
+    }
+
+    impl<'de, T, P> Deserialize<'de> for PriorityQueue<T, P>
+    where
+        T: Ord + Clone + Deserialize<'de>,
+        P: SharedPointerKind,
+    {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<PriorityQueue<T, P>, D::Error> {
+            deserializer.deserialize_seq(PriorityQueueVisitor {
+                _phantom_t: PhantomData,
+                _phantom_p: PhantomData,
+            })
+        }
+    }
+
+    struct PriorityQueueVisitor<T, P> {
+        _phantom_t: PhantomData<T>,
+        _phantom_p: PhantomData<P>,
+    }
+
+    impl<'de, T, P> Visitor<'de> for PriorityQueueVisitor<T, P>
+    where
+        T: Ord + Clone + Deserialize<'de>,
+        P: SharedPointerKind,
+    {
+        type Value = PriorityQueue<T, P>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("a sequence")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<PriorityQueue<T, P>, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut queue = PriorityQueue::new_with_ptr_kind();
+
+            while let Some(value) = seq.next_element()? {
+                queue.push_mut(value);
+            }
+
+            Ok(queue)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,121 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::*;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use pretty_assertions::assert_eq;
+use static_assertions::assert_impl_all;
+
+assert_impl_all!(PriorityQueueSync<i32>: Send, Sync);
+
+fn drain<T: Ord + Clone, P: SharedPointerKind>(queue: &PriorityQueue<T, P>) -> Vec<T> {
+    let mut queue = queue.clone();
+    let mut result = Vec::new();
+
+    while let Some(v) = queue.peek() {
+        result.push(v.clone());
+        assert!(queue.pop_mut());
+    }
+
+    result
+}
+
+#[test]
+fn test_new() {
+    let empty_queue: PriorityQueue<i32> = PriorityQueue::new();
+
+    assert!(empty_queue.is_empty());
+    assert_eq!(empty_queue.len(), 0);
+    assert_eq!(empty_queue.peek(), None);
+    assert!(empty_queue.pop().is_none());
+}
+
+#[test]
+fn test_push_peek_pop() {
+    let values = [5, 3, 9, 1, 9, 0, 7, 2, 8, 4, 6];
+    let mut queue = PriorityQueue::new();
+
+    for (i, v) in values.iter().enumerate() {
+        queue = queue.push(*v);
+
+        assert_eq!(queue.len(), i + 1);
+        assert_eq!(queue.peek(), values[..=i].iter().max());
+    }
+
+    let mut sorted = values.to_vec();
+
+    sorted.sort_by(|a, b| b.cmp(a));
+
+    assert_eq!(drain(&queue), sorted);
+    assert_eq!(queue.len(), values.len());
+}
+
+#[test]
+fn test_pop_many() {
+    let queue: PriorityQueue<u32> = (0..1000).map(|i| (i * 7919) % 1000).collect();
+    let drained = drain(&queue);
+
+    assert!(drained.into_iter().eq((0..1000).rev()));
+}
+
+#[test]
+fn test_pop_equal_priorities_in_insertion_order() {
+    let queue: PriorityQueue<(u32, Reverse<u32>)> =
+        vec![(1, Reverse(0)), (2, Reverse(1)), (1, Reverse(2)), (2, Reverse(3))]
+            .into_iter()
+            .collect();
+
+    let order: Vec<u32> = drain(&queue).into_iter().map(|(_, Reverse(i))| i).collect();
+
+    assert_eq!(order, vec![1, 3, 0, 2]);
+}
+
+#[test]
+fn test_min_queue() {
+    let queue: PriorityQueue<Reverse<i32>> = vec![3, 1, 2].into_iter().map(Reverse).collect();
+
+    assert_eq!(queue.peek(), Some(&Reverse(1)));
+}
+
+#[test]
+fn test_append() {
+    let queue_a: PriorityQueue<i32> = vec![1, 8, 3].into_iter().collect();
+    let queue_b: PriorityQueue<i32> = vec![7, 2, 9].into_iter().collect();
+    let queue = queue_a.append(&queue_b);
+
+    assert_eq!(queue.len(), 6);
+    assert_eq!(drain(&queue), vec![9, 8, 7, 3, 2, 1]);
+    assert_eq!(drain(&queue_a), vec![8, 3, 1]);
+}
+
+#[test]
+fn test_iter() {
+    let queue: PriorityQueue<i32> = vec![1, 8, 3].into_iter().collect();
+    let mut values: Vec<i32> = queue.iter().copied().collect();
+
+    values.sort_unstable();
+
+    assert_eq!(values, vec![1, 3, 8]);
+}
+
+#[test]
+fn test_default() {
+    let queue: PriorityQueue<i32> = PriorityQueue::default();
+
+    assert!(queue.is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use bincode::{deserialize, serialize};
+    let queue: PriorityQueue<i32> = vec![5, 8, 6].into_iter().collect();
+    let encoded = serialize(&queue).unwrap();
+    let decoded: PriorityQueue<i32> = deserialize(&encoded).unwrap();
+
+    assert_eq!(decoded.len(), 3);
+    assert_eq!(drain(&decoded), vec![8, 6, 5]);
+}
@@ -0,0 +1,552 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::*;
+use alloc::vec::Vec;
+use pretty_assertions::assert_eq;
+use static_assertions::assert_impl_all;
+
+assert_impl_all!(FingerTreeSync<i32, Count>: Send, Sync);
+
+#[allow(dead_code)]
+fn compile_time_macro_finger_tree_sync_is_send_and_sync() -> impl Send + Sync {
+    let t: FingerTreeSync<i32, Count> = finger_tree_sync!(0);
+    t
+}
+
+/// A measure that sums the elements, so that the measure depends on the value of the element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sum(u64);
+
+impl Monoid for Sum {
+    fn empty() -> Sum {
+        Sum(0)
+    }
+
+    fn combine(&self, other: &Sum) -> Sum {
+        Sum(self.0 + other.0)
+    }
+}
+
+impl Measured<Sum> for u64 {
+    fn measure(&self) -> Sum {
+        Sum(*self)
+    }
+}
+
+/// A measure that counts the elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Count(usize);
+
+impl Monoid for Count {
+    fn empty() -> Count {
+        Count(0)
+    }
+
+    fn combine(&self, other: &Count) -> Count {
+        Count(self.0 + other.0)
+    }
+}
+
+macro_rules! impl_measured_count {
+    ($($t:ty),*) => {
+        $(
+            impl Measured<Count> for $t {
+                fn measure(&self) -> Count {
+                    Count(1)
+                }
+            }
+        )*
+    };
+}
+
+impl_measured_count!(&str, f32, i32, u32, usize);
+
+mod internal {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Checks the invariants of a tree whose nodes are at the given depth.
+    fn check_tree<T, M, P>(tree: &Tree<T, M, P>, depth: usize)
+    where
+        T: Measured<M>,
+        M: Monoid + PartialEq + core::fmt::Debug,
+        P: SharedPointerKind,
+    {
+        match tree {
+            Tree::Empty => (),
+            Tree::Single(node) => check_node(node, depth),
+            Tree::Deep(deep) => {
+                assert!(!deep.prefix.is_empty() && deep.prefix.len() <= 4);
+                assert!(!deep.suffix.is_empty() && deep.suffix.len() <= 4);
+
+                deep.prefix.iter().for_each(|node| check_node(node, depth));
+                deep.suffix.iter().for_each(|node| check_node(node, depth));
+                check_tree(&deep.middle, depth + 1);
+
+                let measure = digit_utils::measure(&deep.prefix)
+                    .combine(&deep.middle.measure())
+                    .combine(&digit_utils::measure(&deep.suffix));
+
+                assert_eq!(deep.measure, measure);
+            }
+        }
+    }
+
+    fn check_node<T, M, P>(node: &Node<T, M, P>, depth: usize)
+    where
+        T: Measured<M>,
+        M: Monoid + PartialEq + core::fmt::Debug,
+        P: SharedPointerKind,
+    {
+        match node {
+            Node::Leaf(_) => assert_eq!(depth, 0),
+            Node::Node2(measure, _) | Node::Node3(measure, _) => {
+                assert!(depth > 0);
+
+                node.children().iter().for_each(|child| check_node(child, depth - 1));
+
+                assert_eq!(*measure, digit_utils::measure(node.children()));
+            }
+        }
+    }
+
+    pub fn check<T, M, P>(tree: &FingerTree<T, M, P>)
+    where
+        T: Measured<M>,
+        M: Monoid + PartialEq + core::fmt::Debug,
+        P: SharedPointerKind,
+    {
+        check_tree(&tree.tree, 0);
+    }
+
+    #[test]
+    fn test_nodes() {
+        for len in 2..20_usize {
+            let digit: Vec<NodePtr<u64, Sum, RcK>> =
+                (0..len as u64).map(|v| SharedPointer::new(Node::Leaf(v))).collect();
+            let nodes = digit_utils::nodes(digit);
+            let sizes: Vec<usize> = nodes.iter().map(|n| n.children().len()).collect();
+
+            assert!(sizes.iter().all(|&s| s == 2 || s == 3));
+            assert_eq!(sizes.iter().sum::<usize>(), len);
+            assert_eq!(digit_utils::measure(&nodes), Sum((0..len as u64).sum()));
+        }
+    }
+
+    #[test]
+    fn test_invariants_push_drop() {
+        let mut tree: FingerTree<u64, Sum> = FingerTree::new();
+
+        for i in 0..1000 {
+            if i % 2 == 0 {
+                tree.push_back_mut(i);
+            } else {
+                tree.push_front_mut(i);
+            }
+
+            check(&tree);
+        }
+
+        while !tree.is_empty() {
+            if tree.first().unwrap() & 1 == 0 {
+                tree.drop_last_mut();
+            } else {
+                tree.drop_first_mut();
+            }
+
+            check(&tree);
+        }
+    }
+
+    #[test]
+    fn test_invariants_append_split() {
+        for left_len in 0..40 {
+            for right_len in 0..40 {
+                let left: FingerTree<u64, Sum> = (0..left_len).collect();
+                let right: FingerTree<u64, Sum> = (left_len..left_len + right_len).collect();
+                let tree = left.append(&right);
+
+                check(&tree);
+                assert!(tree.iter().copied().eq(0..left_len + right_len));
+
+                let (l, r) = tree.split(|sum| sum.0 >= 100);
+
+                check(&l);
+                check(&r);
+            }
+        }
+    }
+}
+
+mod iter {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_iter() {
+        let limit = 1024;
+        let tree: FingerTree<u64, Sum> = (0..limit).collect();
+        let mut iter = tree.iter();
+
+        for i in 0..limit {
+            assert_eq!(iter.next(), Some(&i));
+        }
+
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_iter_empty() {
+        let tree: FingerTree<u64, Sum> = FingerTree::new();
+
+        assert_eq!(tree.iter().next(), None);
+    }
+
+    #[test]
+    fn test_into_iterator() {
+        let tree: FingerTree<u64, Sum> = finger_tree![0, 1, 2, 3];
+        let mut left = 4;
+
+        for (expected, n) in tree.into_iter().enumerate() {
+            left -= 1;
+
+            assert!(left >= 0);
+            assert_eq!(expected as u64, *n);
+        }
+
+        assert_eq!(left, 0);
+    }
+}
+
+#[test]
+fn test_new() {
+    let empty_tree: FingerTree<u64, Sum> = FingerTree::new();
+
+    assert!(empty_tree.is_empty());
+    assert_eq!(empty_tree.measure(), Sum(0));
+    assert_eq!(empty_tree.first(), None);
+    assert_eq!(empty_tree.last(), None);
+}
+
+#[test]
+fn test_macro_finger_tree() {
+    let mut tree_1: FingerTree<_, Count> = FingerTree::new();
+
+    tree_1.push_back_mut(1);
+
+    let mut tree_1_2_3: FingerTree<_, Count> = FingerTree::new();
+
+    tree_1_2_3.push_back_mut(1);
+    tree_1_2_3.push_back_mut(2);
+    tree_1_2_3.push_back_mut(3);
+
+    assert_eq!(FingerTree::<u32, Count>::new(), finger_tree![]);
+    assert_eq!(tree_1, finger_tree![1]);
+    assert_eq!(tree_1_2_3, finger_tree![1, 2, 3]);
+}
+
+#[test]
+fn test_first_last() {
+    let tree: FingerTree<u64, Sum> = finger_tree![1, 2, 3];
+
+    assert_eq!(tree.first(), Some(&1));
+    assert_eq!(tree.last(), Some(&3));
+
+    let tree: FingerTree<u64, Sum> = (0..100).collect();
+
+    assert_eq!(tree.first(), Some(&0));
+    assert_eq!(tree.last(), Some(&99));
+}
+
+#[test]
+fn test_push_front() {
+    let limit = 1024;
+    let mut tree: FingerTree<u64, Sum> = FingerTree::new();
+
+    for i in 0..limit {
+        tree = tree.push_front(i);
+
+        assert_eq!(tree.first(), Some(&i));
+        assert_eq!(tree.last(), Some(&0));
+        assert_eq!(tree.measure(), Sum((0..=i).sum()));
+    }
+
+    assert!(tree.iter().copied().eq((0..limit).rev()));
+}
+
+#[test]
+fn test_push_back() {
+    let limit = 1024;
+    let mut tree: FingerTree<u64, Sum> = FingerTree::new();
+
+    for i in 0..limit {
+        tree = tree.push_back(i);
+
+        assert_eq!(tree.first(), Some(&0));
+        assert_eq!(tree.last(), Some(&i));
+        assert_eq!(tree.measure(), Sum((0..=i).sum()));
+    }
+
+    assert!(tree.iter().copied().eq(0..limit));
+}
+
+#[test]
+fn test_drop_first() {
+    let limit = 1024;
+    let mut tree: FingerTree<u64, Sum> = (0..limit).collect();
+
+    for i in 0..limit {
+        assert_eq!(tree.first(), Some(&i));
+
+        tree = tree.drop_first().unwrap();
+
+        assert_eq!(tree.measure(), Sum((i + 1..limit).sum()));
+    }
+
+    assert!(tree.is_empty());
+    assert!(tree.drop_first().is_none());
+}
+
+#[test]
+fn test_drop_last() {
+    let limit = 1024;
+    let mut tree: FingerTree<u64, Sum> = (0..limit).collect();
+
+    for i in (0..limit).rev() {
+        assert_eq!(tree.last(), Some(&i));
+
+        tree = tree.drop_last().unwrap();
+
+        assert_eq!(tree.measure(), Sum((0..i).sum()));
+    }
+
+    assert!(tree.is_empty());
+    assert!(tree.drop_last().is_none());
+}
+
+#[test]
+fn test_persistence() {
+    let tree: FingerTree<u64, Sum> = (0..100).collect();
+    let tree_front = tree.push_front(1000);
+    let tree_back = tree.push_back(1000);
+    let tree_dropped = tree.drop_first().unwrap();
+
+    assert!(tree.iter().copied().eq(0..100));
+    assert!(tree_front.iter().copied().eq(core::iter::once(1000).chain(0..100)));
+    assert!(tree_back.iter().copied().eq((0..100).chain(core::iter::once(1000))));
+    assert!(tree_dropped.iter().copied().eq(1..100));
+}
+
+#[test]
+fn test_append() {
+    for left_len in 0..50 {
+        for right_len in 0..50 {
+            let left: FingerTree<u64, Sum> = (0..left_len).collect();
+            let right: FingerTree<u64, Sum> = (left_len..left_len + right_len).collect();
+            let tree = left.append(&right);
+
+            assert!(tree.iter().copied().eq(0..left_len + right_len));
+            assert_eq!(tree.measure(), Sum((0..left_len + right_len).sum()));
+
+            assert!(left.iter().copied().eq(0..left_len));
+            assert!(right.iter().copied().eq(left_len..left_len + right_len));
+        }
+    }
+}
+
+#[test]
+fn test_append_mut() {
+    let mut tree: FingerTree<u64, Sum> = FingerTree::new();
+
+    for i in 0..100 {
+        let other: FingerTree<u64, Sum> = (i * 10..i * 10 + 10).collect();
+
+        tree.append_mut(&other);
+    }
+
+    assert!(tree.iter().copied().eq(0..1000));
+}
+
+#[test]
+fn test_split() {
+    let limit = 200;
+    let tree: FingerTree<_, Count> = (0..limit).collect();
+
+    for i in 0..=limit {
+        let (left, right) = tree.split(|size| size.0 > i);
+
+        assert!(left.iter().copied().eq(0..i), "split at {}", i);
+        assert!(right.iter().copied().eq(i..limit), "split at {}", i);
+    }
+
+    let (left, right) = tree.split(|_| true);
+
+    assert!(left.is_empty());
+    assert_eq!(right, tree);
+
+    let (left, right) = tree.split(|_| false);
+
+    assert_eq!(left, tree);
+    assert!(right.is_empty());
+
+    let empty: FingerTree<usize, Count> = FingerTree::new();
+    let (left, right) = empty.split(|_| true);
+
+    assert!(left.is_empty());
+    assert!(right.is_empty());
+}
+
+#[test]
+fn test_split_by_value_measure() {
+    let tree: FingerTree<u64, Sum> = (1..=10).collect();
+
+    // Prefix sums: 1, 3, 6, 10, 15, ...
+    let (left, right) = tree.split(|sum| sum.0 > 10);
+
+    assert_eq!(left, finger_tree![1, 2, 3, 4]);
+    assert!(right.iter().copied().eq(5..=10));
+}
+
+#[test]
+fn test_lookup() {
+    let limit = 500;
+    let tree: FingerTree<_, Count> = (0..limit).collect();
+
+    for i in 0..limit {
+        assert_eq!(tree.lookup(|size| size.0 > i), Some(&i));
+    }
+
+    assert_eq!(tree.lookup(|size| size.0 > limit), None);
+
+    let sum_tree: FingerTree<u64, Sum> = (1..=10).collect();
+
+    assert_eq!(sum_tree.lookup(|sum| sum.0 > 10), Some(&5));
+    assert_eq!(sum_tree.lookup(|sum| sum.0 >= 10), Some(&4));
+}
+
+#[test]
+fn test_from_iterator() {
+    let vec: Vec<u32> = vec![10, 11, 12, 13];
+    let tree: FingerTree<u32, Count> = vec.iter().copied().collect();
+
+    assert!(vec.iter().eq(tree.iter()));
+}
+
+#[test]
+fn test_default() {
+    let tree: FingerTree<i32, Count> = FingerTree::default();
+
+    assert!(tree.is_empty());
+}
+
+#[test]
+fn test_display() {
+    let empty_tree: FingerTree<i32, Count> = FingerTree::new();
+    let singleton_tree: FingerTree<_, Count> = finger_tree!["hello"];
+    let tree: FingerTree<_, Count> = finger_tree![0, 1, 2, 3];
+
+    assert_eq!(format!("{}", empty_tree), "[]");
+    assert_eq!(format!("{}", singleton_tree), "[hello]");
+    assert_eq!(format!("{}", tree), "[0, 1, 2, 3]");
+}
+
+#[test]
+fn test_eq() {
+    let tree_1: FingerTree<_, Count> = finger_tree!["a", "a"];
+    let tree_1_prime: FingerTree<_, Count> = finger_tree!["a", "a"];
+    let tree_2: FingerTree<_, Count> = finger_tree!["a", "b"];
+
+    assert_ne!(tree_1, tree_2);
+    assert_eq!(tree_1, tree_1);
+    assert_eq!(tree_1, tree_1_prime);
+    assert_eq!(tree_2, tree_2);
+}
+
+#[test]
+fn test_eq_pointer_kind_consistent() {
+    let tree_a: FingerTree<_, Count> = finger_tree!["a"];
+    let tree_a_sync: FingerTreeSync<_, Count> = finger_tree_sync!["a"];
+    let tree_b: FingerTree<_, Count> = finger_tree!["b"];
+    let tree_b_sync: FingerTreeSync<_, Count> = finger_tree_sync!["b"];
+
+    assert!(tree_a == tree_a_sync);
+    assert!(tree_a != tree_b_sync);
+    assert!(tree_b == tree_b_sync);
+}
+
+#[test]
+fn test_partial_ord() {
+    let tree_1: FingerTree<_, Count> = finger_tree!["a"];
+    let tree_1_prime: FingerTree<_, Count> = finger_tree!["a"];
+    let tree_2: FingerTree<_, Count> = finger_tree!["b"];
+    let tree_3: FingerTree<_, Count> = finger_tree![0.0];
+    let tree_4: FingerTree<_, Count> = finger_tree![f32::NAN];
+
+    assert_eq!(tree_1.partial_cmp(&tree_1_prime), Some(Ordering::Equal));
+    assert_eq!(tree_1.partial_cmp(&tree_2), Some(Ordering::Less));
+    assert_eq!(tree_2.partial_cmp(&tree_1), Some(Ordering::Greater));
+    assert_eq!(tree_3.partial_cmp(&tree_4), None);
+}
+
+#[test]
+fn test_ord() {
+    let tree_1: FingerTree<_, Count> = finger_tree!["a"];
+    let tree_1_prime: FingerTree<_, Count> = finger_tree!["a"];
+    let tree_2: FingerTree<_, Count> = finger_tree!["b"];
+
+    assert_eq!(tree_1.cmp(&tree_1_prime), Ordering::Equal);
+    assert_eq!(tree_1.cmp(&tree_2), Ordering::Less);
+    assert_eq!(tree_2.cmp(&tree_1), Ordering::Greater);
+}
+
+fn hash<T: Hash + Measured<M>, M: Monoid, P: SharedPointerKind>(tree: &FingerTree<T, M, P>) -> u64 {
+    #[allow(deprecated)]
+    let mut hasher = core::hash::SipHasher::new();
+
+    tree.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[test]
+fn test_hash() {
+    let tree_1: FingerTree<_, Count> = finger_tree!["a"];
+    let tree_1_prime: FingerTree<_, Count> = finger_tree!["a"];
+    let tree_2: FingerTree<_, Count> = finger_tree!["a", "b"];
+
+    assert_eq!(hash(&tree_1), hash(&tree_1));
+    assert_eq!(hash(&tree_1), hash(&tree_1_prime));
+    assert_ne!(hash(&tree_1), hash(&tree_2));
+}
+
+#[test]
+fn test_hash_pointer_kind_consistent() {
+    let tree: FingerTree<_, Count> = finger_tree!["a"];
+    let tree_sync: FingerTreeSync<_, Count> = finger_tree_sync!["a"];
+
+    assert_eq!(hash(&tree), hash(&tree_sync));
+}
+
+#[test]
+fn test_clone() {
+    let tree: FingerTree<_, Count> = finger_tree!["hello", "there"];
+    let clone = tree.clone();
+
+    assert!(clone.iter().eq(tree.iter()));
+    assert_eq!(clone.measure(), tree.measure());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use bincode::{deserialize, serialize};
+    let tree: FingerTree<u64, Sum> = finger_tree![5, 6, 7, 8];
+    let encoded = serialize(&tree).unwrap();
+    let decoded: FingerTree<u64, Sum> = deserialize(&encoded).unwrap();
+
+    assert_eq!(tree, decoded);
+    assert_eq!(tree.measure(), decoded.measure());
+}
@@ -0,0 +1,667 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use crate::List;
+use alloc::vec::Vec;
+use archery::*;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::iter::FromIterator;
+
+/// Creates a [`Heap`](heap/struct.Heap.html) containing the given arguments:
+///
+/// ```
+/// # use rpds::*;
+/// #
+/// let h = Heap::new()
+///     .push(3)
+///     .push(1)
+///     .push(2);
+///
+/// assert_eq!(heap![3, 1, 2].peek(), h.peek());
+/// ```
+#[macro_export]
+macro_rules! heap {
+    ($($e:expr),*) => {
+        {
+            #[allow(unused_mut)]
+            let mut h = $crate::Heap::new();
+            $(
+                h.push_mut($e);
+            )*
+            h
+        }
+    };
+}
+
+/// Creates a [`Heap`](heap/struct.Heap.html) that implements `Sync`, containing the given
+/// arguments:
+///
+/// ```
+/// # use rpds::*;
+/// #
+/// let h = Heap::new_sync()
+///     .push(3)
+///     .push(1)
+///     .push(2);
+///
+/// assert_eq!(heap_sync![3, 1, 2].peek(), h.peek());
+/// ```
+#[macro_export]
+macro_rules! heap_sync {
+    ($($e:expr),*) => {
+        {
+            #[allow(unused_mut)]
+            let mut h = $crate::Heap::new_sync();
+            $(
+                h.push_mut($e);
+            )*
+            h
+        }
+    };
+}
+
+/// Defines the order of the elements of a [`Heap`](struct.Heap.html).
+///
+/// This is implemented by any `Fn(&T, &T) -> Ordering`, so you can use a closure as a comparator.
+pub trait Comparator<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// A [`Comparator`](trait.Comparator.html) that uses the `Ord` implementation of the elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct OrdComparator;
+
+impl<T: Ord + ?Sized> Comparator<T> for OrdComparator {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+impl<T: ?Sized, F> Comparator<T> for F
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+/// A persistent min-heap with structural sharing.
+///
+/// The order of the elements is defined by a [`Comparator`](trait.Comparator.html), which by
+/// default is the `Ord` implementation of the elements.  To get a max-heap use a comparator that
+/// reverses the order, or wrap the elements in
+/// [`Reverse`](https://doc.rust-lang.org/std/cmp/struct.Reverse.html).
+///
+/// # Complexity
+///
+/// Let *n* be the number of elements in the heap.
+///
+/// ## Temporal complexity
+///
+/// | Operation         | Average   | Worst case  |
+/// |:----------------- | ---------:| -----------:|
+/// | `new()`           |      Θ(1) |        Θ(1) |
+/// | `push()`          |      Θ(1) |        Θ(1) |
+/// | `peek()`          |      Θ(1) |        Θ(1) |
+/// | `pop()`           | Θ(log(n)) |   Θ(log(n)) |
+/// | `merge()`         | Θ(log(n)) |   Θ(log(n)) |
+/// | `len()`           |      Θ(1) |        Θ(1) |
+/// | `clone()`         |      Θ(1) |        Θ(1) |
+/// | iterator creation | Θ(log(n)) |   Θ(log(n)) |
+/// | iterator step     |      Θ(1) |        Θ(1) |
+/// | iterator full     |      Θ(n) |        Θ(n) |
+///
+/// # Implementation details
+///
+/// This is a skew binomial heap as described in
+/// [Optimal Purely Functional Priority Queues](https://www.brics.dk/RS/96/37/BRICS-RS-96-37.pdf)
+/// by Gerth Stølting Brodal and Chris Okasaki.  We keep a pointer to the minimum element so that
+/// `peek()` runs in constant time.
+#[derive(Debug)]
+pub struct Heap<T, P = RcK, C = OrdComparator>
+where
+    P: SharedPointerKind,
+    C: Comparator<T>,
+{
+    /// Trees in increasing order of rank.  Only the first two trees can have the same rank.
+    trees: List<Tree<T, P>, P>,
+    /// The root of the first tree with the minimum root.
+    min: Option<SharedPointer<T, P>>,
+    size: usize,
+    comparator: C,
+}
+
+pub type HeapSync<T, C = OrdComparator> = Heap<T, ArcK, C>;
+
+#[derive(Debug)]
+struct Tree<T, P>
+where
+    P: SharedPointerKind,
+{
+    rank: usize,
+    root: SharedPointer<T, P>,
+    /// Elements added by skew links.  These are not smaller than `root`.
+    elements: List<T, P>,
+    /// Children in decreasing order of rank.
+    children: List<Tree<T, P>, P>,
+}
+
+impl<T, P> Clone for Tree<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> Tree<T, P> {
+        Tree {
+            rank: self.rank,
+            root: SharedPointer::clone(&self.root),
+            elements: self.elements.clone(),
+            children: self.children.clone(),
+        }
+    }
+}
+
+impl<T, P> Tree<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn new_singleton(v: SharedPointer<T, P>) -> Tree<T, P> {
+        Tree {
+            rank: 0,
+            root: v,
+            elements: List::new_with_ptr_kind(),
+            children: List::new_with_ptr_kind(),
+        }
+    }
+}
+
+impl<T> HeapSync<T>
+where
+    T: Ord,
+{
+    #[must_use]
+    pub fn new_sync() -> HeapSync<T> {
+        Heap::new_with_ptr_kind()
+    }
+}
+
+impl<T> Heap<T>
+where
+    T: Ord,
+{
+    #[must_use]
+    pub fn new() -> Heap<T> {
+        Heap::new_with_ptr_kind()
+    }
+}
+
+impl<T, P> Heap<T, P>
+where
+    T: Ord,
+    P: SharedPointerKind,
+{
+    #[must_use]
+    pub fn new_with_ptr_kind() -> Heap<T, P> {
+        Heap::new_with_comparator_and_ptr_kind(OrdComparator)
+    }
+}
+
+impl<T, C> Heap<T, RcK, C>
+where
+    C: Comparator<T>,
+{
+    /// Creates an empty heap where the elements are ordered by `comparator`.
+    ///
+    /// ```
+    /// # use rpds::Heap;
+    /// #
+    /// let heap = Heap::new_with_comparator(|a: &i32, b: &i32| b.cmp(a))
+    ///     .push(1)
+    ///     .push(3)
+    ///     .push(2);
+    ///
+    /// assert_eq!(heap.peek(), Some(&3));
+    /// ```
+    #[must_use]
+    pub fn new_with_comparator(comparator: C) -> Heap<T, RcK, C> {
+        Heap::new_with_comparator_and_ptr_kind(comparator)
+    }
+}
+
+impl<T, C> HeapSync<T, C>
+where
+    C: Comparator<T>,
+{
+    #[must_use]
+    pub fn new_sync_with_comparator(comparator: C) -> HeapSync<T, C> {
+        Heap::new_with_comparator_and_ptr_kind(comparator)
+    }
+}
+
+impl<T, P, C> Heap<T, P, C>
+where
+    P: SharedPointerKind,
+    C: Comparator<T>,
+{
+    #[must_use]
+    pub fn new_with_comparator_and_ptr_kind(comparator: C) -> Heap<T, P, C> {
+        Heap { trees: List::new_with_ptr_kind(), min: None, size: 0, comparator }
+    }
+
+    /// Returns the smallest element of the heap.  If there are several smallest elements this
+    /// will be the one removed by [`pop()`](#method.pop).
+    #[must_use]
+    pub fn peek(&self) -> Option<&T> {
+        self.min.as_ref().map(Borrow::borrow)
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the elements of the heap in an unspecified order.
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter::new(self)
+    }
+
+    fn less_or_equal(&self, a: &T, b: &T) -> bool {
+        self.comparator.compare(a, b) != Ordering::Greater
+    }
+
+    fn link(&self, t1: Tree<T, P>, t2: Tree<T, P>) -> Tree<T, P> {
+        debug_assert_eq!(t1.rank, t2.rank);
+
+        let (mut parent, child) =
+            if self.less_or_equal(&t1.root, &t2.root) { (t1, t2) } else { (t2, t1) };
+
+        parent.rank += 1;
+        parent.children.push_front_mut(child);
+
+        parent
+    }
+
+    fn skew_link(&self, v: SharedPointer<T, P>, t1: Tree<T, P>, t2: Tree<T, P>) -> Tree<T, P> {
+        let mut tree = self.link(t1, t2);
+
+        if self.less_or_equal(&v, &tree.root) {
+            let old_root = core::mem::replace(&mut tree.root, v);
+
+            tree.elements.push_front_ptr_mut(old_root);
+        } else {
+            tree.elements.push_front_ptr_mut(v);
+        }
+
+        tree
+    }
+
+    /// Inserts an element without updating the size of the heap.
+    fn insert_ptr_mut(&mut self, v: SharedPointer<T, P>) {
+        let mut first_two = self.trees.iter();
+        let tree = match (first_two.next(), first_two.next()) {
+            (Some(t1), Some(t2)) if t1.rank == t2.rank => {
+                let (t1, t2) = (t1.clone(), t2.clone());
+
+                self.trees.drop_first_mut();
+                self.trees.drop_first_mut();
+
+                self.skew_link(v, t1, t2)
+            }
+            _ => Tree::new_singleton(v),
+        };
+
+        // The new tree comes first, so it has precedence if its root ties with the current minimum.
+        let root_is_min = match self.min.as_ref() {
+            Some(min) => self.less_or_equal(&tree.root, min),
+            None => true,
+        };
+
+        if root_is_min {
+            self.min = Some(SharedPointer::clone(&tree.root));
+        }
+
+        self.trees.push_front_mut(tree);
+    }
+
+    /// Merges two sequences of trees in strictly increasing order of rank.  The result is also
+    /// in strictly increasing order of rank.
+    fn merge_trees(&self, trees_a: &[Tree<T, P>], trees_b: &[Tree<T, P>]) -> Vec<Tree<T, P>> {
+        let mut result = Vec::with_capacity(trees_a.len() + trees_b.len());
+        let mut iter_a = trees_a.iter().cloned().peekable();
+        let mut iter_b = trees_b.iter().cloned().peekable();
+        let mut carry: Option<Tree<T, P>> = None;
+
+        while let Some(rank) = [iter_a.peek(), iter_b.peek(), carry.as_ref()]
+            .iter()
+            .filter_map(|t| t.map(|t| t.rank))
+            .min()
+        {
+            let mut same_rank: Vec<Tree<T, P>> = Vec::with_capacity(3);
+
+            if carry.as_ref().map(|t| t.rank) == Some(rank) {
+                same_rank.extend(carry.take());
+            }
+
+            if iter_a.peek().map(|t| t.rank) == Some(rank) {
+                same_rank.extend(iter_a.next());
+            }
+
+            if iter_b.peek().map(|t| t.rank) == Some(rank) {
+                same_rank.extend(iter_b.next());
+            }
+
+            let mut same_rank = same_rank.into_iter();
+
+            match same_rank.len() {
+                1 => result.extend(same_rank),
+                2 => carry = Some(self.link(same_rank.next().unwrap(), same_rank.next().unwrap())),
+                _ => {
+                    result.extend(same_rank.next());
+                    carry = Some(self.link(same_rank.next().unwrap(), same_rank.next().unwrap()));
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Makes the ranks of the trees strictly increasing.
+    fn normalize(&self, trees: &[Tree<T, P>]) -> Vec<Tree<T, P>> {
+        if trees.is_empty() {
+            Vec::new()
+        } else {
+            self.merge_trees(&trees[..1], &trees[1..])
+        }
+    }
+
+    fn set_trees(&mut self, trees: Vec<Tree<T, P>>) {
+        self.trees = List::new_with_ptr_kind();
+
+        for tree in trees.into_iter().rev() {
+            self.trees.push_front_mut(tree);
+        }
+
+        self.min = self.find_min_tree().map(|(_, tree)| SharedPointer::clone(&tree.root));
+    }
+
+    /// Returns the first tree with the minimum root, and its position.
+    fn find_min_tree(&self) -> Option<(usize, &Tree<T, P>)> {
+        let mut min: Option<(usize, &Tree<T, P>)> = None;
+
+        for (i, tree) in self.trees.iter().enumerate() {
+            match min {
+                Some((_, min_tree)) if self.less_or_equal(&min_tree.root, &tree.root) => (),
+                _ => min = Some((i, tree)),
+            }
+        }
+
+        min
+    }
+}
+
+impl<T, P, C> Heap<T, P, C>
+where
+    P: SharedPointerKind,
+    C: Comparator<T> + Clone,
+{
+    #[must_use]
+    pub fn push(&self, v: T) -> Heap<T, P, C> {
+        let mut new_heap = self.clone();
+
+        new_heap.push_mut(v);
+
+        new_heap
+    }
+
+    pub fn push_mut(&mut self, v: T) {
+        self.insert_ptr_mut(SharedPointer::new(v));
+        self.size += 1;
+    }
+
+    /// Returns the heap without its smallest element.
+    #[must_use]
+    pub fn pop(&self) -> Option<Heap<T, P, C>> {
+        let mut new_heap = self.clone();
+
+        if new_heap.pop_mut() {
+            Some(new_heap)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the operation was successful.
+    pub fn pop_mut(&mut self) -> bool {
+        let (min_index, min_tree) = match self.find_min_tree() {
+            Some((i, tree)) => (i, tree.clone()),
+            None => return false,
+        };
+
+        let mut trees: Vec<Tree<T, P>> = self.trees.iter().cloned().collect();
+
+        trees.remove(min_index);
+
+        let mut children: Vec<Tree<T, P>> = min_tree.children.iter().cloned().collect();
+
+        children.reverse();
+
+        let trees = self.merge_trees(&children, &self.normalize(&trees));
+
+        self.set_trees(trees);
+
+        for v in min_tree.elements.iter_ptr() {
+            self.insert_ptr_mut(SharedPointer::clone(v));
+        }
+
+        self.size -= 1;
+
+        true
+    }
+
+    /// Returns a heap with the elements of both `self` and `other`.  The resulting heap uses the
+    /// comparator of `self`.
+    #[must_use]
+    pub fn merge(&self, other: &Heap<T, P, C>) -> Heap<T, P, C> {
+        let mut new_heap = self.clone();
+
+        new_heap.merge_mut(other);
+
+        new_heap
+    }
+
+    pub fn merge_mut(&mut self, other: &Heap<T, P, C>) {
+        if other.is_empty() {
+            return;
+        }
+
+        let trees_self: Vec<Tree<T, P>> = self.trees.iter().cloned().collect();
+        let trees_other: Vec<Tree<T, P>> = other.trees.iter().cloned().collect();
+        let trees = self.merge_trees(&self.normalize(&trees_self), &self.normalize(&trees_other));
+
+        self.set_trees(trees);
+        self.size += other.size;
+    }
+}
+
+impl<T, P, C> Default for Heap<T, P, C>
+where
+    P: SharedPointerKind,
+    C: Comparator<T> + Default,
+{
+    fn default() -> Heap<T, P, C> {
+        Heap::new_with_comparator_and_ptr_kind(C::default())
+    }
+}
+
+impl<T, P, C> Clone for Heap<T, P, C>
+where
+    P: SharedPointerKind,
+    C: Comparator<T> + Clone,
+{
+    fn clone(&self) -> Heap<T, P, C> {
+        Heap {
+            trees: self.trees.clone(),
+            min: self.min.clone(),
+            size: self.size,
+            comparator: self.comparator.clone(),
+        }
+    }
+}
+
+impl<'a, T, P, C> IntoIterator for &'a Heap<T, P, C>
+where
+    P: SharedPointerKind,
+    C: Comparator<T>,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, P, C> FromIterator<T> for Heap<T, P, C>
+where
+    P: SharedPointerKind,
+    C: Comparator<T> + Clone + Default,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(into_iter: I) -> Heap<T, P, C> {
+        let mut heap = Heap::default();
+
+        for e in into_iter {
+            heap.push_mut(e);
+        }
+
+        heap
+    }
+}
+
+#[derive(Debug)]
+pub struct Iter<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    stack: Vec<&'a Tree<T, P>>,
+    elements: Option<crate::list::Iter<'a, T, P>>,
+    remaining: usize,
+}
+
+impl<'a, T, P> Iter<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    fn new<C: Comparator<T>>(heap: &Heap<T, P, C>) -> Iter<'_, T, P> {
+        Iter { stack: heap.trees.iter().collect(), elements: None, remaining: heap.len() }
+    }
+}
+
+impl<'a, T, P> Iterator for Iter<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if let Some(v) = self.elements.as_mut().and_then(Iterator::next) {
+            self.remaining -= 1;
+
+            return Some(v);
+        }
+
+        let tree = self.stack.pop()?;
+
+        self.stack.extend(tree.children.iter());
+        self.elements = Some(tree.elements.iter());
+        self.remaining -= 1;
+
+        Some(tree.root.borrow())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, P> ExactSizeIterator for Iter<'a, T, P> where P: SharedPointerKind {}
+
+#[cfg(feature = "serde")]
+pub mod serde {
+    use super::*;
+    use ::serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+    use ::serde::ser::{Serialize, Serializer};
+    use core::fmt;
+    use core::marker::PhantomData;
+
+    impl<T, P, C> Serialize for Heap<T, P, C>
+    where
+        T: Serialize,
+        P: SharedPointerKind,
+        C: Comparator<T>,
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self)
+        }
+    }
+
+    impl<'de, T, P, C> Deserialize<'de> for Heap<T, P, C>
+    where
+        T: Deserialize<'de>,
+        P: SharedPointerKind,
+        C: Comparator<T> + Clone + Default,
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Heap<T, P, C>, D::Error> {
+            deserializer.deserialize_seq(HeapVisitor {
+                _phantom_t: PhantomData,
+                _phantom_p: PhantomData,
+                _phantom_c: PhantomData,
+            })
+        }
+    }
+
+    struct HeapVisitor<T, P, C> {
+        _phantom_t: PhantomData<T>,
+        _phantom_p: PhantomData<P>,
+        _phantom_c: PhantomData<C>,
+    }
+
+    impl<'de, T, P, C> Visitor<'de> for HeapVisitor<T, P, C>
+    where
+        T: Deserialize<'de>,
+        P: SharedPointerKind,
+        C: Comparator<T> + Clone + Default,
+    {
+        type Value = Heap<T, P, C>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("a sequence")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Heap<T, P, C>, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut heap = Heap::default();
+
+            while let Some(value) = seq.next_element()? {
+                heap.push_mut(value);
+            }
+
+            Ok(heap)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
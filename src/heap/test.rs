@@ -0,0 +1,323 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::*;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use pretty_assertions::assert_eq;
+use static_assertions::assert_impl_all;
+
+assert_impl_all!(HeapSync<i32>: Send, Sync);
+
+#[allow(dead_code)]
+fn compile_time_macro_heap_sync_is_send_and_sync() -> impl Send + Sync {
+    heap_sync!(0)
+}
+
+fn drain<T: Clone, P: SharedPointerKind, C: Comparator<T> + Clone>(heap: &Heap<T, P, C>) -> Vec<T> {
+    let mut heap = heap.clone();
+    let mut result = Vec::with_capacity(heap.len());
+
+    while let Some(v) = heap.peek() {
+        result.push(v.clone());
+
+        assert!(heap.pop_mut());
+    }
+
+    assert!(heap.is_empty());
+
+    result
+}
+
+mod internal {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn check_tree<T, P: SharedPointerKind, C: Comparator<T>>(
+        heap: &Heap<T, P, C>,
+        tree: &Tree<T, P>,
+    ) -> usize {
+        let mut size = 1 + tree.elements.len();
+        let mut prev_rank: Option<usize> = None;
+
+        assert_eq!(tree.children.len(), tree.rank);
+
+        for v in &tree.elements {
+            assert!(heap.less_or_equal(&tree.root, v));
+        }
+
+        for child in &tree.children {
+            assert!(heap.less_or_equal(&tree.root, &child.root));
+            assert!(prev_rank.is_none() || prev_rank > Some(child.rank));
+
+            prev_rank = Some(child.rank);
+            size += check_tree(heap, child);
+        }
+
+        size
+    }
+
+    fn check<T, P: SharedPointerKind, C: Comparator<T>>(heap: &Heap<T, P, C>) {
+        let ranks: Vec<usize> = heap.trees.iter().map(|t| t.rank).collect();
+        let size: usize = heap.trees.iter().map(|t| check_tree(heap, t)).sum();
+
+        for (i, w) in ranks.windows(2).enumerate() {
+            assert!(w[0] < w[1] || (i == 0 && w[0] == w[1]), "ranks: {:?}", ranks);
+        }
+
+        assert_eq!(size, heap.len());
+
+        match heap.find_min_tree() {
+            Some((_, tree)) => {
+                assert!(SharedPointer::ptr_eq(&tree.root, heap.min.as_ref().unwrap()));
+            }
+            None => assert!(heap.min.is_none()),
+        }
+    }
+
+    #[test]
+    fn test_invariants() {
+        let mut heap: Heap<u32> = Heap::new();
+
+        for i in 0..500 {
+            heap.push_mut((i * 7919) % 500);
+            check(&heap);
+        }
+
+        for _ in 0..250 {
+            heap.pop_mut();
+            check(&heap);
+        }
+
+        let other: Heap<u32> = (0..123).collect();
+
+        heap.merge_mut(&other);
+        check(&heap);
+
+        while heap.pop_mut() {
+            check(&heap);
+        }
+    }
+}
+
+mod iter {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_iter() {
+        let limit = 1000;
+        let heap: Heap<u32> = (0..limit).rev().collect();
+        let heap = heap.pop().unwrap().pop().unwrap().push(5000);
+        let mut values: Vec<u32> = heap.iter().copied().collect();
+
+        values.sort_unstable();
+
+        assert_eq!(values, (2..limit).chain(core::iter::once(5000)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_iter_size_hint() {
+        let heap = heap![0, 1, 2];
+        let mut iterator = heap.iter();
+
+        assert_eq!(iterator.size_hint(), (3, Some(3)));
+
+        iterator.next();
+
+        assert_eq!(iterator.size_hint(), (2, Some(2)));
+
+        iterator.next();
+
+        assert_eq!(iterator.size_hint(), (1, Some(1)));
+
+        iterator.next();
+
+        assert_eq!(iterator.size_hint(), (0, Some(0)));
+        assert_eq!(iterator.next(), None);
+    }
+
+    #[test]
+    fn test_into_iterator() {
+        let heap = heap![3, 1, 2];
+        let mut values: Vec<i32> = Vec::new();
+
+        for v in &heap {
+            values.push(*v);
+        }
+
+        values.sort_unstable();
+
+        assert_eq!(values, vec![1, 2, 3]);
+    }
+}
+
+#[test]
+fn test_new() {
+    let empty_heap: Heap<i32> = Heap::new();
+
+    assert!(empty_heap.is_empty());
+    assert_eq!(empty_heap.len(), 0);
+    assert_eq!(empty_heap.peek(), None);
+    assert!(empty_heap.pop().is_none());
+}
+
+#[test]
+fn test_macro_heap() {
+    let heap_1_2_3 = Heap::new().push(2).push(1).push(3);
+
+    assert!(heap![].peek().map(|_: &i32| ()).is_none());
+    assert_eq!(drain(&heap![2, 1, 3]), drain(&heap_1_2_3));
+}
+
+#[test]
+fn test_push_peek() {
+    let values = [5, 3, 9, 1, 9, 0, 7, 2, 8, 4, 6];
+    let mut heap = Heap::new();
+
+    for (i, v) in values.iter().enumerate() {
+        heap = heap.push(*v);
+
+        assert_eq!(heap.len(), i + 1);
+        assert_eq!(heap.peek(), values[..=i].iter().min());
+    }
+}
+
+#[test]
+fn test_pop() {
+    let values = [5, 3, 9, 1, 9, 0, 7, 2, 8, 4, 6];
+    let heap: Heap<i32> = values.iter().copied().collect();
+    let mut sorted = values.to_vec();
+
+    sorted.sort_unstable();
+
+    assert_eq!(drain(&heap), sorted);
+    assert_eq!(heap.len(), values.len());
+
+    let popped = heap.pop().unwrap();
+
+    assert_eq!(popped.len(), values.len() - 1);
+    assert_eq!(popped.peek(), Some(&1));
+    assert_eq!(heap.peek(), Some(&0));
+}
+
+#[test]
+fn test_pop_many() {
+    let limit = 2000;
+    let heap: Heap<u32> = (0..limit).map(|i| (i * 7919) % limit).collect();
+
+    assert!(drain(&heap).into_iter().eq(0..limit));
+}
+
+#[test]
+fn test_peek_pop_consistent_with_ties() {
+    let mut heap: Heap<(u32, u32), RcK, _> =
+        vec![(1, 0), (0, 1), (1, 2), (0, 3), (0, 4), (1, 5), (0, 6)].into_iter().fold(
+            Heap::new_with_comparator(|a: &(u32, u32), b: &(u32, u32)| a.0.cmp(&b.0)),
+            |heap, v| heap.push(v),
+        );
+    let mut ids: Vec<u32> = Vec::new();
+
+    while let Some(&(priority, id)) = heap.peek() {
+        let popped = heap.pop().unwrap();
+
+        // The element we peeked must be the one that was removed.
+        let mut remaining: Vec<(u32, u32)> = popped.iter().copied().collect();
+
+        remaining.push((priority, id));
+        remaining.sort_unstable();
+
+        let mut before: Vec<(u32, u32)> = heap.iter().copied().collect();
+
+        before.sort_unstable();
+
+        assert_eq!(remaining, before);
+
+        ids.push(id);
+        heap = popped;
+    }
+
+    assert_eq!(ids.len(), 7);
+}
+
+#[test]
+fn test_merge() {
+    let heap_a: Heap<i32> = vec![1, 8, 3, 11].into_iter().collect();
+    let heap_b: Heap<i32> = vec![7, 2, 9].into_iter().collect();
+    let heap = heap_a.merge(&heap_b);
+
+    assert_eq!(heap.len(), 7);
+    assert_eq!(drain(&heap), vec![1, 2, 3, 7, 8, 9, 11]);
+    assert_eq!(drain(&heap_a), vec![1, 3, 8, 11]);
+    assert_eq!(drain(&heap_b), vec![2, 7, 9]);
+
+    let empty: Heap<i32> = Heap::new();
+
+    assert_eq!(drain(&empty.merge(&heap_b)), vec![2, 7, 9]);
+    assert_eq!(drain(&heap_b.merge(&empty)), vec![2, 7, 9]);
+}
+
+#[test]
+fn test_merge_many() {
+    let mut heap: Heap<u32> = Heap::new();
+
+    for i in 0..100 {
+        let other: Heap<u32> = (0..i).map(|j| j * 100 + i).collect();
+
+        heap.merge_mut(&other);
+    }
+
+    let mut expected: Vec<u32> = (0..100).flat_map(|i| (0..i).map(move |j| j * 100 + i)).collect();
+
+    expected.sort_unstable();
+
+    assert_eq!(drain(&heap), expected);
+}
+
+#[test]
+fn test_comparator() {
+    let heap = Heap::new_with_comparator(|a: &i32, b: &i32| b.cmp(a));
+    let heap = heap.push(1).push(5).push(3);
+
+    assert_eq!(drain(&heap), vec![5, 3, 1]);
+
+    let heap: Heap<Reverse<i32>> = vec![1, 5, 3].into_iter().map(Reverse).collect();
+
+    assert_eq!(heap.peek(), Some(&Reverse(5)));
+}
+
+#[test]
+fn test_comparator_sync() {
+    let heap = Heap::new_sync_with_comparator(|a: &i32, b: &i32| b.cmp(a)).push(1).push(2);
+
+    assert_eq!(heap.peek(), Some(&2));
+}
+
+#[test]
+fn test_default() {
+    let heap: Heap<i32> = Heap::default();
+
+    assert!(heap.is_empty());
+}
+
+#[test]
+fn test_clone() {
+    let heap = heap![3, 1, 2];
+    let clone = heap.clone();
+
+    assert_eq!(clone.len(), heap.len());
+    assert_eq!(drain(&clone), drain(&heap));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use bincode::{deserialize, serialize};
+    let heap: Heap<i32> = heap![5, 6, 7, 8];
+    let encoded = serialize(&heap).unwrap();
+    let decoded: Heap<i32> = deserialize(&encoded).unwrap();
+
+    assert_eq!(drain(&heap), drain(&decoded));
+}
@@ -0,0 +1,637 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use self::suspension::{Node, Suspension, Thunk};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use archery::*;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! lazy_list_reverse {
+    ($ptr_kind:ty ; ; $($reversed:expr),*) => {
+         {
+            #[allow(unused_mut)]
+            let mut l: $crate::LazyList<_, $ptr_kind> = $crate::LazyList::new_with_ptr_kind();
+            $(
+                l.push_front_mut($reversed);
+            )*
+            l
+        }
+    };
+    ($ptr_kind:ty ; $h:expr ; $($reversed:expr),*) => {
+        $crate::lazy_list_reverse!($ptr_kind ; ; $h, $($reversed),*)
+    };
+    ($ptr_kind:ty ; $h:expr, $($t:expr),+ ; $($reversed:expr),*) => {
+        $crate::lazy_list_reverse!($ptr_kind ; $($t),* ; $h, $($reversed),*)
+    };
+
+    // This is just to handle the cases where this macro is called with an extra comma in the
+    // reserve list, which can happen in a recursive call.
+    ($ptr_kind:ty ; $($t:expr),* ; $($reversed:expr),*,) => {
+        $crate::lazy_list_reverse!($ptr_kind ; $($t),* ; $($reversed),*)
+    };
+}
+
+/// Creates a [`LazyList`](lazy_list/struct.LazyList.html) containing the given arguments, which
+/// are evaluated eagerly:
+///
+/// ```
+/// # use rpds::*;
+/// #
+/// let l = LazyList::new()
+///     .push_front(3)
+///     .push_front(2)
+///     .push_front(1);
+///
+/// assert_eq!(lazy_list![1, 2, 3], l);
+/// ```
+#[macro_export]
+macro_rules! lazy_list {
+    ($($e:expr),*) => {
+        $crate::lazy_list_reverse!(::archery::RcK ; $($e),* ; )
+    };
+}
+
+/// Creates a [`LazyList`](lazy_list/struct.LazyList.html) that implements `Sync`, containing the
+/// given arguments, which are evaluated eagerly:
+///
+/// ```
+/// # use rpds::*;
+/// #
+/// let l = LazyList::new_sync()
+///     .push_front(3)
+///     .push_front(2)
+///     .push_front(1);
+///
+/// assert_eq!(lazy_list_sync![1, 2, 3], l);
+///
+/// fn is_sync() -> impl Sync {
+///     lazy_list_sync![0, 1, 1, 2, 3, 5, 8]
+/// }
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! lazy_list_sync {
+    ($($e:expr),*) => {
+        $crate::lazy_list_reverse!(::archery::ArcK ; $($e),* ; )
+    };
+}
+
+/// The pointer kinds that a [`LazyList`](struct.LazyList.html) can use.
+///
+/// This is implemented for `RcK` and, if the `std` feature is enabled, for `ArcK`.  Besides the
+/// pointers, the pointer kind defines how the evaluation of the list is memoized: a `LazyList`
+/// with `ArcK` memoizes its elements in a thread-safe way.
+pub trait LazyListPointerKind<T>: SharedPointerKind + Sized {
+    #[doc(hidden)]
+    type Suspension: Suspension<T, Self>;
+}
+
+impl<T> LazyListPointerKind<T> for RcK {
+    type Suspension = suspension::RcSuspension<T>;
+}
+
+#[cfg(feature = "std")]
+impl<T> LazyListPointerKind<T> for ArcK {
+    type Suspension = suspension::ArcSuspension<T>;
+}
+
+/// A persistent lazy list with structural sharing.
+///
+/// The tail of the list can be a suspension, i.e. a computation that produces the rest of the list
+/// when it is needed.  Each suspension is evaluated at most once, and its result is shared by all
+/// the lists that contain it, including clones.  This makes it possible to describe infinite
+/// sequences, or sequences that are expensive to compute, and only pay for the elements that are
+/// actually used:
+///
+/// ```
+/// # use rpds::LazyList;
+/// #
+/// fn naturals_from(n: u64) -> LazyList<u64> {
+///     LazyList::lazy(move || naturals_from(n + 1).push_front(n))
+/// }
+///
+/// let naturals = naturals_from(0);
+///
+/// assert!(naturals.take(3).iter().eq([0, 1, 2].iter()));
+/// assert_eq!(naturals.drop_first().unwrap().first(), Some(&1));
+/// ```
+///
+/// Operations that need to see the whole list, such as comparisons or hashing, do not terminate on
+/// infinite lists.
+///
+/// # Complexity
+///
+/// Let *n* be the number of elements in the list.  The complexity below does not include the cost
+/// of evaluating the suspensions.
+///
+/// ## Temporal complexity
+///
+/// | Operation         | Average | Worst case  |
+/// |:----------------- | -------:| -----------:|
+/// | `new()`           |    Θ(1) |        Θ(1) |
+/// | `lazy()`          |    Θ(1) |        Θ(1) |
+/// | `push_front()`    |    Θ(1) |        Θ(1) |
+/// | `drop_first()`    |    Θ(1) |        Θ(1) |
+/// | `first()`         |    Θ(1) |        Θ(1) |
+/// | `take()`          |    Θ(1) |        Θ(1) |
+/// | `clone()`         |    Θ(1) |        Θ(1) |
+/// | iterator creation |    Θ(1) |        Θ(1) |
+/// | iterator step     |    Θ(1) |        Θ(1) |
+/// | iterator full     |    Θ(n) |        Θ(n) |
+///
+/// # Implementation details
+///
+/// This is a stream as described in "Purely Functional Data Structures" by Chris Okasaki.  Every
+/// node of the list is a memoized suspension, and operations like `take()` are themselves lazy:
+/// they only evaluate the list as their result is evaluated.
+pub struct LazyList<T, P = RcK>
+where
+    P: LazyListPointerKind<T>,
+{
+    /// `None` is the empty list.
+    head: Option<SharedPointer<P::Suspension, P>>,
+}
+
+#[cfg(feature = "std")]
+pub type LazyListSync<T> = LazyList<T, ArcK>;
+
+/// The first element of a non-empty list and the rest of the list.
+type Cons<'a, T, P> = (&'a SharedPointer<T, P>, &'a LazyList<T, P>);
+
+#[cfg(feature = "std")]
+impl<T> LazyListSync<T> {
+    #[must_use]
+    pub fn new_sync() -> LazyListSync<T> {
+        LazyList::new_with_ptr_kind()
+    }
+
+    /// Creates a list that is computed by `f` the first time it is needed.  See
+    /// [`lazy()`](#method.lazy).
+    #[must_use]
+    pub fn lazy_sync<F>(f: F) -> LazyListSync<T>
+    where
+        F: FnOnce() -> LazyListSync<T> + Send + 'static,
+    {
+        let function: Box<dyn FnOnce() -> LazyListSync<T> + Send> = Box::new(f);
+
+        LazyList::suspend(Thunk::Function(function))
+    }
+}
+
+impl<T> LazyList<T> {
+    #[must_use]
+    pub fn new() -> LazyList<T> {
+        LazyList::new_with_ptr_kind()
+    }
+
+    /// Creates a list that is computed by `f` the first time it is needed.  The function is
+    /// called at most once, and its result is shared by every clone of the list.
+    ///
+    /// ```
+    /// # use rpds::LazyList;
+    /// #
+    /// let list = LazyList::lazy(|| LazyList::new().push_front("computed"));
+    ///
+    /// assert_eq!(list.first(), Some(&"computed"));
+    /// ```
+    #[must_use]
+    pub fn lazy<F>(f: F) -> LazyList<T>
+    where
+        F: FnOnce() -> LazyList<T> + 'static,
+    {
+        let function: Box<dyn FnOnce() -> LazyList<T>> = Box::new(f);
+
+        LazyList::suspend(Thunk::Function(function))
+    }
+}
+
+impl<T, P> LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    #[must_use]
+    pub fn new_with_ptr_kind() -> LazyList<T, P> {
+        LazyList { head: None }
+    }
+
+    fn suspend(thunk: Thunk<T, P>) -> LazyList<T, P> {
+        LazyList { head: Some(SharedPointer::new(P::Suspension::new(thunk))) }
+    }
+
+    /// Evaluates the first node of the list, if needed, and returns its element and tail.
+    fn force(&self) -> Option<Cons<'_, T, P>> {
+        match self.head.as_ref().map(|suspension| suspension.force()) {
+            Some(Node::Cons(v, tail)) => Some((v, tail)),
+            Some(Node::Nil) | None => None,
+        }
+    }
+
+    /// Evaluates the first node of the list, if needed, and returns a copy of it.
+    fn force_node(&self) -> Node<T, P> {
+        match self.force() {
+            Some((v, tail)) => Node::Cons(SharedPointer::clone(v), tail.clone()),
+            None => Node::Nil,
+        }
+    }
+
+    /// Returns the first element of the list, evaluating it if needed.
+    #[must_use]
+    pub fn first(&self) -> Option<&T> {
+        self.force().map(|(v, _)| v.borrow())
+    }
+
+    /// Returns the list without its first element, evaluating the first element if needed.  The
+    /// rest of the list is not evaluated.
+    #[must_use]
+    pub fn drop_first(&self) -> Option<LazyList<T, P>> {
+        self.force().map(|(_, tail)| tail.clone())
+    }
+
+    /// Returns `true` if the operation was successful.
+    pub fn drop_first_mut(&mut self) -> bool {
+        match self.drop_first() {
+            Some(tail) => {
+                *self = tail;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns a list with `v` followed by this list.  This list is not evaluated.
+    #[must_use]
+    pub fn push_front(&self, v: T) -> LazyList<T, P> {
+        let mut new_list = self.clone();
+
+        new_list.push_front_mut(v);
+
+        new_list
+    }
+
+    pub fn push_front_mut(&mut self, v: T) {
+        let tail = core::mem::take(self);
+        let node = Node::Cons(SharedPointer::new(v), tail);
+
+        self.head = Some(SharedPointer::new(P::Suspension::new_evaluated(node)));
+    }
+
+    /// Returns a list with the first `n` elements of this list, or the whole list if it has less
+    /// than `n` elements.  This does not evaluate any element: the elements are evaluated as the
+    /// returned list is evaluated.
+    #[must_use]
+    pub fn take(&self, n: usize) -> LazyList<T, P> {
+        match n {
+            0 => LazyList::new_with_ptr_kind(),
+            n => LazyList::suspend(Thunk::Take(self.clone(), n)),
+        }
+    }
+
+    /// Returns `true` if the list is empty, evaluating the first element if needed.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.force().is_none()
+    }
+
+    /// Returns an iterator over the elements of the list.  Elements are evaluated as the
+    /// iterator advances.
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter::new(self)
+    }
+}
+
+impl<T, P> Default for LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    fn default() -> LazyList<T, P> {
+        LazyList::new_with_ptr_kind()
+    }
+}
+
+impl<T, P> Clone for LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    fn clone(&self) -> LazyList<T, P> {
+        LazyList { head: self.head.clone() }
+    }
+}
+
+impl<T: PartialEq, P, PO> PartialEq<LazyList<T, PO>> for LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+    PO: LazyListPointerKind<T>,
+{
+    fn eq(&self, other: &LazyList<T, PO>) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, P> Eq for LazyList<T, P> where P: LazyListPointerKind<T> {}
+
+impl<T: PartialOrd<T>, P, PO> PartialOrd<LazyList<T, PO>> for LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+    PO: LazyListPointerKind<T>,
+{
+    fn partial_cmp(&self, other: &LazyList<T, PO>) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord, P> Ord for LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    fn cmp(&self, other: &LazyList<T, P>) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Hash, P> Hash for LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut length: usize = 0;
+
+        for e in self {
+            e.hash(state);
+            length += 1;
+        }
+
+        // Add the hash of length so that if two collections are added one after the other it doesn't
+        // hash to the same thing as a single collection with the same elements in the same order.
+        length.hash(state);
+    }
+}
+
+/// Only shows the elements that are already evaluated, since the list can be infinite.  An
+/// unevaluated suspension is shown as `..`.
+impl<T: fmt::Debug, P> fmt::Debug for LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug_list = fmt.debug_list();
+        let mut list = self;
+
+        loop {
+            match list.head.as_ref().map(|suspension| suspension.evaluated()) {
+                Some(Some(Node::Cons(v, tail))) => {
+                    debug_list.entry(v.borrow() as &T);
+                    list = tail;
+                }
+                Some(None) => {
+                    debug_list.entry(&format_args!(".."));
+
+                    return debug_list.finish();
+                }
+                Some(Some(Node::Nil)) | None => return debug_list.finish(),
+            }
+        }
+    }
+}
+
+impl<'a, T, P> IntoIterator for &'a LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, P> FromIterator<T> for LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    /// Creates a list with all the elements of the iterator.  The iterator is consumed eagerly.
+    fn from_iter<I: IntoIterator<Item = T>>(into_iter: I) -> LazyList<T, P> {
+        let vec: Vec<T> = into_iter.into_iter().collect();
+        let mut list: LazyList<T, P> = LazyList::new_with_ptr_kind();
+
+        for e in vec.into_iter().rev() {
+            list.push_front_mut(e);
+        }
+
+        list
+    }
+}
+
+// Drop the evaluated part of the list iteratively to prevent stack overflow.
+impl<T, P> Drop for LazyList<T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    fn drop(&mut self) {
+        let mut head = self.head.take();
+
+        while let Some(suspension) = head {
+            match SharedPointer::try_unwrap(suspension).ok().and_then(Suspension::into_evaluated) {
+                Some(Node::Cons(_, mut tail)) => head = tail.head.take(),
+                _ => break,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Iter<'a, T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    list: &'a LazyList<T, P>,
+}
+
+impl<'a, T, P> Iter<'a, T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    fn new(list: &LazyList<T, P>) -> Iter<'_, T, P> {
+        Iter { list }
+    }
+}
+
+impl<'a, T, P> Iterator for Iter<'a, T, P>
+where
+    P: LazyListPointerKind<T>,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let (v, tail) = self.list.force()?;
+
+        self.list = tail;
+
+        Some(v.borrow())
+    }
+}
+
+mod suspension {
+    use super::{LazyList, LazyListPointerKind};
+    use alloc::boxed::Box;
+    use archery::*;
+    use core::cell::{Cell, OnceCell};
+
+    /// An evaluated node of a lazy list.
+    pub enum Node<T, P>
+    where
+        P: LazyListPointerKind<T>,
+    {
+        Nil,
+        Cons(SharedPointer<T, P>, LazyList<T, P>),
+    }
+
+    /// A computation that produces a node of a lazy list.
+    ///
+    /// The operations of the list are represented as data rather than closures, so that they do
+    /// not impose any requirement on the pointer kind.
+    pub enum Thunk<T, P>
+    where
+        P: LazyListPointerKind<T>,
+    {
+        Function(<P::Suspension as Suspension<T, P>>::Function),
+        Take(LazyList<T, P>, usize),
+    }
+
+    impl<T, P> Thunk<T, P>
+    where
+        P: LazyListPointerKind<T>,
+    {
+        fn evaluate(self) -> Node<T, P> {
+            match self {
+                Thunk::Function(function) => P::Suspension::call(function).force_node(),
+                Thunk::Take(list, n) => match list.force() {
+                    Some((v, tail)) => Node::Cons(SharedPointer::clone(v), tail.take(n - 1)),
+                    None => Node::Nil,
+                },
+            }
+        }
+    }
+
+    /// A memoized thunk.
+    pub trait Suspension<T, P>: Sized
+    where
+        P: LazyListPointerKind<T>,
+    {
+        /// The type of the user defined functions that produce a lazy list.
+        type Function;
+
+        fn new(thunk: Thunk<T, P>) -> Self;
+
+        fn new_evaluated(node: Node<T, P>) -> Self;
+
+        /// Returns the node if it is already evaluated.
+        fn evaluated(&self) -> Option<&Node<T, P>>;
+
+        /// Evaluates the thunk, if it was not evaluated before, and returns the node.
+        fn force(&self) -> &Node<T, P>;
+
+        fn into_evaluated(self) -> Option<Node<T, P>>;
+
+        fn call(function: Self::Function) -> LazyList<T, P>;
+    }
+
+    const RECURSIVE_EVALUATION: &str =
+        "lazy list suspension evaluated recursively or after a panic";
+
+    pub struct RcSuspension<T> {
+        node: OnceCell<Node<T, RcK>>,
+        thunk: Cell<Option<Thunk<T, RcK>>>,
+    }
+
+    impl<T> Suspension<T, RcK> for RcSuspension<T> {
+        type Function = Box<dyn FnOnce() -> LazyList<T, RcK>>;
+
+        fn new(thunk: Thunk<T, RcK>) -> RcSuspension<T> {
+            RcSuspension { node: OnceCell::new(), thunk: Cell::new(Some(thunk)) }
+        }
+
+        fn new_evaluated(node: Node<T, RcK>) -> RcSuspension<T> {
+            RcSuspension { node: OnceCell::from(node), thunk: Cell::new(None) }
+        }
+
+        fn evaluated(&self) -> Option<&Node<T, RcK>> {
+            self.node.get()
+        }
+
+        fn force(&self) -> &Node<T, RcK> {
+            self.node.get_or_init(|| self.thunk.take().expect(RECURSIVE_EVALUATION).evaluate())
+        }
+
+        fn into_evaluated(self) -> Option<Node<T, RcK>> {
+            self.node.into_inner()
+        }
+
+        fn call(function: Self::Function) -> LazyList<T, RcK> {
+            function()
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub struct ArcSuspension<T> {
+        node: std::sync::OnceLock<Node<T, ArcK>>,
+        thunk: std::sync::Mutex<Option<Thunk<T, ArcK>>>,
+    }
+
+    #[cfg(feature = "std")]
+    impl<T> Suspension<T, ArcK> for ArcSuspension<T> {
+        type Function = Box<dyn FnOnce() -> LazyList<T, ArcK> + Send>;
+
+        fn new(thunk: Thunk<T, ArcK>) -> ArcSuspension<T> {
+            ArcSuspension {
+                node: std::sync::OnceLock::new(),
+                thunk: std::sync::Mutex::new(Some(thunk)),
+            }
+        }
+
+        fn new_evaluated(node: Node<T, ArcK>) -> ArcSuspension<T> {
+            ArcSuspension {
+                node: std::sync::OnceLock::from(node),
+                thunk: std::sync::Mutex::new(None),
+            }
+        }
+
+        fn evaluated(&self) -> Option<&Node<T, ArcK>> {
+            self.node.get()
+        }
+
+        fn force(&self) -> &Node<T, ArcK> {
+            self.node.get_or_init(|| {
+                let thunk = self
+                    .thunk
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .take()
+                    .expect(RECURSIVE_EVALUATION);
+
+                thunk.evaluate()
+            })
+        }
+
+        fn into_evaluated(self) -> Option<Node<T, ArcK>> {
+            self.node.into_inner()
+        }
+
+        fn call(function: Self::Function) -> LazyList<T, ArcK> {
+            function()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,387 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::*;
+use alloc::rc::Rc;
+use core::cell::Cell;
+use pretty_assertions::assert_eq;
+#[cfg(feature = "std")]
+use static_assertions::assert_impl_all;
+
+#[cfg(feature = "std")]
+assert_impl_all!(LazyListSync<i32>: Send, Sync);
+
+#[cfg(feature = "std")]
+#[allow(dead_code)]
+fn compile_time_macro_lazy_list_sync_is_send_and_sync() -> impl Send + Sync {
+    lazy_list_sync!(0)
+}
+
+/// Returns the infinite list `n, n + 1, n + 2, ...`, counting the number of evaluated elements
+/// in `evaluations`.
+fn naturals_from(n: u64, evaluations: Rc<Cell<usize>>) -> LazyList<u64> {
+    LazyList::lazy(move || {
+        evaluations.set(evaluations.get() + 1);
+
+        naturals_from(n + 1, evaluations).push_front(n)
+    })
+}
+
+#[cfg(feature = "std")]
+fn naturals_from_sync(n: u64) -> LazyListSync<u64> {
+    LazyList::lazy_sync(move || naturals_from_sync(n + 1).push_front(n))
+}
+
+mod iter {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_iter() {
+        let list = lazy_list![0, 1, 2, 3];
+
+        assert!(list.iter().copied().eq(0..4));
+    }
+
+    #[test]
+    fn test_iter_infinite() {
+        let evaluations = Rc::new(Cell::new(0));
+        let list = naturals_from(0, Rc::clone(&evaluations));
+
+        assert!(list.iter().copied().take(100).eq(0..100));
+        assert_eq!(evaluations.get(), 100);
+    }
+
+    #[test]
+    fn test_into_iterator() {
+        let list = lazy_list![0, 1, 2, 3];
+        let mut expected = 0;
+
+        for v in &list {
+            assert_eq!(*v, expected);
+            expected += 1;
+        }
+
+        assert_eq!(expected, 4);
+    }
+}
+
+mod internal {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn evaluated_len<T, P: LazyListPointerKind<T>>(list: &LazyList<T, P>) -> usize {
+        let mut len = 0;
+        let mut list = list;
+
+        while let Some(Some(Node::Cons(_, tail))) =
+            list.head.as_ref().map(|suspension| suspension.evaluated())
+        {
+            len += 1;
+            list = tail;
+        }
+
+        len
+    }
+
+    #[test]
+    fn test_take_is_lazy() {
+        let evaluations = Rc::new(Cell::new(0));
+        let list = naturals_from(0, Rc::clone(&evaluations));
+        let prefix = list.take(10);
+
+        assert_eq!(evaluations.get(), 0);
+        assert_eq!(prefix.first(), Some(&0));
+        assert_eq!(evaluations.get(), 1);
+        assert_eq!(evaluated_len(&list), 1);
+        assert_eq!(evaluated_len(&prefix), 1);
+    }
+
+    #[test]
+    fn test_push_front_is_evaluated() {
+        let list = LazyList::lazy(|| lazy_list![1, 2]).push_front(0);
+
+        assert_eq!(evaluated_len(&list), 1);
+        assert_eq!(list, lazy_list![0, 1, 2]);
+        assert_eq!(evaluated_len(&list), 3);
+    }
+}
+
+#[test]
+fn test_new() {
+    let empty_list: LazyList<i32> = LazyList::new();
+
+    assert!(empty_list.head.is_none());
+    assert!(empty_list.is_empty());
+    assert_eq!(empty_list.first(), None);
+}
+
+#[test]
+fn test_macro_lazy_list() {
+    let list_1 = LazyList::new().push_front(1);
+    let list_1_2_3 = LazyList::new().push_front(3).push_front(2).push_front(1);
+
+    assert_eq!(LazyList::<u32>::new(), lazy_list![]);
+    assert_eq!(list_1, lazy_list![1]);
+    assert_eq!(list_1_2_3, lazy_list![1, 2, 3]);
+}
+
+#[test]
+fn test_lazy() {
+    let evaluations = Rc::new(Cell::new(0));
+    let counter = Rc::clone(&evaluations);
+    let list: LazyList<&str> = LazyList::lazy(move || {
+        counter.set(counter.get() + 1);
+
+        lazy_list!["a", "b"]
+    });
+
+    assert_eq!(evaluations.get(), 0);
+    assert_eq!(list.first(), Some(&"a"));
+    assert_eq!(list.first(), Some(&"a"));
+    assert_eq!(evaluations.get(), 1);
+    assert_eq!(list, lazy_list!["a", "b"]);
+    assert_eq!(evaluations.get(), 1);
+}
+
+#[test]
+fn test_lazy_empty() {
+    let list: LazyList<i32> = LazyList::lazy(LazyList::new);
+
+    assert!(list.is_empty());
+    assert_eq!(list.first(), None);
+    assert!(list.drop_first().is_none());
+}
+
+#[test]
+fn test_memoization_is_shared_between_clones() {
+    let evaluations = Rc::new(Cell::new(0));
+    let list = naturals_from(0, Rc::clone(&evaluations));
+    let clone = list.clone();
+    let extended = list.push_front(100);
+
+    assert!(list.iter().copied().take(10).eq(0..10));
+    assert_eq!(evaluations.get(), 10);
+
+    assert!(clone.iter().copied().take(10).eq(0..10));
+    assert!(extended.iter().copied().skip(1).take(10).eq(0..10));
+    assert_eq!(evaluations.get(), 10);
+
+    assert!(list.drop_first().unwrap().iter().copied().take(10).eq(1..11));
+    assert_eq!(evaluations.get(), 11);
+}
+
+#[test]
+fn test_first() {
+    let empty_list: LazyList<i32> = LazyList::new();
+    let singleton_list = lazy_list!["hello"];
+    let list = lazy_list![0, 1, 2, 3];
+
+    assert_eq!(empty_list.first(), None);
+    assert_eq!(singleton_list.first(), Some(&"hello"));
+    assert_eq!(list.first(), Some(&0));
+}
+
+#[test]
+fn test_drop_first() {
+    let empty_list: LazyList<i32> = LazyList::new();
+    let list = lazy_list![0, 1, 2, 3];
+
+    assert!(empty_list.drop_first().is_none());
+    assert_eq!(list.drop_first().unwrap(), lazy_list![1, 2, 3]);
+    assert_eq!(list, lazy_list![0, 1, 2, 3]);
+}
+
+#[test]
+fn test_drop_first_mut() {
+    let mut list = lazy_list![0, 1];
+
+    assert!(list.drop_first_mut());
+    assert_eq!(list, lazy_list![1]);
+    assert!(list.drop_first_mut());
+    assert!(list.is_empty());
+    assert!(!list.drop_first_mut());
+}
+
+#[test]
+fn test_take() {
+    let list = lazy_list![0, 1, 2, 3];
+    let naturals = naturals_from(0, Rc::new(Cell::new(0)));
+
+    assert_eq!(list.take(0), lazy_list![]);
+    assert_eq!(list.take(2), lazy_list![0, 1]);
+    assert_eq!(list.take(4), list);
+    assert_eq!(list.take(10), list);
+    assert_eq!(list.take(3).take(2), lazy_list![0, 1]);
+    assert_eq!(naturals.take(5), lazy_list![0, 1, 2, 3, 4]);
+    assert_eq!(naturals.drop_first().unwrap().take(2), lazy_list![1, 2]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_sync() {
+    let list = naturals_from_sync(0);
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let list = list.clone();
+
+            std::thread::spawn(move || list.iter().copied().take(1000).sum::<u64>())
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), 999 * 1000 / 2);
+    }
+
+    assert!(list.take(3).iter().eq([0, 1, 2].iter()));
+}
+
+#[test]
+#[should_panic(expected = "evaluated recursively")]
+fn test_recursive_evaluation() {
+    let list: Rc<Cell<Option<LazyList<i32>>>> = Rc::new(Cell::new(None));
+    let list_in_thunk = Rc::clone(&list);
+    let lazy = LazyList::lazy(move || {
+        let lazy: LazyList<i32> = list_in_thunk.take().unwrap();
+
+        lazy.drop_first().unwrap_or_default()
+    });
+
+    list.set(Some(lazy.clone()));
+
+    let _ = lazy.first();
+}
+
+#[test]
+fn test_from_iterator() {
+    let vec: Vec<u32> = vec![10, 11, 12, 13];
+    let list: LazyList<u32> = vec.iter().copied().collect();
+
+    assert!(vec.iter().eq(list.iter()));
+}
+
+#[test]
+fn test_default() {
+    let list: LazyList<i32> = LazyList::default();
+
+    assert!(list.is_empty());
+}
+
+#[test]
+fn test_debug() {
+    let evaluations = Rc::new(Cell::new(0));
+    let naturals = naturals_from(0, evaluations);
+    let unevaluated: LazyList<i32> = LazyList::lazy(|| lazy_list![1]);
+
+    assert_eq!(format!("{:?}", LazyList::<i32>::new()), "[]");
+    assert_eq!(format!("{:?}", lazy_list![0, 1, 2]), "[0, 1, 2]");
+    assert_eq!(format!("{:?}", naturals), "[..]");
+
+    assert_eq!(naturals.take(2), lazy_list![0, 1]);
+
+    assert_eq!(format!("{:?}", naturals), "[0, 1, ..]");
+    assert_eq!(format!("{:?}", unevaluated.push_front(0)), "[0, ..]");
+}
+
+#[test]
+fn test_eq() {
+    let list_1 = lazy_list!["a", "a"];
+    let list_1_prime = LazyList::lazy(|| lazy_list!["a"]).push_front("a");
+    let list_2 = lazy_list!["a", "b"];
+
+    assert_ne!(list_1, list_2);
+    assert_eq!(list_1, list_1);
+    assert_eq!(list_1, list_1_prime);
+    assert_eq!(list_2, list_2);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eq_pointer_kind_consistent() {
+    let list_a = lazy_list!["a"];
+    let list_a_sync = lazy_list_sync!["a"];
+    let list_b = lazy_list!["b"];
+    let list_b_sync = lazy_list_sync!["b"];
+
+    assert!(list_a == list_a_sync);
+    assert!(list_a != list_b_sync);
+    assert!(list_b == list_b_sync);
+}
+
+#[test]
+fn test_partial_ord() {
+    let list_1 = lazy_list!["a"];
+    let list_1_prime = lazy_list!["a"];
+    let list_2 = lazy_list!["b"];
+    let list_3 = lazy_list![0.0];
+    let list_4 = lazy_list![core::f32::NAN];
+
+    assert_eq!(list_1.partial_cmp(&list_1_prime), Some(Ordering::Equal));
+    assert_eq!(list_1.partial_cmp(&list_2), Some(Ordering::Less));
+    assert_eq!(list_2.partial_cmp(&list_1), Some(Ordering::Greater));
+    assert_eq!(list_3.partial_cmp(&list_4), None);
+}
+
+#[test]
+fn test_ord() {
+    let list_1 = lazy_list!["a"];
+    let list_1_prime = lazy_list!["a"];
+    let list_2 = lazy_list!["b"];
+
+    assert_eq!(list_1.cmp(&list_1_prime), Ordering::Equal);
+    assert_eq!(list_1.cmp(&list_2), Ordering::Less);
+    assert_eq!(list_2.cmp(&list_1), Ordering::Greater);
+}
+
+fn hash<T: Hash, P: LazyListPointerKind<T>>(list: &LazyList<T, P>) -> u64 {
+    #[allow(deprecated)]
+    let mut hasher = core::hash::SipHasher::new();
+
+    list.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[test]
+fn test_hash() {
+    let list_1 = lazy_list!["a"];
+    let list_1_prime = lazy_list!["a"];
+    let list_2 = lazy_list!["a", "b"];
+
+    assert_eq!(hash(&list_1), hash(&list_1));
+    assert_eq!(hash(&list_1), hash(&list_1_prime));
+    assert_ne!(hash(&list_1), hash(&list_2));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_hash_pointer_kind_consistent() {
+    let list = lazy_list!["a"];
+    let list_sync = lazy_list_sync!["a"];
+
+    assert_eq!(hash(&list), hash(&list_sync));
+}
+
+#[test]
+fn test_clone() {
+    let list = lazy_list!["hello", "there"];
+    let clone = list.clone();
+
+    assert!(clone.iter().eq(list.iter()));
+}
+
+#[test]
+fn test_drop_large() {
+    let limit = 1024 * 1024;
+    let naturals = naturals_from(0, Rc::new(Cell::new(0)));
+
+    assert_eq!(naturals.iter().nth(limit), Some(&(limit as u64)));
+
+    let mut list = LazyList::new();
+
+    for i in 0..limit {
+        list.push_front_mut(i);
+    }
+}
@@ -40,6 +40,10 @@
 //!   6. [`HashTrieSet`](#hashtrieset)
 //!   7. [`RedBlackTreeMap`](#redblacktreemap)
 //!   8. [`RedBlackTreeSet`](#redblacktreeset)
+//!   9. [`FingerTree`](#fingertree)
+//!  10. [`Heap`](#heap)
+//!  11. [`RandomAccessList`](#randomaccesslist)
+//!  12. [`LazyList`](#lazylist)
 //!
 //! ### `List`
 //! [![List documentation](https://img.shields.io/badge/doc-List-303070.svg)](./list/struct.List.html)
@@ -245,6 +249,118 @@
 //! assert_eq!(set_positive.first(), Some(&"one"));
 //! ```
 //!
+//! ### `FingerTree`
+//! [![`FingerTree` documentation](https://img.shields.io/badge/doc-FingerTree-303070.svg)](./finger_tree/struct.FingerTree.html)
+//!
+//! A sequence annotated with a user defined measure, that can be split and concatenated
+//! efficiently.  See [Finger Trees: A Simple General-purpose Data Structure](http://www.staff.city.ac.uk/~ross/papers/FingerTree.pdf)
+//! for details.  This crate includes an indexed sequence ([`IndexedSeq`](./finger_tree/indexed_seq/struct.IndexedSeq.html))
+//! and a priority queue ([`PriorityQueue`](./finger_tree/priority_queue/struct.PriorityQueue.html))
+//! built on top of it.
+//!
+//! #### Example
+//!
+//! ```rust
+//! use rpds::IndexedSeq;
+//!
+//! let seq = IndexedSeq::new()
+//!     .push_back("zero")
+//!     .push_back("one")
+//!     .push_back("two");
+//!
+//! assert_eq!(seq.get(1), Some(&"one"));
+//!
+//! let (left, right) = seq.split_at(1);
+//!
+//! assert_eq!(right.first(), Some(&"one"));
+//!
+//! let seq_again = left.append(&right);
+//!
+//! assert_eq!(seq_again, seq);
+//! ```
+//!
+//! ### `Heap`
+//! [![`Heap` documentation](https://img.shields.io/badge/doc-Heap-303070.svg)](./heap/struct.Heap.html)
+//!
+//! A min-heap implemented with a skew binomial heap.  See
+//! [Optimal Purely Functional Priority Queues](https://www.brics.dk/RS/96/37/BRICS-RS-96-37.pdf)
+//! for details.
+//!
+//! #### Example
+//!
+//! ```rust
+//! use rpds::Heap;
+//!
+//! let heap = Heap::new()
+//!     .push(4)
+//!     .push(2)
+//!     .push(7);
+//!
+//! assert_eq!(heap.peek(), Some(&2));
+//!
+//! let heap_popped = heap.pop().unwrap();
+//!
+//! assert_eq!(heap_popped.peek(), Some(&4));
+//!
+//! let heap_merged = heap_popped.merge(&Heap::new().push(1));
+//!
+//! assert_eq!(heap_merged.peek(), Some(&1));
+//! ```
+//!
+//! ### `RandomAccessList`
+//! [![`RandomAccessList` documentation](https://img.shields.io/badge/doc-RandomAccessList-303070.svg)](./random_access_list/struct.RandomAccessList.html)
+//!
+//! A list with constant time operations at the front and logarithmic time access by index,
+//! implemented with a skew-binary random-access list.  See
+//! [Purely Functional Random-Access Lists](https://dl.acm.org/doi/10.1145/224164.224187) for
+//! details.
+//!
+//! #### Example
+//!
+//! ```rust
+//! use rpds::RandomAccessList;
+//!
+//! let list = RandomAccessList::new()
+//!     .push_front("two")
+//!     .push_front("one")
+//!     .push_front("zero");
+//!
+//! assert_eq!(list.get(1), Some(&"one"));
+//!
+//! let list_updated = list.set(2, "TWO").unwrap();
+//!
+//! assert_eq!(list_updated[2], "TWO");
+//!
+//! let list_dropped = list_updated.drop_first().unwrap();
+//!
+//! assert_eq!(list_dropped.first(), Some(&"one"));
+//! ```
+//!
+//! ### `LazyList`
+//! [![`LazyList` documentation](https://img.shields.io/badge/doc-LazyList-303070.svg)](./lazy_list/struct.LazyList.html)
+//!
+//! A list whose elements are computed on demand, which can be infinite.  Each element is computed
+//! at most once, and the computed elements are shared by all the versions of the list.  See
+//! "Purely Functional Data Structures" by Chris Okasaki for details.
+//!
+//! #### Example
+//!
+//! ```rust
+//! use rpds::LazyList;
+//!
+//! fn fibonacci(a: u64, b: u64) -> LazyList<u64> {
+//!     LazyList::lazy(move || fibonacci(b, a + b).push_front(a))
+//! }
+//!
+//! let fib = fibonacci(0, 1);
+//!
+//! assert_eq!(fib.iter().nth(10), Some(&55));
+//!
+//! let first_five: Vec<u64> = fib.take(5).iter().copied().collect();
+//!
+//! assert_eq!(first_five, vec![0, 1, 1, 2, 3]);
+//! ```
+//!
 //! ## Other features
 //!
 //! ### Mutable methods
@@ -352,6 +468,17 @@
 //! [dependencies]
 //! rpds = { version = "<version>", features = ["serde"] }
 //! ```
+//!
+//! ### Random sampling
+//!
+//! The maps and sets can return the entry at a given position with `nth()` in *O(log(n))* time.
+//! To pick an entry uniformly at random with `sample()` enable the `rand` feature, which uses
+//! [rand](https://crates.io/crates/rand):
+//!
+//! ```toml
+//! [dependencies]
+//! rpds = { version = "<version>", features = ["rand"] }
+//! ```
 
 extern crate alloc;
 
@@ -359,15 +486,35 @@ extern crate alloc;
 #[macro_use]
 extern crate std;
 
+pub mod equivalent;
+pub mod finger_tree;
+pub mod heap;
+#[macro_use]
+pub mod lazy_list;
 mod utils;
 #[macro_use]
 pub mod list;
 pub mod map;
 pub mod queue;
+#[macro_use]
+pub mod random_access_list;
 pub mod set;
 pub mod stack;
 pub mod vector;
 
+pub use crate::equivalent::Comparable;
+pub use crate::equivalent::Equivalent;
+pub use crate::finger_tree::FingerTree;
+pub use crate::finger_tree::FingerTreeSync;
+pub use crate::finger_tree::IndexedSeq;
+pub use crate::finger_tree::IndexedSeqSync;
+pub use crate::finger_tree::PriorityQueue;
+pub use crate::finger_tree::PriorityQueueSync;
+pub use crate::heap::Heap;
+pub use crate::heap::HeapSync;
+pub use crate::lazy_list::LazyList;
+#[cfg(feature = "std")]
+pub use crate::lazy_list::LazyListSync;
 pub use crate::list::List;
 pub use crate::list::ListSync;
 pub use crate::map::hash_trie_map::HashTrieMap;
@@ -376,6 +523,8 @@ pub use crate::map::red_black_tree_map::RedBlackTreeMap;
 pub use crate::map::red_black_tree_map::RedBlackTreeMapSync;
 pub use crate::queue::Queue;
 pub use crate::queue::QueueSync;
+pub use crate::random_access_list::RandomAccessList;
+pub use crate::random_access_list::RandomAccessListSync;
 pub use crate::set::hash_trie_set::HashTrieSet;
 pub use crate::set::hash_trie_set::HashTrieSetSync;
 pub use crate::set::red_black_tree_set::RedBlackTreeSet;
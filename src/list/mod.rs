@@ -199,7 +199,7 @@ where
         }
     }
 
-    fn push_front_ptr_mut(&mut self, v: SharedPointer<T, P>) {
+    pub(crate) fn push_front_ptr_mut(&mut self, v: SharedPointer<T, P>) {
         if self.length == 0 {
             self.last = Some(SharedPointer::clone(&v));
         }
@@ -3,6 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
+use archery::{SharedPointer, SharedPointerKind};
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Entry<K, V> {
     pub key: K,
@@ -15,3 +17,14 @@ impl<K, V> Entry<K, V> {
         Entry { key, value }
     }
 }
+
+impl<K: Clone, V: Clone> Entry<K, V> {
+    /// Takes the key and value out of a shared entry, cloning them only if the entry is still
+    /// referenced elsewhere.
+    pub fn into_key_value<P: SharedPointerKind>(entry: SharedPointer<Entry<K, V>, P>) -> (K, V) {
+        match SharedPointer::try_unwrap(entry) {
+            Ok(entry) => (entry.key, entry.value),
+            Err(entry) => (entry.key.clone(), entry.value.clone()),
+        }
+    }
+}
@@ -5,33 +5,169 @@
 
 mod sparse_array_usize;
 
-use super::entry::Entry;
-use crate::list;
+use super::entry::Entry as KeyValue;
+use crate::equivalent::Equivalent;
+use crate::map::red_black_tree_map;
 use crate::utils::DefaultBuildHasher;
 use crate::List;
+use crate::RedBlackTreeMap;
+use alloc::vec;
 use alloc::vec::Vec;
 use archery::{ArcK, RcK, SharedPointer, SharedPointerKind};
 use core::borrow::Borrow;
 use core::fmt::Display;
 use core::hash::BuildHasher;
 use core::hash::Hash;
+use core::hash::Hasher;
 use core::iter::FromIterator;
-use core::iter::Peekable;
 use core::mem::size_of;
 use core::ops::Index;
-use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "rand")]
+use rand::{Rng, RngCore};
 use sparse_array_usize::SparseArrayUsize;
 
 type HashValue = u64;
 
 // TODO Use impl trait instead of this when available.
-pub type Iter<'a, K, V, P> =
-    core::iter::Map<IterPtr<'a, K, V, P>, fn(&'a SharedPointer<Entry<K, V>, P>) -> (&'a K, &'a V)>;
-pub type IterKeys<'a, K, V, P> = core::iter::Map<Iter<'a, K, V, P>, fn((&'a K, &V)) -> &'a K>;
-pub type IterValues<'a, K, V, P> = core::iter::Map<Iter<'a, K, V, P>, fn((&K, &'a V)) -> &'a V>;
+pub type Iter<'a, K, V, P, W = u64> = core::iter::Map<
+    IterPtr<'a, K, V, P, W>,
+    fn(&'a SharedPointer<KeyValue<K, V>, P>) -> (&'a K, &'a V),
+>;
+pub type IterKeys<'a, K, V, P, W = u64> =
+    core::iter::Map<Iter<'a, K, V, P, W>, fn((&'a K, &V)) -> &'a K>;
+pub type IterValues<'a, K, V, P, W = u64> =
+    core::iter::Map<Iter<'a, K, V, P, W>, fn((&K, &'a V)) -> &'a V>;
 
 const DEFAULT_DEGREE: u8 = 8 * size_of::<usize>() as u8;
 
+/// Maximum number of entries of a map whose entries are kept in a flat array instead of a trie.
+const FLAT_MAX_SIZE: usize = 8;
+
+/// The hash of a key, as used by a [`HashTrieMap`] to place the key in its trie.  The map uses
+/// `log2(degree)` bits of the hash at each level, so the width of the hash sets the maximum depth
+/// of the trie.  Keys whose hashes are equal in all those bits end up in a collision node.
+///
+/// The hash is computed from the output of the hasher of the map, which is 64 bits wide, called
+/// the raw hash of the key (see [`HashTrieMap::hash_key()`]):
+///
+///   * `u32` folds the 64 bits into 32 bits.  This gives shallower tries, which is useful for
+///     small maps or with a 32-bit hasher.
+///   * `u64` is the output of the hasher.  This is the default.
+///   * `u128` hashes the key twice, with different salts, to make the hash wider.  This makes a
+///     full hash collision practically impossible, even for huge maps, at the cost of hashing the
+///     key twice.
+pub trait TrieHash: Copy + Default + Eq + core::fmt::Debug {
+    /// The number of bits of the hash.
+    const BITS: usize;
+
+    /// Computes the hash of `value` with a hasher from `hasher_builder`.
+    fn hash_with<T: ?Sized + Hash, H: BuildHasher>(value: &T, hasher_builder: &H) -> Self {
+        Self::from_raw_hash(node_utils::raw_hash(value, hasher_builder), value, hasher_builder)
+    }
+
+    /// Computes the hash of `value` from `raw_hash`, its raw hash with a hasher from
+    /// `hasher_builder`.  Only the widths that need more than the raw hash hash `value` again.
+    fn from_raw_hash<T: ?Sized + Hash, H: BuildHasher>(
+        raw_hash: u64,
+        value: &T,
+        hasher_builder: &H,
+    ) -> Self;
+
+    /// Returns `true` if this can be the hash of a value whose raw hash is `raw_hash`.
+    fn matches_raw_hash(self, raw_hash: u64) -> bool;
+
+    /// Returns the bits of the hash selected by `mask` after shifting it right by `shift` bits,
+    /// where `shift` is less than [`BITS`](TrieHash::BITS).
+    fn bits(self, shift: usize, mask: usize) -> usize;
+
+    /// Same as [`bits()`](TrieHash::bits()) for the hash of a value whose raw hash is `raw_hash`,
+    /// or `None` if these bits do not follow from the raw hash.
+    fn raw_hash_bits(raw_hash: u64, shift: usize, mask: usize) -> Option<usize>;
+}
+
+impl TrieHash for u32 {
+    const BITS: usize = 32;
+
+    fn from_raw_hash<T: ?Sized + Hash, H: BuildHasher>(raw_hash: u64, _: &T, _: &H) -> u32 {
+        node_utils::fold_raw_hash(raw_hash)
+    }
+
+    #[inline]
+    fn matches_raw_hash(self, raw_hash: u64) -> bool {
+        self == node_utils::fold_raw_hash(raw_hash)
+    }
+
+    #[inline]
+    fn bits(self, shift: usize, mask: usize) -> usize {
+        (self >> shift) as usize & mask
+    }
+
+    #[inline]
+    fn raw_hash_bits(raw_hash: u64, shift: usize, mask: usize) -> Option<usize> {
+        Some(node_utils::fold_raw_hash(raw_hash).bits(shift, mask))
+    }
+}
+
+impl TrieHash for u64 {
+    const BITS: usize = 64;
+
+    fn from_raw_hash<T: ?Sized + Hash, H: BuildHasher>(raw_hash: u64, _: &T, _: &H) -> u64 {
+        raw_hash
+    }
+
+    #[inline]
+    fn matches_raw_hash(self, raw_hash: u64) -> bool {
+        self == raw_hash
+    }
+
+    #[inline]
+    fn bits(self, shift: usize, mask: usize) -> usize {
+        (self >> shift) as usize & mask
+    }
+
+    #[inline]
+    fn raw_hash_bits(raw_hash: u64, shift: usize, mask: usize) -> Option<usize> {
+        Some(raw_hash.bits(shift, mask))
+    }
+}
+
+impl TrieHash for u128 {
+    const BITS: usize = 128;
+
+    fn from_raw_hash<T: ?Sized + Hash, H: BuildHasher>(
+        raw_hash: u64,
+        value: &T,
+        hasher_builder: &H,
+    ) -> u128 {
+        // The salt makes the high half of the hash differ from the low half.
+        const SALT: u64 = 0x6a09_e667_f3bc_c908;
+
+        let high = node_utils::raw_hash(&(value, SALT), hasher_builder);
+
+        (u128::from(high) << 64) | u128::from(raw_hash)
+    }
+
+    #[inline]
+    fn matches_raw_hash(self, raw_hash: u64) -> bool {
+        self & u128::from(u64::MAX) == u128::from(raw_hash)
+    }
+
+    #[inline]
+    fn bits(self, shift: usize, mask: usize) -> usize {
+        (self >> shift) as usize & mask
+    }
+
+    /// Only the bits of the low half of the hash, which is the raw hash, are known.
+    #[inline]
+    fn raw_hash_bits(raw_hash: u64, shift: usize, mask: usize) -> Option<usize> {
+        match shift + mask.count_ones() as usize <= 64 {
+            true => Some(raw_hash.bits(shift, mask)),
+            false => None,
+        }
+    }
+}
+
 /// Creates a [`HashTrieMap`](map/hash_trie_map/struct.HashTrieMap.html) containing the
 /// given arguments:
 ///
@@ -98,36 +234,113 @@ macro_rules! ht_map_sync {
 /// | Operation                  | Average   | Worst case  |
 /// |:-------------------------- | ---------:| -----------:|
 /// | `new()`                    |      Θ(1) |        Θ(1) |
-/// | `insert()`                 |      Θ(1) |        Θ(n) |
-/// | `remove()`                 |      Θ(1) |        Θ(n) |
-/// | `get()`                    |      Θ(1) |        Θ(n) |
-/// | `contains_key()`           |      Θ(1) |        Θ(n) |
+/// | `insert()`                 |      Θ(1) | Θ(log(n))\* |
+/// | `remove()`                 |      Θ(1) | Θ(log(n))\* |
+/// | `get()`                    |      Θ(1) | Θ(log(n))\* |
+/// | `contains_key()`           |      Θ(1) | Θ(log(n))\* |
+/// | `entry()`                  |      Θ(1) | Θ(log(n))\* |
 /// | `size()`                   |      Θ(1) |        Θ(1) |
 /// | `clone()`                  |      Θ(1) |        Θ(1) |
 /// | iterator creation          |      Θ(1) |        Θ(1) |
-/// | iterator step              |      Θ(1) |        Θ(1) |
+/// | iterator step              |      Θ(1) | Θ(log(n))\* |
 /// | iterator full              |      Θ(n) |        Θ(n) |
 ///
+/// \* The worst case happens when many keys have the same hash.  Keys with the same hash are
+/// told apart by a secondary hash, which keeps the operations logarithmic.  The secondary hash is
+/// computed with a hasher of its own, independent of the hasher of the map, so this holds even if
+/// the hasher of the map gives the same hash to every key.  Only if the keys also have the same
+/// secondary hash are the operations Θ(n).
+///
+/// With `std` the secondary hasher has random keys, so keys chosen by an attacker to collide
+/// cannot be made to collide on the secondary hash as well.  Without `std` the secondary hasher
+/// is a `SipHash` with fixed keys: it still tells apart the keys of a weak hasher, but gives no
+/// protection against an attacker who chooses the keys.
+///
 /// # Implementation details
 ///
 /// This implementation uses a
 /// [hash array mapped trie](https://en.wikipedia.org/wiki/Hash_array_mapped_trie).
 /// Details can be found in
-/// [Ideal Hash Trees](https://infoscience.epfl.ch/record/64398/files/idealhashtrees.pdf).
+/// [Ideal Hash Trees](https://infoscience.epfl.ch/record/64398/files/idealhashtrees.pdf).  The
+/// nodes use the CHAMP layout, from *Optimizing Hash-Array Mapped Tries for Fast and Lean
+/// Immutable JVM Collections*, which stores the entries inline in the nodes.  Small maps, with up
+/// to eight entries, do not use a trie at all: their entries are kept in a flat array, which is
+/// faster and smaller for so few entries.
 ///
 /// See the `Node` documentation for details.
+///
+/// The width of the hash used to place the keys in the trie is given by the `W` type parameter,
+/// which defaults to `u64`.  See [`TrieHash`] for the available widths.
+///
+/// # Equality
+///
+/// Maps derived from one another (by inserting, removing, cloning, merging, and so on) share their
+/// hasher, so their tries are laid out the same way and can be compared node by node.  Other maps
+/// are compared by looking up every key, since their hashers may not agree.
+///
+/// Comparing two maps skips the subtrees and entries they share, for instance after a clone, so
+/// shared values are considered equal without calling [`PartialEq::eq`] on them.  This is only
+/// noticeable with values that are not equal to themselves, like `f64::NAN`:
+///
+/// ```rust
+/// # use rpds::*;
+/// let map = ht_map![1 => f64::NAN];
+///
+/// assert!(map == map.clone());
+/// ```
+///
+/// # Example
+///
+/// ```
+/// use archery::RcK;
+/// use rpds::HashTrieMap;
+/// use std::collections::hash_map::RandomState;
+///
+/// let map: HashTrieMap<i32, &str, RcK, RandomState, u128> =
+///     HashTrieMap::new_with_hasher_and_ptr_kind(RandomState::new());
+/// let map = map.insert(1, "one").insert(2, "two");
+///
+/// assert_eq!(map.get(&1), Some(&"one"));
+/// assert_eq!(map.size(), 2);
+/// ```
 #[derive(Debug)]
-pub struct HashTrieMap<K, V, P = RcK, H: BuildHasher = DefaultBuildHasher>
+pub struct HashTrieMap<K, V, P = RcK, H: BuildHasher = DefaultBuildHasher, W: TrieHash = u64>
 where
     P: SharedPointerKind,
 {
-    root: SharedPointer<Node<K, V, P>, P>,
+    root: SharedPointer<Node<K, V, P, W>, P>,
     size: usize,
     degree: u8,
+    /// The hashers are shared by all the maps derived from the same map, so that we can tell when
+    /// two maps are sure to hash keys the same way.
+    ///
+    /// This costs an allocation for each new map, on top of the one for its root, even when `H`
+    /// is zero-sized.  The allocation is what gives a map its identity: hashers have no notion of
+    /// equality, and two maps with zero-sized hashers still have secondary hashers with different
+    /// keys, so comparing pointers is the only way to know that two maps share their layout.  Maps
+    /// derived from a map, which are the common case, share the allocation and never pay for it.
+    hashers: SharedPointer<Hashers<H>, P>,
+}
+
+pub type HashTrieMapSync<K, V, H = DefaultBuildHasher, W = u64> = HashTrieMap<K, V, ArcK, H, W>;
+
+/// The hashers of a map.
+#[derive(Debug)]
+struct Hashers<H> {
+    /// Hashes the keys to place them in the trie.
     hasher_builder: H,
+    /// Gives the secondary hash that tells apart the keys with the same hash in a collision node.
+    /// This is independent of `hasher_builder`, so that keys which collide because of a weak
+    /// hasher are still told apart.  With `std` this is a `RandomState`, whose creation only
+    /// reads and bumps thread-local keys; without `std` it has fixed keys.
+    secondary_hasher: DefaultBuildHasher,
 }
 
-pub type HashTrieMapSync<K, V, H = DefaultBuildHasher> = HashTrieMap<K, V, ArcK, H>;
+impl<H> Hashers<H> {
+    fn new(hasher_builder: H) -> Hashers<H> {
+        Hashers { hasher_builder, secondary_hasher: DefaultBuildHasher::default() }
+    }
+}
 
 /// This map works like a trie that breaks the hash of the key in segments, and the segments are
 /// used as the index in the trie branches.
@@ -163,742 +376,3723 @@ pub type HashTrieMapSync<K, V, H = DefaultBuildHasher> = HashTrieMap<K, V, ArcK,
 ///                                   ·
 ///                                   │
 ///                            0  ···   7   ···
-///                          ├───┼───┼─────┼───┤
-///                          │ ∅ │ ∅ │ D E │ ∅ │          depth 16 (maximum depth)
-///                          └───┴───┴─────┴───┘
+///                          ├───┼───┼───┼───┤
+///                          │ ∅ │ ∅ │ • │ ∅ │            depth 15
+///                          └───┴───┴─│─┴───┘
+///                                    │
+///                                 ├─────┤
+///                                 │ D E │               depth 16 (maximum depth)
+///                                 └─────┘
 /// ```
 ///
 /// Note that we stop the insertion process early when possible.  In the example above we did not
 /// had to expand the tree any further to accommodate *C*, since there is no other entry with a
 /// hash that starts with `0b0010`.  The entries *A* and *B* exemplifies the case where a single
 /// level is not enough because their hash both start with `0b0110`.  In case of a full hash
-/// collision we dig through all the levels of the tree so we get to the final leaf where a
-/// collision exists, like we can see in the case of *D* and *E*.
+/// collision we dig through all the levels of the tree so we get to the final collision node,
+/// like we can see in the case of *D* and *E*.  Inside the collision node the entries are
+/// grouped by a secondary hash of their key, in a red-black tree, so that keys with the same hash,
+/// whether chosen by an attacker or given by a weak hasher, cannot make the operations linear.
+/// The secondary hash does not use the hasher of the map, so that it tells those keys apart.  (Only
+/// with `std`, where the secondary hasher has random keys, does this hold against an attacker.)
+///
+/// The branches follow the CHAMP layout described by Steindorfer and Vinju in *Optimizing
+/// Hash-Array Mapped Tries for Fast and Lean Immutable JVM Collections*: the entries are stored
+/// inline in the branch, in an array separate from the array of subtrees, each with its own
+/// bitmap.  This way the entries do not need an allocation of their own, and an iteration goes
+/// through all the entries of a branch before moving on to its subtrees.
+///
+/// A lookup follows one pointer per level of the trie, plus one to the key and value of the entry
+/// it finds.  Each entry keeps the hash of its key next to that pointer, so an entry with another
+/// key is almost always rejected without following it.  The key and value stay behind a shared
+/// pointer, instead of inline in the branch, so that copying the path to a changed entry only
+/// copies a pointer per entry, and does not require `K` or `V` to be `Clone`.
+///
+/// Most maps are small, and for them a trie is overkill.  A map with at most `FLAT_MAX_SIZE`
+/// entries has a flat root instead, which keeps the entries in a single array that is scanned
+/// linearly, comparing the hashes first.  The root becomes a branch when the map grows past that
+/// size, and flat again when it shrinks back.
 ///
 /// # Invariants
 ///
 /// The tree has the following invariants (among others):
 ///
-///   1. The root is the only node that can have zero children.
-///   2. A node with a collision can only exist at the maximum depth of the tree.
-///   3. A non-root branch always have two or more entries under it (because it could be
-///      compressed).
+///   1. The root is flat if the map has at most `FLAT_MAX_SIZE` entries, and a branch otherwise.
+///      Only the root can be flat.
+///   2. A position of a branch holds either an entry or a subtree, never both.
+///   3. A collision node can only exist at the maximum depth of the tree.
+///   4. A subtree never has a single entry under it (it is stored inline in the parent instead).
+///
+/// These invariants make the shape of the tree canonical: it only depends on the keys in the
+/// map (and their hashes), not on the order in which they were inserted or removed.
+#[derive(Debug)]
+enum Node<K, V, P = RcK, W = u64>
+where
+    P: SharedPointerKind,
+{
+    Branch {
+        entries: SparseArrayUsize<EntryWithHash<K, V, P, W>>,
+        subtrees: SparseArrayUsize<SharedPointer<Node<K, V, P, W>, P>>,
+        entries_hash: EntriesHash,
+        /// Number of entries under this branch.
+        size: usize,
+    },
+    Collision(CollisionEntries<K, V, P, W>),
+    Flat(Vec<EntryWithHash<K, V, P, W>>),
+}
+
+/// The entries of a collision node that share the same secondary hash.
+type CollisionBucket<K, V, P, W> = List<EntryWithHash<K, V, P, W>, P>;
+
+/// The entries of a collision node, which all have the same hash.  To keep the operations
+/// logarithmic when there are many of them, e.g. because the keys were chosen to collide, the
+/// entries are grouped by a secondary hash of their key.  The rare keys that also share the
+/// secondary hash are kept in a list.
 #[derive(Debug)]
-enum Node<K, V, P = RcK>
+struct CollisionEntries<K, V, P = RcK, W = u64>
 where
     P: SharedPointerKind,
 {
-    Branch(SparseArrayUsize<SharedPointer<Node<K, V, P>, P>>),
-    Leaf(Bucket<K, V, P>),
+    buckets: RedBlackTreeMap<HashValue, CollisionBucket<K, V, P, W>, P>,
+    size: usize,
+}
+
+/// The hash of all the entries under a branch, used to hash the map.  It is computed when the map
+/// is first hashed, and cleared whenever the branch changes.  This costs a word per branch, but
+/// without it hashing a map would visit every entry, even when most of the trie is shared with a
+/// map that was hashed before.
+///
+/// Zero means that the hash was not computed yet, so a hash of zero is never cached.  This is rare
+/// enough not to matter.
+#[derive(Debug, Default)]
+struct EntriesHash {
+    hash: AtomicUsize,
+}
+
+type CollisionIter<'a, K, V, P, W> = core::iter::Flatten<
+    red_black_tree_map::IterValues<'a, HashValue, List<EntryWithHash<K, V, P, W>, P>, P>,
+>;
+
+/// Where the entry of a key is in the trie, as found by `HashTrieMap::entry()`.  Since the path
+/// to the entry is given by the hash of the key, this is enough to reach the entry again without
+/// comparing keys on the way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryLocation {
+    /// In the entries of the branch at this depth.
+    Branch(usize),
+    /// In the collision node at the end of the path.
+    Collision,
+    /// At this position of the flat root.
+    Flat(usize),
 }
 
+/// The content of a position of a branch.
 #[derive(Debug)]
-enum Bucket<K, V, P = RcK>
+enum Slot<'a, K, V, P, W>
 where
     P: SharedPointerKind,
 {
-    Single(EntryWithHash<K, V, P>),
-    Collision(List<EntryWithHash<K, V, P>, P>),
+    Empty,
+    Entry(&'a EntryWithHash<K, V, P, W>),
+    Subtree(&'a SharedPointer<Node<K, V, P, W>, P>),
 }
 
 #[derive(Debug)]
-struct EntryWithHash<K, V, P = RcK>
+struct EntryWithHash<K, V, P = RcK, W = u64>
 where
     P: SharedPointerKind,
 {
-    entry: SharedPointer<Entry<K, V>, P>,
-    key_hash: HashValue,
+    entry: SharedPointer<KeyValue<K, V>, P>,
+    key_hash: W,
 }
 
 mod node_utils {
-    use super::HashValue;
+    use super::{HashValue, TrieHash};
+    use core::cmp::Ordering;
     use core::hash::BuildHasher;
     use core::hash::Hash;
     use core::hash::Hasher;
-    use core::mem::size_of_val;
 
     // Returns the index of the array for the given hash on depth `depth`.
     //
     // When the hash is exhausted, meaning that we are at the maximum depth, this returns `None`.
     #[inline]
-    pub fn index_from_hash(hash: HashValue, depth: usize, degree: u8) -> Option<usize> {
+    pub fn index_from_hash<W: TrieHash>(hash: W, depth: usize, degree: u8) -> Option<usize> {
         debug_assert!(degree.is_power_of_two());
 
-        let shift = depth as u32 * degree.trailing_zeros();
+        let shift = depth * degree.trailing_zeros() as usize;
 
-        if (shift as usize) < 8 * size_of_val(&hash) {
-            let mask = degree as HashValue - 1;
-            Some(((hash >> shift) & mask) as usize)
+        if shift < W::BITS {
+            Some(hash.bits(shift, usize::from(degree) - 1))
         } else {
             None
         }
     }
 
-    pub fn hash<T: ?Sized + Hash, H: BuildHasher>(v: &T, hasher_builder: &H) -> HashValue {
+    /// Same as `index_from_hash()` for a key whose raw hash is `raw_hash`, or `None` if the index
+    /// does not follow from the raw hash.
+    pub fn index_from_raw_hash<W: TrieHash>(
+        raw_hash: u64,
+        depth: usize,
+        degree: u8,
+    ) -> Option<usize> {
+        let shift = depth * degree.trailing_zeros() as usize;
+
+        W::raw_hash_bits(raw_hash, shift, usize::from(degree) - 1)
+    }
+
+    /// Compares two hashes by the sequence of their indices on each level, which is the order of
+    /// their positions in the trie.
+    pub fn cmp_trie_order<W: TrieHash>(a: W, b: W, degree: u8) -> Ordering {
+        let mut depth = 0;
+
+        while let Some(index_a) = index_from_hash(a, depth, degree) {
+            match index_a.cmp(&index_from_hash(b, depth, degree).unwrap()) {
+                Ordering::Equal => depth += 1,
+                ordering => return ordering,
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    /// Returns `true` if the hash is exhausted at depth `depth`, i.e. if this is the depth of the
+    /// collision nodes.
+    pub fn is_hash_exhausted<W: TrieHash>(depth: usize, degree: u8) -> bool {
+        index_from_hash(W::default(), depth, degree).is_none()
+    }
+
+    /// The hash of `v` as used to place it in the trie.
+    pub fn hash<W: TrieHash, T: ?Sized + Hash, H: BuildHasher>(v: &T, hasher_builder: &H) -> W {
+        W::hash_with(v, hasher_builder)
+    }
+
+    /// Folds a raw hash into 32 bits.
+    #[inline]
+    pub fn fold_raw_hash(raw_hash: u64) -> u32 {
+        (raw_hash ^ (raw_hash >> 32)) as u32
+    }
+
+    /// The output of a hasher built by `hasher_builder` for `v`.
+    pub fn raw_hash<T: ?Sized + Hash, H: BuildHasher>(v: &T, hasher_builder: &H) -> HashValue {
         let mut hasher = hasher_builder.build_hasher();
 
         v.hash(&mut hasher);
 
         hasher.finish()
     }
+
+    /// A hasher with a fixed state, used to hash the entries of a map independently of the hasher
+    /// of the map.  This is FNV-1a followed by a final mix of the bits, since the hashes of the
+    /// entries are summed.
+    pub struct EntryHasher {
+        state: u64,
+    }
+
+    impl Default for EntryHasher {
+        fn default() -> EntryHasher {
+            EntryHasher { state: 0xcbf2_9ce4_8422_2325 }
+        }
+    }
+
+    impl Hasher for EntryHasher {
+        fn finish(&self) -> u64 {
+            let mut h = self.state;
+
+            h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+            h ^ (h >> 31)
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.state = (self.state ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+    }
+
+    /// The hash of an entry, as used to hash a map.  This does not use the hasher of the map,
+    /// since maps with different hashers can be equal.
+    pub fn entry_hash<K: Hash, V: Hash>(key: &K, value: &V) -> usize {
+        let mut hasher = EntryHasher::default();
+
+        key.hash(&mut hasher);
+        value.hash(&mut hasher);
+
+        hasher.finish() as usize
+    }
+
+    /// The hash used to tell apart keys with the same hash in a collision node.  This is computed
+    /// with the secondary hasher of the map, not the one that placed the keys in the trie.
+    pub fn secondary_hash<T: ?Sized + Hash, H: BuildHasher>(
+        v: &T,
+        secondary_hasher: &H,
+    ) -> HashValue {
+        raw_hash(v, secondary_hasher)
+    }
 }
 
-impl<K, V, P> Node<K, V, P>
+impl<K, V, P, W: TrieHash> Node<K, V, P, W>
 where
     K: Eq + Hash,
     P: SharedPointerKind,
 {
-    fn new_empty_branch() -> Node<K, V, P> {
-        Node::Branch(SparseArrayUsize::new())
+    fn branch(
+        entries: SparseArrayUsize<EntryWithHash<K, V, P, W>>,
+        subtrees: SparseArrayUsize<SharedPointer<Node<K, V, P, W>, P>>,
+    ) -> Node<K, V, P, W> {
+        let size = entries.size() + subtrees.iter().map(|subtree| subtree.size()).sum::<usize>();
+
+        Node::Branch { entries, subtrees, entries_hash: EntriesHash::default(), size }
+    }
+
+    fn new_empty_branch() -> Node<K, V, P, W> {
+        Node::branch(SparseArrayUsize::new(), SparseArrayUsize::new())
+    }
+
+    /// Creates an empty node for depth `depth`, which is a collision node if we are at the
+    /// maximum depth.
+    fn new_empty(depth: usize, degree: u8) -> Node<K, V, P, W> {
+        match node_utils::is_hash_exhausted::<W>(depth, degree) {
+            false => Node::new_empty_branch(),
+            true => Node::Collision(CollisionEntries::new()),
+        }
+    }
+
+    /// Returns the content of position `index` of a branch.
+    fn slot(&self, index: usize) -> Slot<'_, K, V, P, W> {
+        match self {
+            Node::Branch { entries, subtrees, .. } => {
+                match (entries.get(index), subtrees.get(index)) {
+                    (Some(entry), _) => Slot::Entry(entry),
+                    (None, Some(subtree)) => Slot::Subtree(subtree),
+                    (None, None) => Slot::Empty,
+                }
+            }
+            Node::Collision(_) | Node::Flat(_) => unreachable!("only branches have positions"),
+        }
     }
 
-    fn get<Q: ?Sized>(
+    fn get<Q: ?Sized, H: BuildHasher>(
         &self,
         key: &Q,
-        key_hash: HashValue,
+        key_hash: W,
         depth: usize,
         degree: u8,
-    ) -> Option<&EntryWithHash<K, V, P>>
+        secondary_hasher: &H,
+    ) -> Option<&EntryWithHash<K, V, P, W>>
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Equivalent<K>,
     {
         match self {
-            Node::Branch(subtrees) => {
+            Node::Branch { entries, subtrees, .. } => {
                 let index: usize = node_utils::index_from_hash(key_hash, depth, degree)
                     .expect("hash cannot be exhausted if we are on a branch");
 
-                subtrees
-                    .get(index)
-                    .and_then(|subtree| subtree.get(key, key_hash, depth + 1, degree))
+                match entries.get(index) {
+                    Some(entry) => Some(entry).filter(|e| e.matches(key, key_hash)),
+                    None => subtrees.get(index).and_then(|subtree| {
+                        subtree.get(key, key_hash, depth + 1, degree, secondary_hasher)
+                    }),
+                }
+            }
+            Node::Collision(entries) => entries.get(key, key_hash, secondary_hasher),
+            Node::Flat(entries) => entries.iter().find(|e| e.matches(key, key_hash)),
+        }
+    }
+
+    /// Same as `get()` but the entry is the one whose key has raw hash `raw_hash` and matches
+    /// `is_match`.  Since the secondary hash of the key is not known, this looks at every entry of
+    /// a collision node.
+    fn find<F>(
+        &self,
+        raw_hash: u64,
+        depth: usize,
+        degree: u8,
+        is_match: &mut F,
+    ) -> Option<&EntryWithHash<K, V, P, W>>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        match self {
+            Node::Branch { entries, subtrees, .. } => {
+                match node_utils::index_from_raw_hash::<W>(raw_hash, depth, degree) {
+                    Some(index) => match entries.get(index) {
+                        Some(entry) => {
+                            Some(entry).filter(|e| e.matches_raw_hash(raw_hash, is_match))
+                        }
+                        None => subtrees.get(index).and_then(|subtree| {
+                            subtree.find(raw_hash, depth + 1, degree, is_match)
+                        }),
+                    },
+                    // The index is not known from the raw hash at this depth, so we look at every
+                    // position.  This only happens with hashes wider than the raw hash, under the
+                    // rare branches whose keys all share a long prefix of their raw hash.
+                    None => entries
+                        .iter()
+                        .find(|e| e.matches_raw_hash(raw_hash, is_match))
+                        .or_else(|| {
+                            subtrees.iter().find_map(|subtree| {
+                                subtree.find(raw_hash, depth + 1, degree, is_match)
+                            })
+                        }),
+                }
             }
-            Node::Leaf(bucket) => bucket.get(key, key_hash),
+            Node::Collision(entries) => {
+                entries.iter().find(|e| e.matches_raw_hash(raw_hash, is_match))
+            }
+            Node::Flat(entries) => entries.iter().find(|e| e.matches_raw_hash(raw_hash, is_match)),
         }
     }
 
-    fn get_mut<Q: ?Sized>(
+    fn get_mut<Q: ?Sized, H: BuildHasher>(
         &mut self,
         key: &Q,
-        key_hash: HashValue,
+        key_hash: W,
         depth: usize,
         degree: u8,
-    ) -> Option<&mut EntryWithHash<K, V, P>>
+        secondary_hasher: &H,
+    ) -> Option<&mut EntryWithHash<K, V, P, W>>
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Equivalent<K>,
     {
         match self {
-            Node::Branch(subtrees) => {
+            Node::Branch { entries, subtrees, entries_hash, .. } => {
+                entries_hash.clear();
+
                 let index: usize = node_utils::index_from_hash(key_hash, depth, degree)
                     .expect("hash cannot be exhausted if we are on a branch");
 
-                subtrees.get_mut(index).and_then(|subtree| {
-                    SharedPointer::make_mut(subtree).get_mut(key, key_hash, depth + 1, degree)
-                })
+                match entries.get_mut(index) {
+                    Some(entry) => Some(entry).filter(|e| e.matches(key, key_hash)),
+                    None => subtrees.get_mut(index).and_then(|subtree| {
+                        SharedPointer::make_mut(subtree).get_mut(
+                            key,
+                            key_hash,
+                            depth + 1,
+                            degree,
+                            secondary_hasher,
+                        )
+                    }),
+                }
             }
-            Node::Leaf(bucket) => bucket.get_mut(key, key_hash),
+            Node::Collision(entries) => entries.get_mut(key, key_hash, secondary_hasher),
+            Node::Flat(entries) => entries.iter_mut().find(|e| e.matches(key, key_hash)),
         }
     }
 
-    /// Returns a pair with the node with the new entry and whether the key is new.
-    fn insert(&mut self, entry: EntryWithHash<K, V, P>, depth: usize, degree: u8) -> bool {
+    /// Same as `get()`, but returns where the entry is instead of the entry itself.
+    fn locate<Q: ?Sized, H: BuildHasher>(
+        &self,
+        key: &Q,
+        key_hash: W,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+    ) -> Option<EntryLocation>
+    where
+        Q: Hash + Equivalent<K>,
+    {
         match self {
-            Node::Branch(subtrees) => {
-                let index: usize = node_utils::index_from_hash(entry.key_hash, depth, degree)
+            Node::Branch { entries, subtrees, .. } => {
+                let index: usize = node_utils::index_from_hash(key_hash, depth, degree)
                     .expect("hash cannot be exhausted if we are on a branch");
 
-                match subtrees.get_mut(index) {
-                    Some(subtree) => {
-                        SharedPointer::make_mut(subtree).insert(entry, depth + 1, degree)
-                    }
-
-                    None => {
-                        let new_subtree = Node::Leaf(Bucket::Single(entry));
-                        subtrees.set(index, SharedPointer::new(new_subtree));
-                        true
+                match entries.get(index) {
+                    Some(entry) if entry.matches(key, key_hash) => {
+                        Some(EntryLocation::Branch(depth))
                     }
+                    Some(_) => None,
+                    None => subtrees.get(index).and_then(|subtree| {
+                        subtree.locate(key, key_hash, depth + 1, degree, secondary_hasher)
+                    }),
                 }
             }
-            Node::Leaf(bucket) => {
-                // If we are at maximum depth then the hash was totally consumed and we have a
-                // collision.
-                let maximum_depth =
-                    node_utils::index_from_hash(entry.key_hash, depth, degree).is_none();
+            Node::Collision(entries) => {
+                entries.get(key, key_hash, secondary_hasher).map(|_| EntryLocation::Collision)
+            }
+            Node::Flat(entries) => {
+                entries.iter().position(|e| e.matches(key, key_hash)).map(EntryLocation::Flat)
+            }
+        }
+    }
 
-                let bucket_contains_key: bool = bucket.contains_key(entry.key(), entry.key_hash);
+    /// Returns the entry at `location`, which was returned by `locate()` for `key`.  Only a
+    /// collision node has to compare keys to find it.
+    fn at<H: BuildHasher>(
+        &self,
+        location: EntryLocation,
+        key: &K,
+        key_hash: W,
+        degree: u8,
+        secondary_hasher: &H,
+    ) -> &EntryWithHash<K, V, P, W> {
+        let mut node = self;
+        let mut depth = 0;
+
+        loop {
+            match node {
+                Node::Branch { entries, subtrees, .. } => {
+                    let index: usize = node_utils::index_from_hash(key_hash, depth, degree)
+                        .expect("hash cannot be exhausted if we are on a branch");
+
+                    if location == EntryLocation::Branch(depth) {
+                        return entries.get(index).expect("location must be in the trie");
+                    }
 
-                match maximum_depth {
-                    // We reached a bucket.  If the bucket contains the key we are inserting then
-                    // we just need to replace it.
-                    false if bucket_contains_key => bucket.insert(entry),
+                    node = subtrees.get(index).expect("location must be in the trie");
+                    depth += 1;
+                }
+                Node::Collision(entries) => {
+                    return entries
+                        .get(key, key_hash, secondary_hasher)
+                        .expect("location must be in the trie");
+                }
+                Node::Flat(entries) => match location {
+                    EntryLocation::Flat(position) => return &entries[position],
+                    EntryLocation::Branch(_) | EntryLocation::Collision => {
+                        unreachable!("location must be in the trie")
+                    }
+                },
+            }
+        }
+    }
 
-                    // We reached a bucket and the key we will insert is not there.  We need to
-                    // create a `Node::Branch` and insert the elements of the bucket there, as well
-                    // as the new element.
-                    false => {
-                        // TODO This clone should not be needed.
-                        let old_entry: EntryWithHash<K, V, P> = match bucket {
-                            Bucket::Single(e) => e.clone(),
-                            Bucket::Collision(_) => unreachable!(
-                                "hash is not exhausted, so there cannot be a collision here"
-                            ),
-                        };
+    /// Same as `at()`, but the nodes on the path to the entry are made unique.
+    fn at_mut<H: BuildHasher>(
+        &mut self,
+        location: EntryLocation,
+        key: &K,
+        key_hash: W,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+    ) -> &mut EntryWithHash<K, V, P, W> {
+        match self {
+            Node::Branch { entries, subtrees, entries_hash, .. } => {
+                entries_hash.clear();
 
-                        *self = Node::new_empty_branch();
+                let index: usize = node_utils::index_from_hash(key_hash, depth, degree)
+                    .expect("hash cannot be exhausted if we are on a branch");
 
-                        self.insert(old_entry, depth, degree);
-                        self.insert(entry, depth, degree);
+                if location == EntryLocation::Branch(depth) {
+                    return entries.get_mut(index).expect("location must be in the trie");
+                }
 
-                        true
-                    }
+                let subtree = subtrees.get_mut(index).expect("location must be in the trie");
 
-                    // Hash was already totally consumed.  This is a collision.
-                    true => bucket.insert(entry),
-                }
+                SharedPointer::make_mut(subtree).at_mut(
+                    location,
+                    key,
+                    key_hash,
+                    depth + 1,
+                    degree,
+                    secondary_hasher,
+                )
             }
+            Node::Collision(entries) => entries
+                .get_mut(key, key_hash, secondary_hasher)
+                .expect("location must be in the trie"),
+            Node::Flat(entries) => match location {
+                EntryLocation::Flat(position) => &mut entries[position],
+                EntryLocation::Branch(_) | EntryLocation::Collision => {
+                    unreachable!("location must be in the trie")
+                }
+            },
         }
     }
 
-    /// Compresses a node.  This makes the shallowest tree that is well-formed, i.e. branches with
-    /// a single entry become a leaf with it.
-    fn compress(&mut self) {
-        let new_node = match self {
-            Node::Branch(subtrees) => {
-                match subtrees.size() {
-                    1 => {
-                        let compress: bool = {
-                            let subtree = subtrees.first().unwrap();
+    /// Inserts an entry whose key is not in the trie, and returns it.  The new entry is found
+    /// while inserting it, so there is no need to look it up again from the root.
+    fn insert_new<H: BuildHasher>(
+        &mut self,
+        entry: EntryWithHash<K, V, P, W>,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+    ) -> &mut EntryWithHash<K, V, P, W> {
+        if let Node::Flat(entries) = self {
+            if entries.len() == FLAT_MAX_SIZE {
+                // The map is too big to stay flat, so we turn it into a trie.
+                let entries = core::mem::take(entries);
+
+                *self = Node::from_entries(entries, depth, degree, secondary_hasher);
+            }
+        }
+
+        // For the cases where the new entry ends up in a new node, we look it up in that node.
+        let key_hash = entry.key_hash;
+        let lookup = SharedPointer::clone(&entry.entry);
 
-                            // Keep collision at the bottom of the tree.
-                            match subtree.borrow() {
-                                Node::Leaf(Bucket::Single(_)) => true,
-                                _ => false,
-                            }
-                        };
+        let inserted = match self {
+            Node::Branch { entries, subtrees, entries_hash, size } => {
+                entries_hash.clear();
+                *size += 1;
 
-                        match compress {
-                            true => subtrees.pop(),
-                            false => None,
-                        }
+                let index: usize = node_utils::index_from_hash(key_hash, depth, degree)
+                    .expect("hash cannot be exhausted if we are on a branch");
+
+                if subtrees.get(index).is_some() {
+                    drop(lookup);
+
+                    let subtree = subtrees.get_mut(index).unwrap();
+
+                    return SharedPointer::make_mut(subtree).insert_new(
+                        entry,
+                        depth + 1,
+                        degree,
+                        secondary_hasher,
+                    );
+                }
+
+                match entries.remove(index) {
+                    None => {
+                        drop(lookup);
+                        entries.set(index, entry);
+
+                        return entries.get_mut(index).unwrap();
+                    }
+                    // Another key is here.  Both entries need to move to a new subtree.
+                    Some(existing_entry) => {
+                        let subtree = Node::from_entries(
+                            vec![existing_entry, entry],
+                            depth + 1,
+                            degree,
+                            secondary_hasher,
+                        );
+
+                        subtrees.set(index, SharedPointer::new(subtree));
+                        SharedPointer::make_mut(subtrees.get_mut(index).unwrap()).get_mut(
+                            &lookup.key,
+                            key_hash,
+                            depth + 1,
+                            degree,
+                            secondary_hasher,
+                        )
                     }
-                    _ => None,
                 }
             }
-            Node::Leaf(_) => None,
+            Node::Collision(entries) => {
+                entries.insert(entry, secondary_hasher);
+                entries.get_mut(&lookup.key, key_hash, secondary_hasher)
+            }
+            Node::Flat(entries) => {
+                drop(lookup);
+                entries.push(entry);
+
+                return entries.last_mut().unwrap();
+            }
         };
 
-        if let Some(node) = new_node {
-            crate::utils::replace(self, node);
-        }
+        // This must happen before the entry is changed, otherwise it would be copied.
+        drop(lookup);
+
+        inserted.expect("entry was just inserted")
     }
 
-    /// Returns `true` if the key was present.
-    fn remove<Q: ?Sized>(&mut self, key: &Q, key_hash: HashValue, depth: usize, degree: u8) -> bool
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
+    /// Returns the entry that was replaced, or `None` if the key is new.
+    fn insert<H: BuildHasher>(
+        &mut self,
+        entry: EntryWithHash<K, V, P, W>,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+    ) -> Option<EntryWithHash<K, V, P, W>> {
         match self {
-            Node::Branch(subtrees) => {
-                let index: usize = node_utils::index_from_hash(key_hash, depth, degree)
-                    .expect("hash cannot be exhausted if we are on a branch");
+            Node::Branch { entries, subtrees, entries_hash, size } => {
+                entries_hash.clear();
 
-                match subtrees.get_mut(index) {
-                    Some(subtree) => {
-                        let subtree = SharedPointer::make_mut(subtree);
-                        let removed = subtree.remove(key, key_hash, depth + 1, degree);
-
-                        match (subtree.is_empty(), removed) {
-                            (_, false) => (),
-                            (false, true) => {
-                                // Note that we still must call compress because it is possible that
-                                // we had a node with just one entry, which was not compressed
-                                // because it had a collision.  Maybe now we do not have a collision
-                                // and we can compress it.
-                                self.compress();
-                            }
-                            (true, true) => {
-                                subtrees.remove(index);
+                let index: usize = node_utils::index_from_hash(entry.key_hash, depth, degree)
+                    .expect("hash cannot be exhausted if we are on a branch");
 
-                                self.compress();
-                            }
-                        };
+                let replaced = match entries.get_mut(index) {
+                    // The key is already here, so we just need to replace the entry.
+                    Some(existing_entry) if existing_entry.matches(entry.key(), entry.key_hash) => {
+                        Some(core::mem::replace(existing_entry, entry))
+                    }
 
-                        removed
+                    // Another key is here.  Both entries need to move to a new subtree.
+                    Some(_) => {
+                        let existing_entry = entries.remove(index).unwrap();
+                        let subtree = Node::from_entries(
+                            vec![existing_entry, entry],
+                            depth + 1,
+                            degree,
+                            secondary_hasher,
+                        );
+
+                        subtrees.set(index, SharedPointer::new(subtree));
+                        None
                     }
 
-                    None => false,
+                    None => match subtrees.get_mut(index) {
+                        Some(subtree) => SharedPointer::make_mut(subtree).insert(
+                            entry,
+                            depth + 1,
+                            degree,
+                            secondary_hasher,
+                        ),
+                        None => {
+                            entries.set(index, entry);
+                            None
+                        }
+                    },
+                };
+
+                if replaced.is_none() {
+                    *size += 1;
                 }
+
+                replaced
             }
+            Node::Collision(entries) => entries.insert(entry, secondary_hasher),
+            Node::Flat(entries) => {
+                if let Some(existing_entry) =
+                    entries.iter_mut().find(|e| e.matches(entry.key(), entry.key_hash))
+                {
+                    return Some(core::mem::replace(existing_entry, entry));
+                }
 
-            Node::Leaf(bucket) => {
-                let mut bucket_ref = Some(bucket);
-                let removed = Bucket::remove(&mut bucket_ref, key, key_hash);
+                if entries.len() < FLAT_MAX_SIZE {
+                    entries.push(entry);
+                } else {
+                    // The map is too big to stay flat, so we turn it into a trie.
+                    let mut entries = core::mem::take(entries);
 
-                if bucket_ref.is_none() {
-                    // TODO Most of these empty branches will be dropped very soon.  We might
-                    //      gain some speed if we avoid this.  (However, currently no heap
-                    //      allocation happens anyway.)
-                    //      We can do something similar to Bucket::remove() where we receive
-                    //      a `&mut Option<&mut Bucket<_, _>>`.
-                    *self = Node::new_empty_branch();
+                    entries.push(entry);
+                    *self = Node::from_entries(entries, depth, degree, secondary_hasher);
                 }
 
-                removed
+                None
             }
         }
     }
 
-    fn is_empty(&self) -> bool {
+    /// Returns the removed entry, or `None` if the key was not present.
+    ///
+    /// A subtree left with a single entry is replaced by that entry, so that the invariants are
+    /// kept in all nodes below this one.  This node, however, can be left with a single entry.
+    fn remove<Q: ?Sized, H: BuildHasher>(
+        &mut self,
+        key: &Q,
+        key_hash: W,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+    ) -> Option<EntryWithHash<K, V, P, W>>
+    where
+        Q: Hash + Equivalent<K>,
+    {
         match self {
-            Node::Branch(subtrees) => subtrees.size() == 0,
-            Node::Leaf(Bucket::Single(_)) => false,
-            Node::Leaf(Bucket::Collision(entries)) => {
-                debug_assert!(entries.len() >= 2, "collisions must have at least two entries");
-                false
-            }
-        }
-    }
-}
+            Node::Branch { entries, subtrees, entries_hash, size } => {
+                entries_hash.clear();
 
-impl<K, V, P> Clone for Node<K, V, P>
-where
-    K: Eq + Hash,
-    P: SharedPointerKind,
-{
-    fn clone(&self) -> Node<K, V, P> {
+                let index: usize = node_utils::index_from_hash(key_hash, depth, degree)
+                    .expect("hash cannot be exhausted if we are on a branch");
+
+                let removed = if let Some(entry) = entries.get(index) {
+                    match entry.matches(key, key_hash) {
+                        true => entries.remove(index),
+                        false => None,
+                    }
+                } else if let Some(subtree) = subtrees.get_mut(index) {
+                    let subtree = SharedPointer::make_mut(subtree);
+                    let removed =
+                        subtree.remove(key, key_hash, depth + 1, degree, secondary_hasher);
+
+                    if let Some(entry) = subtree.single_entry() {
+                        let entry = entry.clone();
+
+                        subtrees.remove(index);
+                        entries.set(index, entry);
+                    }
+
+                    removed
+                } else {
+                    None
+                };
+
+                if removed.is_some() {
+                    *size -= 1;
+                }
+
+                removed
+            }
+            Node::Collision(entries) => entries.remove(key, key_hash, secondary_hasher),
+            Node::Flat(entries) => entries
+                .iter()
+                .position(|e| e.matches(key, key_hash))
+                .map(|index| entries.swap_remove(index)),
+        }
+    }
+
+    /// Same as `remove()` but removes the entry whose key has raw hash `raw_hash` and matches
+    /// `is_match`.
+    fn remove_matching<F>(
+        &mut self,
+        raw_hash: u64,
+        depth: usize,
+        degree: u8,
+        is_match: &mut F,
+    ) -> Option<EntryWithHash<K, V, P, W>>
+    where
+        F: FnMut(&K) -> bool,
+    {
         match self {
-            Node::Branch(subtrees) => Node::Branch(subtrees.clone()),
-            Node::Leaf(bucket) => Node::Leaf(bucket.clone()),
+            Node::Branch { entries, subtrees, entries_hash, size } => {
+                let index: usize =
+                    match node_utils::index_from_raw_hash::<W>(raw_hash, depth, degree) {
+                        Some(index) => index,
+                        // See `find()`.  We look for the position of the entry before removing it,
+                        // so that only the subtree that holds it is copied.
+                        None => entries
+                            .iter_with_index()
+                            .find(|(_, e)| e.matches_raw_hash(raw_hash, is_match))
+                            .map(|(index, _)| index)
+                            .or_else(|| {
+                                subtrees
+                                    .iter_with_index()
+                                    .find(|(_, subtree)| {
+                                        subtree
+                                            .find(raw_hash, depth + 1, degree, is_match)
+                                            .is_some()
+                                    })
+                                    .map(|(index, _)| index)
+                            })?,
+                    };
+
+                entries_hash.clear();
+
+                let removed = if let Some(entry) = entries.get(index) {
+                    match entry.matches_raw_hash(raw_hash, is_match) {
+                        true => entries.remove(index),
+                        false => None,
+                    }
+                } else if let Some(subtree) = subtrees.get_mut(index) {
+                    let subtree = SharedPointer::make_mut(subtree);
+                    let removed = subtree.remove_matching(raw_hash, depth + 1, degree, is_match);
+
+                    if let Some(entry) = subtree.single_entry() {
+                        let entry = entry.clone();
+
+                        subtrees.remove(index);
+                        entries.set(index, entry);
+                    }
+
+                    removed
+                } else {
+                    None
+                };
+
+                if removed.is_some() {
+                    *size -= 1;
+                }
+
+                removed
+            }
+            Node::Collision(entries) => entries.remove_matching(raw_hash, is_match),
+            Node::Flat(entries) => entries
+                .iter()
+                .position(|e| e.matches_raw_hash(raw_hash, is_match))
+                .map(|index| entries.swap_remove(index)),
         }
     }
-}
 
-mod bucket_utils {
-    use super::*;
+    /// Returns the entry of this node if it is the only entry under it.
+    fn single_entry(&self) -> Option<&EntryWithHash<K, V, P, W>> {
+        match self {
+            Node::Branch { entries, subtrees, .. }
+                if entries.size() == 1 && subtrees.size() == 0 =>
+            {
+                entries.first()
+            }
+            Node::Collision(entries) if entries.len() == 1 => entries.first(),
+            Node::Flat(entries) if entries.len() == 1 => entries.first(),
+            Node::Branch { .. } | Node::Collision(_) | Node::Flat(_) => None,
+        }
+    }
 
-    pub fn list_remove_first<T: Clone, P: SharedPointerKind, F: Fn(&T) -> bool>(
-        list: &mut List<T, P>,
-        predicate: F,
-    ) -> Option<T> {
-        let mut before_needle: Vec<T> = Vec::with_capacity(list.len());
-        let remaining: &mut List<T, P> = list;
-        let mut removed = None;
+    fn is_empty(&self) -> bool {
+        match self {
+            Node::Branch { entries, subtrees, .. } => entries.size() == 0 && subtrees.size() == 0,
+            Node::Collision(entries) => entries.is_empty(),
+            Node::Flat(entries) => entries.is_empty(),
+        }
+    }
 
-        while !remaining.is_empty() {
-            let e: T = remaining.first().unwrap().clone();
+    /// Returns the number of entries under this node.
+    fn size(&self) -> usize {
+        match self {
+            Node::Branch { size, .. } => *size,
+            Node::Collision(entries) => entries.len(),
+            Node::Flat(entries) => entries.len(),
+        }
+    }
 
-            remaining.drop_first_mut();
+    /// Returns the entry at position `index` of the entries under this node, in the order an
+    /// iterator visits them.
+    fn nth(&self, index: usize) -> Option<&EntryWithHash<K, V, P, W>> {
+        match self {
+            Node::Branch { entries, subtrees, .. } => {
+                if let Some(entry) = entries.as_slice().get(index) {
+                    return Some(entry);
+                }
 
-            if predicate(&e) {
-                removed = Some(e);
-                break;
+                let mut index = index - entries.size();
+
+                for subtree in subtrees.iter() {
+                    match subtree.size() {
+                        size if index < size => return subtree.nth(index),
+                        size => index -= size,
+                    }
+                }
+
+                None
             }
+            Node::Collision(entries) => entries.iter().nth(index),
+            Node::Flat(entries) => entries.get(index),
+        }
+    }
 
-            before_needle.push(e);
+    /// Folds the entries under this node with `f`, in the same order as an iterator visits them.
+    fn fold_entries<'a, B, F>(&'a self, init: B, f: &mut F) -> B
+    where
+        F: FnMut(B, &'a SharedPointer<KeyValue<K, V>, P>) -> B,
+    {
+        match self {
+            Node::Branch { entries, subtrees, .. } => {
+                let acc = entries.iter().fold(init, |acc, e| f(acc, &e.entry));
+
+                subtrees.iter().fold(acc, |acc, subtree| subtree.fold_entries(acc, f))
+            }
+            Node::Collision(entries) => entries.iter().fold(init, |acc, e| f(acc, &e.entry)),
+            Node::Flat(entries) => entries.iter().fold(init, |acc, e| f(acc, &e.entry)),
         }
+    }
 
-        let new_entries = remaining;
+    /// Pushes all the entries under this node to `out`.
+    fn collect_entries(&self, out: &mut Vec<EntryWithHash<K, V, P, W>>) {
+        match self {
+            Node::Branch { entries, subtrees, .. } => {
+                out.extend(entries.iter().cloned());
 
-        while let Some(e) = before_needle.pop() {
-            new_entries.push_front_mut(e);
+                for subtree in subtrees.iter() {
+                    subtree.collect_entries(out);
+                }
+            }
+            Node::Collision(entries) => out.extend(entries.iter().cloned()),
+            Node::Flat(entries) => out.extend(entries.iter().cloned()),
         }
+    }
 
-        removed
+    /// Returns the sum of the hashes of the entries under this node.  Since the sum does not
+    /// depend on the order of the entries, nor on the shape of the tree, equal maps have the same
+    /// sum.
+    fn entries_hash(&self) -> usize
+    where
+        V: Hash,
+    {
+        let sum = |entries: &mut dyn Iterator<Item = &EntryWithHash<K, V, P, W>>| {
+            entries.fold(0, |sum: usize, e| {
+                sum.wrapping_add(node_utils::entry_hash(e.key(), e.value()))
+            })
+        };
+
+        match self {
+            Node::Branch { entries, subtrees, entries_hash, .. } => {
+                entries_hash.get_or_compute(|| {
+                    subtrees.iter().fold(sum(&mut entries.iter()), |s, subtree| {
+                        s.wrapping_add(subtree.entries_hash())
+                    })
+                })
+            }
+            Node::Collision(entries) => sum(&mut entries.iter()),
+            Node::Flat(entries) => sum(&mut entries.iter()),
+        }
+    }
+
+    /// Returns `true` if `self` and `other` are the same branch.  The nodes of maps with different
+    /// pointer kinds have different types, so we compare the address of their entries hash
+    /// instead, which is part of the branch and has the same type in both.
+    fn is_same_branch<PO: SharedPointerKind>(&self, other: &Node<K, V, PO, W>) -> bool {
+        match (self, other) {
+            (
+                Node::Branch { entries_hash, .. },
+                Node::Branch { entries_hash: other_entries_hash, .. },
+            ) => crate::utils::same_object(entries_hash, other_entries_hash),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if both nodes have the same entries.  The nodes must be at the same position
+    /// of tries with the same layout, so that they have the same shape if they have the same
+    /// entries.  Subtrees shared by both nodes are not visited.
+    fn eq_entries<PO: SharedPointerKind>(&self, other: &Node<K, V, PO, W>) -> bool
+    where
+        V: PartialEq,
+    {
+        if self.is_same_branch(other) {
+            return true;
+        }
+
+        match (self, other) {
+            (
+                Node::Branch { entries, subtrees, .. },
+                Node::Branch { entries: other_entries, subtrees: other_subtrees, .. },
+            ) => {
+                entries.bitmap() == other_entries.bitmap()
+                    && subtrees.bitmap() == other_subtrees.bitmap()
+                    && entries.iter().zip(other_entries.iter()).all(|(a, b)| a.eq_entry(b))
+                    && subtrees.iter().zip(other_subtrees.iter()).all(|(a, b)| a.eq_entries(b))
+            }
+            (Node::Collision(entries), Node::Collision(other_entries)) => {
+                entries.eq_entries(other_entries)
+            }
+            // The keys are distinct, so this is enough to know that both have the same entries.
+            (Node::Flat(entries), Node::Flat(other_entries)) => {
+                entries.len() == other_entries.len()
+                    && entries.iter().all(|a| other_entries.iter().any(|b| a.eq_entry(b)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds the shape of this node, which is at depth `depth`, to `stats`.  `other_nodes` are the
+    /// sorted addresses of the nodes of the map we compare with, and `shared` is `true` if this
+    /// node is known to be one of them (which is the case if its parent is).
+    fn collect_stats(
+        &self,
+        depth: usize,
+        other_nodes: &[*const Node<K, V, P, W>],
+        shared: bool,
+        stats: &mut Stats,
+    ) {
+        let address: *const Node<K, V, P, W> = self;
+        let shared = shared || other_nodes.binary_search(&address).is_ok();
+
+        stats.node_count += 1;
+
+        if shared {
+            stats.shared_node_count += 1;
+        }
+
+        let entry_count = match self {
+            Node::Branch { entries, subtrees, .. } => {
+                stats.branch_count += 1;
+                stats.leaf_count += entries.size();
+                stats.occupied_slot_count += entries.size() + subtrees.size();
+
+                for subtree in subtrees.iter() {
+                    subtree.collect_stats(depth + 1, other_nodes, shared, stats);
+                }
+
+                entries.size()
+            }
+            Node::Collision(entries) => {
+                stats.collision_count += 1;
+                stats.collision_bucket_count += entries.buckets.size();
+
+                entries.len()
+            }
+            Node::Flat(entries) => {
+                stats.leaf_count += entries.len();
+
+                entries.len()
+            }
+        };
+
+        if stats.depth_histogram.len() <= depth {
+            stats.depth_histogram.resize(depth + 1, 0);
+        }
+
+        stats.depth_histogram[depth] += entry_count;
+    }
+
+    /// Pushes the addresses of this node and of all nodes below it to `addresses`.
+    fn collect_addresses(&self, addresses: &mut Vec<*const Node<K, V, P, W>>) {
+        addresses.push(self);
+
+        if let Node::Branch { subtrees, .. } = self {
+            for subtree in subtrees.iter() {
+                subtree.collect_addresses(addresses);
+            }
+        }
+    }
+
+    /// Creates the node at depth `depth` with the given entries, which must have distinct keys.
+    fn from_entries<H: BuildHasher>(
+        entries: Vec<EntryWithHash<K, V, P, W>>,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+    ) -> Node<K, V, P, W> {
+        let mut node = Node::new_empty(depth, degree);
+
+        for entry in entries {
+            node.insert(entry, depth, degree, secondary_hasher);
+        }
+
+        node
+    }
+
+    /// Returns the node at depth `depth` with the entries of `node` for which `f` returns `true`.
+    /// The subtrees where `f` keeps every entry are shared, and so is `node` itself if `f` keeps
+    /// all of its entries.
+    fn filter<H: BuildHasher, F>(
+        node: &SharedPointer<Node<K, V, P, W>, P>,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+        f: &mut F,
+    ) -> Merged<K, V, P, W>
+    where
+        F: FnMut(&EntryWithHash<K, V, P, W>) -> bool,
+    {
+        match &**node {
+            Node::Branch { entries, subtrees, .. } => {
+                let mut new_entries = SparseArrayUsize::new();
+                let mut new_subtrees = SparseArrayUsize::new();
+                let mut changed = false;
+
+                for (index, entry) in entries.iter_with_index() {
+                    match f(entry) {
+                        true => new_entries.set(index, entry.clone()),
+                        false => changed = true,
+                    }
+                }
+
+                for (index, subtree) in subtrees.iter_with_index() {
+                    match Node::filter(subtree, depth + 1, degree, secondary_hasher, f) {
+                        Merged::Empty => changed = true,
+                        Merged::Entry(entry) => {
+                            changed = true;
+                            new_entries.set(index, entry);
+                        }
+                        Merged::Subtree(new_subtree) => {
+                            changed |= !SharedPointer::ptr_eq(&new_subtree, subtree);
+                            new_subtrees.set(index, new_subtree);
+                        }
+                    }
+                }
+
+                match changed {
+                    false => Merged::Subtree(SharedPointer::clone(node)),
+                    true => Merged::from_node(Node::branch(new_entries, new_subtrees)),
+                }
+            }
+            Node::Collision(entries) => {
+                let kept: Vec<_> = entries.iter().filter(|e| f(e)).cloned().collect();
+
+                match kept.len() == entries.len() {
+                    true => Merged::Subtree(SharedPointer::clone(node)),
+                    false => {
+                        Merged::from_node(Node::from_entries(kept, depth, degree, secondary_hasher))
+                    }
+                }
+            }
+            Node::Flat(entries) => {
+                let kept: Vec<_> = entries.iter().filter(|e| f(e)).cloned().collect();
+
+                match kept.len() == entries.len() {
+                    true => Merged::Subtree(SharedPointer::clone(node)),
+                    false => Merged::from_node(Node::Flat(kept)),
+                }
+            }
+        }
+    }
+
+    /// Returns the node at depth `depth` with the entries given by `f` for the entries of this
+    /// node, leaving out the ones for which it returns `None`.  The entries given by `f` must keep
+    /// the key and hash of the original entry, so the trie keeps its shape except where entries
+    /// were left out.
+    fn filter_map<U, H: BuildHasher, F>(
+        &self,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+        f: &mut F,
+    ) -> Merged<K, U, P, W>
+    where
+        F: FnMut(&EntryWithHash<K, V, P, W>) -> Option<EntryWithHash<K, U, P, W>>,
+    {
+        match self {
+            Node::Branch { entries, subtrees, .. } => {
+                let mut new_entries = SparseArrayUsize::new();
+                let mut new_subtrees = SparseArrayUsize::new();
+
+                for (index, entry) in entries.iter_with_index() {
+                    if let Some(entry) = f(entry) {
+                        new_entries.set(index, entry);
+                    }
+                }
+
+                for (index, subtree) in subtrees.iter_with_index() {
+                    match subtree.filter_map(depth + 1, degree, secondary_hasher, f) {
+                        Merged::Empty => (),
+                        Merged::Entry(entry) => new_entries.set(index, entry),
+                        Merged::Subtree(subtree) => new_subtrees.set(index, subtree),
+                    }
+                }
+
+                Merged::from_node(Node::branch(new_entries, new_subtrees))
+            }
+            Node::Collision(entries) => {
+                let entries = entries.iter().filter_map(&mut *f).collect();
+
+                Merged::from_node(Node::from_entries(entries, depth, degree, secondary_hasher))
+            }
+            Node::Flat(entries) => {
+                Merged::from_node(Node::Flat(entries.iter().filter_map(f).collect()))
+            }
+        }
+    }
+
+    /// Removes the entries under this node for which `f` returns `false`.  Like `remove()`, this
+    /// keeps the invariants in all nodes below this one, but this node can be left with a single
+    /// entry.
+    fn retain_mut<H: BuildHasher, F>(
+        &mut self,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+        f: &mut F,
+    ) where
+        F: FnMut(&mut EntryWithHash<K, V, P, W>) -> bool,
+    {
+        match self {
+            Node::Branch { entries, subtrees, entries_hash, size } => {
+                entries_hash.clear();
+
+                let mut bitmap = entries.bitmap() | subtrees.bitmap();
+
+                while bitmap != 0 {
+                    let index = bitmap.trailing_zeros() as usize;
+
+                    bitmap &= bitmap - 1;
+
+                    if let Some(entry) = entries.get_mut(index) {
+                        if !f(entry) {
+                            entries.remove(index);
+                        }
+
+                        continue;
+                    }
+
+                    let subtree = SharedPointer::make_mut(subtrees.get_mut(index).unwrap());
+
+                    subtree.retain_mut(depth + 1, degree, secondary_hasher, f);
+
+                    if let Some(entry) = subtree.single_entry().cloned() {
+                        subtrees.remove(index);
+                        entries.set(index, entry);
+                    } else if subtree.is_empty() {
+                        subtrees.remove(index);
+                    }
+                }
+
+                *size =
+                    entries.size() + subtrees.iter().map(|subtree| subtree.size()).sum::<usize>();
+            }
+            Node::Collision(entries) => {
+                // Collision nodes are rare, so we simply rebuild them.
+                let kept = entries
+                    .iter()
+                    .cloned()
+                    .filter_map(|mut e| if f(&mut e) { Some(e) } else { None })
+                    .collect();
+
+                *self = Node::from_entries(kept, depth, degree, secondary_hasher);
+            }
+            Node::Flat(entries) => {
+                *entries = core::mem::take(entries)
+                    .into_iter()
+                    .filter_map(|mut e| if f(&mut e) { Some(e) } else { None })
+                    .collect();
+            }
+        }
     }
 }
 
-impl<K, V, P> Bucket<K, V, P>
+impl<K, V, P, W: TrieHash> Clone for Node<K, V, P, W>
 where
     K: Eq + Hash,
     P: SharedPointerKind,
 {
-    fn get<Q: ?Sized>(&self, key: &Q, key_hash: HashValue) -> Option<&EntryWithHash<K, V, P>>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
+    fn clone(&self) -> Node<K, V, P, W> {
+        match self {
+            Node::Branch { entries, subtrees, entries_hash, size } => Node::Branch {
+                entries: entries.clone(),
+                subtrees: subtrees.clone(),
+                entries_hash: entries_hash.clone(),
+                size: *size,
+            },
+            Node::Collision(entries) => Node::Collision(entries.clone()),
+            Node::Flat(entries) => Node::Flat(entries.clone()),
+        }
+    }
+}
+
+impl<K, V, P, W: TrieHash> Slot<'_, K, V, P, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+{
+    /// Returns the number of entries in this slot.
+    fn size(&self) -> usize {
         match self {
-            Bucket::Single(entry) if entry.matches(key, key_hash) => Some(entry.borrow()),
-            Bucket::Single(_) => None,
-            Bucket::Collision(entries) => {
-                entries.iter().find(|e| e.matches(key, key_hash)).map(|e| e.borrow())
+            Slot::Empty => 0,
+            Slot::Entry(_) => 1,
+            Slot::Subtree(subtree) => subtree.size(),
+        }
+    }
+}
+
+impl EntriesHash {
+    fn get_or_compute<F: FnOnce() -> usize>(&self, compute: F) -> usize {
+        // Concurrent computations store the same value, so there is no harm in racing.
+        match self.hash.load(Ordering::Relaxed) {
+            0 => {
+                let hash = compute();
+
+                self.hash.store(hash, Ordering::Relaxed);
+
+                hash
             }
+            hash => hash,
         }
     }
 
-    fn get_mut<Q: ?Sized>(
-        &mut self,
-        key: &Q,
-        key_hash: HashValue,
-    ) -> Option<&mut EntryWithHash<K, V, P>>
+    fn clear(&mut self) {
+        *self.hash.get_mut() = 0;
+    }
+}
+
+impl Clone for EntriesHash {
+    fn clone(&self) -> EntriesHash {
+        EntriesHash { hash: AtomicUsize::new(self.hash.load(Ordering::Relaxed)) }
+    }
+}
+
+impl<K, V, P, W: TrieHash> CollisionEntries<K, V, P, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+{
+    fn new() -> CollisionEntries<K, V, P, W> {
+        CollisionEntries { buckets: RedBlackTreeMap::new_with_ptr_kind(), size: 0 }
+    }
+
+    fn get<Q: ?Sized, H: BuildHasher>(
+        &self,
+        key: &Q,
+        key_hash: W,
+        secondary_hasher: &H,
+    ) -> Option<&EntryWithHash<K, V, P, W>>
+    where
+        Q: Hash + Equivalent<K>,
+    {
+        let bucket = self.buckets.get(&node_utils::secondary_hash(key, secondary_hasher))?;
+
+        bucket.iter().find(|e| e.matches(key, key_hash))
+    }
+
+    /// The entry found is moved to the front of its bucket, so that it can be borrowed mutably.
+    fn get_mut<Q: ?Sized, H: BuildHasher>(
+        &mut self,
+        key: &Q,
+        key_hash: W,
+        secondary_hasher: &H,
+    ) -> Option<&mut EntryWithHash<K, V, P, W>>
+    where
+        Q: Hash + Equivalent<K>,
+    {
+        let bucket = self.buckets.get_mut(&node_utils::secondary_hash(key, secondary_hasher))?;
+        let entry = collision_utils::list_remove_first(bucket, |e| e.matches(key, key_hash))?;
+
+        bucket.push_front_mut(entry);
+        bucket.first_mut()
+    }
+
+    /// Returns the entry that was replaced, or `None` if the key is new.
+    ///
+    /// The entry is put on the front of its bucket to improve performance with high temporal
+    /// locality (since `get()` will try to match according to the list order).
+    fn insert<H: BuildHasher>(
+        &mut self,
+        entry: EntryWithHash<K, V, P, W>,
+        secondary_hasher: &H,
+    ) -> Option<EntryWithHash<K, V, P, W>> {
+        let secondary_hash = node_utils::secondary_hash(entry.key(), secondary_hasher);
+
+        let replaced = match self.buckets.get_mut(&secondary_hash) {
+            Some(bucket) => {
+                let replaced = collision_utils::list_remove_first(bucket, |e| {
+                    e.matches(entry.key(), entry.key_hash)
+                });
+
+                bucket.push_front_mut(entry);
+
+                replaced
+            }
+            None => {
+                let mut bucket = List::new_with_ptr_kind();
+
+                bucket.push_front_mut(entry);
+                self.buckets.insert_mut(secondary_hash, bucket);
+
+                None
+            }
+        };
+
+        if replaced.is_none() {
+            self.size += 1;
+        }
+
+        replaced
+    }
+
+    /// Returns the removed entry, or `None` if the key was not present.
+    fn remove<Q: ?Sized, H: BuildHasher>(
+        &mut self,
+        key: &Q,
+        key_hash: W,
+        secondary_hasher: &H,
+    ) -> Option<EntryWithHash<K, V, P, W>>
+    where
+        Q: Hash + Equivalent<K>,
+    {
+        let secondary_hash = node_utils::secondary_hash(key, secondary_hasher);
+        let (removed, bucket_is_empty) = match self.buckets.get_mut(&secondary_hash) {
+            Some(bucket) => (
+                collision_utils::list_remove_first(bucket, |e| e.matches(key, key_hash)),
+                bucket.is_empty(),
+            ),
+            None => (None, false),
+        };
+
+        if bucket_is_empty {
+            self.buckets.remove_mut(&secondary_hash);
+        }
+
+        if removed.is_some() {
+            self.size -= 1;
+        }
+
+        removed
+    }
+
+    /// Same as `remove()` but removes the entry whose key has raw hash `raw_hash` and matches
+    /// `is_match`.  Since the secondary hash of the key is not known, this looks at every entry.
+    fn remove_matching<F>(
+        &mut self,
+        raw_hash: u64,
+        is_match: &mut F,
+    ) -> Option<EntryWithHash<K, V, P, W>>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let (secondary_hash, rank) = self
+            .iter_ranked()
+            .find(|(_, _, e)| e.matches_raw_hash(raw_hash, is_match))
+            .map(|(secondary_hash, rank, _)| (secondary_hash, rank))?;
+        let bucket = self.buckets.get_mut(&secondary_hash)?;
+        let mut position = 0;
+        let removed = collision_utils::list_remove_first(bucket, |_| {
+            position += 1;
+            position > rank
+        });
+
+        if bucket.is_empty() {
+            self.buckets.remove_mut(&secondary_hash);
+        }
+
+        if removed.is_some() {
+            self.size -= 1;
+        }
+
+        removed
+    }
+
+    fn len(&self) -> usize {
+        self.size
+    }
+
+    fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    fn first(&self) -> Option<&EntryWithHash<K, V, P, W>> {
+        self.buckets.first().and_then(|(_, bucket)| bucket.first())
+    }
+
+    fn iter(&self) -> CollisionIter<'_, K, V, P, W> {
+        self.buckets.values().flatten()
+    }
+
+    /// Iterates over the entries along with the secondary hash of their key and their position in
+    /// their bucket.
+    fn iter_ranked(&self) -> impl Iterator<Item = (HashValue, usize, &EntryWithHash<K, V, P, W>)> {
+        self.buckets.iter().flat_map(|(secondary_hash, bucket)| {
+            bucket.iter().enumerate().map(move |(rank, entry)| (*secondary_hash, rank, entry))
+        })
+    }
+
+    /// Returns `true` if both have the same entries.  Both must come from maps that share their
+    /// hashers, so that the keys have the same secondary hashes.
+    fn eq_entries<PO: SharedPointerKind>(&self, other: &CollisionEntries<K, V, PO, W>) -> bool
+    where
+        V: PartialEq,
+    {
+        self.size == other.size
+            && self.buckets.size() == other.buckets.size()
+            && self.buckets.iter().zip(other.buckets.iter()).all(
+                |((hash, bucket), (other_hash, other_bucket))| {
+                    hash == other_hash
+                        && bucket.len() == other_bucket.len()
+                        && bucket.iter().all(|a| other_bucket.iter().any(|b| a.eq_entry(b)))
+                },
+            )
+    }
+}
+
+impl<K, V, P, W: TrieHash> Clone for CollisionEntries<K, V, P, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> CollisionEntries<K, V, P, W> {
+        CollisionEntries { buckets: self.buckets.clone(), size: self.size }
+    }
+}
+
+mod collision_utils {
+    use super::*;
+
+    pub fn list_remove_first<T: Clone, P: SharedPointerKind, F: FnMut(&T) -> bool>(
+        list: &mut List<T, P>,
+        mut predicate: F,
+    ) -> Option<T> {
+        let mut before_needle: Vec<T> = Vec::with_capacity(list.len());
+        let remaining: &mut List<T, P> = list;
+        let mut removed = None;
+
+        while !remaining.is_empty() {
+            let e: T = remaining.first().unwrap().clone();
+
+            remaining.drop_first_mut();
+
+            if predicate(&e) {
+                removed = Some(e);
+                break;
+            }
+
+            before_needle.push(e);
+        }
+
+        let new_entries = remaining;
+
+        while let Some(e) = before_needle.pop() {
+            new_entries.push_front_mut(e);
+        }
+
+        removed
+    }
+}
+
+impl<K, V, P, W: TrieHash> EntryWithHash<K, V, P, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+{
+    fn new<H: BuildHasher>(key: K, value: V, hash_builder: &H) -> EntryWithHash<K, V, P, W> {
+        let key_hash = node_utils::hash(&key, hash_builder);
+
+        EntryWithHash { entry: SharedPointer::new(KeyValue::new(key, value)), key_hash }
+    }
+
+    fn key(&self) -> &K {
+        &self.entry.key
+    }
+
+    fn value(&self) -> &V {
+        &self.entry.value
+    }
+
+    fn eq_entry<PO: SharedPointerKind>(&self, other: &EntryWithHash<K, V, PO, W>) -> bool
+    where
+        V: PartialEq,
+    {
+        self.key_hash == other.key_hash
+            && (SharedPointer::ptr_eq(&self.entry, &other.entry)
+                || (self.key() == other.key() && self.value() == other.value()))
+    }
+
+    #[inline]
+    fn matches<Q: ?Sized>(&self, key: &Q, key_hash: W) -> bool
+    where
+        Q: Hash + Equivalent<K>,
+    {
+        self.key_hash == key_hash && key.equivalent(self.key())
+    }
+
+    #[inline]
+    fn matches_raw_hash<F>(&self, raw_hash: u64, is_match: &mut F) -> bool
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.key_hash.matches_raw_hash(raw_hash) && is_match(self.key())
+    }
+}
+
+impl<K, V, P, W: TrieHash> EntryWithHash<K, V, P, W>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    P: SharedPointerKind,
+{
+    fn value_mut(&mut self) -> &mut V {
+        &mut SharedPointer::make_mut(&mut self.entry).value
+    }
+
+    fn key_and_value_mut(&mut self) -> (&K, &mut V) {
+        let entry = SharedPointer::make_mut(&mut self.entry);
+
+        (&entry.key, &mut entry.value)
+    }
+}
+
+impl<K, V, P, W: TrieHash> Clone for EntryWithHash<K, V, P, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> EntryWithHash<K, V, P, W> {
+        EntryWithHash { entry: SharedPointer::clone(&self.entry), key_hash: self.key_hash }
+    }
+}
+
+/// Combines two entries with the same key, the first from the left trie and the second from the
+/// right trie.  The resulting entry must keep the hash of the first entry.
+type CombineEntries<'f, K, V, P, W> = dyn FnMut(&EntryWithHash<K, V, P, W>, &EntryWithHash<K, V, P, W>) -> EntryWithHash<K, V, P, W>
+    + 'f;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MergeKind {
+    Union,
+    Intersection,
+    Difference,
+    SymmetricDifference,
+}
+
+/// The result of merging the content of a position of two branches, or of filtering a node.  A
+/// subtree cannot have a single entry under it, so in that case the entry is returned, to be
+/// stored inline in the parent branch.
+enum Merged<K, V, P, W>
+where
+    P: SharedPointerKind,
+{
+    Empty,
+    Entry(EntryWithHash<K, V, P, W>),
+    Subtree(SharedPointer<Node<K, V, P, W>, P>),
+}
+
+impl<K, V, P, W: TrieHash> Merged<K, V, P, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+{
+    fn from_slot(slot: &Slot<'_, K, V, P, W>) -> Merged<K, V, P, W> {
+        match slot {
+            Slot::Empty => Merged::Empty,
+            Slot::Entry(entry) => Merged::Entry(EntryWithHash::clone(entry)),
+            Slot::Subtree(subtree) => Merged::Subtree(SharedPointer::clone(subtree)),
+        }
+    }
+
+    fn from_node(node: Node<K, V, P, W>) -> Merged<K, V, P, W> {
+        match node.single_entry() {
+            Some(entry) => Merged::Entry(entry.clone()),
+            None if node.is_empty() => Merged::Empty,
+            None => Merged::Subtree(SharedPointer::new(node)),
+        }
+    }
+
+    /// Makes the root of a trie.  The root can be left as a small branch, which the map then
+    /// makes flat.
+    fn into_root(self) -> SharedPointer<Node<K, V, P, W>, P> {
+        match self {
+            Merged::Empty => SharedPointer::new(Node::Flat(Vec::new())),
+            Merged::Entry(entry) => SharedPointer::new(Node::Flat(vec![entry])),
+            Merged::Subtree(subtree) => subtree,
+        }
+    }
+}
+
+/// Builds a trie from a batch of entries, top-down: the entries of each node are grouped by their
+/// index in the node, keeping their insertion order, and each group becomes an entry or a subtree.
+/// Each node is thus created once, with its final content, instead of being updated for every
+/// entry.
+struct Build<'h, K, V, P, H, W>
+where
+    P: SharedPointerKind,
+{
+    degree: u8,
+    secondary_hasher: &'h H,
+    /// The entries in insertion order, taken out as they are placed in the trie.
+    entries: Vec<Option<EntryWithHash<K, V, P, W>>>,
+    /// The hashes of `entries`, kept apart since the partitioning goes over them many times.
+    hashes: Vec<W>,
+    /// Buffer for the partitioning.
+    scratch: Vec<usize>,
+    /// Buffer for the groups of `insert()` that include an entry of the trie.
+    merge_group: Vec<usize>,
+    /// Number of entries that were dropped because a later entry had the same key.
+    replaced: usize,
+}
+
+impl<'h, K, V, P, H, W: TrieHash> Build<'h, K, V, P, H, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+    H: BuildHasher,
+{
+    fn new(
+        degree: u8,
+        secondary_hasher: &'h H,
+        entries: Vec<EntryWithHash<K, V, P, W>>,
+    ) -> Build<'h, K, V, P, H, W> {
+        Build {
+            degree,
+            secondary_hasher,
+            hashes: entries.iter().map(|e| e.key_hash).collect(),
+            scratch: Vec::with_capacity(entries.len()),
+            merge_group: Vec::new(),
+            entries: entries.into_iter().map(Some).collect(),
+            replaced: 0,
+        }
+    }
+
+    fn hash(&self, i: usize) -> W {
+        self.hashes[i]
+    }
+
+    /// Adds an entry after the ones given on creation, and returns its index.
+    fn push(&mut self, entry: EntryWithHash<K, V, P, W>) -> usize {
+        self.hashes.push(entry.key_hash);
+        self.entries.push(Some(entry));
+
+        self.entries.len() - 1
+    }
+
+    fn take(&mut self, i: usize) -> EntryWithHash<K, V, P, W> {
+        self.entries[i].take().unwrap()
+    }
+
+    /// Builds the node at depth `depth` with the entries of indices `group`, which are in
+    /// insertion order.
+    fn build(&mut self, group: &mut [usize], depth: usize) -> Merged<K, V, P, W> {
+        let degree = self.degree;
+
+        if group.len() == 1 {
+            return Merged::Entry(self.take(group[0]));
+        }
+
+        let hash = self.hash(group[0]);
+
+        if group.iter().all(|i| self.hash(*i) == hash) {
+            return self.build_collision(group, depth, hash);
+        }
+
+        self.partition(group, depth);
+
+        let index = |build: &Self, i: usize| {
+            node_utils::index_from_hash(build.hash(i), depth, degree).unwrap()
+        };
+        let run_end = |build: &Self, group: &[usize], start: usize| {
+            let slot_index = index(build, group[start]);
+
+            start + group[start..].iter().take_while(|i| index(build, **i) == slot_index).count()
+        };
+
+        let mut run_count = 0;
+        let mut single_count = 0;
+        let mut start = 0;
+
+        while start < group.len() {
+            let end = run_end(self, group, start);
+
+            run_count += 1;
+            single_count += usize::from(end - start == 1);
+            start = end;
+        }
+
+        let mut node_entries = SparseArrayUsize::with_capacity(single_count);
+        let mut node_subtrees = SparseArrayUsize::with_capacity(run_count - single_count);
+        let mut start = 0;
+
+        while start < group.len() {
+            let end = run_end(self, group, start);
+            let slot_index = index(self, group[start]);
+
+            match self.build(&mut group[start..end], depth + 1) {
+                Merged::Empty => (),
+                Merged::Entry(entry) => node_entries.set(slot_index, entry),
+                Merged::Subtree(subtree) => node_subtrees.set(slot_index, subtree),
+            }
+
+            start = end;
+        }
+
+        Merged::Subtree(SharedPointer::new(Node::branch(node_entries, node_subtrees)))
+    }
+
+    /// Sorts `group` by the index of the entries at depth `depth`, keeping the insertion order of
+    /// the entries with the same index.
+    fn partition(&mut self, group: &mut [usize], depth: usize) {
+        let degree = self.degree;
+        let index = |hash: W| node_utils::index_from_hash(hash, depth, degree).unwrap();
+
+        // Small groups are sorted directly, since counting would go over all the slots.
+        if group.len() <= usize::from(degree) {
+            let hashes = &self.hashes;
+
+            group.sort_by_key(|i| index(hashes[*i]));
+
+            return;
+        }
+
+        let mut offsets = [0_usize; DEFAULT_DEGREE as usize];
+
+        for i in group.iter() {
+            offsets[index(self.hash(*i))] += 1;
+        }
+
+        let mut offset = 0;
+
+        for count in &mut offsets[..usize::from(degree)] {
+            offset += *count;
+            *count = offset - *count;
+        }
+
+        self.scratch.clear();
+        self.scratch.extend_from_slice(group);
+
+        for i in &self.scratch {
+            let slot_index = index(self.hashes[*i]);
+
+            group[offsets[slot_index]] = *i;
+            offsets[slot_index] += 1;
+        }
+    }
+
+    /// Builds the node at depth `depth` with the entries of indices `group`, which all have hash
+    /// `hash`.  This is a collision node at the maximum depth, below a chain of branches.
+    fn build_collision(&mut self, group: &[usize], depth: usize, hash: W) -> Merged<K, V, P, W> {
+        let degree = self.degree;
+        let mut max_depth = depth;
+
+        while !node_utils::is_hash_exhausted::<W>(max_depth, degree) {
+            max_depth += 1;
+        }
+
+        let entries = group.iter().map(|i| self.take(*i)).collect();
+        let node = Node::from_entries(entries, max_depth, degree, self.secondary_hasher);
+
+        self.replaced += group.len() - node.size();
+
+        if let Some(entry) = node.single_entry() {
+            return Merged::Entry(entry.clone());
+        }
+
+        let mut subtree = SharedPointer::new(node);
+
+        for d in (depth..max_depth).rev() {
+            let mut subtrees = SparseArrayUsize::with_capacity(1);
+
+            subtrees.set(node_utils::index_from_hash(hash, d, degree).unwrap(), subtree);
+            subtree = SharedPointer::new(Node::branch(SparseArrayUsize::new(), subtrees));
+        }
+
+        Merged::Subtree(subtree)
+    }
+
+    /// Inserts the entries of indices `group`, which are in insertion order, in `node`, which is
+    /// at depth `depth`.  Unlike `build()`, this keeps the existing trie: each node on the paths to
+    /// the new entries is made unique once for the whole batch and then changed in place, and the
+    /// subtrees without new entries stay shared.  Returns the number of keys that were added.
+    fn insert(&mut self, node: &mut Node<K, V, P, W>, group: &mut [usize], depth: usize) -> usize {
+        let degree = self.degree;
+
+        // Most groups deep in the trie have a single entry, which is quicker to insert directly.
+        if let [i] = group {
+            let entry = self.take(*i);
+
+            return usize::from(node.insert(entry, depth, degree, self.secondary_hasher).is_none());
+        }
+
+        match node {
+            Node::Branch { entries, subtrees, entries_hash, size } => {
+                entries_hash.clear();
+
+                self.partition(group, depth);
+
+                let index = |build: &Self, i: usize| {
+                    node_utils::index_from_hash(build.hash(i), depth, degree).unwrap()
+                };
+                let mut added = 0;
+                let mut start = 0;
+
+                while start < group.len() {
+                    let slot_index = index(self, group[start]);
+                    let end = start
+                        + group[start..]
+                            .iter()
+                            .take_while(|i| index(self, **i) == slot_index)
+                            .count();
+                    let run = &mut group[start..end];
+
+                    if let Some(subtree) = subtrees.get_mut(slot_index) {
+                        added += self.insert(SharedPointer::make_mut(subtree), run, depth + 1);
+                    } else {
+                        // The entry already in this slot goes first, so that a new entry with the
+                        // same key replaces it.
+                        let (merged, existing_count) = match entries.remove(slot_index) {
+                            Some(existing_entry) => {
+                                // `build()` never calls `insert()`, so the buffer is free.
+                                let mut merge_group = core::mem::take(&mut self.merge_group);
+
+                                merge_group.clear();
+                                merge_group.push(self.push(existing_entry));
+                                merge_group.extend_from_slice(run);
+
+                                let merged = self.build(&mut merge_group, depth + 1);
+
+                                self.merge_group = merge_group;
+
+                                (merged, 1)
+                            }
+                            None => (self.build(run, depth + 1), 0),
+                        };
+
+                        match merged {
+                            Merged::Empty => unreachable!("the run has at least one entry"),
+                            Merged::Entry(entry) => {
+                                entries.set(slot_index, entry);
+                                added += 1 - existing_count;
+                            }
+                            Merged::Subtree(subtree) => {
+                                added += subtree.size() - existing_count;
+                                subtrees.set(slot_index, subtree);
+                            }
+                        }
+                    }
+
+                    start = end;
+                }
+
+                *size += added;
+
+                added
+            }
+            Node::Collision(entries) => {
+                let mut added = 0;
+
+                for i in group.iter() {
+                    if entries.insert(self.take(*i), self.secondary_hasher).is_none() {
+                        added += 1;
+                    }
+                }
+
+                added
+            }
+            Node::Flat(_) => unreachable!("only the root can be flat"),
+        }
+    }
+}
+
+/// Walks two tries with the same layout simultaneously and builds the trie that results from
+/// combining them.  Subtrees shared by both tries are handled without being visited, so the work
+/// done is proportional to the parts of the tries that differ.
+///
+/// To avoid visiting shared subtrees, the meaning of `count` depends on the kind of merge:
+///
+///   * `Union`: the number of entries of the right trie whose key is not in the left trie.
+///   * `Intersection`: the number of entries of the left trie whose key is not in the right trie.
+///   * `Difference` and `SymmetricDifference`: the number of entries of the resulting trie.
+struct Merge<'f, K, V, P, H, W>
+where
+    P: SharedPointerKind,
+{
+    kind: MergeKind,
+    degree: u8,
+    secondary_hasher: &'f H,
+    combine: &'f mut CombineEntries<'f, K, V, P, W>,
+    count: usize,
+}
+
+impl<K, V, P, H: BuildHasher, W: TrieHash> Merge<'_, K, V, P, H, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+{
+    /// Entries shared by both tries are kept as they are.
+    fn combine_entries(
+        &mut self,
+        left: &EntryWithHash<K, V, P, W>,
+        right: &EntryWithHash<K, V, P, W>,
+    ) -> EntryWithHash<K, V, P, W> {
+        if SharedPointer::ptr_eq(&left.entry, &right.entry) {
+            left.clone()
+        } else {
+            (self.combine)(left, right)
+        }
+    }
+
+    fn merge(
+        &mut self,
+        left: &SharedPointer<Node<K, V, P, W>, P>,
+        right: &SharedPointer<Node<K, V, P, W>, P>,
+        depth: usize,
+    ) -> Merged<K, V, P, W> {
+        if SharedPointer::ptr_eq(left, right) {
+            return match self.kind {
+                MergeKind::Union | MergeKind::Intersection => {
+                    Merged::Subtree(SharedPointer::clone(left))
+                }
+                MergeKind::Difference | MergeKind::SymmetricDifference => Merged::Empty,
+            };
+        }
+
+        match (&**left, &**right) {
+            (
+                Node::Branch { entries: left_entries, subtrees: left_subtrees, .. },
+                Node::Branch { entries: right_entries, subtrees: right_subtrees, .. },
+            ) => {
+                let mut entries = SparseArrayUsize::new();
+                let mut subtrees = SparseArrayUsize::new();
+                let mut bitmap = left_entries.bitmap()
+                    | left_subtrees.bitmap()
+                    | right_entries.bitmap()
+                    | right_subtrees.bitmap();
+
+                while bitmap != 0 {
+                    let index = bitmap.trailing_zeros() as usize;
+
+                    bitmap &= bitmap - 1;
+
+                    match self.merge_slots(left.slot(index), right.slot(index), depth + 1) {
+                        Merged::Empty => (),
+                        Merged::Entry(entry) => entries.set(index, entry),
+                        Merged::Subtree(subtree) => subtrees.set(index, subtree),
+                    }
+                }
+
+                Self::branch(entries, subtrees, left, right)
+            }
+            (Node::Collision(entries), _) => {
+                self.merge_left_entries(entries.iter().collect(), right, depth)
+            }
+            (Node::Flat(entries), _) => {
+                self.merge_left_entries(entries.iter().collect(), right, depth)
+            }
+            (_, Node::Collision(entries)) => {
+                self.merge_right_entries(entries.iter().collect(), left, depth)
+            }
+            (_, Node::Flat(entries)) => {
+                self.merge_right_entries(entries.iter().collect(), left, depth)
+            }
+        }
+    }
+
+    /// Merges the content of the same position of two branches.  The depth is the depth of the
+    /// subtrees in the slots.
+    fn merge_slots(
+        &mut self,
+        left: Slot<'_, K, V, P, W>,
+        right: Slot<'_, K, V, P, W>,
+        depth: usize,
+    ) -> Merged<K, V, P, W> {
+        match (left, right) {
+            (Slot::Subtree(l), Slot::Subtree(r)) => self.merge(l, r, depth),
+            (Slot::Entry(l), Slot::Entry(r)) => self.merge_entries(l, r, depth),
+            (Slot::Entry(l), Slot::Subtree(r)) => self.merge_left_entries(vec![l], r, depth),
+            (Slot::Subtree(l), Slot::Entry(r)) => self.merge_right_entries(vec![r], l, depth),
+            (left, Slot::Empty) => self.only_left(&left),
+            (Slot::Empty, right) => self.only_right(&right),
+        }
+    }
+
+    fn merge_entries(
+        &mut self,
+        left: &EntryWithHash<K, V, P, W>,
+        right: &EntryWithHash<K, V, P, W>,
+        depth: usize,
+    ) -> Merged<K, V, P, W> {
+        let degree = self.degree;
+        let secondary_hasher = self.secondary_hasher;
+        let pair = || {
+            Merged::from_node(Node::from_entries(
+                vec![left.clone(), right.clone()],
+                depth,
+                degree,
+                secondary_hasher,
+            ))
+        };
+
+        if left.matches(right.key(), right.key_hash) {
+            return match self.kind {
+                MergeKind::Union | MergeKind::Intersection => {
+                    Merged::Entry(self.combine_entries(left, right))
+                }
+                MergeKind::Difference | MergeKind::SymmetricDifference => Merged::Empty,
+            };
+        }
+
+        match self.kind {
+            MergeKind::Union => {
+                self.count += 1;
+                pair()
+            }
+            MergeKind::Intersection => {
+                self.count += 1;
+                Merged::Empty
+            }
+            MergeKind::Difference => {
+                self.count += 1;
+                Merged::Entry(left.clone())
+            }
+            MergeKind::SymmetricDifference => {
+                self.count += 2;
+                pair()
+            }
+        }
+    }
+
+    fn only_left(&mut self, slot: &Slot<'_, K, V, P, W>) -> Merged<K, V, P, W> {
+        match self.kind {
+            MergeKind::Union => Merged::from_slot(slot),
+            MergeKind::Intersection => {
+                self.count += slot.size();
+                Merged::Empty
+            }
+            MergeKind::Difference | MergeKind::SymmetricDifference => {
+                self.count += slot.size();
+                Merged::from_slot(slot)
+            }
+        }
+    }
+
+    fn only_right(&mut self, slot: &Slot<'_, K, V, P, W>) -> Merged<K, V, P, W> {
+        match self.kind {
+            MergeKind::Union | MergeKind::SymmetricDifference => {
+                self.count += slot.size();
+                Merged::from_slot(slot)
+            }
+            MergeKind::Intersection | MergeKind::Difference => Merged::Empty,
+        }
+    }
+
+    /// Creates a branch with the given entries and subtrees.  If `left` or `right` already has
+    /// exactly these entries and subtrees we reuse it, to keep maximum sharing.
+    fn branch(
+        entries: SparseArrayUsize<EntryWithHash<K, V, P, W>>,
+        subtrees: SparseArrayUsize<SharedPointer<Node<K, V, P, W>, P>>,
+        left: &SharedPointer<Node<K, V, P, W>, P>,
+        right: &SharedPointer<Node<K, V, P, W>, P>,
+    ) -> Merged<K, V, P, W> {
+        for node in [left, right].iter().copied() {
+            if let Node::Branch { entries: node_entries, subtrees: node_subtrees, .. } = &**node {
+                if node_entries.bitmap() == entries.bitmap()
+                    && node_subtrees.bitmap() == subtrees.bitmap()
+                    && node_entries
+                        .iter()
+                        .zip(entries.iter())
+                        .all(|(a, b)| SharedPointer::ptr_eq(&a.entry, &b.entry))
+                    && node_subtrees
+                        .iter()
+                        .zip(subtrees.iter())
+                        .all(|(a, b)| SharedPointer::ptr_eq(a, b))
+                {
+                    return Merged::Subtree(SharedPointer::clone(node));
+                }
+            }
+        }
+
+        Merged::from_node(Node::branch(entries, subtrees))
+    }
+
+    /// Merges a few entries of the left trie, either an entry stored inline in a branch or the
+    /// entries of a collision node or of a flat root, with a node of the right trie.  Since there are very few
+    /// entries we just look them up in the right trie.
+    fn merge_left_entries(
+        &mut self,
+        left_entries: Vec<&EntryWithHash<K, V, P, W>>,
+        right: &SharedPointer<Node<K, V, P, W>, P>,
+        depth: usize,
+    ) -> Merged<K, V, P, W> {
+        let degree = self.degree;
+        let secondary_hasher = self.secondary_hasher;
+
+        match self.kind {
+            MergeKind::Union => {
+                let mut node = Node::clone(right);
+                let mut common = 0;
+
+                for l in left_entries {
+                    let entry =
+                        match right.get(l.key(), l.key_hash, depth, degree, secondary_hasher) {
+                            Some(r) => {
+                                common += 1;
+                                self.combine_entries(l, r)
+                            }
+                            None => l.clone(),
+                        };
+
+                    node.insert(entry, depth, degree, secondary_hasher);
+                }
+
+                self.count += right.size() - common;
+
+                Merged::from_node(node)
+            }
+            MergeKind::Intersection | MergeKind::Difference => {
+                let mut entries = Vec::new();
+
+                for l in left_entries {
+                    match (
+                        self.kind,
+                        right.get(l.key(), l.key_hash, depth, degree, secondary_hasher),
+                    ) {
+                        (MergeKind::Intersection, Some(r)) => {
+                            entries.push(self.combine_entries(l, r));
+                        }
+                        (MergeKind::Intersection, None) => self.count += 1,
+                        (_, Some(_)) => (),
+                        (_, None) => entries.push(l.clone()),
+                    }
+                }
+
+                if self.kind == MergeKind::Difference {
+                    self.count += entries.len();
+                }
+
+                Merged::from_node(Node::from_entries(entries, depth, degree, secondary_hasher))
+            }
+            MergeKind::SymmetricDifference => {
+                self.symmetric_difference_entries(left_entries, right, depth)
+            }
+        }
+    }
+
+    /// Same as `merge_left_entries()` but with the few entries on the right trie.
+    fn merge_right_entries(
+        &mut self,
+        right_entries: Vec<&EntryWithHash<K, V, P, W>>,
+        left: &SharedPointer<Node<K, V, P, W>, P>,
+        depth: usize,
+    ) -> Merged<K, V, P, W> {
+        let degree = self.degree;
+        let secondary_hasher = self.secondary_hasher;
+
+        match self.kind {
+            MergeKind::Union => {
+                let mut node: Option<Node<K, V, P, W>> = None;
+
+                for r in right_entries {
+                    let entry = match left.get(r.key(), r.key_hash, depth, degree, secondary_hasher)
+                    {
+                        Some(l) if SharedPointer::ptr_eq(&l.entry, &r.entry) => continue,
+                        Some(l) => self.combine_entries(l, r),
+                        None => {
+                            self.count += 1;
+                            r.clone()
+                        }
+                    };
+
+                    node.get_or_insert_with(|| Node::clone(left)).insert(
+                        entry,
+                        depth,
+                        degree,
+                        secondary_hasher,
+                    );
+                }
+
+                node.map_or_else(|| Merged::Subtree(SharedPointer::clone(left)), Merged::from_node)
+            }
+            MergeKind::Intersection => {
+                let mut entries = Vec::new();
+
+                for r in right_entries {
+                    if let Some(l) = left.get(r.key(), r.key_hash, depth, degree, secondary_hasher)
+                    {
+                        entries.push(self.combine_entries(l, r));
+                    }
+                }
+
+                self.count += left.size() - entries.len();
+
+                Merged::from_node(Node::from_entries(entries, depth, degree, secondary_hasher))
+            }
+            MergeKind::Difference => {
+                let mut node: Option<Node<K, V, P, W>> = None;
+                let mut removed = 0;
+
+                for r in right_entries {
+                    if left.get(r.key(), r.key_hash, depth, degree, secondary_hasher).is_some() {
+                        node.get_or_insert_with(|| Node::clone(left)).remove(
+                            r.key(),
+                            r.key_hash,
+                            depth,
+                            degree,
+                            secondary_hasher,
+                        );
+                        removed += 1;
+                    }
+                }
+
+                self.count += left.size() - removed;
+
+                node.map_or_else(|| Merged::Subtree(SharedPointer::clone(left)), Merged::from_node)
+            }
+            MergeKind::SymmetricDifference => {
+                self.symmetric_difference_entries(right_entries, left, depth)
+            }
+        }
+    }
+
+    /// The symmetric difference of a few entries with some node.  This operation is symmetric so
+    /// we do not care which side the entries are on.
+    fn symmetric_difference_entries(
+        &mut self,
+        entries: Vec<&EntryWithHash<K, V, P, W>>,
+        other: &SharedPointer<Node<K, V, P, W>, P>,
+        depth: usize,
+    ) -> Merged<K, V, P, W> {
+        let degree = self.degree;
+        let secondary_hasher = self.secondary_hasher;
+        let mut node = Node::clone(other);
+        let mut size = other.size();
+
+        for entry in entries {
+            if other.get(entry.key(), entry.key_hash, depth, degree, secondary_hasher).is_some() {
+                node.remove(entry.key(), entry.key_hash, depth, degree, secondary_hasher);
+                size -= 1;
+            } else {
+                node.insert(entry.clone(), depth, degree, secondary_hasher);
+                size += 1;
+            }
+        }
+
+        self.count += size;
+
+        Merged::from_node(node)
+    }
+}
+
+impl<K, V> HashTrieMap<K, V>
+where
+    K: Eq + Hash,
+{
+    #[must_use]
+    pub fn new() -> HashTrieMap<K, V> {
+        HashTrieMap::new_with_degree(DEFAULT_DEGREE)
+    }
+
+    #[must_use]
+    pub fn new_with_degree(degree: u8) -> HashTrieMap<K, V> {
+        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(DefaultBuildHasher::default(), degree)
+    }
+
+    /// Returns a builder for a new map.  See [`HashTrieMapBuilder`].
+    #[must_use]
+    pub fn builder() -> HashTrieMapBuilder<K, V> {
+        HashTrieMap::new().into_builder()
+    }
+}
+
+impl<K, V> HashTrieMapSync<K, V>
+where
+    K: Eq + Hash,
+{
+    #[must_use]
+    pub fn new_sync() -> HashTrieMapSync<K, V> {
+        HashTrieMap::new_sync_with_degree(DEFAULT_DEGREE)
+    }
+
+    #[must_use]
+    pub fn new_sync_with_degree(degree: u8) -> HashTrieMapSync<K, V> {
+        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(DefaultBuildHasher::default(), degree)
+    }
+}
+
+impl<K, V, P, H: BuildHasher, W: TrieHash> HashTrieMap<K, V, P, H, W>
+where
+    K: Eq + Hash,
+    H: Clone,
+    P: SharedPointerKind,
+{
+    #[must_use]
+    pub fn new_with_hasher_and_ptr_kind(hasher_builder: H) -> HashTrieMap<K, V, P, H, W> {
+        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher_builder, DEFAULT_DEGREE)
+    }
+
+    #[must_use]
+    pub fn new_with_hasher_and_degree_and_ptr_kind(
+        hasher_builder: H,
+        degree: u8,
+    ) -> HashTrieMap<K, V, P, H, W> {
+        assert!(degree.is_power_of_two(), "degree must be a power of two");
+        assert!(degree <= DEFAULT_DEGREE, "degree is too big");
+
+        HashTrieMap::new_with_shared_hashers(
+            SharedPointer::new(Hashers::new(hasher_builder)),
+            degree,
+        )
+    }
+
+    /// Creates an empty map that shares the hashers of other maps, so that it has the same layout
+    /// as them.
+    fn new_with_shared_hashers(
+        hashers: SharedPointer<Hashers<H>, P>,
+        degree: u8,
+    ) -> HashTrieMap<K, V, P, H, W> {
+        HashTrieMap { root: SharedPointer::new(Node::Flat(Vec::new())), size: 0, degree, hashers }
+    }
+
+    #[must_use]
+    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        self.get_equiv(key)
+    }
+
+    /// Same as [`get()`](HashTrieMap::get()) but the key can be of any type
+    /// [equivalent](Equivalent) to `K`.
+    #[must_use]
+    pub fn get_equiv<Q: ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Hash + Equivalent<K>,
+    {
+        let key_hash = node_utils::hash(key, &self.hashers.hasher_builder);
+
+        self.root
+            .get(key, key_hash, 0, self.degree, &self.hashers.secondary_hasher)
+            .map(|e| e.value())
+    }
+
+    #[must_use]
+    pub fn insert(&self, key: K, value: V) -> HashTrieMap<K, V, P, H, W> {
+        let mut new_map = self.clone();
+
+        new_map.insert_mut(key, value);
+
+        new_map
+    }
+
+    pub fn insert_mut(&mut self, key: K, value: V) {
+        let entry = EntryWithHash::new(key, value, &self.hashers.hasher_builder);
+
+        self.insert_entry_mut(entry);
+    }
+
+    /// Returns the entry that was replaced, if any.
+    fn insert_entry_mut(
+        &mut self,
+        entry: EntryWithHash<K, V, P, W>,
+    ) -> Option<EntryWithHash<K, V, P, W>> {
+        let replaced = SharedPointer::make_mut(&mut self.root).insert(
+            entry,
+            0,
+            self.degree,
+            &self.hashers.secondary_hasher,
+        );
+
+        if replaced.is_none() {
+            self.size += 1;
+        }
+
+        replaced
+    }
+
+    #[must_use]
+    pub fn remove<Q: ?Sized>(&self, key: &Q) -> HashTrieMap<K, V, P, H, W>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let mut new_map = self.clone();
+
+        if new_map.remove_mut(key) {
+            new_map
+        } else {
+            // We want to keep maximum sharing so in case of no change we just `clone()` ourselves.
+            self.clone()
+        }
+    }
+
+    pub fn remove_mut<Q: ?Sized>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        self.remove_equiv_mut(key)
+    }
+
+    /// Same as [`remove()`](HashTrieMap::remove()) but the key can be of any type
+    /// [equivalent](Equivalent) to `K`.
+    #[must_use]
+    pub fn remove_equiv<Q: ?Sized>(&self, key: &Q) -> HashTrieMap<K, V, P, H, W>
+    where
+        Q: Hash + Equivalent<K>,
+    {
+        let mut new_map = self.clone();
+
+        if new_map.remove_equiv_mut(key) {
+            new_map
+        } else {
+            // We want to keep maximum sharing so in case of no change we just `clone()` ourselves.
+            self.clone()
+        }
+    }
+
+    /// Same as [`remove_mut()`](HashTrieMap::remove_mut()) but the key can be of any type
+    /// [equivalent](Equivalent) to `K`.
+    pub fn remove_equiv_mut<Q: ?Sized>(&mut self, key: &Q) -> bool
+    where
+        Q: Hash + Equivalent<K>,
+    {
+        let key_hash = node_utils::hash(key, &self.hashers.hasher_builder);
+
+        self.remove_hashed_mut(key, key_hash).is_some()
+    }
+
+    /// Returns the removed entry, if any.
+    fn remove_hashed_mut<Q: ?Sized>(
+        &mut self,
+        key: &Q,
+        key_hash: W,
+    ) -> Option<EntryWithHash<K, V, P, W>>
+    where
+        Q: Hash + Equivalent<K>,
+    {
+        let removed = SharedPointer::make_mut(&mut self.root).remove(
+            key,
+            key_hash,
+            0,
+            self.degree,
+            &self.hashers.secondary_hasher,
+        );
+
+        // Note that unfortunately, even if nothing was removed, we still might have cloned some
+        // part of the tree unnecessarily.
+
+        if removed.is_some() {
+            self.size -= 1;
+            self.flatten_root_if_small();
+        }
+
+        removed
+    }
+
+    /// Makes the root flat if the map is small enough, as required by the invariants of the trie.
+    fn flatten_root_if_small(&mut self) {
+        if self.size <= FLAT_MAX_SIZE && !matches!(*self.root, Node::Flat(_)) {
+            let mut entries = Vec::with_capacity(self.size);
+
+            self.root.collect_entries(&mut entries);
+            self.root = SharedPointer::new(Node::Flat(entries));
+        }
+    }
+
+    #[must_use]
+    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Same as [`contains_key()`](HashTrieMap::contains_key()) but the key can be of any type
+    /// [equivalent](Equivalent) to `K`.
+    #[must_use]
+    pub fn contains_key_equiv<Q: ?Sized>(&self, key: &Q) -> bool
+    where
+        Q: Hash + Equivalent<K>,
+    {
+        self.get_equiv(key).is_some()
+    }
+
+    /// Returns the raw hash of `key`, i.e. the output of the hasher of this map for `key`.  This
+    /// can be given to [`get_with_hash()`](HashTrieMap::get_with_hash()),
+    /// [`insert_with_hash()`](HashTrieMap::insert_with_hash()) and
+    /// [`remove_with_hash()`](HashTrieMap::remove_with_hash()) to look up the same key several
+    /// times, or in several maps with the same hasher, while hashing it only once.
+    ///
+    /// The hash depends on the hasher of the map, so it is only valid for maps whose hasher builds
+    /// the same hashers (e.g. clones of the same map).  It does not depend on the width of the
+    /// hash of the map (see [`TrieHash`]), so it is valid for maps of any width.  Maps with `u128`
+    /// hashes hash the key once more on insertion, to get the high half of their hash.
+    #[must_use]
+    pub fn hash_key<Q: ?Sized + Hash>(&self, key: &Q) -> u64 {
+        node_utils::raw_hash(key, &self.hashers.hasher_builder)
+    }
+
+    /// Returns the key and value of the entry with hash `hash` for which `is_match(key)` returns
+    /// `true`, if any.  The hash must be the one given by [`hash_key()`](HashTrieMap::hash_key())
+    /// for the key looked up.
+    ///
+    /// If many keys share the same raw hash this has to call `is_match` on all of them, so the
+    /// lookup can be slower than [`get()`](HashTrieMap::get()) in that case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = ht_map!["a" => 1, "b" => 2];
+    /// let other = map.insert("a", 10);
+    /// let hash = map.hash_key("a");
+    ///
+    /// assert_eq!(map.get_with_hash(hash, |k| *k == "a"), Some((&"a", &1)));
+    /// assert_eq!(other.get_with_hash(hash, |k| *k == "a"), Some((&"a", &10)));
+    /// ```
+    #[must_use]
+    pub fn get_with_hash<F>(&self, hash: u64, mut is_match: F) -> Option<(&K, &V)>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.root.find(hash, 0, self.degree, &mut is_match).map(|e| (e.key(), e.value()))
+    }
+
+    /// Same as [`insert()`](HashTrieMap::insert()) but with the hash of `key` already computed
+    /// by [`hash_key()`](HashTrieMap::hash_key()).
+    ///
+    /// If the hash is not the one of `key` the map will misbehave: the entry might not be found.
+    #[must_use]
+    pub fn insert_with_hash(&self, hash: u64, key: K, value: V) -> HashTrieMap<K, V, P, H, W> {
+        let mut new_map = self.clone();
+
+        new_map.insert_with_hash_mut(hash, key, value);
+
+        new_map
+    }
+
+    /// Same as [`insert_mut()`](HashTrieMap::insert_mut()) but with the hash of `key` already
+    /// computed by [`hash_key()`](HashTrieMap::hash_key()).
+    ///
+    /// If the hash is not the one of `key` the map will misbehave: the entry might not be found.
+    pub fn insert_with_hash_mut(&mut self, hash: u64, key: K, value: V) {
+        debug_assert!(hash == self.hash_key(&key), "hash is not the hash of the key");
+
+        let key_hash = W::from_raw_hash(hash, &key, &self.hashers.hasher_builder);
+
+        self.insert_entry_mut(EntryWithHash {
+            entry: SharedPointer::new(KeyValue::new(key, value)),
+            key_hash,
+        });
+    }
+
+    /// Same as [`remove()`](HashTrieMap::remove()) but removes the entry with hash `hash` for
+    /// which `is_match(key)` returns `true`, if any.  The hash must be the one given by
+    /// [`hash_key()`](HashTrieMap::hash_key()) for the key to remove.
+    #[must_use]
+    pub fn remove_with_hash<F>(&self, hash: u64, is_match: F) -> HashTrieMap<K, V, P, H, W>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let mut new_map = self.clone();
+
+        if new_map.remove_with_hash_mut(hash, is_match) {
+            new_map
+        } else {
+            // We want to keep maximum sharing so in case of no change we just `clone()` ourselves.
+            self.clone()
+        }
+    }
+
+    /// Same as [`remove_mut()`](HashTrieMap::remove_mut()) but removes the entry with hash `hash`
+    /// for which `is_match(key)` returns `true`, if any.  The hash must be the one given by
+    /// [`hash_key()`](HashTrieMap::hash_key()) for the key to remove.
+    pub fn remove_with_hash_mut<F>(&mut self, hash: u64, mut is_match: F) -> bool
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let removed = SharedPointer::make_mut(&mut self.root).remove_matching(
+            hash,
+            0,
+            self.degree,
+            &mut is_match,
+        );
+
+        // As in `remove_mut()`, even if nothing was removed we still might have cloned some part
+        // of the tree unnecessarily.
+
+        if removed.is_some() {
+            self.size -= 1;
+            self.flatten_root_if_small();
+        }
+
+        removed.is_some()
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// Returns the entry at position `index` in the order of [`iter()`](HashTrieMap::iter()), or
+    /// `None` if `index` is out of bounds.  Since each branch knows how many entries are under it,
+    /// this takes *O(log(n))* time.
+    #[must_use]
+    pub fn nth(&self, index: usize) -> Option<(&K, &V)> {
+        self.root.nth(index).map(|e| (e.key(), e.value()))
+    }
+
+    /// Returns an entry chosen uniformly at random, or `None` if the map is empty.  This takes
+    /// *O(log(n))* time.
+    ///
+    /// This requires the `rand` feature.
+    #[cfg(feature = "rand")]
+    #[must_use]
+    pub fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Option<(&K, &V)> {
+        match self.size {
+            0 => None,
+            size => self.nth(rng.gen_range(0..size)),
+        }
+    }
+
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, K, V, P, W> {
+        self.iter_ptr().map(|e| (&e.key, &e.value))
+    }
+
+    #[must_use]
+    fn iter_ptr(&self) -> IterPtr<'_, K, V, P, W> {
+        IterPtr::new(self)
+    }
+
+    /// Returns an iterator over the entries after `cursor`, to go through the map in several
+    /// steps, e.g. page by page.  Use [`Cursor::start()`] to begin, and
+    /// [`IterFrom::cursor()`] to get the cursor to resume from.  The iterator starts in
+    /// *O(log(n))* time.
+    ///
+    /// The entries are in the order of their hashes, which does not depend on the other entries
+    /// of the map.  A cursor can then be used on a later version of the map, derived from it: the
+    /// iteration resumes after the position of the last entry returned, whether or not it was
+    /// removed.  Entries inserted after that position are returned and entries before it are not.
+    /// The only exception is for keys that share both the hash and the secondary hash, which are
+    /// ordered by insertion: when those change, some of them can be skipped or returned twice.
+    /// Keys that share the hash are ordered by their secondary hash, which can differ from map to
+    /// map, so they can also be skipped or returned twice by a map that is not derived from the
+    /// one the cursor comes from, even if it has the same hasher.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::HashTrieMap;
+    /// # use rpds::map::hash_trie_map::Cursor;
+    /// #
+    /// let map: HashTrieMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+    /// let mut cursor = Cursor::start();
+    /// let mut count = 0;
+    ///
+    /// loop {
+    ///     let mut iter = map.iter_from(&cursor);
+    ///     let page: Vec<(&u32, &u32)> = iter.by_ref().take(30).collect();
+    ///
+    ///     if page.is_empty() {
+    ///         break;
+    ///     }
+    ///
+    ///     count += page.len();
+    ///     cursor = iter.cursor();
+    /// }
+    ///
+    /// assert_eq!(count, 100);
+    /// ```
+    #[must_use]
+    pub fn iter_from(&self, cursor: &Cursor<W>) -> IterFrom<'_, K, V, P, H, W> {
+        IterFrom::new(self, cursor)
+    }
+
+    #[must_use]
+    pub fn keys(&self) -> IterKeys<'_, K, V, P, W> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    #[must_use]
+    pub fn values(&self) -> IterValues<'_, K, V, P, W> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Turns this map into a builder, to insert many entries at once.  The map built has the
+    /// entries of this map and the ones inserted in the builder, with the same hasher and degree.
+    /// See [`HashTrieMapBuilder`].
+    #[must_use]
+    pub fn into_builder(self) -> HashTrieMapBuilder<K, V, P, H, W> {
+        let mut entries = Vec::with_capacity(self.size);
+
+        self.root.collect_entries(&mut entries);
+
+        HashTrieMapBuilder { entries, degree: self.degree, hashers: self.hashers }
+    }
+
+    /// Returns a map with the entries for which `f(key, value)` returns `true`.
+    ///
+    /// The trie is walked once, and the parts of it where every entry is kept are shared with
+    /// `self`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = ht_map![1 => "one", 2 => "two", 3 => "three"];
+    ///
+    /// assert_eq!(map.filter(|k, _| k % 2 == 1), ht_map![1 => "one", 3 => "three"]);
+    /// ```
+    #[must_use]
+    pub fn filter<F>(&self, f: F) -> HashTrieMap<K, V, P, H, W>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut new_map = self.clone();
+
+        new_map.retain(f);
+
+        new_map
+    }
+
+    /// Keeps only the entries for which `f(key, value)` returns `true`.  This is the in-place
+    /// version of [`filter()`](HashTrieMap::filter()).
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut removed = 0;
+        let root =
+            Node::filter(&self.root, 0, self.degree, &self.hashers.secondary_hasher, &mut |e| {
+                let keep = f(e.key(), e.value());
+
+                if !keep {
+                    removed += 1;
+                }
+
+                keep
+            });
+
+        self.root = root.into_root();
+        self.size -= removed;
+        self.flatten_root_if_small();
+    }
+
+    /// Returns a map with the entries of `self` whose key is not in `other`.
+    ///
+    /// If the maps share structure, for instance because one was derived from the other, the
+    /// shared parts are skipped without being visited, so the cost is proportional to how much
+    /// the maps differ.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = ht_map![1 => "one", 2 => "two", 3 => "three"];
+    /// let other = map.remove(&2).insert(4, "four");
+    ///
+    /// assert_eq!(map.difference(&other), ht_map![2 => "two"]);
+    /// ```
+    #[must_use]
+    pub fn difference(&self, other: &HashTrieMap<K, V, P, H, W>) -> HashTrieMap<K, V, P, H, W> {
+        self.merge(other, MergeKind::Difference, &mut |_, _| {
+            unreachable!("difference never combines entries")
+        })
+    }
+
+    /// Returns a map with the entries whose key is in exactly one of the maps.
+    ///
+    /// If the maps share structure, for instance because one was derived from the other, the
+    /// shared parts are skipped without being visited, so the cost is proportional to how much
+    /// the maps differ.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = ht_map![1 => "one", 2 => "two", 3 => "three"];
+    /// let other = map.remove(&2).insert(4, "four");
+    ///
+    /// assert_eq!(map.symmetric_difference(&other), ht_map![2 => "two", 4 => "four"]);
+    /// ```
+    #[must_use]
+    pub fn symmetric_difference(
+        &self,
+        other: &HashTrieMap<K, V, P, H, W>,
+    ) -> HashTrieMap<K, V, P, H, W> {
+        self.merge(other, MergeKind::SymmetricDifference, &mut |_, _| {
+            unreachable!("symmetric difference never combines entries")
+        })
+    }
+
+    /// Returns an iterator over the differences between `self` and `other`: the entries only in
+    /// `other` are [`Added`](DiffItem::Added), the entries only in `self` are
+    /// [`Removed`](DiffItem::Removed), and the keys whose value differs are
+    /// [`Changed`](DiffItem::Changed).  The order of the iteration is unspecified.
+    ///
+    /// If the maps share structure, for instance because one was derived from the other, the
+    /// shared parts are skipped without being visited, so the cost is proportional to how much
+    /// the maps differ.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// # use rpds::map::hash_trie_map::DiffItem;
+    /// #
+    /// let map = ht_map![1 => "one", 2 => "two", 3 => "three"];
+    /// let other = map.remove(&1).insert(2, "dos").insert(4, "four");
+    ///
+    /// let mut diff: Vec<_> = map.diff(&other).collect();
+    /// diff.sort_by_key(|item| match item {
+    ///     DiffItem::Added(k, _) | DiffItem::Removed(k, _) | DiffItem::Changed(k, _, _) => **k,
+    /// });
+    ///
+    /// assert_eq!(
+    ///     diff,
+    ///     vec![
+    ///         DiffItem::Removed(&1, &"one"),
+    ///         DiffItem::Changed(&2, &"two", &"dos"),
+    ///         DiffItem::Added(&4, &"four"),
+    ///     ]
+    /// );
+    /// ```
+    #[must_use]
+    pub fn diff<'a>(&'a self, other: &'a HashTrieMap<K, V, P, H, W>) -> Diff<'a, K, V, P, H, W>
+    where
+        V: PartialEq,
+    {
+        Diff::new(self, other)
+    }
+
+    /// Returns statistics about the shape of the trie, such as how many nodes it has, how full
+    /// they are and how deep the entries are.  This is meant to help choosing the degree and the
+    /// hasher of a map.  It takes time proportional to the number of nodes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map: HashTrieMap<_, _> = (0..1000).map(|i| (i, i)).collect();
+    /// let stats = map.stats();
+    ///
+    /// assert_eq!(stats.node_count, stats.branch_count + stats.collision_count);
+    /// assert_eq!(stats.depth_histogram.iter().sum::<usize>(), map.size());
+    /// assert!(stats.average_fill() > 0.0);
+    /// ```
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        self.collect_stats(&[])
+    }
+
+    /// Same as [`stats()`](HashTrieMap::stats()) but also counts how many nodes of this map are
+    /// shared, by pointer, with `other`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map: HashTrieMap<_, _> = (0..1000).map(|i| (i, i)).collect();
+    /// let other = map.insert(1000, 1000);
+    /// let stats = map.stats_shared_with(&other);
+    ///
+    /// // Only the nodes on the path to the new entry were copied.
+    /// assert!(stats.shared_node_count > 0);
+    /// assert!(stats.shared_node_count < stats.node_count);
+    /// ```
+    #[must_use]
+    pub fn stats_shared_with(&self, other: &HashTrieMap<K, V, P, H, W>) -> Stats {
+        let mut other_nodes = Vec::new();
+
+        other.root.collect_addresses(&mut other_nodes);
+        other_nodes.sort_unstable();
+
+        self.collect_stats(&other_nodes)
+    }
+
+    fn collect_stats(&self, other_nodes: &[*const Node<K, V, P, W>]) -> Stats {
+        let mut stats = Stats::default();
+
+        self.root.collect_stats(0, other_nodes, false, &mut stats);
+        stats.slot_count = stats.branch_count * usize::from(self.degree);
+
+        stats
+    }
+
+    /// Returns `true` if both tries have the same layout, so they can be walked together.  This
+    /// requires both maps to have the same degree and to hash keys the same way.  Hashers cannot
+    /// be compared, and two equal-looking hashers may still hash differently (think of a hasher
+    /// with random keys), so we only rely on this when both maps share the same hasher, which is
+    /// the case for maps derived from one another.
+    fn has_same_layout<PO: SharedPointerKind>(&self, other: &HashTrieMap<K, V, PO, H, W>) -> bool {
+        self.degree == other.degree && SharedPointer::ptr_eq(&self.hashers, &other.hashers)
+    }
+
+    fn merge(
+        &self,
+        other: &HashTrieMap<K, V, P, H, W>,
+        kind: MergeKind,
+        combine: &mut CombineEntries<'_, K, V, P, W>,
+    ) -> HashTrieMap<K, V, P, H, W> {
+        if !self.has_same_layout(other) {
+            return self.merge_by_lookup(other, kind, combine);
+        }
+
+        let mut merge = Merge {
+            kind,
+            degree: self.degree,
+            secondary_hasher: &self.hashers.secondary_hasher,
+            combine,
+            count: 0,
+        };
+        let root = merge.merge(&self.root, &other.root, 0).into_root();
+        let size = match kind {
+            MergeKind::Union => self.size + merge.count,
+            MergeKind::Intersection => self.size - merge.count,
+            MergeKind::Difference | MergeKind::SymmetricDifference => merge.count,
+        };
+        let mut map = HashTrieMap {
+            root,
+            size,
+            degree: self.degree,
+            hashers: SharedPointer::clone(&self.hashers),
+        };
+
+        map.flatten_root_if_small();
+
+        map
+    }
+
+    /// Same as `merge()` but for maps whose tries have different layouts.  Here we have no choice
+    /// but to look up every key of one map in the other.
+    fn merge_by_lookup(
+        &self,
+        other: &HashTrieMap<K, V, P, H, W>,
+        kind: MergeKind,
+        combine: &mut CombineEntries<'_, K, V, P, W>,
+    ) -> HashTrieMap<K, V, P, H, W> {
+        let mut combine_entries =
+            |left: &EntryWithHash<K, V, P, W>, right: &EntryWithHash<K, V, P, W>| {
+                if SharedPointer::ptr_eq(&left.entry, &right.entry) {
+                    left.clone()
+                } else {
+                    combine(left, right)
+                }
+            };
+        let entry_with_hash =
+            |map: &HashTrieMap<K, V, P, H, W>, entry: &SharedPointer<KeyValue<K, V>, P>| {
+                EntryWithHash {
+                    entry: SharedPointer::clone(entry),
+                    key_hash: node_utils::hash(&entry.key, &map.hashers.hasher_builder),
+                }
+            };
+
+        match kind {
+            MergeKind::Intersection => {
+                let mut map = HashTrieMap::new_with_shared_hashers(
+                    SharedPointer::clone(&self.hashers),
+                    self.degree,
+                );
+
+                for entry in self.iter_ptr() {
+                    let left = entry_with_hash(self, entry);
+                    let right_hash = node_utils::hash(left.key(), &other.hashers.hasher_builder);
+
+                    if let Some(right) = other.root.get(
+                        left.key(),
+                        right_hash,
+                        0,
+                        other.degree,
+                        &other.hashers.secondary_hasher,
+                    ) {
+                        map.insert_entry_mut(combine_entries(&left, right));
+                    }
+                }
+
+                map
+            }
+            MergeKind::Union | MergeKind::Difference | MergeKind::SymmetricDifference => {
+                let mut map = self.clone();
+
+                for entry in other.iter_ptr() {
+                    let right = entry_with_hash(self, entry);
+
+                    match (
+                        kind,
+                        self.root.get(
+                            right.key(),
+                            right.key_hash,
+                            0,
+                            self.degree,
+                            &self.hashers.secondary_hasher,
+                        ),
+                    ) {
+                        (MergeKind::Union, Some(left)) => {
+                            map.insert_entry_mut(combine_entries(left, &right));
+                        }
+                        (MergeKind::Union | MergeKind::SymmetricDifference, None) => {
+                            map.insert_entry_mut(right);
+                        }
+                        (_, Some(_)) => {
+                            map.remove_mut(right.key());
+                        }
+                        (_, None) => (),
+                    }
+                }
+
+                map
+            }
+        }
+    }
+}
+
+impl<K, V, P, H: BuildHasher, W: TrieHash> HashTrieMap<K, V, P, H, W>
+where
+    K: Eq + Hash + Clone,
+    H: Clone,
+    P: SharedPointerKind,
+{
+    /// Returns a map with the entries of both maps.  The value of a key present in both maps is
+    /// given by `f(key, self_value, other_value)`.
+    ///
+    /// If the maps share structure, for instance because one was derived from the other, the
+    /// shared parts are reused without being visited, so the cost is proportional to how much
+    /// the maps differ.  For this reason `f` is not called for the entries shared by both maps,
+    /// which are kept as they are.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = ht_map!["a" => 1, "b" => 2];
+    /// let other = map.insert("b", 20).insert("c", 30);
+    ///
+    /// assert_eq!(
+    ///     map.union_with(&other, |_, v, w| v + w),
+    ///     ht_map!["a" => 1, "b" => 22, "c" => 30]
+    /// );
+    /// ```
+    #[must_use]
+    pub fn union_with<F>(
+        &self,
+        other: &HashTrieMap<K, V, P, H, W>,
+        mut f: F,
+    ) -> HashTrieMap<K, V, P, H, W>
+    where
+        F: FnMut(&K, &V, &V) -> V,
+    {
+        self.merge(other, MergeKind::Union, &mut |left, right| {
+            let value = f(left.key(), left.value(), right.value());
+
+            EntryWithHash {
+                entry: SharedPointer::new(KeyValue::new(left.key().clone(), value)),
+                key_hash: left.key_hash,
+            }
+        })
+    }
+
+    /// Returns a map with the keys present in both maps.  The value of each key is given by
+    /// `f(key, self_value, other_value)`.
+    ///
+    /// If the maps share structure, for instance because one was derived from the other, the
+    /// shared parts are reused without being visited, so the cost is proportional to how much
+    /// the maps differ.  For this reason `f` is not called for the entries shared by both maps,
+    /// which are kept as they are.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = ht_map!["a" => 1, "b" => 2];
+    /// let other = map.insert("b", 20).insert("c", 30);
+    ///
+    /// assert_eq!(map.intersection_with(&other, |_, v, w| v + w), ht_map!["a" => 1, "b" => 22]);
+    /// ```
+    #[must_use]
+    pub fn intersection_with<F>(
+        &self,
+        other: &HashTrieMap<K, V, P, H, W>,
+        mut f: F,
+    ) -> HashTrieMap<K, V, P, H, W>
+    where
+        F: FnMut(&K, &V, &V) -> V,
+    {
+        self.merge(other, MergeKind::Intersection, &mut |left, right| {
+            let value = f(left.key(), left.value(), right.value());
+
+            EntryWithHash {
+                entry: SharedPointer::new(KeyValue::new(left.key().clone(), value)),
+                key_hash: left.key_hash,
+            }
+        })
+    }
+
+    /// Returns a map with the same keys, where the value of each key is `f(key, value)`.
+    ///
+    /// The trie is walked once and keeps its shape, so no key is hashed again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = ht_map!["a" => 1, "b" => 2];
+    ///
+    /// assert_eq!(map.map_values(|_, v| v * 10), ht_map!["a" => 10, "b" => 20]);
+    /// ```
+    #[must_use]
+    pub fn map_values<U, F>(&self, mut f: F) -> HashTrieMap<K, U, P, H, W>
+    where
+        F: FnMut(&K, &V) -> U,
+    {
+        self.filter_map_values(|k, v| Some(f(k, v)))
+    }
+
+    /// Returns a map with the keys for which `f(key, value)` returns `Some`, with the value it
+    /// returns.
+    ///
+    /// The trie is walked once, and no key is hashed again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = ht_map!["a" => "1", "b" => "two", "c" => "3"];
+    ///
+    /// assert_eq!(map.filter_map_values(|_, v| v.parse::<i32>().ok()), ht_map!["a" => 1, "c" => 3]);
+    /// ```
+    #[must_use]
+    pub fn filter_map_values<U, F>(&self, mut f: F) -> HashTrieMap<K, U, P, H, W>
+    where
+        F: FnMut(&K, &V) -> Option<U>,
+    {
+        let mut size = 0;
+        let root = self.root.filter_map(0, self.degree, &self.hashers.secondary_hasher, &mut |e| {
+            f(e.key(), e.value()).map(|value| {
+                size += 1;
+
+                EntryWithHash {
+                    entry: SharedPointer::new(KeyValue::new(e.key().clone(), value)),
+                    key_hash: e.key_hash,
+                }
+            })
+        });
+        let mut map = HashTrieMap {
+            root: root.into_root(),
+            size,
+            degree: self.degree,
+            hashers: SharedPointer::clone(&self.hashers),
+        };
+
+        map.flatten_root_if_small();
+
+        map
+    }
+}
+
+impl<K, V, P, H: BuildHasher, W: TrieHash> HashTrieMap<K, V, P, H, W>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    H: Clone,
+    P: SharedPointerKind,
+{
+    /// Same as `insert()` but also returns the value that was replaced, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = HashTrieMap::new().insert("a", 1);
+    ///
+    /// let (new_map, previous) = map.replace("a", 2);
+    ///
+    /// assert_eq!(previous, Some(1));
+    /// assert_eq!(new_map.get("a"), Some(&2));
+    /// assert_eq!(map.get("a"), Some(&1));
+    /// ```
+    #[must_use]
+    pub fn replace(&self, key: K, value: V) -> (HashTrieMap<K, V, P, H, W>, Option<V>) {
+        let mut new_map = self.clone();
+        let previous = new_map.replace_mut(key, value);
+
+        (new_map, previous)
+    }
+
+    /// Same as `insert_mut()` but also returns the value that was replaced, if any.
+    ///
+    /// The value is only cloned if it is still shared with another map.
+    pub fn replace_mut(&mut self, key: K, value: V) -> Option<V> {
+        self.replace_entry_mut(key, value).map(|(_, value)| value)
+    }
+
+    /// Inserts the entry and returns the key and value that were replaced, if any.
+    pub(crate) fn replace_entry_mut(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let entry = EntryWithHash::new(key, value, &self.hashers.hasher_builder);
+
+        self.insert_entry_mut(entry).map(|e| KeyValue::into_key_value(e.entry))
+    }
+
+    /// Same as `remove()` but also returns the key and value that were removed, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = HashTrieMap::new().insert("a", 1);
+    ///
+    /// let (new_map, removed) = map.remove_entry("a");
+    ///
+    /// assert_eq!(removed, Some(("a", 1)));
+    /// assert!(new_map.is_empty());
+    /// assert_eq!(map.remove_entry("b").1, None);
+    /// ```
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn remove_entry<Q: ?Sized>(&self, key: &Q) -> (HashTrieMap<K, V, P, H, W>, Option<(K, V)>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let mut new_map = self.clone();
+
+        match new_map.remove_entry_mut(key) {
+            Some(entry) => (new_map, Some(entry)),
+            // We want to keep maximum sharing so in case of no change we just `clone()` ourselves.
+            None => (self.clone(), None),
+        }
+    }
+
+    /// Same as `remove_mut()` but also returns the key and value that were removed, if any.
+    ///
+    /// The key and value are only cloned if they are still shared with another map.
+    pub fn remove_entry_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let key_hash = node_utils::hash(key, &self.hashers.hasher_builder);
+
+        self.remove_hashed_mut(key, key_hash).map(|e| KeyValue::into_key_value(e.entry))
+    }
+
+    /// Same as `remove()` but also returns the value that was removed, if any.
+    #[must_use]
+    pub fn take<Q: ?Sized>(&self, key: &Q) -> (HashTrieMap<K, V, P, H, W>, Option<V>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let (new_map, removed) = self.remove_entry(key);
+
+        (new_map, removed.map(|(_, value)| value))
+    }
+
+    /// Same as `remove_mut()` but also returns the value that was removed, if any.
+    pub fn take_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq,
     {
-        match self {
-            Bucket::Single(entry) if entry.matches(key, key_hash) => Some(entry),
-            Bucket::Single(_) => None,
-            Bucket::Collision(entries) => {
-                let removed =
-                    bucket_utils::list_remove_first(entries, |e| e.matches(key, key_hash));
-                if let Some(e) = removed {
-                    entries.push_front_mut(e);
-                    entries.first_mut()
-                } else {
-                    None
-                }
-            }
-        }
+        self.remove_entry_mut(key).map(|(_, value)| value)
     }
 
-    #[inline]
-    fn contains_key<Q: ?Sized>(&self, key: &Q, key_hash: HashValue) -> bool
+    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq,
     {
-        self.get(key, key_hash).is_some()
+        // Note that unfortunately, even if nothing is found, we still might have cloned some
+        // part of the tree unnecessarily.
+        let key_hash = node_utils::hash(key, &self.hashers.hasher_builder);
+        SharedPointer::make_mut(&mut self.root)
+            .get_mut(key, key_hash, 0, self.degree, &self.hashers.secondary_hasher)
+            .map(|e| e.value_mut())
     }
 
-    /// Returns `true` if the key is new.
+    /// Same as [`retain()`](HashTrieMap::retain()) but `f` can also modify the values.  Since
+    /// every value can be modified, every node of the trie is copied if it is shared.
     ///
-    /// If there is a collision then `entry` will be put on the front of the entries list to
-    /// improve performance with high temporal locality (since `get()` will try to match according
-    /// to the list order).  The order of the rest of the list must be preserved for the same
-    /// reason.
-    fn insert(&mut self, entry: EntryWithHash<K, V, P>) -> bool {
-        match self {
-            Bucket::Single(existing_entry)
-                if existing_entry.matches(entry.key(), entry.key_hash) =>
-            {
-                *existing_entry = entry;
-                false
-            }
-            Bucket::Single(existing_entry) => {
-                let mut entries = List::new_with_ptr_kind();
-
-                // TODO In theory we should not need to clone `existing_entry`.
-                entries.push_front_mut(existing_entry.clone());
-                entries.push_front_mut(entry);
-
-                *self = Bucket::Collision(entries);
-
-                true
-            }
-            Bucket::Collision(entries) => {
-                let key_existed = bucket_utils::list_remove_first(entries, |e| {
-                    e.matches(entry.key(), entry.key_hash)
-                })
-                .is_some();
-
-                entries.push_front_mut(entry);
-
-                !key_existed
-            }
-        }
-    }
-
-    /// Returns `true` if the key was present.
+    /// # Example
     ///
-    /// If the bucket becomes empty `bucket` it be set to `None`.
-    fn remove<Q: ?Sized>(
-        bucket: &mut Option<&mut Bucket<K, V, P>>,
-        key: &Q,
-        key_hash: HashValue,
-    ) -> bool
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let mut map = ht_map!["a" => 1, "b" => 2, "c" => 3];
+    ///
+    /// map.retain_mut(|_, v| {
+    ///     *v *= 10;
+    ///     *v > 10
+    /// });
+    ///
+    /// assert_eq!(map, ht_map!["b" => 20, "c" => 30]);
+    /// ```
+    pub fn retain_mut<F>(&mut self, mut f: F)
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
+        F: FnMut(&K, &mut V) -> bool,
     {
-        match bucket.take() {
-            Some(b) => {
-                match b {
-                    Bucket::Single(existing_entry) if existing_entry.matches(key, key_hash) => {
-                        // bucket is already `None`.
-                        true
-                    }
-                    Bucket::Single(_) => {
-                        // Nothing to change.
-                        *bucket = Some(b);
-                        false
-                    }
-
-                    Bucket::Collision(entries) => {
-                        let removed =
-                            bucket_utils::list_remove_first(entries, |e| e.matches(key, key_hash))
-                                .is_some();
-
-                        match entries.len() {
-                            0 => unreachable!(
-                                "impossible to have collision with a single or no entry"
-                            ),
-                            1 => {
-                                let entry = entries.first().unwrap().clone();
-
-                                *b = Bucket::Single(entry);
-                            }
-                            _ => (),
-                        };
+        let mut removed = 0;
+
+        SharedPointer::make_mut(&mut self.root).retain_mut(
+            0,
+            self.degree,
+            &self.hashers.secondary_hasher,
+            &mut |e| {
+                let (key, value) = e.key_and_value_mut();
+                let keep = f(key, value);
+
+                if !keep {
+                    removed += 1;
+                }
 
-                        *bucket = Some(b);
+                keep
+            },
+        );
 
-                        removed
-                    }
-                }
-            }
-            None => false,
-        }
+        self.size -= removed;
+        self.flatten_root_if_small();
     }
-}
 
-impl<K, V, P> Clone for Bucket<K, V, P>
-where
-    K: Eq + Hash,
-    P: SharedPointerKind,
-{
-    fn clone(&self) -> Bucket<K, V, P> {
-        match self {
-            Bucket::Single(entry) => Bucket::Single(EntryWithHash::clone(entry)),
-            Bucket::Collision(entries) => Bucket::Collision(List::clone(entries)),
+    /// Gets the entry of `key` in the map, to inspect or modify it in place.  The key is hashed
+    /// only once, and the map is only copied (as needed by the structural sharing) when the entry
+    /// is modified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let mut map = HashTrieMap::new();
+    ///
+    /// for word in "the quick brown fox jumps over the lazy dog".split(' ') {
+    ///     map.entry(word).and_modify(|count| *count += 1).or_insert(1);
+    /// }
+    ///
+    /// assert_eq!(map.get("the"), Some(&2));
+    /// assert_eq!(map.get("fox"), Some(&1));
+    /// ```
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, P, H, W> {
+        let key_hash = node_utils::hash(&key, &self.hashers.hasher_builder);
+
+        match self.root.locate(&key, key_hash, 0, self.degree, &self.hashers.secondary_hasher) {
+            Some(location) => Entry::Occupied(OccupiedEntry { map: self, key, key_hash, location }),
+            None => Entry::Vacant(VacantEntry { map: self, key, key_hash }),
         }
     }
 }
 
-impl<K, V, P> EntryWithHash<K, V, P>
+/// A view into a single entry of a map, which may either be vacant or occupied.  This is
+/// obtained with `HashTrieMap::entry()`.
+#[derive(Debug)]
+pub enum Entry<'a, K, V, P, H: BuildHasher, W: TrieHash = u64>
 where
-    K: Eq + Hash,
     P: SharedPointerKind,
 {
-    fn new<H: BuildHasher>(key: K, value: V, hash_builder: &H) -> EntryWithHash<K, V, P> {
-        let key_hash = node_utils::hash(&key, hash_builder);
-
-        EntryWithHash { entry: SharedPointer::new(Entry::new(key, value)), key_hash }
-    }
-
-    fn key(&self) -> &K {
-        &self.entry.key
-    }
-
-    fn value(&self) -> &V {
-        &self.entry.value
-    }
-
-    #[inline]
-    fn matches<Q: ?Sized>(&self, key: &Q, key_hash: HashValue) -> bool
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
-        self.key_hash == key_hash && self.key().borrow() == key
-    }
+    Occupied(OccupiedEntry<'a, K, V, P, H, W>),
+    Vacant(VacantEntry<'a, K, V, P, H, W>),
 }
 
-impl<K, V, P> EntryWithHash<K, V, P>
+/// A view into an occupied entry of a map.
+#[derive(Debug)]
+pub struct OccupiedEntry<'a, K, V, P, H: BuildHasher, W: TrieHash = u64>
 where
-    K: Eq + Hash + Clone,
-    V: Clone,
     P: SharedPointerKind,
 {
-    fn value_mut(&mut self) -> &mut V {
-        &mut SharedPointer::make_mut(&mut self.entry).value
-    }
+    map: &'a mut HashTrieMap<K, V, P, H, W>,
+    key: K,
+    key_hash: W,
+    location: EntryLocation,
 }
 
-impl<K, V, P> Clone for EntryWithHash<K, V, P>
+/// A view into a vacant entry of a map.
+#[derive(Debug)]
+pub struct VacantEntry<'a, K, V, P, H: BuildHasher, W: TrieHash = u64>
 where
-    K: Eq + Hash,
     P: SharedPointerKind,
 {
-    fn clone(&self) -> EntryWithHash<K, V, P> {
-        EntryWithHash { entry: SharedPointer::clone(&self.entry), key_hash: self.key_hash }
-    }
+    map: &'a mut HashTrieMap<K, V, P, H, W>,
+    key: K,
+    key_hash: W,
 }
 
-impl<K, V> HashTrieMap<K, V>
+impl<'a, K, V, P, H: BuildHasher, W: TrieHash> Entry<'a, K, V, P, H, W>
 where
-    K: Eq + Hash,
+    K: Eq + Hash + Clone,
+    V: Clone,
+    H: Clone,
+    P: SharedPointerKind,
 {
     #[must_use]
-    pub fn new() -> HashTrieMap<K, V> {
-        HashTrieMap::new_with_degree(DEFAULT_DEGREE)
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
     }
 
-    #[must_use]
-    pub fn new_with_degree(degree: u8) -> HashTrieMap<K, V> {
-        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(DefaultBuildHasher::default(), degree)
+    /// Inserts `default` if the entry is vacant, and returns a mutable reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
     }
-}
 
-impl<K, V> HashTrieMapSync<K, V>
-where
-    K: Eq + Hash,
-{
-    #[must_use]
-    pub fn new_sync() -> HashTrieMapSync<K, V> {
-        HashTrieMap::new_sync_with_degree(DEFAULT_DEGREE)
+    /// Inserts the result of `default()` if the entry is vacant, and returns a mutable reference
+    /// to the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
     }
 
+    /// Calls `f` with the value if the entry is occupied.
     #[must_use]
-    pub fn new_sync_with_degree(degree: u8) -> HashTrieMapSync<K, V> {
-        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(DefaultBuildHasher::default(), degree)
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Entry<'a, K, V, P, H, W> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
     }
 }
 
-impl<K, V, P, H: BuildHasher> HashTrieMap<K, V, P, H>
+impl<'a, K, V, P, H: BuildHasher, W: TrieHash> OccupiedEntry<'a, K, V, P, H, W>
 where
-    K: Eq + Hash,
+    K: Eq + Hash + Clone,
+    V: Clone,
     H: Clone,
     P: SharedPointerKind,
 {
-    #[must_use]
-    pub fn new_with_hasher_and_ptr_kind(hasher_builder: H) -> HashTrieMap<K, V, P, H> {
-        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher_builder, DEFAULT_DEGREE)
+    fn entry_with_hash(&self) -> &EntryWithHash<K, V, P, W> {
+        let map = &self.map;
+
+        map.root.at(
+            self.location,
+            &self.key,
+            self.key_hash,
+            map.degree,
+            &map.hashers.secondary_hasher,
+        )
     }
 
+    /// Returns the key as stored in the map.
     #[must_use]
-    pub fn new_with_hasher_and_degree_and_ptr_kind(
-        hasher_builder: H,
-        degree: u8,
-    ) -> HashTrieMap<K, V, P, H> {
-        assert!(degree.is_power_of_two(), "degree must be a power of two");
-        assert!(degree <= DEFAULT_DEGREE, "degree is too big");
-
-        HashTrieMap {
-            root: SharedPointer::new(Node::new_empty_branch()),
-            size: 0,
-            degree,
-            hasher_builder,
-        }
+    pub fn key(&self) -> &K {
+        self.entry_with_hash().key()
     }
 
     #[must_use]
-    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&V>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
-        let key_hash = node_utils::hash(key, &self.hasher_builder);
+    pub fn get(&self) -> &V {
+        self.entry_with_hash().value()
+    }
 
-        self.root.get(key, key_hash, 0, self.degree).map(|e| e.value())
+    pub fn get_mut(&mut self) -> &mut V {
+        let map = &mut *self.map;
+
+        SharedPointer::make_mut(&mut map.root)
+            .at_mut(
+                self.location,
+                &self.key,
+                self.key_hash,
+                0,
+                map.degree,
+                &map.hashers.secondary_hasher,
+            )
+            .value_mut()
     }
 
+    /// Same as `get_mut()` but the reference lives as long as the map borrow.
     #[must_use]
-    pub fn insert(&self, key: K, value: V) -> HashTrieMap<K, V, P, H> {
-        let mut new_map = self.clone();
+    pub fn into_mut(self) -> &'a mut V {
+        let OccupiedEntry { map, key, key_hash, location } = self;
 
-        new_map.insert_mut(key, value);
+        SharedPointer::make_mut(&mut map.root)
+            .at_mut(location, &key, key_hash, 0, map.degree, &map.hashers.secondary_hasher)
+            .value_mut()
+    }
 
-        new_map
+    /// Replaces the value, returning the previous one.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
     }
 
-    pub fn insert_mut(&mut self, key: K, value: V) {
-        let entry = EntryWithHash::new(key, value, &self.hasher_builder);
-        let is_new_key = SharedPointer::make_mut(&mut self.root).insert(entry, 0, self.degree);
+    /// Removes the entry from the map, returning its value.
+    pub fn remove(self) -> V {
+        let entry = self
+            .map
+            .remove_hashed_mut(&self.key, self.key_hash)
+            .expect("occupied entry must be in the map")
+            .entry;
 
-        if is_new_key {
-            self.size += 1;
-        }
+        // The value is only cloned if the entry is still shared with another map.
+        SharedPointer::try_unwrap(entry).map_or_else(|e| e.value.clone(), |e| e.value)
     }
+}
 
+impl<'a, K, V, P, H: BuildHasher, W: TrieHash> VacantEntry<'a, K, V, P, H, W>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    H: Clone,
+    P: SharedPointerKind,
+{
     #[must_use]
-    pub fn remove<Q: ?Sized>(&self, key: &Q) -> HashTrieMap<K, V, P, H>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
-        let mut new_map = self.clone();
-
-        if new_map.remove_mut(key) {
-            new_map
-        } else {
-            // We want to keep maximum sharing so in case of no change we just `clone()` ourselves.
-            self.clone()
-        }
+    pub fn key(&self) -> &K {
+        &self.key
     }
 
-    pub fn remove_mut<Q: ?Sized>(&mut self, key: &Q) -> bool
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
-        let key_hash = node_utils::hash(key, &self.hasher_builder);
-        let removed = SharedPointer::make_mut(&mut self.root).remove(key, key_hash, 0, self.degree);
+    #[must_use]
+    pub fn into_key(self) -> K {
+        self.key
+    }
 
-        // Note that unfortunately, even if nothing was removed, we still might have cloned some
-        // part of the tree unnecessarily.
+    /// Inserts the value in the map, returning a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let VacantEntry { map, key, key_hash } = self;
+        let entry =
+            EntryWithHash { entry: SharedPointer::new(KeyValue::new(key, value)), key_hash };
 
-        if removed {
-            self.size -= 1;
-        }
+        map.size += 1;
 
-        removed
+        SharedPointer::make_mut(&mut map.root)
+            .insert_new(entry, 0, map.degree, &map.hashers.secondary_hasher)
+            .value_mut()
     }
+}
 
-    #[must_use]
-    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
-        self.get(key).is_some()
-    }
+/// A builder that creates a [`HashTrieMap`] from many entries at once.
+///
+/// Inserting entries one by one in a map updates the path to each new entry, which means checking
+/// whether each node on the path is shared and growing its arrays one element at a time.  The
+/// builder instead collects the entries, and [`build()`](HashTrieMapBuilder::build()) creates
+/// each node of the trie only once, with its final content.  This is what `collect()` uses.
+///
+/// This is a batch builder, not a transient map: there is no trie until `build()`, which
+/// partitions the collected entries by their hash, level by level, and creates the trie top-down.
+/// Until then the builder holds every entry along with its hash, so it takes *O(n)* memory on top
+/// of the map built.  [`into_builder()`](HashTrieMap::into_builder()) takes the entries out of the
+/// map, so the whole trie is created again; to add a few entries to a large map use
+/// [`insert_mut()`](HashTrieMap::insert_mut()) or `extend()` instead.
+///
+/// As with [`insert()`](HashTrieMap::insert()), if the same key is inserted more than once the
+/// last value wins.
+///
+/// # Example
+///
+/// ```
+/// # use rpds::*;
+/// #
+/// let mut builder = HashTrieMap::builder();
+///
+/// for i in 0..1000 {
+///     builder.insert(i, i * i);
+/// }
+///
+/// let map = builder.build();
+///
+/// assert_eq!(map.size(), 1000);
+/// assert_eq!(map.get(&12), Some(&144));
+/// ```
+#[derive(Debug)]
+pub struct HashTrieMapBuilder<K, V, P = RcK, H: BuildHasher = DefaultBuildHasher, W: TrieHash = u64>
+where
+    P: SharedPointerKind,
+{
+    entries: Vec<EntryWithHash<K, V, P, W>>,
+    degree: u8,
+    hashers: SharedPointer<Hashers<H>, P>,
+}
 
-    #[must_use]
-    #[inline]
-    pub fn size(&self) -> usize {
-        self.size
+impl<K, V, P, H: BuildHasher, W: TrieHash> HashTrieMapBuilder<K, V, P, H, W>
+where
+    K: Eq + Hash,
+    H: Clone,
+    P: SharedPointerKind,
+{
+    pub fn insert(&mut self, key: K, value: V) {
+        self.entries.push(EntryWithHash::new(key, value, &self.hashers.hasher_builder));
     }
 
+    /// Builds the map.  This takes *O(n log(n))* time, where *n* is the number of entries
+    /// inserted.
     #[must_use]
-    #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.size() == 0
-    }
+    pub fn build(self) -> HashTrieMap<K, V, P, H, W> {
+        let HashTrieMapBuilder { entries, degree, hashers } = self;
+        let mut map = HashTrieMap::new_with_shared_hashers(hashers, degree);
+
+        // A small map has a flat root, which we can just fill.
+        if entries.len() <= FLAT_MAX_SIZE {
+            for entry in entries {
+                map.insert_entry_mut(entry);
+            }
 
-    #[must_use]
-    pub fn iter(&self) -> Iter<'_, K, V, P> {
-        self.iter_ptr().map(|e| (&e.key, &e.value))
-    }
+            return map;
+        }
 
-    #[must_use]
-    fn iter_ptr(&self) -> IterPtr<'_, K, V, P> {
-        IterPtr::new(self)
-    }
+        let size = entries.len();
+        let mut build = Build::new(degree, &map.hashers.secondary_hasher, entries);
+        let mut group: Vec<usize> = (0..size).collect();
+        let root = build.build(&mut group, 0).into_root();
 
-    #[must_use]
-    pub fn keys(&self) -> IterKeys<'_, K, V, P> {
-        self.iter().map(|(k, _)| k)
-    }
+        map.size = size - build.replaced;
+        map.root = root;
+        map.flatten_root_if_small();
 
-    #[must_use]
-    pub fn values(&self) -> IterValues<'_, K, V, P> {
-        self.iter().map(|(_, v)| v)
+        map
     }
 }
 
-impl<K, V, P, H: BuildHasher> HashTrieMap<K, V, P, H>
+impl<K, V, P, H: BuildHasher, W: TrieHash> Extend<(K, V)> for HashTrieMapBuilder<K, V, P, H, W>
 where
-    K: Eq + Hash + Clone,
-    V: Clone,
+    K: Eq + Hash,
     H: Clone,
     P: SharedPointerKind,
 {
-    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut V>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
-        // Note that unfortunately, even if nothing is found, we still might have cloned some
-        // part of the tree unnecessarily.
-        let key_hash = node_utils::hash(key, &self.hasher_builder);
-        SharedPointer::make_mut(&mut self.root)
-            .get_mut(key, key_hash, 0, self.degree)
-            .map(|e| e.value_mut())
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+
+        self.entries.reserve(iter.size_hint().0);
+
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
     }
 }
 
-impl<'a, K, Q: ?Sized, V, P, H: BuildHasher> Index<&'a Q> for HashTrieMap<K, V, P, H>
+impl<'a, K, Q: ?Sized, V, P, H: BuildHasher, W: TrieHash> Index<&'a Q>
+    for HashTrieMap<K, V, P, H, W>
 where
     K: Eq + Hash + Borrow<Q>,
     Q: Hash + Eq,
@@ -912,56 +4106,84 @@ where
     }
 }
 
-impl<K, V, P, H: BuildHasher> Clone for HashTrieMap<K, V, P, H>
+impl<K, V, P, H: BuildHasher, W: TrieHash> Clone for HashTrieMap<K, V, P, H, W>
 where
     K: Eq + Hash,
     H: Clone,
     P: SharedPointerKind,
 {
-    fn clone(&self) -> HashTrieMap<K, V, P, H> {
+    fn clone(&self) -> HashTrieMap<K, V, P, H, W> {
         HashTrieMap {
             root: SharedPointer::clone(&self.root),
             size: self.size,
             degree: self.degree,
-            hasher_builder: self.hasher_builder.clone(),
+            hashers: SharedPointer::clone(&self.hashers),
         }
     }
 }
 
-impl<K, V, P, H: BuildHasher> Default for HashTrieMap<K, V, P, H>
+impl<K, V, P, H: BuildHasher, W: TrieHash> Default for HashTrieMap<K, V, P, H, W>
 where
     K: Eq + Hash,
     H: Default + Clone,
     P: SharedPointerKind,
 {
-    fn default() -> HashTrieMap<K, V, P, H> {
+    fn default() -> HashTrieMap<K, V, P, H, W> {
         HashTrieMap::new_with_hasher_and_ptr_kind(H::default())
     }
 }
 
-impl<K: Eq, V: PartialEq, P, PO, H: BuildHasher> PartialEq<HashTrieMap<K, V, PO, H>>
-    for HashTrieMap<K, V, P, H>
+impl<K: Eq, V: PartialEq, P, PO, H: BuildHasher, W: TrieHash> PartialEq<HashTrieMap<K, V, PO, H, W>>
+    for HashTrieMap<K, V, P, H, W>
+where
+    K: Hash,
+    H: Clone,
+    P: SharedPointerKind,
+    PO: SharedPointerKind,
+{
+    /// If both maps share their hasher and degree, their tries are compared node by node, skipping
+    /// the subtrees and entries they share.  Note that this means that shared values are considered
+    /// equal without being compared, even if they are not equal to themselves (like `f64::NAN`).
+    fn eq(&self, other: &HashTrieMap<K, V, PO, H, W>) -> bool {
+        if self.size() != other.size() {
+            return false;
+        }
+
+        if self.has_same_layout(other) {
+            // Tries with the same layout have the same shape when they have the same entries.
+            return self.root.eq_entries(&other.root);
+        }
+
+        self.iter().all(|(key, value)| other.get(key).map_or(false, |v| *value == *v))
+    }
+}
+
+impl<K: Eq, V: Eq, P, H: BuildHasher, W: TrieHash> Eq for HashTrieMap<K, V, P, H, W>
 where
     K: Hash,
     H: Clone,
     P: SharedPointerKind,
-    PO: SharedPointerKind,
 {
-    fn eq(&self, other: &HashTrieMap<K, V, PO, H>) -> bool {
-        self.size() == other.size()
-            && self.iter().all(|(key, value)| other.get(key).map_or(false, |v| *value == *v))
-    }
 }
 
-impl<K: Eq, V: Eq, P, H: BuildHasher> Eq for HashTrieMap<K, V, P, H>
+/// The hash of a map does not depend on its hasher, degree, or on the order in which the entries
+/// were inserted, so it is consistent with `Eq`.  The hashes of the entries are combined in an
+/// order-independent way, and the combined hash of each subtree is cached, so hashing a map again
+/// after a few changes only needs to visit the changed subtrees.
+impl<K: Eq + Hash, V: Hash, P, H: BuildHasher, W: TrieHash> Hash for HashTrieMap<K, V, P, H, W>
 where
-    K: Hash,
     H: Clone,
     P: SharedPointerKind,
 {
+    fn hash<HS: Hasher>(&self, state: &mut HS) {
+        // Add the hash of length so that if two collections are added one after the other it
+        // doesn't hash to the same thing as a single collection with the same elements.
+        self.size().hash(state);
+        self.root.entries_hash().hash(state);
+    }
 }
 
-impl<K, V, P, H: BuildHasher> Display for HashTrieMap<K, V, P, H>
+impl<K, V, P, H: BuildHasher, W: TrieHash> Display for HashTrieMap<K, V, P, H, W>
 where
     K: Eq + Hash + Display,
     V: Display,
@@ -987,187 +4209,854 @@ where
     }
 }
 
-impl<'a, K, V, P, H: BuildHasher> IntoIterator for &'a HashTrieMap<K, V, P, H>
+impl<'a, K, V, P, H: BuildHasher, W: TrieHash> IntoIterator for &'a HashTrieMap<K, V, P, H, W>
 where
     K: Eq + Hash,
     H: Default + Clone,
     P: SharedPointerKind,
 {
     type Item = (&'a K, &'a V);
-    type IntoIter = Iter<'a, K, V, P>;
+    type IntoIter = Iter<'a, K, V, P, W>;
 
-    fn into_iter(self) -> Iter<'a, K, V, P> {
+    fn into_iter(self) -> Iter<'a, K, V, P, W> {
         self.iter()
     }
 }
 
-impl<K, V, P, H> FromIterator<(K, V)> for HashTrieMap<K, V, P, H>
+impl<K, V, P, H, W: TrieHash> FromIterator<(K, V)> for HashTrieMap<K, V, P, H, W>
 where
     K: Eq + Hash,
     H: BuildHasher + Clone + Default,
     P: SharedPointerKind,
 {
-    fn from_iter<I: IntoIterator<Item = (K, V)>>(into_iter: I) -> HashTrieMap<K, V, P, H> {
-        let mut map = HashTrieMap::new_with_hasher_and_ptr_kind(Default::default());
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(into_iter: I) -> HashTrieMap<K, V, P, H, W> {
+        let mut builder: HashTrieMapBuilder<K, V, P, H, W> =
+            HashTrieMap::new_with_hasher_and_ptr_kind(Default::default()).into_builder();
+
+        builder.extend(into_iter);
+
+        builder.build()
+    }
+}
+
+impl<K, V, P, H: BuildHasher, W: TrieHash> Extend<(K, V)> for HashTrieMap<K, V, P, H, W>
+where
+    K: Eq + Hash,
+    H: Clone,
+    P: SharedPointerKind,
+{
+    /// The new entries are inserted as a batch: each node on the paths to them is made unique
+    /// once, and then changed in place, instead of once per entry.  The parts of the trie without
+    /// new entries stay shared with other versions of the map.
+    ///
+    /// The batch is collected in a vector first, and the gain comes from the new entries sharing
+    /// paths, so extending with a few entries at a time costs about as much as calling
+    /// `insert_mut()` for each of them.  A map with a flat root is created again with a
+    /// [`HashTrieMapBuilder`] when the new entries make it too large to stay flat.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let new_entries: Vec<EntryWithHash<K, V, P, W>> = iter
+            .into_iter()
+            .map(|(k, v)| EntryWithHash::new(k, v, &self.hashers.hasher_builder))
+            .collect();
+
+        if let Node::Flat(flat_entries) = self.root.borrow() {
+            if flat_entries.len() + new_entries.len() > FLAT_MAX_SIZE {
+                // A flat root is small, so we just build the trie from scratch.
+                let mut builder = core::mem::replace(
+                    self,
+                    HashTrieMap::new_with_shared_hashers(
+                        SharedPointer::clone(&self.hashers),
+                        self.degree,
+                    ),
+                )
+                .into_builder();
+
+                builder.entries.extend(new_entries);
+                *self = builder.build();
+            } else {
+                for entry in new_entries {
+                    self.insert_entry_mut(entry);
+                }
+            }
 
-        for (k, v) in into_iter {
-            map.insert_mut(k, v);
+            return;
         }
 
-        map
+        let size = new_entries.len();
+        let mut build = Build::new(self.degree, &self.hashers.secondary_hasher, new_entries);
+        let mut group: Vec<usize> = (0..size).collect();
+
+        self.size += build.insert(SharedPointer::make_mut(&mut self.root), &mut group, 0);
     }
 }
 
+/// Number of levels of the iteration stack that are kept inline in the iterator.  With the default
+/// degree this covers tries with far more evenly spread keys than fit in memory, so iterating a map
+/// does not allocate unless many keys share a long hash prefix.
+///
+/// Deeper tries still allocate: their levels past this bound are kept on the heap, in a vector
+/// that is sized for the maximum height of the trie the first time it is needed, so iterating
+/// allocates at most once.  The bound cannot be derived from `W::BITS` and the degree, since the
+/// length of an array cannot depend on a type parameter, and the maximum height over every width
+/// and degree (128 levels for `u128` hashes with degree 2) would make the iterator too expensive
+/// to move, since each level takes two slices.
+const ITER_STACK_INLINE_SIZE: usize = 8;
+
 #[derive(Debug)]
-pub struct IterPtr<'a, K, V, P>
+pub struct IterPtr<'a, K, V, P, W = u64>
 where
     P: SharedPointerKind,
 {
-    stack: Vec<IterStackElement<'a, K, V, P>>,
+    /// The first `stack_len` elements are the top levels of the stack, from the root down.
+    stack: [IterStackElement<'a, K, V, P, W>; ITER_STACK_INLINE_SIZE],
+    stack_len: usize,
+    /// The levels of the stack below the first `ITER_STACK_INLINE_SIZE`.
+    deep_stack: Vec<IterStackElement<'a, K, V, P, W>>,
+    /// The maximum number of levels of `deep_stack`, given by the maximum height of the trie.
+    deep_stack_max_len: usize,
+    /// The collision node being visited, which is always below the last level of the stack.
+    collision: Option<CollisionIter<'a, K, V, P, W>>,
     size: usize,
 }
 
+/// The entries and subtrees of a branch (or of a flat root) that were not visited yet.  The
+/// entries of a branch are visited before its subtrees.
 #[derive(Debug)]
-enum IterStackElement<'a, K, V, P>
+struct IterStackElement<'a, K, V, P, W>
+where
+    P: SharedPointerKind,
+{
+    entries: &'a [EntryWithHash<K, V, P, W>],
+    subtrees: &'a [SubtreePtr<K, V, P, W>],
+}
+
+type SubtreePtr<K, V, P, W> = SharedPointer<Node<K, V, P, W>, P>;
+
+impl<'a, K, V, P, W> IterStackElement<'a, K, V, P, W>
 where
     P: SharedPointerKind,
 {
-    Branch(Peekable<slice::Iter<'a, SharedPointer<Node<K, V, P>, P>>>),
-    LeafSingle(&'a EntryWithHash<K, V, P>),
-    LeafCollision(Peekable<list::Iter<'a, EntryWithHash<K, V, P>, P>>),
+    fn empty() -> IterStackElement<'a, K, V, P, W> {
+        IterStackElement { entries: &[], subtrees: &[] }
+    }
+}
+
+impl<K, V, P, W> Clone for IterStackElement<'_, K, V, P, W>
+where
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V, P, W> Copy for IterStackElement<'_, K, V, P, W> where P: SharedPointerKind {}
+
+mod iter_utils {
+    use super::TrieHash;
+
+    pub fn trie_max_height<W: TrieHash>(degree: u8) -> usize {
+        let bits_per_level = (degree - 1).count_ones() as usize;
+        let hash_bits = W::BITS;
+
+        (hash_bits / bits_per_level) + if hash_bits % bits_per_level > 0 { 1 } else { 0 }
+    }
 }
 
-impl<'a, K, V, P> IterStackElement<'a, K, V, P>
+impl<'a, K, V, P, W: TrieHash> IterPtr<'a, K, V, P, W>
 where
     K: Eq + Hash,
     P: SharedPointerKind,
 {
-    fn new(node: &Node<K, V, P>) -> IterStackElement<'_, K, V, P> {
-        match node {
-            Node::Branch(children) => IterStackElement::Branch(children.iter().peekable()),
-            Node::Leaf(Bucket::Single(entry)) => IterStackElement::LeafSingle(entry),
-            Node::Leaf(Bucket::Collision(entries)) => {
-                IterStackElement::LeafCollision(entries.iter().peekable())
+    fn new<H: BuildHasher + Clone>(map: &HashTrieMap<K, V, P, H, W>) -> IterPtr<'_, K, V, P, W> {
+        let mut iter = IterPtr {
+            stack: [IterStackElement::empty(); ITER_STACK_INLINE_SIZE],
+            stack_len: 0,
+            deep_stack: Vec::new(),
+            deep_stack_max_len: iter_utils::trie_max_height::<W>(map.degree)
+                .saturating_sub(ITER_STACK_INLINE_SIZE),
+            collision: None,
+            size: map.size(),
+        };
+
+        if map.size() > 0 {
+            iter.push(map.root.borrow());
+        }
+
+        iter
+    }
+
+    fn push(&mut self, node: &'a Node<K, V, P, W>) {
+        let element = match node {
+            Node::Branch { entries, subtrees, .. } => {
+                IterStackElement { entries: entries.as_slice(), subtrees: subtrees.as_slice() }
+            }
+            Node::Flat(entries) => IterStackElement { entries, subtrees: &[] },
+            Node::Collision(entries) => {
+                self.collision = Some(entries.iter());
+                return;
+            }
+        };
+
+        if self.stack_len < ITER_STACK_INLINE_SIZE {
+            self.stack[self.stack_len] = element;
+            self.stack_len += 1;
+        } else {
+            if self.deep_stack.capacity() == 0 {
+                self.deep_stack.reserve_exact(self.deep_stack_max_len);
             }
+
+            self.deep_stack.push(element);
         }
     }
 
-    fn current_elem(&mut self) -> &'a SharedPointer<Entry<K, V>, P> {
-        match self {
-            IterStackElement::Branch(_) => panic!("called current element of a branch"),
-            IterStackElement::LeafSingle(entry) => &entry.entry,
-            IterStackElement::LeafCollision(iter) => &iter.peek().unwrap().entry,
+    fn pop(&mut self) -> Option<IterStackElement<'a, K, V, P, W>> {
+        match self.deep_stack.pop() {
+            Some(element) => Some(element),
+            None if self.stack_len > 0 => {
+                self.stack_len -= 1;
+                Some(self.stack[self.stack_len])
+            }
+            None => None,
         }
     }
 
-    /// Advance and returns `true` if finished.
-    #[inline]
-    fn advance(&mut self) -> bool {
-        match self {
-            IterStackElement::Branch(iter) => {
-                iter.next();
-                iter.peek().is_none()
+    fn top_mut(&mut self) -> Option<&mut IterStackElement<'a, K, V, P, W>> {
+        if !self.deep_stack.is_empty() {
+            return self.deep_stack.last_mut();
+        }
+
+        match self.stack_len {
+            0 => None,
+            len => Some(&mut self.stack[len - 1]),
+        }
+    }
+}
+
+impl<'a, K, V, P, W: TrieHash> Iterator for IterPtr<'a, K, V, P, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+{
+    type Item = &'a SharedPointer<KeyValue<K, V>, P>;
+
+    fn next(&mut self) -> Option<&'a SharedPointer<KeyValue<K, V>, P>> {
+        loop {
+            if let Some(collision) = &mut self.collision {
+                match collision.next() {
+                    Some(entry) => {
+                        self.size -= 1;
+                        return Some(&entry.entry);
+                    }
+                    None => self.collision = None,
+                }
+            }
+
+            let top = self.top_mut()?;
+
+            if let Some((entry, entries)) = top.entries.split_first() {
+                top.entries = entries;
+                self.size -= 1;
+                return Some(&entry.entry);
             }
-            IterStackElement::LeafSingle(_) => true,
-            IterStackElement::LeafCollision(iter) => {
-                iter.next();
-                iter.peek().is_none()
+
+            match top.subtrees.split_first() {
+                Some((subtree, subtrees)) => {
+                    top.subtrees = subtrees;
+                    self.push(subtree);
+                }
+                None => {
+                    self.pop();
+                }
             }
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.size, Some(self.size))
+    }
+
+    /// Visits the remaining entries by recursing over the nodes, which is faster than calling
+    /// `next()` for each entry.  This is also used by `for_each()`.
+    ///
+    /// `try_fold()` (and so `find()`, `any()`, `all()` and the like) is not overridden: its
+    /// signature needs the `Try` trait, which is unstable, so it goes through `next()`.
+    fn fold<B, F>(mut self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, Self::Item) -> B,
+    {
+        let mut acc = init;
+
+        if let Some(collision) = self.collision.take() {
+            acc = collision.fold(acc, |acc, e| f(acc, &e.entry));
+        }
+
+        while let Some(element) = self.pop() {
+            acc = element.entries.iter().fold(acc, |acc, e| f(acc, &e.entry));
+            acc =
+                element.subtrees.iter().fold(acc, |acc, subtree| subtree.fold_entries(acc, &mut f));
+        }
+
+        acc
+    }
 }
 
-mod iter_utils {
-    use super::HashValue;
-    use core::mem::size_of;
+impl<'a, K: Eq + Hash, V, P, W: TrieHash> ExactSizeIterator for IterPtr<'a, K, V, P, W> where
+    P: SharedPointerKind
+{
+}
 
-    pub fn trie_max_height(degree: u8) -> usize {
-        let bits_per_level = (degree - 1).count_ones() as usize;
-        let hash_bits = 8 * size_of::<HashValue>();
+/// A position in the iteration of a [`HashTrieMap`] with [`HashTrieMap::iter_from()`].
+///
+/// The position of an entry is given by the hash of its key, then by a secondary hash of the key
+/// and by its place among the keys that share both hashes.  With the `serde` feature the cursor
+/// can be serialized, e.g. to send it to a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Cursor<W = u64> {
+    /// The position of the last entry returned, or `None` at the start.
+    last: Option<(W, HashValue, usize)>,
+}
 
-        (hash_bits / bits_per_level) + if hash_bits % bits_per_level > 0 { 1 } else { 0 }
+impl<W> Cursor<W> {
+    /// The cursor to iterate from the first entry.
+    #[must_use]
+    pub fn start() -> Cursor<W> {
+        Cursor { last: None }
     }
 }
 
-impl<'a, K, V, P> IterPtr<'a, K, V, P>
+/// An entry with its position among the entries that share both its hash and its secondary hash.
+type RankedEntry<'a, K, V, P, W> = (&'a EntryWithHash<K, V, P, W>, usize);
+
+type BranchVisit<'a, K, V, P, W> = (&'a Node<K, V, P, W>, usize);
+
+/// An iterator over the entries of a map after a [`Cursor`].  See [`HashTrieMap::iter_from()`].
+#[derive(Debug)]
+pub struct IterFrom<'a, K, V, P, H, W = u64>
+where
+    P: SharedPointerKind,
+{
+    /// The branches being visited, from the root down, with the next index to visit in each.
+    stack: Vec<BranchVisit<'a, K, V, P, W>>,
+    /// Entries to return before going on with the stack, the last one first.
+    pending: Vec<RankedEntry<'a, K, V, P, W>>,
+    start: Cursor<W>,
+    last: Option<RankedEntry<'a, K, V, P, W>>,
+    degree: u8,
+    hashers: &'a Hashers<H>,
+}
+
+impl<'a, K, V, P, H, W: TrieHash> IterFrom<'a, K, V, P, H, W>
 where
     K: Eq + Hash,
     P: SharedPointerKind,
+    H: BuildHasher,
 {
-    fn new<H: BuildHasher + Clone>(map: &HashTrieMap<K, V, P, H>) -> IterPtr<'_, K, V, P> {
-        let mut stack: Vec<IterStackElement<'_, K, V, P>> =
-            Vec::with_capacity(iter_utils::trie_max_height(map.degree) + 1);
+    fn new(map: &'a HashTrieMap<K, V, P, H, W>, cursor: &Cursor<W>) -> IterFrom<'a, K, V, P, H, W> {
+        let mut iter = IterFrom {
+            stack: Vec::with_capacity(iter_utils::trie_max_height::<W>(map.degree)),
+            pending: Vec::new(),
+            start: *cursor,
+            last: None,
+            degree: map.degree,
+            hashers: &map.hashers,
+        };
 
-        if map.size() > 0 {
-            stack.push(IterStackElement::new(map.root.borrow()));
+        iter.seek(&map.root, 0);
+
+        iter
+    }
+
+    /// Returns `true` if an entry at the given position comes after the start cursor.  The
+    /// secondary hash is only computed if the hashes are equal.
+    fn is_after_start<F: FnOnce() -> HashValue>(
+        &self,
+        key_hash: W,
+        secondary_hash: F,
+        rank: usize,
+    ) -> bool {
+        match self.start.last {
+            None => true,
+            Some((hash, start_secondary_hash, start_rank)) => {
+                node_utils::cmp_trie_order(key_hash, hash, self.degree)
+                    .then_with(|| secondary_hash().cmp(&start_secondary_hash))
+                    .then(rank.cmp(&start_rank))
+                    == core::cmp::Ordering::Greater
+            }
         }
+    }
 
-        let mut iter = IterPtr { stack, size: map.size() };
+    /// Sets up the iteration of `node`, at depth `depth`, from the start cursor, by going down
+    /// the path of the cursor.
+    fn seek(&mut self, node: &'a Node<K, V, P, W>, depth: usize) {
+        match node {
+            Node::Branch { .. } if self.start.last.is_none() => self.stack.push((node, 0)),
+            Node::Branch { entries, subtrees, .. } => {
+                let (hash, _, _) = self.start.last.unwrap();
+                let index = node_utils::index_from_hash(hash, depth, self.degree).unwrap();
 
-        iter.dig();
+                self.stack.push((node, index + 1));
 
-        iter
-    }
+                if let Some(entry) = entries.get(index) {
+                    let secondary_hash =
+                        || node_utils::secondary_hash(entry.key(), &self.hashers.secondary_hasher);
 
-    fn dig(&mut self) {
-        let next_stack_elem: Option<IterStackElement<'_, K, V, P>> =
-            self.stack.last_mut().and_then(|stack_top| match stack_top {
-                IterStackElement::Branch(iter) => {
-                    iter.peek().map(|node| IterStackElement::new(node))
+                    if self.is_after_start(entry.key_hash, secondary_hash, 0) {
+                        self.pending.push((entry, 0));
+                    }
+                } else if let Some(subtree) = subtrees.get(index) {
+                    self.seek(subtree, depth + 1);
+                }
+            }
+            Node::Collision(entries) => {
+                let mut pending: Vec<_> = entries
+                    .iter_ranked()
+                    .filter(|(secondary_hash, rank, entry)| {
+                        self.is_after_start(entry.key_hash, || *secondary_hash, *rank)
+                    })
+                    .map(|(_, rank, entry)| (entry, rank))
+                    .collect();
+
+                pending.reverse();
+                self.pending = pending;
+            }
+            Node::Flat(entries) => {
+                let mut ranked: Vec<_> = entries
+                    .iter()
+                    .map(|entry| {
+                        (
+                            node_utils::secondary_hash(entry.key(), &self.hashers.secondary_hasher),
+                            0,
+                            entry,
+                        )
+                    })
+                    .collect();
+
+                // The sort is stable, so entries with the same hashes are ranked in the order of
+                // the array.
+                ranked.sort_by(|(a_secondary_hash, _, a), (b_secondary_hash, _, b)| {
+                    node_utils::cmp_trie_order(a.key_hash, b.key_hash, self.degree)
+                        .then(a_secondary_hash.cmp(b_secondary_hash))
+                });
+
+                for i in 1..ranked.len() {
+                    let (previous_secondary_hash, previous_rank, previous) = ranked[i - 1];
+                    let (secondary_hash, _, entry) = ranked[i];
+
+                    if entry.key_hash == previous.key_hash
+                        && secondary_hash == previous_secondary_hash
+                    {
+                        ranked[i].1 = previous_rank + 1;
+                    }
                 }
-                _ => None,
-            });
 
-        if let Some(e) = next_stack_elem {
-            self.stack.push(e);
-            self.dig();
+                self.pending = ranked
+                    .into_iter()
+                    .rev()
+                    .filter(|(secondary_hash, rank, entry)| {
+                        self.is_after_start(entry.key_hash, || *secondary_hash, *rank)
+                    })
+                    .map(|(_, rank, entry)| (entry, rank))
+                    .collect();
+            }
         }
     }
 
-    fn advance(&mut self) {
-        if let Some(mut stack_element) = self.stack.pop() {
-            let finished = stack_element.advance();
+    /// Returns the cursor to resume the iteration after the last entry returned, or the cursor
+    /// this iterator started from if no entry was returned.
+    #[must_use]
+    pub fn cursor(&self) -> Cursor<W> {
+        match self.last {
+            None => self.start,
+            Some((entry, rank)) => Cursor {
+                last: Some((
+                    entry.key_hash,
+                    node_utils::secondary_hash(entry.key(), &self.hashers.secondary_hasher),
+                    rank,
+                )),
+            },
+        }
+    }
+}
 
-            if finished {
-                self.advance();
-            } else {
-                self.stack.push(stack_element);
+impl<'a, K, V, P, H, W: TrieHash> Iterator for IterFrom<'a, K, V, P, H, W>
+where
+    K: Eq + Hash,
+    P: SharedPointerKind,
+    H: BuildHasher,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((entry, rank)) = self.pending.pop() {
+                self.last = Some((entry, rank));
+                return Some((entry.key(), entry.value()));
+            }
+
+            let (node, next_index) = self.stack.last_mut()?;
+            let (entries, subtrees) = match node {
+                Node::Branch { entries, subtrees, .. } => (entries, subtrees),
+                Node::Collision(_) | Node::Flat(_) => unreachable!("only branches are stacked"),
+            };
+            let remaining = (entries.bitmap() | subtrees.bitmap())
+                & usize::MAX.checked_shl(*next_index as u32).unwrap_or(0);
+
+            if remaining == 0 {
+                self.stack.pop();
+                continue;
+            }
+
+            let index = remaining.trailing_zeros() as usize;
+
+            *next_index = index + 1;
+
+            match entries.get(index) {
+                Some(entry) => self.pending.push((entry, 0)),
+                None => {
+                    let subtree = subtrees.get(index).unwrap();
 
-                self.dig();
+                    match subtree.borrow() {
+                        Node::Branch { .. } => self.stack.push((subtree, 0)),
+                        node => self.seek(node, 0),
+                    }
+                }
             }
         }
     }
+}
+
+/// Statistics about the shape of a map, as returned by [`HashTrieMap::stats()`].
+///
+/// Entries are stored directly in the branch nodes, unless their hash is fully shared with other
+/// keys, in which case they are in a collision node.  A small map has no branches: its entries
+/// are all in a flat root node.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Stats {
+    /// Number of nodes in the trie, including the root.  This is the number of branch nodes plus
+    /// the number of collision nodes, or `1` for a small map with a flat root.
+    pub node_count: usize,
+    /// Number of branch nodes.
+    pub branch_count: usize,
+    /// Number of entries stored directly in branch nodes, or in the flat root of a small map.
+    pub leaf_count: usize,
+    /// Number of collision nodes.
+    pub collision_count: usize,
+    /// Number of buckets in collision nodes, where entries are grouped by a secondary hash.  If
+    /// this is much lower than the number of entries in collision nodes the hasher is poor.
+    pub collision_bucket_count: usize,
+    /// Number of entries at each depth, where the entries of the root node are at depth `0`.
+    pub depth_histogram: Vec<usize>,
+    /// Number of slots of branch nodes that hold either an entry or a subtree.
+    pub occupied_slot_count: usize,
+    /// Number of slots of branch nodes, which is the number of branch nodes times the degree.
+    pub slot_count: usize,
+    /// Number of nodes shared by pointer with the other map given to
+    /// [`HashTrieMap::stats_shared_with()`].  This is `0` for [`HashTrieMap::stats()`].
+    pub shared_node_count: usize,
+}
 
-    fn current(&mut self) -> Option<&'a SharedPointer<Entry<K, V>, P>> {
-        self.stack.last_mut().map(|e| e.current_elem())
+impl Stats {
+    /// Returns the fraction of the slots of branch nodes that are occupied, between `0` and `1`.
+    #[must_use]
+    pub fn average_fill(&self) -> f64 {
+        match self.slot_count {
+            0 => 0.0,
+            slot_count => self.occupied_slot_count as f64 / slot_count as f64,
+        }
     }
 }
 
-impl<'a, K, V, P> Iterator for IterPtr<'a, K, V, P>
+/// A difference between two maps, as returned by [`HashTrieMap::diff()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffItem<'a, K, V> {
+    /// An entry that is only in the second map.
+    Added(&'a K, &'a V),
+    /// An entry that is only in the first map.
+    Removed(&'a K, &'a V),
+    /// A key present in both maps, with its value in the first map and in the second map.
+    Changed(&'a K, &'a V, &'a V),
+}
+
+/// Iterator over the differences between two maps, created by [`HashTrieMap::diff()`].
+///
+/// The iterator walks both tries together, keeping a stack of the subtrees it still has to
+/// compare.  Subtrees that are the same node in both tries are skipped.
+#[derive(Debug)]
+pub struct Diff<'a, K, V, P, H: BuildHasher, W: TrieHash = u64>
+where
+    P: SharedPointerKind,
+{
+    left: &'a HashTrieMap<K, V, P, H, W>,
+    right: &'a HashTrieMap<K, V, P, H, W>,
+    /// If `true` the tries cannot be walked together, because they have different layouts or
+    /// because only one of them is flat, so every entry is looked up in the other map.
+    by_lookup: bool,
+    stack: Vec<DiffStackElement<'a, K, V, P, W>>,
+    pending: Vec<DiffItem<'a, K, V>>,
+}
+
+#[derive(Debug)]
+enum DiffStackElement<'a, K, V, P, W>
+where
+    P: SharedPointerKind,
+{
+    /// Subtrees at the same position of both tries.
+    Both(&'a Node<K, V, P, W>, &'a Node<K, V, P, W>, usize),
+    /// Subtree of the left trie whose entries are not in the right trie, except for the key of
+    /// the entry, which was already compared.
+    Removed(&'a Node<K, V, P, W>, Option<&'a EntryWithHash<K, V, P, W>>),
+    /// Subtree of the right trie whose entries are not in the left trie, except for the key of
+    /// the entry, which was already compared.
+    Added(&'a Node<K, V, P, W>, Option<&'a EntryWithHash<K, V, P, W>>),
+}
+
+impl<'a, K, V, P, H: BuildHasher, W: TrieHash> Diff<'a, K, V, P, H, W>
 where
     K: Eq + Hash,
+    V: PartialEq,
+    H: Clone,
     P: SharedPointerKind,
 {
-    type Item = &'a SharedPointer<Entry<K, V>, P>;
+    fn new(
+        left: &'a HashTrieMap<K, V, P, H, W>,
+        right: &'a HashTrieMap<K, V, P, H, W>,
+    ) -> Diff<'a, K, V, P, H, W> {
+        let by_lookup = !left.has_same_layout(right)
+            || matches!(*left.root, Node::Flat(_)) != matches!(*right.root, Node::Flat(_));
+        let stack = if by_lookup {
+            vec![
+                DiffStackElement::Added(right.root.borrow(), None),
+                DiffStackElement::Removed(left.root.borrow(), None),
+            ]
+        } else {
+            vec![DiffStackElement::Both(left.root.borrow(), right.root.borrow(), 0)]
+        };
 
-    fn next(&mut self) -> Option<&'a SharedPointer<Entry<K, V>, P>> {
-        let current = self.current();
+        Diff { left, right, by_lookup, stack, pending: Vec::new() }
+    }
 
-        self.advance();
+    fn changed(
+        left: &'a EntryWithHash<K, V, P, W>,
+        right: &'a EntryWithHash<K, V, P, W>,
+    ) -> Option<DiffItem<'a, K, V>> {
+        let unchanged =
+            SharedPointer::ptr_eq(&left.entry, &right.entry) || left.value() == right.value();
 
-        if current.is_some() {
-            self.size -= 1;
+        (!unchanged).then(|| DiffItem::Changed(left.key(), left.value(), right.value()))
+    }
+
+    fn compare(&mut self, left: &'a Node<K, V, P, W>, right: &'a Node<K, V, P, W>, depth: usize) {
+        let degree = self.left.degree;
+        let left_secondary_hasher = &self.left.hashers.secondary_hasher;
+        let right_secondary_hasher = &self.right.hashers.secondary_hasher;
+
+        match (left, right) {
+            (
+                Node::Branch { entries: left_entries, subtrees: left_subtrees, .. },
+                Node::Branch { entries: right_entries, subtrees: right_subtrees, .. },
+            ) => {
+                let mut indexes = left_entries.bitmap()
+                    | left_subtrees.bitmap()
+                    | right_entries.bitmap()
+                    | right_subtrees.bitmap();
+
+                while indexes != 0 {
+                    let index = indexes.trailing_zeros() as usize;
+
+                    indexes &= indexes - 1;
+
+                    match (left.slot(index), right.slot(index)) {
+                        (Slot::Subtree(l), Slot::Subtree(r)) => {
+                            self.stack.push(DiffStackElement::Both(l, r, depth + 1));
+                        }
+                        (Slot::Subtree(l), Slot::Empty) => {
+                            self.stack.push(DiffStackElement::Removed(l, None));
+                        }
+                        (Slot::Empty, Slot::Subtree(r)) => {
+                            self.stack.push(DiffStackElement::Added(r, None));
+                        }
+                        (Slot::Entry(l), Slot::Entry(r)) if l.matches(r.key(), r.key_hash) => {
+                            self.pending.extend(Self::changed(l, r));
+                        }
+                        (Slot::Entry(l), Slot::Subtree(r)) => {
+                            match r.get(
+                                l.key(),
+                                l.key_hash,
+                                depth + 1,
+                                degree,
+                                right_secondary_hasher,
+                            ) {
+                                Some(r_entry) => self.pending.extend(Self::changed(l, r_entry)),
+                                None => self.pending.push(DiffItem::Removed(l.key(), l.value())),
+                            }
+
+                            self.stack.push(DiffStackElement::Added(r, Some(l)));
+                        }
+                        (Slot::Subtree(l), Slot::Entry(r)) => {
+                            match l.get(
+                                r.key(),
+                                r.key_hash,
+                                depth + 1,
+                                degree,
+                                left_secondary_hasher,
+                            ) {
+                                Some(l_entry) => self.pending.extend(Self::changed(l_entry, r)),
+                                None => self.pending.push(DiffItem::Added(r.key(), r.value())),
+                            }
+
+                            self.stack.push(DiffStackElement::Removed(l, Some(r)));
+                        }
+                        (l, r) => {
+                            if let Slot::Entry(l) = l {
+                                self.pending.push(DiffItem::Removed(l.key(), l.value()));
+                            }
+                            if let Slot::Entry(r) = r {
+                                self.pending.push(DiffItem::Added(r.key(), r.value()));
+                            }
+                        }
+                    }
+                }
+            }
+            (Node::Collision(left_entries), Node::Collision(right_entries)) => {
+                self.compare_entries(left, right, left_entries.iter(), right_entries.iter(), depth);
+            }
+            (Node::Flat(left_entries), Node::Flat(right_entries)) => {
+                self.compare_entries(left, right, left_entries.iter(), right_entries.iter(), depth);
+            }
+            _ => unreachable!("nodes at the same depth are of the same kind"),
+        }
+    }
+
+    /// Compares two nodes that keep their entries in a list, `left_entries` and `right_entries`,
+    /// by looking up each entry in the other node.
+    fn compare_entries(
+        &mut self,
+        left: &'a Node<K, V, P, W>,
+        right: &'a Node<K, V, P, W>,
+        left_entries: impl Iterator<Item = &'a EntryWithHash<K, V, P, W>>,
+        right_entries: impl Iterator<Item = &'a EntryWithHash<K, V, P, W>>,
+        depth: usize,
+    ) {
+        let degree = self.left.degree;
+        let left_secondary_hasher = &self.left.hashers.secondary_hasher;
+        let right_secondary_hasher = &self.right.hashers.secondary_hasher;
+
+        for l in left_entries {
+            match right.get(l.key(), l.key_hash, depth, degree, right_secondary_hasher) {
+                Some(r) => self.pending.extend(Self::changed(l, r)),
+                None => self.pending.push(DiffItem::Removed(l.key(), l.value())),
+            }
+        }
+
+        for r in right_entries {
+            if left.get(r.key(), r.key_hash, depth, degree, left_secondary_hasher).is_none() {
+                self.pending.push(DiffItem::Added(r.key(), r.value()));
+            }
         }
+    }
 
-        current
+    /// Returns `true` if the entry has the key of `except`, so it was already compared.
+    fn compared(except: Option<&EntryWithHash<K, V, P, W>>, e: &EntryWithHash<K, V, P, W>) -> bool {
+        match except {
+            Some(except) => except.matches(e.key(), e.key_hash),
+            None => false,
+        }
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.size, Some(self.size))
+    fn removed(&mut self, l: &'a EntryWithHash<K, V, P, W>) {
+        if self.by_lookup {
+            let right_hash = node_utils::hash(l.key(), &self.right.hashers.hasher_builder);
+
+            match self.right.root.get(
+                l.key(),
+                right_hash,
+                0,
+                self.right.degree,
+                &self.right.hashers.secondary_hasher,
+            ) {
+                Some(r) => self.pending.extend(Self::changed(l, r)),
+                None => self.pending.push(DiffItem::Removed(l.key(), l.value())),
+            }
+        } else {
+            self.pending.push(DiffItem::Removed(l.key(), l.value()));
+        }
+    }
+
+    fn added(&mut self, r: &'a EntryWithHash<K, V, P, W>) {
+        // When looking up keys, the keys in both maps were already handled by `removed()`.
+        let in_left = self.by_lookup && {
+            let left_hash = node_utils::hash(r.key(), &self.left.hashers.hasher_builder);
+
+            self.left
+                .root
+                .get(r.key(), left_hash, 0, self.left.degree, &self.left.hashers.secondary_hasher)
+                .is_some()
+        };
+
+        if !in_left {
+            self.pending.push(DiffItem::Added(r.key(), r.value()));
+        }
     }
 }
 
-impl<'a, K: Eq + Hash, V, P> ExactSizeIterator for IterPtr<'a, K, V, P> where P: SharedPointerKind {}
+impl<'a, K, V, P, H: BuildHasher, W: TrieHash> Iterator for Diff<'a, K, V, P, H, W>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+    H: Clone,
+    P: SharedPointerKind,
+{
+    type Item = DiffItem<'a, K, V>;
+
+    fn next(&mut self) -> Option<DiffItem<'a, K, V>> {
+        loop {
+            if let Some(item) = self.pending.pop() {
+                return Some(item);
+            }
+
+            match self.stack.pop()? {
+                DiffStackElement::Both(left, right, _) if core::ptr::eq(left, right) => (),
+                DiffStackElement::Both(left, right, depth) => self.compare(left, right, depth),
+                DiffStackElement::Removed(Node::Branch { entries, subtrees, .. }, except) => {
+                    for l in entries.iter().filter(|l| !Self::compared(except, l)) {
+                        self.removed(l);
+                    }
+
+                    self.stack
+                        .extend(subtrees.iter().map(|s| DiffStackElement::Removed(s, except)));
+                }
+                DiffStackElement::Added(Node::Branch { entries, subtrees, .. }, except) => {
+                    for r in entries.iter().filter(|r| !Self::compared(except, r)) {
+                        self.added(r);
+                    }
+
+                    self.stack.extend(subtrees.iter().map(|s| DiffStackElement::Added(s, except)));
+                }
+                DiffStackElement::Removed(Node::Collision(entries), except) => {
+                    for l in entries.iter().filter(|l| !Self::compared(except, l)) {
+                        self.removed(l);
+                    }
+                }
+                DiffStackElement::Added(Node::Collision(entries), except) => {
+                    for r in entries.iter().filter(|r| !Self::compared(except, r)) {
+                        self.added(r);
+                    }
+                }
+                DiffStackElement::Removed(Node::Flat(entries), except) => {
+                    for l in entries.iter().filter(|l| !Self::compared(except, l)) {
+                        self.removed(l);
+                    }
+                }
+                DiffStackElement::Added(Node::Flat(entries), except) => {
+                    for r in entries.iter().filter(|r| !Self::compared(except, r)) {
+                        self.added(r);
+                    }
+                }
+            }
+        }
+    }
+}
 
 #[cfg(feature = "serde")]
 pub mod serde {
@@ -1177,7 +5066,19 @@ pub mod serde {
     use core::fmt;
     use core::marker::PhantomData;
 
-    impl<K, V, P, H> Serialize for HashTrieMap<K, V, P, H>
+    impl<W: Serialize> Serialize for Cursor<W> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.last.serialize(serializer)
+        }
+    }
+
+    impl<'de, W: Deserialize<'de>> Deserialize<'de> for Cursor<W> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Cursor<W>, D::Error> {
+            Option::deserialize(deserializer).map(|last| Cursor { last })
+        }
+    }
+
+    impl<K, V, P, H, W: TrieHash> Serialize for HashTrieMap<K, V, P, H, W>
     where
         K: Eq + Hash + Serialize,
         V: Serialize,
@@ -1189,7 +5090,7 @@ pub mod serde {
         }
     }
 
-    impl<'de, K, V, P, H> Deserialize<'de> for HashTrieMap<K, V, P, H>
+    impl<'de, K, V, P, H, W: TrieHash> Deserialize<'de> for HashTrieMap<K, V, P, H, W>
     where
         K: Eq + Hash + Deserialize<'de>,
         V: Deserialize<'de>,
@@ -1198,48 +5099,51 @@ pub mod serde {
     {
         fn deserialize<D: Deserializer<'de>>(
             deserializer: D,
-        ) -> Result<HashTrieMap<K, V, P, H>, D::Error> {
+        ) -> Result<HashTrieMap<K, V, P, H, W>, D::Error> {
             deserializer.deserialize_map(HashTrieMapVisitor {
                 _phantom_entry: PhantomData,
                 _phantom_h: PhantomData,
                 _phantom_p: PhantomData,
+                _phantom_w: PhantomData,
             })
         }
     }
 
-    struct HashTrieMapVisitor<K, V, P, H>
+    struct HashTrieMapVisitor<K, V, P, H, W>
     where
         P: SharedPointerKind,
     {
         _phantom_entry: PhantomData<(K, V)>,
         _phantom_h: PhantomData<H>,
         _phantom_p: PhantomData<P>,
+        _phantom_w: PhantomData<W>,
     }
 
-    impl<'de, K, V, P, H> Visitor<'de> for HashTrieMapVisitor<K, V, P, H>
+    impl<'de, K, V, P, H, W: TrieHash> Visitor<'de> for HashTrieMapVisitor<K, V, P, H, W>
     where
         K: Eq + Hash + Deserialize<'de>,
         V: Deserialize<'de>,
         H: BuildHasher + Clone + Default,
         P: SharedPointerKind,
     {
-        type Value = HashTrieMap<K, V, P, H>;
+        type Value = HashTrieMap<K, V, P, H, W>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
             formatter.write_str("a map")
         }
 
-        fn visit_map<A>(self, mut map: A) -> Result<HashTrieMap<K, V, P, H>, A::Error>
+        fn visit_map<A>(self, mut map: A) -> Result<HashTrieMap<K, V, P, H, W>, A::Error>
         where
             A: MapAccess<'de>,
         {
-            let mut hash_trie_map = HashTrieMap::new_with_hasher_and_ptr_kind(Default::default());
+            let mut builder =
+                HashTrieMap::new_with_hasher_and_ptr_kind(Default::default()).into_builder();
 
             while let Some((k, v)) = map.next_entry()? {
-                hash_trie_map.insert_mut(k, v);
+                builder.insert(k, v);
             }
 
-            Ok(hash_trie_map)
+            Ok(builder.build())
         }
     }
 }
@@ -33,6 +33,12 @@ impl<T> SparseArrayUsize<T> {
         SparseArrayUsize { bitmap: 0, array: Vec::new() }
     }
 
+    /// Creates an empty array with room for `capacity` elements, for an array built with its
+    /// final content.
+    pub fn with_capacity(capacity: usize) -> SparseArrayUsize<T> {
+        SparseArrayUsize { bitmap: 0, array: Vec::with_capacity(capacity) }
+    }
+
     #[inline]
     pub fn get(&self, index: usize) -> Option<&T> {
         debug_assert!(index < 8 * size_of_val(&self.bitmap));
@@ -52,11 +58,6 @@ impl<T> SparseArrayUsize<T> {
         self.array.first()
     }
 
-    #[inline]
-    pub fn pop(&mut self) -> Option<T> {
-        self.array.pop()
-    }
-
     pub fn set(&mut self, index: usize, value: T) {
         debug_assert!(index < 8 * size_of_val(&self.bitmap));
 
@@ -72,11 +73,17 @@ impl<T> SparseArrayUsize<T> {
         }
     }
 
-    pub fn remove(&mut self, index: usize) {
-        if let Some(i) = sparse_array_usize_utils::map_index(self.bitmap, index) {
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        sparse_array_usize_utils::map_index(self.bitmap, index).map(|i| {
             self.bitmap ^= 1 << index;
-            self.array.remove(i);
-        }
+            self.array.remove(i)
+        })
+    }
+
+    /// Returns the bitmap of the indexes that are set.
+    #[inline]
+    pub fn bitmap(&self) -> usize {
+        self.bitmap
     }
 
     #[inline]
@@ -87,6 +94,24 @@ impl<T> SparseArrayUsize<T> {
     pub fn iter(&self) -> slice::Iter<'_, T> {
         self.array.iter()
     }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        &self.array
+    }
+
+    /// Iterates over the elements along with their index.
+    pub fn iter_with_index(&self) -> impl Iterator<Item = (usize, &T)> {
+        let mut bitmap = self.bitmap;
+
+        self.array.iter().map(move |value| {
+            let index = bitmap.trailing_zeros() as usize;
+
+            bitmap &= bitmap - 1;
+
+            (index, value)
+        })
+    }
 }
 
 impl<T: Clone> Clone for SparseArrayUsize<T> {
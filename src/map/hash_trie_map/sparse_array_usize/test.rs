@@ -54,13 +54,13 @@ fn test_remove() {
     assert_eq!(array.get(3), Some(&'a'));
     assert_eq!(array.get(60), Some(&'b'));
 
-    array.remove(32);
+    assert_eq!(array.remove(32), None);
 
     assert_eq!(array.get(3), Some(&'a'));
     assert_eq!(array.get(60), Some(&'b'));
     assert_eq!(array.size(), 2);
 
-    array.remove(3);
+    assert_eq!(array.remove(3), Some('a'));
 
     assert_eq!(array.get(3), None);
     assert_eq!(array.get(60), Some(&'b'));
@@ -92,18 +92,6 @@ fn test_first() {
     assert_eq!(array.first(), Some(&'c'));
 }
 
-#[test]
-fn test_pop() {
-    let mut array = SparseArrayUsize::new();
-
-    array.set(60, 'b');
-    array.set(31, 'a');
-
-    assert_eq!(array.pop(), Some('b'));
-    assert_eq!(array.pop(), Some('a'));
-    assert_eq!(array.pop(), None);
-}
-
 #[test]
 fn test_map_index() {
     for i in 0..(8 * size_of::<usize>()) {
@@ -129,3 +117,19 @@ fn test_map_index() {
 
     assert_eq!(sparse_array_usize_utils::map_index(bitmap, 12), None);
 }
+
+#[test]
+fn test_bitmap() {
+    let mut array = SparseArrayUsize::new();
+
+    assert_eq!(array.bitmap(), 0);
+
+    array.set(3, 'a');
+    array.set(5, 'b');
+
+    assert_eq!(array.bitmap(), 0b10_1000);
+
+    array.remove(3);
+
+    assert_eq!(array.bitmap(), 0b10_0000);
+}
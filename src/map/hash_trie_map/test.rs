@@ -4,6 +4,8 @@
  */
 
 use super::*;
+use alloc::borrow::ToOwned;
+use alloc::string::{String, ToString};
 use pretty_assertions::assert_eq;
 use static_assertions::assert_impl_all;
 
@@ -14,23 +16,6 @@ fn compile_time_macro_hash_trie_map_sync_is_send_and_sync() -> impl Send + Sync
     ht_map_sync!(0 => 0)
 }
 
-impl<K: PartialEq, V: PartialEq, P> PartialEq for Bucket<K, V, P>
-where
-    P: SharedPointerKind,
-{
-    fn eq(&self, other: &Bucket<K, V, P>) -> bool {
-        match (self, other) {
-            (Bucket::Single(self_entry), Bucket::Single(other_entry)) => self_entry.eq(other_entry),
-            (Bucket::Collision(self_entry_list), Bucket::Collision(other_entry_list)) => {
-                self_entry_list.eq(other_entry_list)
-            }
-            _ => false,
-        }
-    }
-}
-
-impl<K: Eq, V: Eq, P> Eq for Bucket<K, V, P> where P: SharedPointerKind {}
-
 impl<K: PartialEq, V: PartialEq, P> PartialEq for EntryWithHash<K, V, P>
 where
     P: SharedPointerKind,
@@ -48,10 +33,19 @@ where
 {
     fn eq(&self, other: &Node<K, V, P>) -> bool {
         match (self, other) {
-            (Node::Branch(self_children), Node::Branch(other_children)) => {
-                self_children.eq(other_children)
+            (
+                Node::Branch { entries: self_entries, subtrees: self_subtrees, .. },
+                Node::Branch { entries: other_entries, subtrees: other_subtrees, .. },
+            ) => self_entries.eq(other_entries) && self_subtrees.eq(other_subtrees),
+            // The buckets depend on the secondary hasher, which differs from map to map, so we only
+            // compare the entries.
+            (Node::Collision(self_entries), Node::Collision(other_entries)) => {
+                let other_iter = || other_entries.buckets.values().flatten();
+
+                self_entries.size == other_entries.size
+                    && self_entries.buckets.values().flatten().all(|e| other_iter().any(|o| o == e))
             }
-            (Node::Leaf(self_bucket), Node::Leaf(other_bucket)) => self_bucket.eq(other_bucket),
+            (Node::Flat(self_entries), Node::Flat(other_entries)) => self_entries.eq(other_entries),
             _ => false,
         }
     }
@@ -59,13 +53,13 @@ where
 
 impl<K: Eq, V: Eq, P> Eq for Node<K, V, P> where P: SharedPointerKind {}
 
-mod bucket {
+mod collision {
     use super::*;
     use pretty_assertions::assert_eq;
 
     #[test]
     fn test_list_remove_first() {
-        use bucket_utils::list_remove_first;
+        use collision_utils::list_remove_first;
 
         let list_a_b_c = list!['a', 'b', 'c'];
         let list_b_c = list!['b', 'c'];
@@ -89,6 +83,28 @@ mod bucket {
         assert_eq!(list, list_a_b);
     }
 
+    // A collision node ignores the depth, so any depth works in these tests.
+    const DEPTH: usize = 16;
+    const DEGREE: u8 = 16;
+
+    /// A hasher where every key has the same primary and secondary hash.
+    fn constant_hash_builder() -> hasher_mocks::MockedHashBuilder {
+        hasher_mocks::MockedHashBuilder::new((0..=255).map(|b| (b, 0x42)).collect())
+    }
+
+    fn collision<K: Eq + Hash, V, H: BuildHasher>(
+        entries: Vec<EntryWithHash<K, V>>,
+        hash_builder: &H,
+    ) -> Node<K, V> {
+        let mut node = Node::Collision(CollisionEntries::new());
+
+        for entry in entries {
+            node.insert(entry, DEPTH, DEGREE, hash_builder);
+        }
+
+        node
+    }
+
     #[test]
     fn test_get() {
         let hash_builder = crate::utils::DefaultBuildHasher::default();
@@ -97,15 +113,38 @@ mod bucket {
         let entry_b: EntryWithHash<_, _> = EntryWithHash::new(0xBu8, 1, &hash_builder);
         let entry_c: EntryWithHash<_, _> = EntryWithHash::new(0xCu8, 2, &hash_builder);
 
-        let bucket_single = Bucket::Single(entry_a.clone());
-        let bucket_collision = Bucket::Collision(list![entry_b.clone(), entry_a.clone()]);
+        let node = collision(vec![entry_a.clone(), entry_b.clone()], &hash_builder);
+
+        assert_eq!(
+            node.get(entry_a.key(), entry_a.key_hash, DEPTH, DEGREE, &hash_builder),
+            Some(entry_a.borrow())
+        );
+        assert_eq!(
+            node.get(entry_b.key(), entry_b.key_hash, DEPTH, DEGREE, &hash_builder),
+            Some(entry_b.borrow())
+        );
+        assert_eq!(node.get(entry_c.key(), entry_c.key_hash, DEPTH, DEGREE, &hash_builder), None);
+    }
+
+    #[test]
+    fn test_get_same_secondary_hash() {
+        let hash_builder = constant_hash_builder();
 
-        assert_eq!(bucket_single.get(entry_a.key(), entry_a.key_hash), Some(entry_a.borrow()));
-        assert_eq!(bucket_single.get(entry_b.key(), entry_b.key_hash), None);
+        let entry_a: EntryWithHash<_, _> = EntryWithHash::new(0xAu8, 0, &hash_builder);
+        let entry_b: EntryWithHash<_, _> = EntryWithHash::new(0xBu8, 1, &hash_builder);
+        let entry_c: EntryWithHash<_, _> = EntryWithHash::new(0xCu8, 2, &hash_builder);
 
-        assert_eq!(bucket_collision.get(entry_a.key(), entry_a.key_hash), Some(entry_a.borrow()));
-        assert_eq!(bucket_collision.get(entry_b.key(), entry_b.key_hash), Some(entry_b.borrow()));
-        assert_eq!(bucket_collision.get(entry_c.key(), entry_c.key_hash), None);
+        let node = collision(vec![entry_a.clone(), entry_b.clone()], &hash_builder);
+
+        assert_eq!(
+            node.get(entry_a.key(), entry_a.key_hash, DEPTH, DEGREE, &hash_builder),
+            Some(entry_a.borrow())
+        );
+        assert_eq!(
+            node.get(entry_b.key(), entry_b.key_hash, DEPTH, DEGREE, &hash_builder),
+            Some(entry_b.borrow())
+        );
+        assert_eq!(node.get(entry_c.key(), entry_c.key_hash, DEPTH, DEGREE, &hash_builder), None);
     }
 
     #[test]
@@ -113,85 +152,95 @@ mod bucket {
         let hash_builder = crate::utils::DefaultBuildHasher::default();
 
         let entry_a: EntryWithHash<_, _> = EntryWithHash::new(0xAu8, 0, &hash_builder);
-        let entry_a9: EntryWithHash<_, _> = EntryWithHash::new(0xAu8, 9, &hash_builder);
+        let entry_b: EntryWithHash<_, _> = EntryWithHash::new(0xBu8, 1, &hash_builder);
+        let entry_b9: EntryWithHash<_, _> = EntryWithHash::new(0xBu8, 9, &hash_builder);
+
+        let mut node = collision(vec![entry_a.clone()], &hash_builder);
+
+        assert!(node.insert(entry_b.clone(), DEPTH, DEGREE, &hash_builder).is_none());
+        assert!(node.insert(entry_b9.clone(), DEPTH, DEGREE, &hash_builder).is_some());
+        assert_eq!(node, collision(vec![entry_a.clone(), entry_b9.clone()], &hash_builder));
+        assert_eq!(node.size(), 2);
+
+        match &node {
+            Node::Collision(entries) => assert_eq!(entries.buckets.size(), 2),
+            Node::Branch { .. } | Node::Flat(_) => panic!("expected a collision node"),
+        }
+    }
+
+    #[test]
+    fn test_insert_same_secondary_hash() {
+        let hash_builder = constant_hash_builder();
+
+        let entry_a: EntryWithHash<_, _> = EntryWithHash::new(0xAu8, 0, &hash_builder);
         let entry_b: EntryWithHash<_, _> = EntryWithHash::new(0xBu8, 1, &hash_builder);
         let entry_b9: EntryWithHash<_, _> = EntryWithHash::new(0xBu8, 9, &hash_builder);
         let entry_c: EntryWithHash<_, _> = EntryWithHash::new(0xCu8, 2, &hash_builder);
         let entry_d: EntryWithHash<_, _> = EntryWithHash::new(0xDu8, 2, &hash_builder);
 
-        let bucket_single_a = Bucket::Single(entry_a.clone());
-        let bucket_single_a9 = Bucket::Single(entry_a9.clone());
-        let bucket_collision_b_a = Bucket::Collision(list![entry_b.clone(), entry_a.clone()]);
-        let bucket_collision_a_b_c =
-            Bucket::Collision(list![entry_a.clone(), entry_b.clone(), entry_c.clone()]);
-        let bucket_collision_b9_a_c =
-            Bucket::Collision(list![entry_b9.clone(), entry_a.clone(), entry_c.clone()]);
-        let bucket_collision_d_a_b_c = Bucket::Collision(list![
-            entry_d.clone(),
-            entry_a.clone(),
-            entry_b.clone(),
-            entry_c.clone()
-        ]);
+        let bucket = |entries: List<EntryWithHash<u8, i32>>| {
+            let size = entries.len();
+            let mut buckets = RedBlackTreeMap::new_with_ptr_kind();
+
+            buckets.insert_mut(node_utils::secondary_hash(&0xAu8, &hash_builder), entries);
+
+            Node::Collision(CollisionEntries { buckets, size })
+        };
 
         // Note that we care about the position of the inserted entry: we want it to be in the
         // beginning of the list as to improve performance with high temporal locality (since
         // `get()` will try to match according to the list order).  The order of the rest of the
         // list must be preserved for the same reason.
 
-        let mut bucket = bucket_single_a.clone();
-        assert!(!bucket.insert(entry_a9.clone()));
-        assert_eq!(bucket, bucket_single_a9);
-
-        let mut bucket = bucket_single_a.clone();
-        assert!(bucket.insert(entry_b.clone()));
-        assert_eq!(bucket, bucket_collision_b_a);
+        let collision_a_b_c = bucket(list![entry_a.clone(), entry_b.clone(), entry_c.clone()]);
 
-        let mut bucket = bucket_collision_a_b_c.clone();
-        assert!(!bucket.insert(entry_b9.clone()));
-        assert_eq!(bucket, bucket_collision_b9_a_c);
+        let mut node = collision_a_b_c.clone();
+        assert!(node.insert(entry_b9.clone(), DEPTH, DEGREE, &hash_builder).is_some());
+        assert_eq!(node, bucket(list![entry_b9.clone(), entry_a.clone(), entry_c.clone()]));
 
-        let mut bucket = bucket_collision_a_b_c.clone();
-        assert!(bucket.insert(entry_d.clone()));
-        assert_eq!(bucket, bucket_collision_d_a_b_c);
+        let mut node = collision_a_b_c.clone();
+        assert!(node.insert(entry_d.clone(), DEPTH, DEGREE, &hash_builder).is_none());
+        assert_eq!(
+            node,
+            bucket(list![entry_d.clone(), entry_a.clone(), entry_b.clone(), entry_c.clone()])
+        );
     }
 
     #[test]
     fn test_remove() {
-        let hash_builder = crate::utils::DefaultBuildHasher::default();
-
-        let entry_a: EntryWithHash<u8, i32> = EntryWithHash::new(0xAu8, 0, &hash_builder);
-        let entry_b: EntryWithHash<u8, i32> = EntryWithHash::new(0xBu8, 1, &hash_builder);
-        let entry_c: EntryWithHash<u8, i32> = EntryWithHash::new(0xCu8, 2, &hash_builder);
-        let entry_d: EntryWithHash<u8, i32> = EntryWithHash::new(0xDu8, 2, &hash_builder);
-
-        let bucket_single_a = Bucket::Single(entry_a.clone());
-        let bucket_collision_b_c = Bucket::Collision(list![entry_b.clone(), entry_c.clone()]);
-        let bucket_collision_a_b_c =
-            Bucket::Collision(list![entry_a.clone(), entry_b.clone(), entry_c.clone()]);
-
-        let mut bucket_ref: Option<&mut Bucket<u8, i32>> = None;
-        assert!(!Bucket::remove(&mut bucket_ref, entry_a.key(), entry_a.key_hash));
-        assert_eq!(bucket_ref, None);
-
-        let mut bucket = bucket_single_a.clone();
-        let mut bucket_ref = Some(&mut bucket);
-        assert!(Bucket::remove(&mut bucket_ref, entry_a.key(), entry_a.key_hash));
-        assert_eq!(bucket_ref, None);
-
-        let mut bucket = bucket_single_a.clone();
-        let mut bucket_ref = Some(&mut bucket);
-        assert!(!Bucket::remove(&mut bucket_ref, entry_b.key(), entry_b.key_hash));
-        assert_eq!(bucket_ref, Some(&mut bucket_single_a.clone()));
-
-        let mut bucket = bucket_collision_a_b_c.clone();
-        let mut bucket_ref = Some(&mut bucket);
-        assert!(Bucket::remove(&mut bucket_ref, entry_a.key(), entry_a.key_hash));
-        assert_eq!(bucket_ref, Some(&mut bucket_collision_b_c.clone()));
-
-        let mut bucket = bucket_collision_a_b_c.clone();
-        let mut bucket_ref = Some(&mut bucket);
-        assert!(!Bucket::remove(&mut bucket_ref, entry_d.key(), entry_d.key_hash));
-        assert_eq!(bucket_ref, Some(&mut bucket_collision_a_b_c.clone()));
+        for hash_builder in [
+            hasher_mocks::MockedHashBuilder::new((0..=255).map(|b| (b, u64::from(b))).collect()),
+            constant_hash_builder(),
+        ] {
+            let entry_a: EntryWithHash<u8, i32> = EntryWithHash::new(0xAu8, 0, &hash_builder);
+            let entry_b: EntryWithHash<u8, i32> = EntryWithHash::new(0xBu8, 1, &hash_builder);
+            let entry_c: EntryWithHash<u8, i32> = EntryWithHash::new(0xCu8, 2, &hash_builder);
+            let entry_d: EntryWithHash<u8, i32> = EntryWithHash::new(0xDu8, 2, &hash_builder);
+
+            let collision_b_c = collision(vec![entry_c.clone(), entry_b.clone()], &hash_builder);
+            let collision_a_b_c =
+                collision(vec![entry_c.clone(), entry_b.clone(), entry_a.clone()], &hash_builder);
+
+            let mut node = collision_a_b_c.clone();
+            assert!(node
+                .remove(entry_a.key(), entry_a.key_hash, DEPTH, DEGREE, &hash_builder)
+                .is_some());
+            assert_eq!(node, collision_b_c);
+            assert_eq!(node.size(), 2);
+
+            let mut node = collision_a_b_c.clone();
+            assert!(node
+                .remove(entry_d.key(), entry_d.key_hash, DEPTH, DEGREE, &hash_builder)
+                .is_none());
+            assert_eq!(node, collision_a_b_c);
+
+            let mut node = collision(vec![entry_a.clone()], &hash_builder);
+            assert!(node
+                .remove(entry_a.key(), entry_a.key_hash, DEPTH, DEGREE, &hash_builder)
+                .is_some());
+            assert!(node.is_empty());
+            assert_eq!(node, Node::Collision(CollisionEntries::new()));
+        }
     }
 }
 
@@ -288,6 +337,59 @@ mod hasher_mocks {
             self.inner_hasher.write(bytes);
         }
     }
+
+    /// Gives the same hash to every key that is hashed with a single write, like the integers,
+    /// as if the keys were chosen to collide.
+    #[derive(Clone, Default)]
+    pub struct CollidingHashBuilder {
+        inner_hash_builder: crate::utils::DefaultBuildHasher,
+    }
+
+    pub struct CollidingHasher {
+        inner_hasher: Box<dyn core::hash::Hasher>,
+        writes: usize,
+    }
+
+    impl BuildHasher for CollidingHashBuilder {
+        type Hasher = CollidingHasher;
+
+        fn build_hasher(&self) -> CollidingHasher {
+            CollidingHasher {
+                inner_hasher: Box::new(self.inner_hash_builder.build_hasher()),
+                writes: 0,
+            }
+        }
+    }
+
+    impl Hasher for CollidingHasher {
+        fn finish(&self) -> HashValue {
+            if self.writes > 1 {
+                self.inner_hasher.finish()
+            } else {
+                0
+            }
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.writes += 1;
+            self.inner_hasher.write(bytes);
+        }
+    }
+
+    /// Gives the same hash to every value, whatever is written to it, as if the hasher was so
+    /// weak that every key collides.
+    #[derive(Default)]
+    pub struct ConstantHasher;
+
+    pub type ConstantHashBuilder = core::hash::BuildHasherDefault<ConstantHasher>;
+
+    impl Hasher for ConstantHasher {
+        fn finish(&self) -> HashValue {
+            0
+        }
+
+        fn write(&mut self, _: &[u8]) {}
+    }
 }
 
 mod node {
@@ -301,11 +403,23 @@ mod node {
         let node: Node<u32, u32> = Node::new_empty_branch();
 
         match node {
-            Node::Branch(array) => assert_eq!(array.size(), 0),
-            _ => panic!("Invalid node type"),
+            Node::Branch { entries, subtrees, .. } => {
+                assert_eq!(entries.size(), 0);
+                assert_eq!(subtrees.size(), 0);
+            }
+            Node::Collision(_) | Node::Flat(_) => panic!("Invalid node type"),
         }
     }
 
+    #[test]
+    fn test_new_empty() {
+        let branch: Node<u32, u32> = Node::new_empty(15, 16);
+        let collision: Node<u32, u32> = Node::new_empty(16, 16);
+
+        assert_eq!(branch, Node::new_empty_branch());
+        assert_eq!(collision, Node::Collision(CollisionEntries::new()));
+    }
+
     #[test]
     fn test_index_from_hash() {
         let hash: HashValue = 0b_000100_100011_000010_100001 | (1 << 63);
@@ -359,70 +473,74 @@ mod node {
     ///                                   ·
     ///                                   │
     ///                            0  ···   7   ···
-    ///                          ├───┼───┼─────┼───┤
-    ///                          │ ∅ │ ∅ │ D E │ ∅ │          depth 16 (maximum depth)
-    ///                          └───┴───┴─────┴───┘
+    ///                          ├───┼───┼───┼───┤
+    ///                          │ ∅ │ ∅ │ • │ ∅ │            depth 15
+    ///                          └───┴───┴─│─┴───┘
+    ///                                    │
+    ///                                 ├─────┤
+    ///                                 │ D E │               depth 16 (maximum depth)
+    ///                                 └─────┘
     /// ```
     fn dummy_hash_trie_map() -> HashTrieMap<u8, i32, RcK, MockedHashBuilder> {
-        let hash_builder: MockedHashBuilder = dummy_hash_builder();
-
-        let entry_a = EntryWithHash::new(0xAu8, 0, &hash_builder);
-        let entry_b = EntryWithHash::new(0xBu8, 1, &hash_builder);
-        let entry_c = EntryWithHash::new(0xCu8, 2, &hash_builder);
-        let entry_d = EntryWithHash::new(0xDu8, 3, &hash_builder);
-        let entry_e = EntryWithHash::new(0xEu8, 4, &hash_builder);
+        let hashers = Hashers::new(dummy_hash_builder());
+        let hash_builder = &hashers.hasher_builder;
 
-        let bucket_a = Bucket::Single(entry_a);
-        let bucket_b = Bucket::Single(entry_b);
-        let bucket_c = Bucket::Single(entry_c);
-        let bucket_de = Bucket::Collision(list![entry_e, entry_d]);
+        let entry_a = EntryWithHash::new(0xAu8, 0, hash_builder);
+        let entry_b = EntryWithHash::new(0xBu8, 1, hash_builder);
+        let entry_c = EntryWithHash::new(0xCu8, 2, hash_builder);
+        let entry_d = EntryWithHash::new(0xDu8, 3, hash_builder);
+        let entry_e = EntryWithHash::new(0xEu8, 4, hash_builder);
 
         let node_depth_1_first = {
-            let mut array = SparseArrayUsize::new();
+            let mut entries = SparseArrayUsize::new();
 
-            array.set(1, SharedPointer::new(Node::Leaf(bucket_b)));
-            array.set(2, SharedPointer::new(Node::Leaf(bucket_a)));
+            entries.set(1, entry_b);
+            entries.set(2, entry_a);
 
-            Node::Branch(array)
+            Node::branch(entries, SparseArrayUsize::new())
         };
 
         let node_maximum_depth = {
-            let mut array = SparseArrayUsize::new();
+            let mut subtrees = SparseArrayUsize::new();
 
-            array.set(7, SharedPointer::new(Node::Leaf(bucket_de)));
+            let collision =
+                Node::from_entries(vec![entry_d, entry_e], 16, 16, &hashers.secondary_hasher);
 
-            Node::Branch(array)
+            subtrees.set(7, SharedPointer::new(collision));
+
+            Node::branch(SparseArrayUsize::new(), subtrees)
         };
 
         let maximum_depth_branch = {
             let mut branch = node_maximum_depth;
 
             for _ in 0..14 {
-                let mut array = SparseArrayUsize::new();
+                let mut subtrees = SparseArrayUsize::new();
 
-                array.set(0, SharedPointer::new(branch));
+                subtrees.set(0, SharedPointer::new(branch));
 
-                branch = Node::Branch(array);
+                branch = Node::branch(SparseArrayUsize::new(), subtrees);
             }
 
             branch
         };
 
         let node_root = {
-            let mut array = SparseArrayUsize::new();
+            let mut entries = SparseArrayUsize::new();
+            let mut subtrees = SparseArrayUsize::new();
 
-            array.set(2, SharedPointer::new(Node::Leaf(bucket_c)));
-            array.set(6, SharedPointer::new(node_depth_1_first));
-            array.set(8, SharedPointer::new(maximum_depth_branch));
+            entries.set(2, entry_c);
+            subtrees.set(6, SharedPointer::new(node_depth_1_first));
+            subtrees.set(8, SharedPointer::new(maximum_depth_branch));
 
-            Node::Branch(array)
+            Node::branch(entries, subtrees)
         };
 
         HashTrieMap {
             root: SharedPointer::new(node_root),
             size: 5,
             degree: 16,
-            hasher_builder: hash_builder,
+            hashers: SharedPointer::new(hashers),
         }
     }
 
@@ -512,144 +630,137 @@ mod node {
         assert_eq!(map.get(&0x1), None);
         assert_eq!(map.get(&0x2), None);
 
-        assert_eq!(map.root, dummy_hash_trie_map().root);
+        // The map is small, so it is flat, but its entries make the same trie as the dummy map.
+        let mut entries = Vec::new();
+
+        map.root.collect_entries(&mut entries);
+
+        assert!(matches!(*map.root, Node::Flat(_)));
+        assert_eq!(
+            Node::from_entries(entries, 0, 16, &map.hashers.secondary_hasher),
+            *dummy_hash_trie_map().root
+        );
     }
 
     #[test]
-    fn test_compress() {
+    fn test_remove_inlines_single_entry() {
         let hash_builder: MockedHashBuilder = dummy_hash_builder();
 
         let entry_a: EntryWithHash<_, _> = EntryWithHash::new(0xAu8, 0, &hash_builder);
         let entry_b: EntryWithHash<_, _> = EntryWithHash::new(0xBu8, 1, &hash_builder);
+        let entry_d: EntryWithHash<_, _> = EntryWithHash::new(0xDu8, 3, &hash_builder);
+        let entry_e: EntryWithHash<_, _> = EntryWithHash::new(0xEu8, 4, &hash_builder);
+
+        let mut node: Node<u8, i32> =
+            Node::from_entries(vec![entry_a.clone(), entry_b], 0, 16, &hash_builder);
+
+        assert!(node
+            .remove(&0xB, node_utils::hash(&0xBu8, &hash_builder), 0, 16, &hash_builder)
+            .is_some());
+        assert_eq!(node, Node::from_entries(vec![entry_a.clone()], 0, 16, &hash_builder));
+
+        // The collision at the bottom of the tree is inlined all the way up.
+        let mut node: Node<u8, i32> = Node::from_entries(
+            vec![entry_a.clone(), entry_d, entry_e.clone()],
+            0,
+            16,
+            &hash_builder,
+        );
+
+        assert!(node
+            .remove(&0xD, node_utils::hash(&0xDu8, &hash_builder), 0, 16, &hash_builder)
+            .is_some());
+        assert_eq!(node, Node::from_entries(vec![entry_a, entry_e], 0, 16, &hash_builder));
 
-        let bucket_a = Bucket::Single(entry_a.clone());
-        let bucket_b = Bucket::Single(entry_b.clone());
-        let bucket_a_b = Bucket::Collision(list![entry_a.clone(), entry_b.clone()]);
-
-        let empty_branch = Node::<u8, i32>::new_empty_branch();
-        let branch_with_collision = {
-            let mut array = SparseArrayUsize::new();
-
-            array.set(4, SharedPointer::new(Node::Leaf(bucket_a_b.clone())));
+        match node {
+            Node::Branch { entries, subtrees, .. } => {
+                assert_eq!(entries.size(), 2);
+                assert_eq!(subtrees.size(), 0);
+            }
+            Node::Collision(_) | Node::Flat(_) => panic!("Invalid node type"),
+        }
+    }
 
-            SharedPointer::<_, RcK>::new(Node::Branch(array))
+    #[test]
+    fn test_canonical_shape() {
+        let limit = 2_000;
+        let hasher = crate::utils::DefaultBuildHasher::default();
+        let new_map = || -> HashTrieMap<u32, u32> {
+            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher.clone(), 4)
         };
-        let branch_with_two_subtrees = {
-            let mut array = SparseArrayUsize::new();
+        let mut map = new_map();
+        let mut reversed = new_map();
+
+        for i in 0..limit {
+            map.insert_mut(i, i);
+            reversed.insert_mut(limit - 1 - i, limit - 1 - i);
+        }
 
-            array.set(4, SharedPointer::new(Node::Leaf(bucket_a.clone())));
-            array.set(7, SharedPointer::new(Node::Leaf(bucket_b.clone())));
+        assert!(map.root == reversed.root);
 
-            SharedPointer::<_, RcK>::new(Node::Branch(array))
-        };
-        let branch_with_single_bucket = {
-            let mut array = SparseArrayUsize::new();
+        for i in (0..limit).filter(|i| i % 3 != 0) {
+            map.remove_mut(&i);
+        }
 
-            array.set(4, SharedPointer::new(Node::Leaf(bucket_a.clone())));
+        let expected = (0..limit).step_by(3).fold(new_map(), |m, i| m.insert(i, i));
 
-            Node::Branch(array)
-        };
-        let branch_with_branch = {
-            let mut array = SparseArrayUsize::new();
+        assert!(map.root == expected.root);
+    }
 
-            array.set(4, SharedPointer::new(Node::<u8, i32>::new_empty_branch()));
+    /// Builds the trie with the given keys of the dummy map, directly with the nodes, since a map
+    /// this small would be flat.
+    fn dummy_trie(keys: &[u8]) -> Node<u8, i32> {
+        let hash_builder = dummy_hash_builder();
+        let entries = keys
+            .iter()
+            .map(|k| EntryWithHash::new(*k, i32::from(*k) - 0xA, &hash_builder))
+            .collect();
 
-            SharedPointer::<_, RcK>::new(Node::Branch(array))
-        };
-        let leaf_with_single_bucket_a = SharedPointer::<_, RcK>::new(Node::Leaf(bucket_a.clone()));
-        let leaf_with_collision_bucket_a_b =
-            SharedPointer::<_, RcK>::new(Node::Leaf(bucket_a_b.clone()));
+        Node::from_entries(entries, 0, 16, &hash_builder)
+    }
 
-        let mut node = Node::clone(&empty_branch);
-        node.compress();
-        assert_eq!(node, empty_branch);
+    #[test]
+    fn test_remove() {
+        let hash_builder = dummy_hash_builder();
+        let remove = |node: &Node<u8, i32>, keys: &[u8]| {
+            let mut node = node.clone();
 
-        let mut node = Node::clone(&branch_with_collision);
-        node.compress();
-        assert_eq!(node, *branch_with_collision.borrow());
+            for key in keys {
+                node.remove(key, node_utils::hash(key, &hash_builder), 0, 16, &hash_builder);
+            }
 
-        let mut node = Node::clone(&branch_with_two_subtrees);
-        node.compress();
-        assert_eq!(node, *branch_with_two_subtrees.borrow());
+            node
+        };
 
-        let mut node = Node::clone(&branch_with_single_bucket);
-        node.compress();
-        assert_eq!(node, *leaf_with_single_bucket_a.borrow());
+        let trie_a_b_c_d_e = dummy_trie(&[0xA, 0xB, 0xC, 0xD, 0xE]);
 
-        let mut node = Node::clone(&branch_with_branch);
-        node.compress();
-        assert_eq!(node, *branch_with_branch.borrow());
+        // Just a sanity check.
+        assert_eq!(trie_a_b_c_d_e, *dummy_hash_trie_map().root);
 
-        let mut node = Node::clone(&leaf_with_single_bucket_a);
-        node.compress();
-        assert_eq!(node, *leaf_with_single_bucket_a.borrow());
+        assert_eq!(remove(&trie_a_b_c_d_e, &[0xC]), dummy_trie(&[0xA, 0xB, 0xD, 0xE]));
+        assert_eq!(remove(&trie_a_b_c_d_e, &[0xB]), dummy_trie(&[0xA, 0xC, 0xD, 0xE]));
+        assert_eq!(remove(&trie_a_b_c_d_e, &[0xB, 0xA]), dummy_trie(&[0xC, 0xD, 0xE]));
+        assert_eq!(remove(&trie_a_b_c_d_e, &[0xD]), dummy_trie(&[0xA, 0xB, 0xC, 0xE]));
+        assert_eq!(remove(&trie_a_b_c_d_e, &[0xD, 0xE]), dummy_trie(&[0xA, 0xB, 0xC]));
+        assert_eq!(remove(&trie_a_b_c_d_e, &[0xA, 0xB, 0xC, 0xD, 0xE]), dummy_trie(&[]));
 
-        let mut node = Node::clone(&leaf_with_collision_bucket_a_b);
-        node.compress();
-        assert_eq!(node, *leaf_with_collision_bucket_a_b.borrow());
-    }
+        assert_eq!(remove(&trie_a_b_c_d_e, &[0x0]), trie_a_b_c_d_e);
+        assert_eq!(remove(&trie_a_b_c_d_e, &[0x1]), trie_a_b_c_d_e);
+        assert_eq!(remove(&trie_a_b_c_d_e, &[0x2]), trie_a_b_c_d_e);
 
-    #[test]
-    fn test_remove() {
-        let map_a_b_c_d_e: HashTrieMap<_, _, RcK, _> =
+        let map: HashTrieMap<_, _, RcK, _> =
             HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(dummy_hash_builder(), 16)
                 .insert(0xA, 0)
                 .insert(0xB, 1)
                 .insert(0xC, 2)
                 .insert(0xD, 3)
                 .insert(0xE, 4);
-        let map_a_b_d_e: HashTrieMap<_, _, RcK, _> =
-            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(dummy_hash_builder(), 16)
-                .insert(0xA, 0)
-                .insert(0xB, 1)
-                .insert(0xD, 3)
-                .insert(0xE, 4);
-        let map_a_c_d_e: HashTrieMap<_, _, RcK, _> =
-            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(dummy_hash_builder(), 16)
-                .insert(0xA, 0)
-                .insert(0xC, 2)
-                .insert(0xD, 3)
-                .insert(0xE, 4);
-        let map_c_d_e: HashTrieMap<_, _, RcK, _> =
-            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(dummy_hash_builder(), 16)
-                .insert(0xC, 2)
-                .insert(0xD, 3)
-                .insert(0xE, 4);
-        let map_a_b_c_e: HashTrieMap<_, _, RcK, _> =
-            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(dummy_hash_builder(), 16)
-                .insert(0xA, 0)
-                .insert(0xB, 1)
-                .insert(0xC, 2)
-                .insert(0xE, 4);
-        let map_a_b_c: HashTrieMap<_, _, RcK, _> =
-            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(dummy_hash_builder(), 16)
-                .insert(0xA, 0)
-                .insert(0xB, 1)
-                .insert(0xC, 2);
-        let map_empty: HashTrieMap<_, _, RcK, _> =
-            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(dummy_hash_builder(), 16);
-
-        // Just a sanity check.
-        assert_eq!(map_a_b_c_d_e.root, dummy_hash_trie_map().root);
-
-        let removed_c = map_a_b_c_d_e.remove(&0xC);
-        let removed_b = map_a_b_c_d_e.remove(&0xB);
-        let removed_b_a = map_a_b_c_d_e.remove(&0xB).remove(&0xA);
-        let removed_d = map_a_b_c_d_e.remove(&0xD);
-        let removed_d_e = map_a_b_c_d_e.remove(&0xD).remove(&0xE);
-        let removed_all =
-            map_a_b_c_d_e.remove(&0xA).remove(&0xB).remove(&0xC).remove(&0xD).remove(&0xE);
 
-        assert_eq!(removed_c.root, map_a_b_d_e.root);
-        assert_eq!(removed_b.root, map_a_c_d_e.root);
-        assert_eq!(removed_b_a.root, map_c_d_e.root);
-        assert_eq!(removed_d.root, map_a_b_c_e.root);
-        assert_eq!(removed_d_e.root, map_a_b_c.root);
-        assert_eq!(removed_all.root, map_empty.root);
-
-        assert_eq!(map_a_b_c_d_e.remove(&0x0).root, map_a_b_c_d_e.root);
-        assert_eq!(map_a_b_c_d_e.remove(&0x1).root, map_a_b_c_d_e.root);
-        assert_eq!(map_a_b_c_d_e.remove(&0x2).root, map_a_b_c_d_e.root);
+        assert_eq!(map.remove(&0xC).size(), 4);
+        assert_eq!(map.remove(&0xC).get(&0xC), None);
+        assert_eq!(map.remove(&0xD).remove(&0xE).size(), 3);
+        assert!(map.remove(&0x0) == map);
+        assert!(map.remove(&0xA).remove(&0xB).remove(&0xC).remove(&0xD).remove(&0xE).is_empty());
     }
 }
 
@@ -659,10 +770,15 @@ mod iter {
 
     #[test]
     fn test_trie_max_height() {
-        assert_eq!(iter_utils::trie_max_height(2), 64);
-        assert_eq!(iter_utils::trie_max_height(16), 16);
-        assert_eq!(iter_utils::trie_max_height(32), 13);
-        assert_eq!(iter_utils::trie_max_height(64), 11);
+        assert_eq!(iter_utils::trie_max_height::<u64>(2), 64);
+        assert_eq!(iter_utils::trie_max_height::<u64>(16), 16);
+        assert_eq!(iter_utils::trie_max_height::<u64>(32), 13);
+        assert_eq!(iter_utils::trie_max_height::<u64>(64), 11);
+
+        assert_eq!(iter_utils::trie_max_height::<u32>(2), 32);
+        assert_eq!(iter_utils::trie_max_height::<u32>(32), 7);
+        assert_eq!(iter_utils::trie_max_height::<u128>(2), 128);
+        assert_eq!(iter_utils::trie_max_height::<u128>(32), 26);
     }
 
     #[test]
@@ -780,6 +896,79 @@ mod iter {
 
         assert_eq!(left, 0);
     }
+
+    fn fold_test<H: BuildHasher + Clone>(initial_map: HashTrieMap<u32, i32, RcK, H>) {
+        let mut map = initial_map;
+
+        for i in 0..5_000 {
+            map.insert_mut(i, -(i as i32));
+        }
+
+        let expected: Vec<u32> = map.keys().copied().collect();
+
+        for skip in &[0, 1, 17, 2_500, 4_999, 5_000] {
+            let mut iterator = map.keys();
+            let mut folded: Vec<u32> = iterator.by_ref().take(*skip).copied().collect();
+
+            iterator.fold((), |(), k| folded.push(*k));
+
+            assert_eq!(folded, expected);
+        }
+
+        let mut visited = 0;
+        map.iter().for_each(|(k, v)| {
+            assert_eq!(*k as i32, -*v);
+            visited += 1;
+        });
+
+        assert_eq!(visited, map.size());
+    }
+
+    #[test]
+    fn test_iter_fold() {
+        for degree in &[2, 4, 16, DEFAULT_DEGREE] {
+            fold_test(HashTrieMap::new_with_degree(*degree));
+        }
+    }
+
+    #[test]
+    fn test_iter_fold_high_collision() {
+        for degree in &[2, 4, 16, DEFAULT_DEGREE] {
+            let hasher = hasher_mocks::LimitedHashSpaceHashBuilder::new(1000);
+            fold_test(HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher, *degree));
+        }
+    }
+
+    #[test]
+    fn test_iter_stack_inline() {
+        let mut map: HashTrieMap<u32, i32> = HashTrieMap::new_with_degree(16);
+
+        for i in 0..1_000 {
+            map.insert_mut(i, -(i as i32));
+        }
+
+        assert_eq!(map.iter_ptr().deep_stack.capacity(), 0);
+
+        let mut deep_map: HashTrieMap<u32, i32> = HashTrieMap::new_with_degree(2);
+
+        for i in 0..1_000 {
+            deep_map.insert_mut(i, -(i as i32));
+        }
+
+        let mut iter = deep_map.iter_ptr();
+        let mut count = 0;
+
+        assert_eq!(iter.deep_stack.capacity(), 0);
+
+        while iter.next().is_some() {
+            count += 1;
+        }
+
+        // The trie of a map of degree 2 with this many entries is deeper than the inline stack.  The
+        // deeper levels are allocated once, for the maximum height of the trie.
+        assert_eq!(iter.deep_stack.capacity(), 64 - ITER_STACK_INLINE_SIZE);
+        assert_eq!(count, 1_000);
+    }
 }
 
 #[test]
@@ -1054,6 +1243,45 @@ fn test_remove_high_collision() {
     }
 }
 
+#[test]
+fn test_full_collision() {
+    let limit = 10_000;
+    let mut map: HashTrieMap<u32, i32, RcK, _> =
+        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(
+            hasher_mocks::CollidingHashBuilder::default(),
+            DEFAULT_DEGREE,
+        );
+
+    for i in 0..limit {
+        map.insert_mut(i, -(i as i32));
+    }
+
+    // Every key is in the same collision node, but each one has a bucket of its own.
+    let mut node: &Node<u32, i32> = &map.root;
+    let collision = loop {
+        match node {
+            Node::Branch { subtrees, .. } => node = subtrees.first().unwrap(),
+            Node::Collision(entries) => break entries,
+            Node::Flat(_) => panic!("expected a trie"),
+        }
+    };
+
+    assert_eq!(collision.len(), limit as usize);
+    assert_eq!(collision.buckets.size(), limit as usize);
+
+    for i in 0..limit {
+        assert_eq!(map.get(&i), Some(&-(i as i32)));
+    }
+
+    for i in (0..limit).step_by(2) {
+        assert!(map.remove_mut(&i));
+        assert!(!map.contains_key(&i));
+    }
+
+    assert_eq!(map.size(), limit as usize / 2);
+    assert_eq!(map.iter().count(), limit as usize / 2);
+}
+
 #[test]
 fn test_remove_simple_mut() {
     let mut map = ht_map![
@@ -1148,84 +1376,1823 @@ fn test_remove_high_collision_mut() {
 }
 
 #[test]
-fn test_index() {
-    let map = ht_map![5 => "hello", 12 => "there"];
+fn test_replace() {
+    let map = HashTrieMap::new().insert("a", 1).insert("b", 2);
 
-    assert_eq!(map[&5], "hello");
-    assert_eq!(map[&12], "there");
-}
+    let (new_map, previous) = map.replace("a", 10);
 
-#[test]
-fn test_from_iterator() {
-    let vec: Vec<(i32, &str)> = vec![(2, "two"), (5, "five")];
-    let map: HashTrieMap<i32, &str> = vec.iter().map(|v| *v).collect();
-    let expected_map = ht_map![2 => "two", 5 => "five"];
+    assert_eq!(previous, Some(1));
+    assert_eq!(new_map.get("a"), Some(&10));
+    assert_eq!(new_map.size(), 2);
+    assert_eq!(map.get("a"), Some(&1));
 
-    assert_eq!(map, expected_map);
+    let (new_map, previous) = map.replace("c", 3);
+
+    assert_eq!(previous, None);
+    assert_eq!(new_map.get("c"), Some(&3));
+    assert_eq!(new_map.size(), 3);
+    assert_eq!(map.size(), 2);
 }
 
 #[test]
-fn test_default() {
-    let map: HashTrieMap<u32, char> = HashTrieMap::default();
+fn test_replace_mut() {
+    let mut map = HashTrieMap::new();
+    let limit = 1_000;
 
-    assert_eq!(map.size(), 0);
-    assert!(map.is_empty());
-}
+    for i in 0..limit {
+        assert_eq!(map.replace_mut(i, -i), None);
+    }
 
-#[test]
-fn test_display() {
-    let empty_map: HashTrieMap<i32, i32> = HashTrieMap::new();
-    let singleton_map = ht_map!["hi" =>"hello"];
-    let map = ht_map![5 => "hello", 12 => "there"];
+    let snapshot = map.clone();
 
-    assert_eq!(format!("{}", empty_map), "{}");
-    assert_eq!(format!("{}", singleton_map), "{hi: hello}");
-    assert!(
-        format!("{}", map) == "{5: hello, 12: there}"
-            || format!("{}", map) == "{12: there, 5: hello}"
-    );
+    for i in 0..limit {
+        assert_eq!(map.replace_mut(i, i), Some(-i));
+        assert_eq!(map.get(&i), Some(&i));
+    }
+
+    assert_eq!(map.size(), limit as usize);
+    assert_eq!(snapshot.get(&0), Some(&0));
+    assert_eq!(snapshot.get(&1), Some(&-1));
 }
 
 #[test]
-fn test_eq() {
-    let map_1 = ht_map!["a" => 0xa, "b" => 0xb];
-    let map_1_prime = ht_map!["a" => 0xa, "b" => 0xb];
-    let map_1_prime_2 = ht_map!["a" => 0xa, "b" => 0xb, "b" => 0xb];
-    let map_2 = ht_map!["a" => 0xa, "b" => 0xb + 1];
-    let map_3 = ht_map!["a" => 0xa, "b" => 0xb + 1, "c" => 0xc];
+fn test_remove_entry() {
+    let map = HashTrieMap::new().insert("a", 1).insert("b", 2);
 
-    assert_eq!(map_1, map_1_prime);
-    assert_eq!(map_1, map_1_prime_2);
-    assert_eq!(map_1, map_1);
-    assert_eq!(map_2, map_2);
+    let (new_map, removed) = map.remove_entry("a");
 
-    // We also check this since `assert_ne!()` does not call `ne`.
-    assert!(map_1.ne(&map_2));
-    assert!(map_2.ne(&map_3));
-}
+    assert_eq!(removed, Some(("a", 1)));
+    assert_eq!(new_map.size(), 1);
+    assert!(!new_map.contains_key("a"));
+    assert_eq!(map.get("a"), Some(&1));
 
-#[test]
-fn test_eq_pointer_kind_consistent() {
-    let map_a = ht_map!["a" => 0];
-    let map_a_sync = ht_map_sync!["a" => 0];
-    let map_b = ht_map!["b" => 1];
-    let map_b_sync = ht_map_sync!["b" => 1];
+    let (new_map, removed) = map.remove_entry("z");
 
-    assert!(map_a == map_a_sync);
-    assert!(map_a != map_b_sync);
-    assert!(map_b == map_b_sync);
+    assert_eq!(removed, None);
+    assert_eq!(new_map, map);
 }
 
 #[test]
-fn test_clone() {
-    let map = ht_map!["hello" => 4, "there" => 5];
-    let clone = map.clone();
+fn test_remove_entry_mut() {
+    let mut map = HashTrieMap::new();
+    let limit = 1_000;
 
-    assert_eq!(clone.size(), map.size());
+    for i in 0..limit {
+        map.insert_mut(i, -i);
+    }
+
+    for i in 0..limit {
+        assert_eq!(map.remove_entry_mut(&i), Some((i, -i)));
+        assert_eq!(map.remove_entry_mut(&i), None);
+        assert_eq!(map.size(), (limit - i - 1) as usize);
+    }
+}
+
+#[test]
+fn test_replace_and_remove_entry_high_collision() {
+    let hasher = hasher_mocks::LimitedHashSpaceHashBuilder::new(10);
+    let mut map: HashTrieMap<_, _, RcK, _> =
+        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher, 4);
+    let limit = 200;
+
+    for i in 0..limit {
+        assert_eq!(map.replace_mut(i, -i), None);
+    }
+
+    for i in 0..limit {
+        assert_eq!(map.replace_mut(i, i), Some(-i));
+    }
+
+    for i in 0..limit {
+        assert_eq!(map.remove_entry_mut(&i), Some((i, i)));
+        assert_eq!(map.remove_entry_mut(&i), None);
+    }
+
+    assert!(map.is_empty());
+}
+
+#[test]
+fn test_take() {
+    let mut map = HashTrieMap::new().insert(1, "one").insert(2, "two");
+
+    let (new_map, taken) = map.take(&1);
+
+    assert_eq!(taken, Some("one"));
+    assert_eq!(new_map.size(), 1);
+    assert_eq!(map.size(), 2);
+
+    assert_eq!(map.take_mut(&2), Some("two"));
+    assert_eq!(map.take_mut(&2), None);
+    assert_eq!(map.size(), 1);
+}
+
+mod merge {
+    use super::*;
+    use core::cell::Cell;
+    use hasher_mocks::*;
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+
+    fn check_node<K: Eq + Hash, V, P: SharedPointerKind, H: BuildHasher>(
+        node: &Node<K, V, P>,
+        depth: usize,
+        degree: u8,
+        secondary_hasher: &H,
+    ) -> usize {
+        match node {
+            Node::Branch { entries, subtrees, .. } => {
+                assert_eq!(entries.bitmap() & subtrees.bitmap(), 0, "position with two contents");
+
+                for entry in entries.iter() {
+                    let found =
+                        node.get(entry.key(), entry.key_hash, depth, degree, secondary_hasher);
+
+                    assert!(
+                        matches!(found, Some(e) if SharedPointer::ptr_eq(&e.entry, &entry.entry))
+                    );
+                }
+
+                let size: usize = entries.size()
+                    + subtrees
+                        .iter()
+                        .map(|subtree| check_node(subtree, depth + 1, degree, secondary_hasher))
+                        .sum::<usize>();
+
+                if depth > 0 {
+                    assert!(size >= 2, "non-root branch with less than two entries");
+                }
+
+                assert_eq!(node.size(), size, "wrong branch size");
+
+                size
+            }
+            Node::Collision(entries) => {
+                let entry = entries.first().unwrap();
+
+                assert!(entries.len() >= 2);
+                assert!(node_utils::index_from_hash(entry.key_hash, depth, degree).is_none());
+
+                for (secondary_hash, bucket) in &entries.buckets {
+                    assert!(!bucket.is_empty(), "empty collision bucket");
+
+                    for e in bucket {
+                        assert_eq!(e.key_hash, entry.key_hash);
+                        assert_eq!(
+                            node_utils::secondary_hash(e.key(), secondary_hasher),
+                            *secondary_hash
+                        );
+                    }
+                }
+
+                assert_eq!(entries.iter().count(), entries.len());
+
+                entries.len()
+            }
+            Node::Flat(entries) => {
+                assert_eq!(depth, 0, "flat node below the root");
+                assert!(entries.len() <= FLAT_MAX_SIZE, "flat node too big");
+
+                entries.len()
+            }
+        }
+    }
+
+    pub fn check<H: BuildHasher + Clone>(
+        map: &HashTrieMap<u32, i64, RcK, H>,
+    ) -> BTreeMap<u32, i64> {
+        let entries: BTreeMap<u32, i64> = map.iter().map(|(k, v)| (*k, *v)).collect();
+
+        assert_eq!(check_node(&map.root, 0, map.degree, &map.hashers.secondary_hasher), map.size());
+        assert_eq!(matches!(*map.root, Node::Flat(_)), map.size() <= FLAT_MAX_SIZE);
+        assert_eq!(entries.len(), map.size());
+
+        for (k, v) in &entries {
+            assert_eq!(map.get(k), Some(v));
+        }
+
+        entries
+    }
+
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn combine(_: &u32, v: &i64, w: &i64) -> i64 {
+        1000 * v + w
+    }
+
+    /// Checks all operations against a `BTreeMap` implementation.  Values must be unique, so that
+    /// equal values mean that the entry is shared by both maps.
+    fn merge_test<H: BuildHasher + Clone>(
+        map: &HashTrieMap<u32, i64, RcK, H>,
+        other: &HashTrieMap<u32, i64, RcK, H>,
+    ) {
+        let left = check(map);
+        let right = check(other);
+        let combined = |k: &u32, v: &i64, w: &i64| if v == w { *v } else { combine(k, v, w) };
+
+        let mut expected = left.clone();
+
+        for (k, w) in &right {
+            let value = left.get(k).map_or(*w, |v| combined(k, v, w));
+
+            expected.insert(*k, value);
+        }
+
+        assert_eq!(check(&map.union_with(other, combine)), expected);
+
+        let expected: BTreeMap<u32, i64> = left
+            .iter()
+            .filter_map(|(k, v)| right.get(k).map(|w| (*k, combined(k, v, w))))
+            .collect();
+
+        assert_eq!(check(&map.intersection_with(other, combine)), expected);
+
+        let expected: BTreeMap<u32, i64> =
+            left.iter().filter(|(k, _)| !right.contains_key(k)).map(|(k, v)| (*k, *v)).collect();
+
+        assert_eq!(check(&map.difference(other)), expected);
+
+        let expected: BTreeMap<u32, i64> = left
+            .iter()
+            .filter(|(k, _)| !right.contains_key(k))
+            .chain(right.iter().filter(|(k, _)| !left.contains_key(k)))
+            .map(|(k, v)| (*k, *v))
+            .collect();
+
+        assert_eq!(check(&map.symmetric_difference(other)), expected);
+
+        let mut diff: Vec<(u32, Option<i64>, Option<i64>)> = map
+            .diff(other)
+            .map(|item| match item {
+                DiffItem::Added(k, w) => (*k, None, Some(*w)),
+                DiffItem::Removed(k, v) => (*k, Some(*v), None),
+                DiffItem::Changed(k, v, w) => (*k, Some(*v), Some(*w)),
+            })
+            .collect();
+        let expected: Vec<(u32, Option<i64>, Option<i64>)> = left
+            .keys()
+            .chain(right.keys().filter(|k| !left.contains_key(k)))
+            .map(|k| (*k, left.get(k).copied(), right.get(k).copied()))
+            .filter(|(_, v, w)| v != w)
+            .collect();
+
+        diff.sort_unstable();
+
+        assert_eq!(diff, {
+            let mut expected = expected;
+            expected.sort_unstable();
+            expected
+        });
+    }
+
+    fn merge_tests<H: BuildHasher + Clone>(new_map: impl Fn() -> HashTrieMap<u32, i64, RcK, H>) {
+        let limit = 1_000;
+        let mut base = new_map();
+
+        for i in 0..limit {
+            base.insert_mut(i, i64::from(i));
+        }
+
+        let mut derived = base.clone();
+
+        for i in (0..limit).step_by(7) {
+            derived.remove_mut(&i);
+        }
+
+        for i in (0..limit).step_by(11) {
+            derived.insert_mut(i, -i64::from(i));
+        }
+
+        for i in limit..limit + 100 {
+            derived.insert_mut(i, i64::from(i));
+        }
+
+        // These maps share the hasher of `base`, so they are merged structurally, unlike
+        // `independent`, which has a hasher of its own.
+        let empty = base.filter(|_, _| false);
+        let mut unrelated = empty.clone();
+        let mut independent = new_map();
+
+        for i in (limit / 2)..(limit + limit / 2) {
+            unrelated.insert_mut(i, 3 * i64::from(i) + 1_000_000);
+            independent.insert_mut(i, 3 * i64::from(i) + 1_000_000);
+        }
+
+        let singleton = empty.insert(3, -3);
+
+        assert!(base.has_same_layout(&unrelated));
+        assert!(!base.has_same_layout(&independent));
+
+        for (map, other) in &[
+            (&base, &derived),
+            (&base, &unrelated),
+            (&derived, &unrelated),
+            (&derived, &independent),
+            (&base, &empty),
+            (&base, &singleton),
+            (&singleton, &derived),
+            (&base, &base),
+        ] {
+            merge_test(map, other);
+            merge_test(other, map);
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        for degree in [2, 4, 16, DEFAULT_DEGREE].iter().copied() {
+            merge_tests(|| HashTrieMap::new_with_degree(degree));
+        }
+    }
+
+    #[test]
+    fn test_merge_high_collision() {
+        let hasher = LimitedHashSpaceHashBuilder::new(100);
+
+        for degree in [2, 4, 16, DEFAULT_DEGREE].iter().copied() {
+            merge_tests(|| {
+                HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher.clone(), degree)
+            });
+        }
+    }
+
+    #[test]
+    fn test_merge_full_collision() {
+        let hasher = CollidingHashBuilder::default();
+
+        for degree in [2, 16, DEFAULT_DEGREE].iter().copied() {
+            merge_tests(|| {
+                HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher.clone(), degree)
+            });
+        }
+    }
+
+    #[test]
+    fn test_merge_different_hashers() {
+        let limit = 1_000;
+        let map: HashTrieMap<u32, i64> = (0..limit).map(|i| (i, i64::from(i))).collect();
+        let other: HashTrieMap<u32, i64> =
+            ((limit / 2)..(limit * 2)).map(|i| (i, -i64::from(i))).collect();
+
+        assert!(!map.has_same_layout(&other));
+
+        merge_test(&map, &other);
+        merge_test(&other, &map);
+    }
+
+    #[test]
+    fn test_merge_constant_hash() {
+        let hasher = ConstantHashBuilder::default();
+
+        for degree in [2, 16, DEFAULT_DEGREE].iter().copied() {
+            merge_tests(|| {
+                HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher.clone(), degree)
+            });
+        }
+    }
+
+    #[test]
+    fn test_merge_hashers_agreeing_on_a_key() {
+        // The key that `MockedHashBuilder` hashes like `b`: its bytes end with `b` on any platform.
+        let key = |b: u8| u32::from_ne_bytes([0, 0, 0, b]);
+        let hasher = MockedHashBuilder::new((0..=255).map(|b| (b, HashValue::from(b))).collect());
+        // Only agrees with `hasher` on the key with the smallest hash, which comes first.
+        let other_hasher = MockedHashBuilder::new(
+            (0..=255).map(|b| (b, HashValue::from(b).reverse_bits())).collect(),
+        );
+        let mut map = HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher, 16);
+        let mut other = HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(other_hasher, 16);
+
+        for b in 0..16 {
+            map.insert_mut(key(b), i64::from(b));
+            other.insert_mut(key(b), i64::from(b));
+        }
+
+        assert!(!map.has_same_layout(&other));
+        assert!(map == other);
+        assert!(map != other.insert(key(15), 0));
+        assert_eq!(map.diff(&other).count(), 0);
+
+        other.remove_mut(&key(0));
+
+        for b in 1..48 {
+            other.insert_mut(key(b), -i64::from(b));
+        }
+
+        merge_test(&map, &other);
+        merge_test(&other, &map);
+    }
+
+    #[test]
+    fn test_merge_different_degrees() {
+        let mut map = HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(
+            LimitedHashSpaceHashBuilder::new(1_000),
+            4,
+        );
+        let mut other = HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(
+            map.hashers.hasher_builder.clone(),
+            16,
+        );
+
+        for i in 0..500 {
+            map.insert_mut(i, i64::from(i));
+            other.insert_mut(2 * i, -i64::from(i));
+        }
+
+        assert!(!map.has_same_layout(&other));
+
+        merge_test(&map, &other);
+        merge_test(&other, &map);
+    }
+
+    #[test]
+    fn test_merge_reuses_shared_structure() {
+        let map: HashTrieMap<u32, i64> = (0..1_000).map(|i| (i, i64::from(i))).collect();
+        let other = map.insert(5, -5).insert(1_000, -1_000);
+        let mut calls = 0;
+
+        let union = map.union_with(&other, |_, v, w| {
+            calls += 1;
+            v + w
+        });
+
+        assert_eq!(calls, 1);
+        assert_eq!(union.get(&5), Some(&0));
+        assert_eq!(union.size(), 1_001);
+
+        assert!(SharedPointer::ptr_eq(&map.union_with(&map, combine).root, &map.root));
+        assert!(SharedPointer::ptr_eq(&map.intersection_with(&map, combine).root, &map.root));
+        assert!(map.difference(&map).is_empty());
+        assert!(map.symmetric_difference(&map).is_empty());
+
+        let difference = other.difference(&map);
+
+        assert_eq!(difference, ht_map![1_000 => -1_000]);
+    }
+
+    #[test]
+    fn test_diff_skips_shared_structure() {
+        #[derive(Debug)]
+        struct Counted<'a>(i64, &'a Cell<usize>);
+
+        impl PartialEq for Counted<'_> {
+            fn eq(&self, other: &Counted<'_>) -> bool {
+                self.1.set(self.1.get() + 1);
+                self.0 == other.0
+            }
+        }
+
+        let comparisons = Cell::new(0);
+        let map: HashTrieMap<u32, Counted<'_>> =
+            (0..100_000).map(|i| (i, Counted(i64::from(i), &comparisons))).collect();
+        let other = map
+            .insert(5, Counted(5, &comparisons))
+            .insert(6, Counted(-6, &comparisons))
+            .remove(&7)
+            .insert(100_000, Counted(0, &comparisons));
+
+        let mut diff: Vec<_> = map.diff(&other).collect();
+
+        diff.sort_by_key(|item| match item {
+            DiffItem::Added(k, _) | DiffItem::Removed(k, _) | DiffItem::Changed(k, _, _) => **k,
+        });
+
+        assert_eq!(
+            diff,
+            vec![
+                DiffItem::Changed(&6, &Counted(6, &comparisons), &Counted(-6, &comparisons)),
+                DiffItem::Removed(&7, &Counted(7, &comparisons)),
+                DiffItem::Added(&100_000, &Counted(0, &comparisons)),
+            ]
+        );
+
+        comparisons.set(0);
+
+        assert_eq!(map.diff(&other).count(), 3);
+        assert_eq!(comparisons.get(), 2);
+        assert_eq!(map.diff(&map).count(), 0);
+        assert_eq!(comparisons.get(), 2);
+    }
+}
+
+#[test]
+fn test_entry_or_insert() {
+    let mut map = ht_map![1 => 10];
+
+    assert_eq!(*map.entry(1).or_insert(20), 10);
+    assert_eq!(*map.entry(2).or_insert(20), 20);
+    assert_eq!(*map.entry(3).or_insert_with(|| 30), 30);
+
+    *map.entry(1).or_insert(0) += 1;
+
+    assert_eq!(map, ht_map![1 => 11, 2 => 20, 3 => 30]);
+    assert_eq!(map.size(), 3);
+}
+
+#[test]
+fn test_entry_and_modify() {
+    let mut map = ht_map!["a" => 1];
+
+    map.entry("a").and_modify(|v| *v += 1).or_insert(0);
+    map.entry("b").and_modify(|v| *v += 1).or_insert(0);
+
+    assert_eq!(map, ht_map!["a" => 2, "b" => 0]);
+}
+
+#[test]
+fn test_entry_occupied() {
+    let mut map = ht_map![1 => "one", 2 => "two", 3 => "three"];
+
+    match map.entry(2) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.key(), &2);
+            assert_eq!(entry.get(), &"two");
+
+            *entry.get_mut() = "dos";
+
+            assert_eq!(entry.insert("deux"), "dos");
+            assert_eq!(entry.remove(), "deux");
+        }
+        Entry::Vacant(_) => panic!("entry should be occupied"),
+    }
+
+    assert_eq!(map, ht_map![1 => "one", 3 => "three"]);
+    assert_eq!(map.size(), 2);
+}
+
+#[test]
+fn test_entry_vacant() {
+    let mut map = ht_map![1 => "one"];
+
+    match map.entry(2) {
+        Entry::Occupied(_) => panic!("entry should be vacant"),
+        Entry::Vacant(entry) => {
+            assert_eq!(entry.key(), &2);
+            assert_eq!(entry.insert("two"), &"two");
+        }
+    }
+
+    match map.entry(3) {
+        Entry::Occupied(_) => panic!("entry should be vacant"),
+        Entry::Vacant(entry) => assert_eq!(entry.into_key(), 3),
+    }
+
+    assert_eq!(map, ht_map![1 => "one", 2 => "two"]);
+    assert_eq!(map.size(), 2);
+}
+
+#[test]
+fn test_entry_high_collision() {
+    let hasher = hasher_mocks::LimitedHashSpaceHashBuilder::new(10);
+    let mut map: HashTrieMap<u32, u32, RcK, _> =
+        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher, 4);
+    let mut expected = std::collections::BTreeMap::new();
+
+    for i in 0..2_000_u32 {
+        let key = (i * 7919) % 100;
+
+        match map.entry(key) {
+            Entry::Occupied(entry) if i % 2 == 0 => {
+                assert_eq!(entry.remove(), expected.remove(&key).unwrap());
+            }
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.insert(i), expected.insert(key, i).unwrap());
+            }
+            Entry::Vacant(entry) => {
+                assert_eq!(*entry.insert(i), i);
+                expected.insert(key, i);
+            }
+        }
+
+        assert_eq!(map.size(), expected.len());
+    }
+
+    for (k, v) in &expected {
+        assert_eq!(map.get(k), Some(v));
+    }
+}
+
+#[test]
+fn test_entry_locations() {
+    for hash_space_size in &[1, 10, 1_000_000] {
+        for degree in &[2, 16, DEFAULT_DEGREE] {
+            let hasher = hasher_mocks::LimitedHashSpaceHashBuilder::new(*hash_space_size);
+            let mut map: HashTrieMap<u32, i64, RcK, _> =
+                HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher, *degree);
+
+            for i in 0..200_u32 {
+                let original = map.clone();
+
+                *map.entry(i).or_insert(0) += i64::from(i);
+                assert_eq!(map.entry(i).or_insert(0), &i64::from(i));
+                *map.entry(i / 2).and_modify(|v| *v += 1).or_insert(1) -= 1;
+
+                let value = map.get(&(i / 3)).copied();
+
+                if let Entry::Occupied(entry) = map.entry(i / 3) {
+                    assert_eq!(entry.key(), &(i / 3));
+                    assert_eq!(Some(*entry.get()), value);
+                }
+
+                assert_eq!(merge::check(&map).len(), i as usize + 1);
+                assert_eq!(merge::check(&original).len(), i as usize);
+            }
+
+            for i in 0..200_u32 {
+                assert_eq!(map.get(&i), Some(&i64::from(i)));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_entry_copy_on_write() {
+    let mut map: HashTrieMap<i32, i32> = (0..100).map(|i| (i, i)).collect();
+    let original = map.clone();
+
+    assert_eq!(map.entry(50).key(), &50);
+    assert_eq!(map.entry(500).key(), &500);
+    assert!(SharedPointer::ptr_eq(&map.root, &original.root));
+
+    *map.entry(50).or_insert(0) += 1000;
+    map.entry(500).or_insert(500);
+
+    if let Entry::Occupied(entry) = map.entry(0) {
+        entry.remove();
+    }
+
+    assert_eq!(map.get(&50), Some(&1050));
+    assert_eq!(map.get(&500), Some(&500));
+    assert!(!map.contains_key(&0));
+    assert_eq!(original, (0..100).map(|i| (i, i)).collect::<HashTrieMap<_, _>>());
+}
+
+#[test]
+fn test_index() {
+    let map = ht_map![5 => "hello", 12 => "there"];
+
+    assert_eq!(map[&5], "hello");
+    assert_eq!(map[&12], "there");
+}
+
+#[test]
+fn test_from_iterator() {
+    let vec: Vec<(i32, &str)> = vec![(2, "two"), (5, "five")];
+    let map: HashTrieMap<i32, &str> = vec.iter().map(|v| *v).collect();
+    let expected_map = ht_map![2 => "two", 5 => "five"];
+
+    assert_eq!(map, expected_map);
+}
+
+#[test]
+fn test_default() {
+    let map: HashTrieMap<u32, char> = HashTrieMap::default();
+
+    assert_eq!(map.size(), 0);
+    assert!(map.is_empty());
+}
+
+#[test]
+fn test_display() {
+    let empty_map: HashTrieMap<i32, i32> = HashTrieMap::new();
+    let singleton_map = ht_map!["hi" =>"hello"];
+    let map = ht_map![5 => "hello", 12 => "there"];
+
+    assert_eq!(format!("{}", empty_map), "{}");
+    assert_eq!(format!("{}", singleton_map), "{hi: hello}");
+    assert!(
+        format!("{}", map) == "{5: hello, 12: there}"
+            || format!("{}", map) == "{12: there, 5: hello}"
+    );
+}
+
+#[test]
+fn test_eq() {
+    let map_1 = ht_map!["a" => 0xa, "b" => 0xb];
+    let map_1_prime = ht_map!["a" => 0xa, "b" => 0xb];
+    let map_1_prime_2 = ht_map!["a" => 0xa, "b" => 0xb, "b" => 0xb];
+    let map_2 = ht_map!["a" => 0xa, "b" => 0xb + 1];
+    let map_3 = ht_map!["a" => 0xa, "b" => 0xb + 1, "c" => 0xc];
+
+    assert_eq!(map_1, map_1_prime);
+    assert_eq!(map_1, map_1_prime_2);
+    assert_eq!(map_1, map_1);
+    assert_eq!(map_2, map_2);
+
+    // We also check this since `assert_ne!()` does not call `ne`.
+    assert!(map_1.ne(&map_2));
+    assert!(map_2.ne(&map_3));
+}
+
+#[test]
+fn test_eq_pointer_kind_consistent() {
+    let map_a = ht_map!["a" => 0];
+    let map_a_sync = ht_map_sync!["a" => 0];
+    let map_b = ht_map!["b" => 1];
+    let map_b_sync = ht_map_sync!["b" => 1];
+
+    assert!(map_a == map_a_sync);
+    assert!(map_a != map_b_sync);
+    assert!(map_b == map_b_sync);
+}
+
+#[test]
+fn test_eq_structural() {
+    for hasher in [
+        hasher_mocks::LimitedHashSpaceHashBuilder::new(1 << 20),
+        hasher_mocks::LimitedHashSpaceHashBuilder::new(100),
+    ] {
+        let empty: HashTrieMap<u32, i32, RcK, _> =
+            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher.clone(), 4);
+        let map_1: HashTrieMap<u32, i32, RcK, _> =
+            (0..1000).fold(empty.clone(), |map, i| map.insert(i, -(i as i32)));
+        let map_1_prime: HashTrieMap<u32, i32, ArcK, _> = (0..1000).rev().fold(
+            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher.clone(), 4),
+            |map, i| map.insert(i, -(i as i32)),
+        );
+        let map_2 = map_1.insert(500, 0);
+        let map_3 = map_1.remove(&500).insert(1000, -500);
+
+        assert!(map_1 == map_1_prime);
+        assert!(map_1 == map_1.insert(1000, 0).remove(&1000));
+        assert!(map_1 != map_2);
+        assert!(map_1 != map_3);
+        assert!(empty == empty.insert(0, 0).remove(&0));
+    }
+}
+
+#[test]
+fn test_eq_skips_shared_structure() {
+    let map: HashTrieMap<u32, f64> = (0..1000).map(|i| (i, f64::NAN)).collect();
+
+    // Shared values are not compared, so `NaN`s are equal to themselves.
+    assert!(map == map.clone());
+    assert!(map == map.insert(1000, 0.0).remove(&1000));
+    assert!(map != map.insert(0, f64::NAN));
+}
+
+fn hash<K: Eq + Hash, V: Hash, P, H: BuildHasher + Clone>(map: &HashTrieMap<K, V, P, H>) -> u64
+where
+    P: SharedPointerKind,
+{
+    #[allow(deprecated)]
+    let mut hasher = core::hash::SipHasher::new();
+
+    map.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[test]
+fn test_hash() {
+    let map_1 = ht_map!["a" => 0xa, "b" => 0xb];
+    let map_1_prime = ht_map!["b" => 0xb, "a" => 0xa];
+    let map_2 = ht_map!["a" => 0xa, "b" => 0xb + 1];
+    let map_3 = ht_map!["a" => 0xa];
+
+    assert_eq!(hash(&map_1), hash(&map_1));
+    assert_eq!(hash(&map_1), hash(&map_1_prime));
+    assert_ne!(hash(&map_1), hash(&map_2));
+    assert_ne!(hash(&map_1), hash(&map_3));
+}
+
+#[test]
+fn test_hash_pointer_kind_consistent() {
+    let map = ht_map!["a" => 0];
+    let map_sync = ht_map_sync!["a" => 0];
+
+    assert_eq!(hash(&map), hash(&map_sync));
+}
+
+#[test]
+fn test_hash_independent_of_layout() {
+    let limit = 2_000;
+    let map: HashTrieMap<u32, i32> = (0..limit).map(|i| (i, -(i as i32))).collect();
+    let expected = hash(&map);
+
+    for degree in [2, 4, 16, DEFAULT_DEGREE].iter().copied() {
+        let other: HashTrieMap<u32, i32, RcK, _> = (0..limit).rev().fold(
+            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(
+                hasher_mocks::LimitedHashSpaceHashBuilder::new(100),
+                degree,
+            ),
+            |map, i| map.insert(i, -(i as i32)),
+        );
+
+        assert_eq!(hash(&other), expected);
+    }
+}
+
+#[test]
+fn test_hash_cache_is_updated() {
+    let limit = 2_000;
+    let mut map: HashTrieMap<u32, i32> = (0..limit).map(|i| (i, -(i as i32))).collect();
+    let original = map.clone();
+    let expected = |map: &HashTrieMap<u32, i32>| {
+        let fresh: HashTrieMap<u32, i32> = map.iter().map(|(k, v)| (*k, *v)).collect();
+
+        hash(&fresh)
+    };
+
+    let h = hash(&map);
+
+    map.insert_mut(limit, 0);
+    assert_ne!(hash(&map), h);
+    assert_eq!(hash(&map), expected(&map));
+
+    map.remove_mut(&limit);
+    assert_eq!(hash(&map), h);
+
+    *map.get_mut(&7).unwrap() = 7;
+    assert_ne!(hash(&map), h);
+    assert_eq!(hash(&map), expected(&map));
+
+    *map.entry(7).or_insert(0) = -7;
+    assert_eq!(hash(&map), h);
+
+    assert_eq!(hash(&original), h);
+    let other = map.insert(limit, 1);
+
+    assert_eq!(hash(&original.union_with(&other, |_, _, w| *w)), hash(&other));
+}
+
+mod flat {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn is_flat<K, V, P: SharedPointerKind, H: BuildHasher, W: TrieHash>(
+        map: &HashTrieMap<K, V, P, H, W>,
+    ) -> bool {
+        matches!(*map.root, Node::Flat(_))
+    }
+
+    #[test]
+    fn test_grow_and_shrink() {
+        let limit = FLAT_MAX_SIZE as u32 + 5;
+        let mut map: HashTrieMap<u32, i32> = HashTrieMap::new();
+
+        assert!(is_flat(&map));
+
+        for i in 0..limit {
+            map.insert_mut(i, i as i32);
+
+            assert_eq!(is_flat(&map), map.size() <= FLAT_MAX_SIZE);
+        }
+
+        // Replacing an entry of a full flat map keeps it flat.
+        let full: HashTrieMap<u32, i32> = (0..FLAT_MAX_SIZE as u32).map(|i| (i, 0)).collect();
+
+        assert!(is_flat(&full.insert(0, 1)));
+        assert!(!is_flat(&full.insert(FLAT_MAX_SIZE as u32, 1)));
+
+        for i in 0..limit {
+            assert!(map.remove_mut(&i));
+            assert_eq!(is_flat(&map), map.size() <= FLAT_MAX_SIZE);
+
+            for j in (i + 1)..limit {
+                assert_eq!(map.get(&j), Some(&(j as i32)));
+            }
+        }
+
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_entry_remove_flattens() {
+        let mut map: HashTrieMap<u32, i32> =
+            (0..=FLAT_MAX_SIZE as u32).map(|i| (i, i as i32)).collect();
+
+        assert!(!is_flat(&map));
+
+        match map.entry(0) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 0),
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+
+        assert!(is_flat(&map));
+        assert_eq!(map.size(), FLAT_MAX_SIZE);
+    }
+
+    #[test]
+    fn test_flat_high_collision() {
+        let hash_builder = hasher_mocks::CollidingHashBuilder::default();
+        let mut map: HashTrieMap<u32, i32, RcK, _> =
+            HashTrieMap::new_with_hasher_and_ptr_kind(hash_builder);
+
+        for i in 0..FLAT_MAX_SIZE as u32 {
+            map.insert_mut(i, i as i32);
+        }
+
+        assert!(is_flat(&map));
+
+        for i in 0..FLAT_MAX_SIZE as u32 {
+            assert_eq!(map.get(&i), Some(&(i as i32)));
+        }
+
+        map.insert_mut(FLAT_MAX_SIZE as u32, -1);
+
+        assert!(!is_flat(&map));
+        assert_eq!(map.stats().collision_count, 1);
+        assert_eq!(map.iter().count(), FLAT_MAX_SIZE + 1);
+    }
+
+    #[test]
+    fn test_eq_and_hash_across_representations() {
+        let small: HashTrieMap<u32, i32> = (0..4).map(|i| (i, i as i32)).collect();
+        let shrunk = (4..20)
+            .fold((0..20).map(|i| (i, i as i32)).collect(), |m: HashTrieMap<_, _>, i| m.remove(&i));
+        let reversed: HashTrieMap<u32, i32> = (0..4).rev().map(|i| (i, i as i32)).collect();
+
+        assert!(is_flat(&shrunk));
+        assert_eq!(small, shrunk);
+        assert_eq!(small, reversed);
+        assert_eq!(hash(&small), hash(&shrunk));
+        assert_eq!(hash(&small), hash(&reversed));
+        assert_ne!(small, reversed.insert(0, 1));
+    }
+
+    #[test]
+    fn test_merge_and_diff_across_representations() {
+        let small: HashTrieMap<u32, i32> = (0..4).map(|i| (i, i as i32)).collect();
+        let big: HashTrieMap<u32, i32> = (2..30).map(|i| (i, i as i32)).collect();
+
+        let union = small.union_with(&big, |_, v, _| *v);
+        let intersection = big.intersection_with(&small, |_, v, _| *v);
+        let difference = big.difference(&small);
+
+        assert_eq!(union, (0..30).map(|i| (i, i as i32)).collect::<HashTrieMap<_, _>>());
+        assert!(is_flat(&intersection));
+        assert_eq!(intersection, (2..4).map(|i| (i, i as i32)).collect::<HashTrieMap<_, _>>());
+        assert!(!is_flat(&difference));
+        assert_eq!(difference, (4..30).map(|i| (i, i as i32)).collect::<HashTrieMap<_, _>>());
+        assert!(is_flat(&big.difference(&difference)));
+
+        assert_eq!(small.diff(&big).count(), 2 + 26);
+        assert_eq!(big.diff(&small).count(), 2 + 26);
+        assert_eq!(small.diff(&small.insert(1, -1)).count(), 1);
+    }
+}
+
+mod filter {
+    use super::merge::check;
+    use super::*;
+    use hasher_mocks::*;
+    use pretty_assertions::assert_eq;
+
+    fn maps() -> Vec<HashTrieMap<u32, i64, RcK, LimitedHashSpaceHashBuilder>> {
+        let mut maps = Vec::new();
+
+        for size in &[0, 1, FLAT_MAX_SIZE as u32, FLAT_MAX_SIZE as u32 + 1, 20, 3_000] {
+            for degree in &[2, 16, DEFAULT_DEGREE] {
+                for hash_space_size in &[10, 1_000_000] {
+                    let hasher = LimitedHashSpaceHashBuilder::new(*hash_space_size);
+                    let mut map =
+                        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher, *degree);
+
+                    for i in 0..*size {
+                        map.insert_mut(i, i64::from(i));
+                    }
+
+                    maps.push(map);
+                }
+            }
+        }
+
+        maps
+    }
+
+    /// Builds the expected map from scratch, so that we can check that the result of the
+    /// operation has the same (canonical) shape.
+    fn rebuilt<I: IntoIterator<Item = (u32, i64)>>(
+        map: &HashTrieMap<u32, i64, RcK, LimitedHashSpaceHashBuilder>,
+        entries: I,
+    ) -> HashTrieMap<u32, i64, RcK, LimitedHashSpaceHashBuilder> {
+        let mut expected = HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(
+            map.hashers.hasher_builder.clone(),
+            map.degree,
+        );
+
+        for (k, v) in entries {
+            expected.insert_mut(k, v);
+        }
+
+        expected
+    }
+
+    fn check_same(
+        map: &HashTrieMap<u32, i64, RcK, LimitedHashSpaceHashBuilder>,
+        expected: &HashTrieMap<u32, i64, RcK, LimitedHashSpaceHashBuilder>,
+    ) {
+        assert_eq!(check(map), check(expected));
+        assert_eq!(map.stats(), expected.stats());
+    }
+
+    #[test]
+    fn test_filter() {
+        let predicates: [fn(&u32, &i64) -> bool; 4] =
+            [|_, _| true, |_, _| false, |k, _| k % 3 == 0, |k, _| *k < 5 || *k > 2_990];
+
+        for map in maps() {
+            for predicate in &predicates {
+                let filtered = map.filter(predicate);
+                let expected = rebuilt(
+                    &map,
+                    map.iter().filter(|(k, v)| predicate(k, v)).map(|(k, v)| (*k, *v)),
+                );
+
+                check_same(&filtered, &expected);
+
+                let mut retained = map.clone();
+
+                retained.retain(predicate);
+
+                check_same(&retained, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_sharing() {
+        let map: HashTrieMap<u32, i64> = (0..10_000).map(|i| (i, i64::from(i))).collect();
+
+        let all = map.filter(|_, _| true);
+
+        assert!(SharedPointer::ptr_eq(&map.root, &all.root));
+
+        let filtered = map.filter(|k, _| *k != 1234);
+        let stats = filtered.stats_shared_with(&map);
+
+        assert_eq!(filtered, map.remove(&1234));
+        // Only the nodes on the path to the removed entry are new.
+        assert!(stats.node_count - stats.shared_node_count <= stats.depth_histogram.len());
+    }
+
+    #[test]
+    fn test_retain_mut() {
+        for map in maps() {
+            let mut retained = map.clone();
+
+            retained.retain_mut(|k, v| {
+                *v *= 2;
+                k % 3 != 0
+            });
+
+            let expected =
+                rebuilt(&map, map.iter().filter(|(k, _)| *k % 3 != 0).map(|(k, v)| (*k, v * 2)));
+
+            check_same(&retained, &expected);
+
+            // The original map is not modified.
+            assert!(map.iter().all(|(k, v)| i64::from(*k) == *v));
+        }
+    }
+
+    #[test]
+    fn test_map_values() {
+        for map in maps() {
+            let mapped = map.map_values(|k, v| v + i64::from(*k));
+            let expected = rebuilt(&map, map.iter().map(|(k, v)| (*k, v + i64::from(*k))));
+
+            check_same(&mapped, &expected);
+        }
+
+        let map = ht_map![1 => 10, 2 => 20];
+
+        assert_eq!(map.map_values(|_, v| v % 20 == 0), ht_map![1 => false, 2 => true]);
+    }
+
+    #[test]
+    fn test_filter_map_values() {
+        for map in maps() {
+            let f = |k: &u32, v: &i64| if k % 4 == 1 { None } else { Some(-v) };
+            let mapped = map.filter_map_values(f);
+            let expected = rebuilt(&map, map.iter().filter_map(|(k, v)| f(k, v).map(|v| (*k, v))));
+
+            check_same(&mapped, &expected);
+        }
+    }
+}
+
+mod hash_width {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn check_operations<W: TrieHash>() {
+        let limit = 5_000;
+        let mut map: HashTrieMap<u32, i32, RcK, DefaultBuildHasher, W> =
+            HashTrieMap::new_with_hasher_and_ptr_kind(DefaultBuildHasher::default());
+
+        for i in 0..limit {
+            map.insert_mut(i, -(i as i32));
+        }
+
+        assert_eq!(map.size(), limit as usize);
+        assert_eq!(map.iter().count(), limit as usize);
+
+        for i in 0..limit {
+            assert_eq!(map.get(&i), Some(&-(i as i32)));
+        }
+
+        let removed = map.remove(&0);
+
+        assert!(!removed.contains_key(&0));
+        assert_ne!(removed, map);
+        assert_eq!(removed.insert(0, 0), map);
+
+        for i in 0..limit {
+            assert!(map.remove_mut(&i));
+        }
+
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_operations_u32() {
+        check_operations::<u32>();
+    }
+
+    #[test]
+    fn test_operations_u64() {
+        check_operations::<u64>();
+    }
+
+    #[test]
+    fn test_operations_u128() {
+        check_operations::<u128>();
+    }
+
+    #[test]
+    fn test_sync_hash_width() {
+        let map: HashTrieMapSync<u32, i32, DefaultBuildHasher, u32> =
+            (0..100).map(|i| (i, -(i as i32))).collect();
+
+        assert_eq!(map.size(), 100);
+        assert_eq!(map.get(&42), Some(&-42));
+    }
+
+    fn check_raw_hash<W: TrieHash, H: BuildHasher + Clone>(hasher: H) {
+        let limit = 1_000;
+        let mut map: HashTrieMap<u32, i32, RcK, H, W> =
+            HashTrieMap::new_with_hasher_and_ptr_kind(hasher.clone());
+        // The raw hash of a key does not depend on the width of the hash of the map.
+        let other: HashTrieMap<u32, i32, RcK, H, u64> =
+            HashTrieMap::new_with_hasher_and_ptr_kind(hasher);
+
+        for i in 0..limit {
+            let hash = other.hash_key(&i);
+
+            assert_eq!(map.hash_key(&i), hash);
+            map.insert_with_hash_mut(hash, i, -(i as i32));
+        }
+
+        for i in 0..limit {
+            let hash = other.hash_key(&i);
+
+            assert_eq!(map.get(&i), Some(&-(i as i32)));
+            assert_eq!(map.get_with_hash(hash, |k| *k == i), Some((&i, &-(i as i32))));
+        }
+
+        assert_eq!(map.get_with_hash(other.hash_key(&limit), |k| *k == limit), None);
+
+        for i in 0..limit {
+            let hash = other.hash_key(&i);
+
+            assert!(map.remove_with_hash_mut(hash, |k| *k == i));
+            assert!(!map.remove_with_hash_mut(hash, |k| *k == i));
+            assert!(!map.contains_key(&i));
+            assert_eq!(map.size(), (limit - i - 1) as usize);
+        }
+
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_raw_hash() {
+        check_raw_hash::<u32, _>(DefaultBuildHasher::default());
+        check_raw_hash::<u64, _>(DefaultBuildHasher::default());
+        check_raw_hash::<u128, _>(DefaultBuildHasher::default());
+    }
+
+    #[test]
+    fn test_raw_hash_colliding() {
+        // With `u128` the keys are told apart by the high half of their hash, which does not
+        // follow from the raw hash.
+        check_raw_hash::<u64, _>(hasher_mocks::CollidingHashBuilder::default());
+        check_raw_hash::<u128, _>(hasher_mocks::CollidingHashBuilder::default());
+    }
+
+    fn collision_depth<W: TrieHash>() -> usize {
+        let map: HashTrieMap<u32, i32, RcK, _, W> = (0..10).fold(
+            HashTrieMap::new_with_hasher_and_ptr_kind(hasher_mocks::ConstantHashBuilder::default()),
+            |map, i| map.insert(i, i as i32),
+        );
+        let stats = map.stats();
+
+        assert_eq!(stats.collision_count, 1);
+        assert_eq!(stats.collision_bucket_count, 10);
+        assert_eq!(map.size(), 10);
+
+        stats.depth_histogram.len() - 1
+    }
+
+    #[test]
+    fn test_collision_depth() {
+        assert_eq!(collision_depth::<u32>(), iter_utils::trie_max_height::<u32>(DEFAULT_DEGREE));
+        assert_eq!(collision_depth::<u64>(), iter_utils::trie_max_height::<u64>(DEFAULT_DEGREE));
+        assert_eq!(collision_depth::<u128>(), iter_utils::trie_max_height::<u128>(DEFAULT_DEGREE));
+    }
+}
+
+mod stats {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_stats_empty() {
+        let map: HashTrieMap<u32, i32> = HashTrieMap::new_with_degree(16);
+        let stats = map.stats();
+
+        assert_eq!(stats.node_count, 1);
+        assert_eq!(stats.branch_count, 0);
+        assert_eq!(stats.leaf_count, 0);
+        assert_eq!(stats.depth_histogram, vec![0]);
+        assert_eq!(stats.slot_count, 0);
+        assert_eq!(stats.average_fill(), 0.0);
+    }
+
+    #[test]
+    fn test_stats_flat() {
+        let map: HashTrieMap<u32, i32> = (0..3).map(|i| (i, i as i32)).collect();
+        let stats = map.stats();
+
+        assert_eq!(stats.node_count, 1);
+        assert_eq!(stats.branch_count, 0);
+        assert_eq!(stats.leaf_count, 3);
+        assert_eq!(stats.depth_histogram, vec![3]);
+        assert_eq!(stats.occupied_slot_count, 0);
+        assert_eq!(stats.slot_count, 0);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_stats_layout() {
+        // Keys `1` and `9` share the first position of the root, the others have their own.
+        let hash_builder = hasher_mocks::MockedHashBuilder::new(
+            (0..9).map(|k| (k, HashValue::from(k))).chain(Some((9, 0x11))).collect(),
+        );
+        let mut map: HashTrieMap<u8, (), RcK, _> =
+            HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hash_builder, 16);
+
+        for k in 0..10 {
+            map.insert_mut(k, ());
+        }
+
+        let stats = map.stats();
+
+        assert_eq!(stats.node_count, 2);
+        assert_eq!(stats.branch_count, 2);
+        assert_eq!(stats.leaf_count, 10);
+        assert_eq!(stats.collision_count, 0);
+        assert_eq!(stats.collision_bucket_count, 0);
+        assert_eq!(stats.depth_histogram, vec![8, 2]);
+        assert_eq!(stats.occupied_slot_count, 11);
+        assert_eq!(stats.slot_count, 32);
+        assert_eq!(stats.average_fill(), 0.343_75);
+        assert_eq!(stats.shared_node_count, 0);
+    }
+
+    #[test]
+    fn test_stats_collisions() {
+        let hash_builder = hasher_mocks::CollidingHashBuilder::default();
+        let map: HashTrieMap<u32, i32, RcK, _> = (0..100)
+            .fold(HashTrieMap::new_with_hasher_and_ptr_kind(hash_builder), |map, i| {
+                map.insert(i, i as i32)
+            });
+        let stats = map.stats();
+
+        assert_eq!(stats.node_count, stats.branch_count + stats.collision_count);
+        assert_eq!(stats.collision_count, 1);
+        assert_eq!(stats.leaf_count, 0);
+        assert_eq!(stats.collision_bucket_count, 100);
+        assert_eq!(stats.depth_histogram.iter().sum::<usize>(), 100);
+    }
+
+    #[test]
+    fn test_stats_constant_hash() {
+        // Every key has the same hash, but the secondary hash does not use the hasher of the map,
+        // so the keys are still told apart.
+        let map: HashTrieMap<String, usize, RcK, _> = (0..1_000).fold(
+            HashTrieMap::new_with_hasher_and_ptr_kind(hasher_mocks::ConstantHashBuilder::default()),
+            |map, i| map.insert(i.to_string(), i),
+        );
+        let stats = map.stats();
+
+        assert_eq!(stats.collision_count, 1);
+        assert_eq!(stats.collision_bucket_count, 1_000);
+        assert_eq!(map.size(), 1_000);
+
+        for i in 0..1_000 {
+            assert_eq!(map.get(&i.to_string()), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_stats_shared_with() {
+        let map: HashTrieMap<u32, i32> = (0..1_000).map(|i| (i, i as i32)).collect();
+        let stats = map.stats();
+
+        assert_eq!(stats.depth_histogram.iter().sum::<usize>(), map.size());
+        assert_eq!(stats.leaf_count, map.size());
+
+        assert_eq!(map.stats_shared_with(&map).shared_node_count, stats.node_count);
+        assert_eq!(map.stats_shared_with(&map.clone()).shared_node_count, stats.node_count);
+
+        let other = map.insert(1_000, 0);
+        let shared = map.stats_shared_with(&other).shared_node_count;
+
+        assert!(shared > 0);
+        assert!(shared < stats.node_count);
+        assert_eq!(other.stats_shared_with(&map).shared_node_count, shared);
+
+        let copy: HashTrieMap<u32, i32> = map.iter().map(|(k, v)| (*k, *v)).collect();
+
+        assert_eq!(map.stats_shared_with(&copy).shared_node_count, 0);
+    }
+}
+
+mod builder {
+    use super::merge::check;
+    use super::*;
+    use hasher_mocks::*;
+    use pretty_assertions::assert_eq;
+
+    type Map = HashTrieMap<u32, i64, RcK, LimitedHashSpaceHashBuilder>;
+
+    fn inserted<I: IntoIterator<Item = (u32, i64)>>(map: &Map, entries: I) -> Map {
+        let mut expected = map.clone();
+
+        for (k, v) in entries {
+            expected.insert_mut(k, v);
+        }
+
+        expected
+    }
+
+    fn check_same(map: &Map, expected: &Map) {
+        assert_eq!(check(map), check(expected));
+        assert_eq!(map.stats(), expected.stats());
+    }
+
+    /// Entries with some keys repeated, to check that the last value wins.
+    fn entries(size: u32) -> Vec<(u32, i64)> {
+        (0..size)
+            .map(|i| (i, i64::from(i)))
+            .chain((0..size).step_by(3).map(|i| (i, -i64::from(i))))
+            .chain((0..size).step_by(7).map(|i| (i, 1_000_000 + i64::from(i))))
+            .collect()
+    }
+
+    #[test]
+    fn test_build() {
+        for size in &[0, 1, FLAT_MAX_SIZE as u32, FLAT_MAX_SIZE as u32 + 1, 20, 3_000] {
+            for degree in &[2, 16, DEFAULT_DEGREE] {
+                for hash_space_size in &[1, 10, 1_000_000] {
+                    let hasher = LimitedHashSpaceHashBuilder::new(*hash_space_size);
+                    let empty: Map =
+                        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher, *degree);
+                    let mut builder = empty.clone().into_builder();
+
+                    for (k, v) in entries(*size) {
+                        builder.insert(k, v);
+                    }
+
+                    check_same(&builder.build(), &inserted(&empty, entries(*size)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_extend() {
+        for size in &[0, 1, FLAT_MAX_SIZE as u32 + 1, 1_000] {
+            for extra in &[0, 1, FLAT_MAX_SIZE as u32, 2_000] {
+                for hash_space_size in &[10, 1_000_000] {
+                    let hasher = LimitedHashSpaceHashBuilder::new(*hash_space_size);
+                    let mut map: Map =
+                        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher, 16);
+
+                    map.extend((0..*size).map(|i| (i, i64::from(i))));
+
+                    let extra_entries: Vec<(u32, i64)> =
+                        entries(*extra).into_iter().map(|(k, v)| (k + size / 2, v)).collect();
+                    let expected = inserted(&map, extra_entries.iter().copied());
+                    let original = map.clone();
+
+                    map.extend(extra_entries);
+
+                    check_same(&map, &expected);
+                    assert_eq!(check(&original).len(), *size as usize);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_extend_keeps_sharing() {
+        let original: HashTrieMap<u32, i64> = (0..10_000).map(|i| (i, i64::from(i))).collect();
+        let mut map = original.clone();
+
+        map.extend((10_000..10_010).map(|i| (i, i64::from(i))));
+
+        let stats = map.stats_shared_with(&original);
+
+        assert_eq!(map.size(), 10_010);
+        assert!(stats.shared_node_count > stats.node_count / 2);
+        assert_eq!(original.size(), 10_000);
+        assert_eq!(check(&original).len(), 10_000);
+
+        // A map that is not shared is changed in place.
+        let root = core::ptr::addr_of!(*map.root);
+
+        map.extend((0..10).map(|i| (i, -1)));
+
+        assert_eq!(core::ptr::addr_of!(*map.root), root);
+        assert_eq!(map.get(&3), Some(&-1));
+        assert_eq!(map.size(), 10_010);
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let map: HashTrieMap<u32, i64> = entries(1_000).into_iter().collect();
+        let mut expected: HashTrieMap<u32, i64> =
+            HashTrieMap::new_with_hasher_and_ptr_kind(map.hashers.hasher_builder.clone());
+
+        for (k, v) in entries(1_000) {
+            expected.insert_mut(k, v);
+        }
+
+        assert_eq!(map, expected);
+        assert_eq!(map.stats(), expected.stats());
+        assert_eq!(map.get(&21), Some(&1_000_021));
+        assert_eq!(map.get(&6), Some(&-6));
+        assert_eq!(map.get(&5), Some(&5));
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut builder = HashTrieMap::builder();
+
+        builder.extend((0..100).map(|i| (i, i)));
+        builder.insert(5, 50);
+
+        let map = builder.build();
+
+        assert_eq!(map.size(), 100);
+        assert_eq!(map.get(&5), Some(&50));
+        assert_eq!(map.get(&99), Some(&99));
+    }
+}
+
+mod iter_from {
+    use super::*;
+    use hasher_mocks::*;
+    use pretty_assertions::assert_eq;
+
+    fn maps<H: BuildHasher + Clone>(hasher: &H) -> Vec<HashTrieMap<u32, i64, RcK, H>> {
+        let mut maps = Vec::new();
+
+        for size in &[0, 1, FLAT_MAX_SIZE as u32, FLAT_MAX_SIZE as u32 + 1, 20, 1_000] {
+            for degree in &[2, 16, DEFAULT_DEGREE] {
+                let mut map =
+                    HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher.clone(), *degree);
+
+                for i in 0..*size {
+                    map.insert_mut(i, i64::from(i));
+                }
+
+                maps.push(map);
+            }
+        }
+
+        maps
+    }
+
+    fn keys<H: BuildHasher + Clone>(
+        map: &HashTrieMap<u32, i64, RcK, H>,
+        cursor: &Cursor,
+    ) -> Vec<u32> {
+        map.iter_from(cursor).map(|(k, _)| *k).collect()
+    }
+
+    /// Goes through the map in pages of `page_size` entries.
+    fn paginated<H: BuildHasher + Clone>(
+        map: &HashTrieMap<u32, i64, RcK, H>,
+        page_size: usize,
+    ) -> Vec<u32> {
+        let mut cursor = Cursor::start();
+        let mut keys = Vec::new();
+
+        loop {
+            let mut iter = map.iter_from(&cursor);
+            let len = keys.len();
+
+            keys.extend(iter.by_ref().take(page_size).map(|(k, _)| *k));
+
+            if keys.len() == len {
+                return keys;
+            }
+
+            cursor = iter.cursor();
+        }
+    }
+
+    fn check_pagination<H: BuildHasher + Clone>(map: &HashTrieMap<u32, i64, RcK, H>) {
+        let all = keys(map, &Cursor::start());
+        let mut sorted = all.clone();
+
+        sorted.sort_unstable();
+
+        assert_eq!(sorted, (0..map.size() as u32).collect::<Vec<u32>>());
+
+        for page_size in &[1, 3, 100] {
+            assert_eq!(paginated(map, *page_size), all);
+        }
+    }
+
+    /// The order must not depend on the shape of the trie.
+    fn check_order<H: BuildHasher + Clone>(map: &HashTrieMap<u32, i64, RcK, H>) {
+        let evens = map.filter(|k, _| k % 2 == 0);
+        let all = keys(map, &Cursor::start());
+
+        assert_eq!(
+            keys(&evens, &Cursor::start()),
+            all.into_iter().filter(|k| k % 2 == 0).collect::<Vec<u32>>()
+        );
+    }
+
+    /// Resumes the iteration on a map changed after the first page.
+    fn check_changed_map<H: BuildHasher + Clone>(map: &HashTrieMap<u32, i64, RcK, H>) {
+        if map.size() < 3 {
+            return;
+        }
+
+        let page_size = map.size() / 3;
+        let mut iter = map.iter_from(&Cursor::start());
+        let page: Vec<u32> = iter.by_ref().take(page_size).map(|(k, _)| *k).collect();
+        let cursor = iter.cursor();
+        let mut changed = map.clone();
+
+        changed.remove_mut(&page[page_size - 1]);
+        changed.remove_mut(&page[0]);
+        changed.remove_mut(&(map.size() as u32 - 1));
+
+        for i in 0..map.size() as u32 {
+            changed.insert_mut(10_000 + i, 0);
+        }
+
+        let all = keys(&changed, &Cursor::start());
+        let resumed = keys(&changed, &cursor);
+
+        assert!(all.ends_with(&resumed));
+
+        for k in &all[..all.len() - resumed.len()] {
+            assert!(page.contains(k) || *k >= 10_000);
+        }
+
+        for k in &resumed {
+            assert!(!page.contains(k));
+        }
+    }
+
+    #[test]
+    fn test_iter_from() {
+        for hash_space_size in &[1, 10, 1_000_000] {
+            for map in maps(&LimitedHashSpaceHashBuilder::new(*hash_space_size)) {
+                check_pagination(&map);
+            }
+        }
+
+        for map in maps(&CollidingHashBuilder::default()) {
+            check_pagination(&map);
+        }
+    }
+
+    // Keys that share both the hash and the secondary hash are ordered by their position in
+    // their collision bucket, which depends on the history of the map, so the following tests
+    // use hashers that keep the secondary hashes apart.
+
+    #[test]
+    fn test_iter_from_order() {
+        for map in maps(&LimitedHashSpaceHashBuilder::new(1_000_000)) {
+            check_order(&map);
+        }
+
+        for map in maps(&CollidingHashBuilder::default()) {
+            check_order(&map);
+        }
+    }
+
+    #[test]
+    fn test_iter_from_changed_map() {
+        for map in maps(&LimitedHashSpaceHashBuilder::new(1_000_000)) {
+            check_changed_map(&map);
+        }
+
+        for map in maps(&CollidingHashBuilder::default()) {
+            check_changed_map(&map);
+        }
+    }
+}
+
+#[test]
+fn test_clone() {
+    let map = ht_map!["hello" => 4, "there" => 5];
+    let clone = map.clone();
+
+    assert_eq!(clone.size(), map.size());
     assert_eq!(clone.get("hello"), Some(&4));
     assert_eq!(clone.get("there"), Some(&5));
 }
 
+#[test]
+fn test_raw_hash() {
+    let hashers = [
+        hasher_mocks::LimitedHashSpaceHashBuilder::new(4),
+        hasher_mocks::LimitedHashSpaceHashBuilder::new(1_000_000),
+    ];
+
+    for hasher in &hashers {
+        let mut map: HashTrieMap<u32, u32, RcK, _> =
+            HashTrieMap::new_with_hasher_and_ptr_kind(hasher.clone());
+
+        for i in 0..100 {
+            let hash = map.hash_key(&i);
+
+            assert_eq!(hash, node_utils::hash(&i, hasher));
+
+            map.insert_with_hash_mut(hash, i, 2 * i);
+        }
+
+        let map = map.insert_with_hash(map.hash_key(&100), 100, 200);
+
+        assert_eq!(map.size(), 101);
+
+        for i in 0..=100 {
+            let hash = map.hash_key(&i);
+
+            assert_eq!(map.get(&i), Some(&(2 * i)));
+            assert_eq!(map.get_with_hash(hash, |k| *k == i), Some((&i, &(2 * i))));
+            assert_eq!(map.get_with_hash(hash, |_| false), None);
+        }
+
+        let hash = map.hash_key(&42);
+        let removed = map.remove_with_hash(hash, |k| *k == 42);
+
+        assert_eq!(removed.size(), 100);
+        assert_eq!(removed.get_with_hash(hash, |k| *k == 42), None);
+        assert!(removed == map.remove(&42));
+
+        let not_removed = removed.remove_with_hash(hash, |k| *k == 42);
+
+        assert!(SharedPointer::ptr_eq(&removed.root, &not_removed.root));
+
+        let mut map = map;
+
+        assert!(map.remove_with_hash_mut(hash, |k| *k == 42));
+        assert!(!map.remove_with_hash_mut(hash, |k| *k == 42));
+        assert_eq!(map.size(), 100);
+    }
+}
+
+#[test]
+fn test_raw_hash_is_match_calls() {
+    let map: HashTrieMap<u32, u32> = (0..1_000).map(|i| (i, i)).collect();
+    let mut calls = 0;
+
+    let found = map.get_with_hash(map.hash_key(&7), |k| {
+        calls += 1;
+        *k == 7
+    });
+
+    assert_eq!(found, Some((&7, &7)));
+    // Entries with a different hash are never given to `is_match`.
+    assert_eq!(calls, 1);
+}
+
+/// A borrowed version of a `(String, u32)` key, which hashes the same way.
+#[derive(Hash)]
+struct BorrowedKey<'a>(&'a str, u32);
+
+impl Equivalent<(String, u32)> for BorrowedKey<'_> {
+    fn equivalent(&self, key: &(String, u32)) -> bool {
+        self.0 == key.0 && self.1 == key.1
+    }
+}
+
+#[test]
+fn test_equivalent() {
+    let hashers = [
+        hasher_mocks::LimitedHashSpaceHashBuilder::new(4),
+        hasher_mocks::LimitedHashSpaceHashBuilder::new(1_000_000),
+    ];
+
+    for hasher in &hashers {
+        let mut map: HashTrieMap<(String, u32), u32, RcK, _> =
+            HashTrieMap::new_with_hasher_and_ptr_kind(hasher.clone());
+
+        for i in 0..100 {
+            map.insert_mut((i.to_string(), i), i);
+        }
+
+        for i in 0..100 {
+            let key = i.to_string();
+
+            assert_eq!(map.get_equiv(&BorrowedKey(&key, i)), Some(&i));
+            assert!(map.contains_key_equiv(&BorrowedKey(&key, i)));
+            assert!(!map.contains_key_equiv(&BorrowedKey(&key, i + 1)));
+        }
+
+        let removed = map.remove_equiv(&BorrowedKey("42", 42));
+
+        assert_eq!(removed.size(), 99);
+        assert!(!removed.contains_key(&("42".to_owned(), 42)));
+        assert!(removed == map.remove(&("42".to_owned(), 42)));
+
+        assert!(map.remove_equiv_mut(&BorrowedKey("7", 7)));
+        assert!(!map.remove_equiv_mut(&BorrowedKey("7", 7)));
+        assert_eq!(map.size(), 99);
+    }
+}
+
+mod nth {
+    use super::*;
+    use hasher_mocks::*;
+    use pretty_assertions::assert_eq;
+
+    type Map = HashTrieMap<u32, i64, RcK, LimitedHashSpaceHashBuilder>;
+
+    fn check_nth(map: &Map) {
+        for (i, entry) in map.iter().enumerate() {
+            assert_eq!(map.nth(i), Some(entry));
+        }
+
+        assert_eq!(map.nth(map.size()), None);
+        assert_eq!(map.nth(usize::MAX), None);
+    }
+
+    #[test]
+    fn test_nth() {
+        for size in &[0, 1, FLAT_MAX_SIZE as u32, FLAT_MAX_SIZE as u32 + 1, 100, 1_000] {
+            for degree in &[2, 16, DEFAULT_DEGREE] {
+                for hash_space_size in &[1, 10, 1_000_000] {
+                    let hasher = LimitedHashSpaceHashBuilder::new(*hash_space_size);
+                    let mut map: Map =
+                        HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher, *degree);
+
+                    for i in 0..*size {
+                        map.insert_mut(i, i64::from(i));
+                    }
+
+                    check_nth(&map);
+
+                    for i in (0..*size).step_by(3) {
+                        map.remove_mut(&i);
+                    }
+
+                    check_nth(&map);
+
+                    map.retain(|k, _| k % 2 == 0);
+
+                    check_nth(&map);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_sample() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let empty: HashTrieMap<u32, i64> = HashTrieMap::new();
+
+        assert_eq!(empty.sample(&mut rng), None);
+
+        let map: HashTrieMap<u32, u32> = (0..100).map(|i| (i, i + 1)).collect();
+        let mut seen = [false; 100];
+
+        for _ in 0..10_000 {
+            let (k, v) = map.sample(&mut rng).unwrap();
+
+            assert_eq!(*v, *k + 1);
+            seen[*k as usize] = true;
+        }
+
+        assert!(seen.iter().all(|s| *s));
+    }
+}
+
 #[cfg(feature = "serde")]
 #[test]
 fn test_serde() {
@@ -1235,4 +3202,14 @@ fn test_serde() {
     let decoded: HashTrieMap<i32, i32> = deserialize(&encoded).unwrap();
 
     assert_eq!(map, decoded);
+
+    let mut iter = map.iter_from(&Cursor::start());
+
+    iter.next();
+
+    let cursor = iter.cursor();
+    let decoded: Cursor = deserialize(&serialize(&cursor).unwrap()).unwrap();
+
+    assert_eq!(decoded, cursor);
+    assert_eq!(map.iter_from(&decoded).count(), 3);
 }
@@ -3,7 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
-use super::entry::Entry;
+use super::entry::Entry as KeyValue;
+use crate::equivalent::Comparable;
 use archery::{ArcK, RcK, SharedPointer, SharedPointerKind};
 use core::borrow::Borrow;
 use core::cmp::Ordering;
@@ -12,15 +13,19 @@ use core::hash::{Hash, Hasher};
 use core::iter::FromIterator;
 use core::marker::PhantomData;
 use core::ops::{Index, RangeBounds, RangeFull};
+#[cfg(feature = "rand")]
+use rand::{Rng, RngCore};
 
 // TODO Use impl trait instead of this when available.
-pub type Iter<'a, K, V, P> =
-    core::iter::Map<IterPtr<'a, K, V, P>, fn(&'a SharedPointer<Entry<K, V>, P>) -> (&'a K, &'a V)>;
+pub type Iter<'a, K, V, P> = core::iter::Map<
+    IterPtr<'a, K, V, P>,
+    fn(&'a SharedPointer<KeyValue<K, V>, P>) -> (&'a K, &'a V),
+>;
 pub type IterKeys<'a, K, V, P> = core::iter::Map<Iter<'a, K, V, P>, fn((&'a K, &V)) -> &'a K>;
 pub type IterValues<'a, K, V, P> = core::iter::Map<Iter<'a, K, V, P>, fn((&K, &'a V)) -> &'a V>;
 pub type RangeIter<'a, K, V, RB, Q, P> = core::iter::Map<
     RangeIterPtr<'a, K, V, RB, Q, P>,
-    fn(&'a SharedPointer<Entry<K, V>, P>) -> (&'a K, &'a V),
+    fn(&'a SharedPointer<KeyValue<K, V>, P>) -> (&'a K, &'a V),
 >;
 
 /// Creates a [`RedBlackTreeMap`](map/red_black_tree_map/struct.RedBlackTreeMap.html) containing the
@@ -93,6 +98,7 @@ macro_rules! rbt_map_sync {
 /// | `remove()`                 | Θ(log(n)) |   Θ(log(n)) |
 /// | `get()`                    | Θ(log(n)) |   Θ(log(n)) |
 /// | `contains_key()`           | Θ(log(n)) |   Θ(log(n)) |
+/// | `entry()`                  | Θ(log(n)) |   Θ(log(n)) |
 /// | `size()`                   |      Θ(1) |        Θ(1) |
 /// | `clone()`                  |      Θ(1) |        Θ(1) |
 /// | iterator creation          | Θ(log(n)) |   Θ(log(n)) |
@@ -105,6 +111,18 @@ macro_rules! rbt_map_sync {
 /// described in "Purely Functional Data Structures" by Chris Okasaki, page 27.  Deletion is
 /// implemented according to the paper "Red-Black Trees with Types" by Stefan Kahrs
 /// ([reference implementation](https://www.cs.kent.ac.uk/people/staff/smk/redblack/Untyped.hs))
+///
+/// # Equality
+///
+/// Two maps with the same root are equal without their values being compared, so a map is
+/// equal to its clone even if it holds values that are not equal to themselves, like `f64::NAN`:
+///
+/// ```rust
+/// # use rpds::*;
+/// let map = rbt_map![1 => f64::NAN];
+///
+/// assert!(map == map.clone());
+/// ```
 #[derive(Debug)]
 pub struct RedBlackTreeMap<K, V, P = RcK>
 where
@@ -122,15 +140,156 @@ enum Color {
     Black,
 }
 
+/// The directions taken when descending the tree, so that the same descent can be repeated
+/// without comparing keys.  The height of a red-black tree is at most `2⋅lg(n + 1)`, so a
+/// `u128` can record any path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Path {
+    directions: u128,
+    len: u8,
+}
+
+impl Path {
+    fn new() -> Path {
+        Path { directions: 0, len: 0 }
+    }
+
+    fn push(&mut self, direction: Ordering) {
+        debug_assert!(u32::from(self.len) < u128::BITS, "path is too long");
+
+        match direction {
+            Ordering::Less => (),
+            Ordering::Equal => unreachable!("a path only goes left or right"),
+            Ordering::Greater => self.directions |= 1 << self.len,
+        }
+
+        self.len += 1;
+    }
+
+    /// Prepends a direction, for paths that are built from the bottom of the tree up.
+    fn push_front(&mut self, direction: Ordering) {
+        debug_assert!(u32::from(self.len) < u128::BITS, "path is too long");
+
+        self.directions <<= 1;
+
+        match direction {
+            Ordering::Less => (),
+            Ordering::Equal => unreachable!("a path only goes left or right"),
+            Ordering::Greater => self.directions |= 1,
+        }
+
+        self.len += 1;
+    }
+
+    /// Returns the direction at position `i` of the path, or `Ordering::Equal` past its end.
+    fn get(self, i: u8) -> Ordering {
+        if i >= self.len {
+            Ordering::Equal
+        } else if self.directions & (1 << i) == 0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        }
+    }
+
+    /// Updates a path that starts at a node that `Node::balance()` just rotated, so that it
+    /// still leads to the same entry.  Only the first three directions can change, see the
+    /// figure in `Node::balance()`.
+    fn rotate(&mut self, rotation: Rotation) {
+        use Ordering::Equal as E;
+        use Ordering::Greater as R;
+        use Ordering::Less as L;
+
+        let (skip, prefix): (u8, &[Ordering]) = match rotation {
+            Rotation::LeftLeft => match (self.get(0), self.get(1)) {
+                (E, _) => (0, &[R]),
+                (L, E) => (1, &[]),
+                (L, L) => (2, &[L]),
+                (L, R) => (2, &[R, L]),
+                (R, _) => (1, &[R, R]),
+            },
+            Rotation::LeftRight => match (self.get(0), self.get(1), self.get(2)) {
+                (E, ..) => (0, &[R]),
+                (L, E, _) => (1, &[L]),
+                (L, L, _) => (2, &[L, L]),
+                (L, R, E) => (2, &[]),
+                (L, R, L) => (3, &[L, R]),
+                (L, R, R) => (3, &[R, L]),
+                (R, ..) => (1, &[R, R]),
+            },
+            Rotation::RightLeft => match (self.get(0), self.get(1), self.get(2)) {
+                (E, ..) => (0, &[L]),
+                (L, ..) => (1, &[L, L]),
+                (R, E, _) => (1, &[R]),
+                (R, L, E) => (2, &[]),
+                (R, L, L) => (3, &[L, R]),
+                (R, L, R) => (3, &[R, L]),
+                (R, R, _) => (2, &[R, R]),
+            },
+            Rotation::RightRight => match (self.get(0), self.get(1)) {
+                (E, _) => (0, &[L]),
+                (L, _) => (1, &[L, L]),
+                (R, E) => (1, &[]),
+                (R, L) => (2, &[L, R]),
+                (R, R) => (2, &[R]),
+            },
+        };
+
+        for _ in 0..skip {
+            self.next();
+        }
+
+        for &direction in prefix.iter().rev() {
+            self.push_front(direction);
+        }
+    }
+
+    /// Returns the next direction of the path, or `Ordering::Equal` once we reached its end.
+    fn next(&mut self) -> Ordering {
+        if self.len == 0 {
+            return Ordering::Equal;
+        }
+
+        let direction = match self.directions & 1 {
+            0 => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+
+        self.directions >>= 1;
+        self.len -= 1;
+
+        direction
+    }
+}
+
+/// The entry replaced by an insertion, if any, and the path to the inserted entry.
+type Insertion<K, V, P> = (Option<SharedPointer<KeyValue<K, V>, P>>, Path);
+
+/// The rotation done by `Node::balance()`, named after the two red nodes below the node being
+/// balanced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rotation {
+    /// Case 1.
+    LeftLeft,
+    /// Case 2.
+    LeftRight,
+    /// Case 3.
+    RightLeft,
+    /// Case 4.
+    RightRight,
+}
+
 #[derive(Debug)]
 struct Node<K, V, P>
 where
     P: SharedPointerKind,
 {
-    entry: SharedPointer<Entry<K, V>, P>,
+    entry: SharedPointer<KeyValue<K, V>, P>,
     color: Color,
     left: Option<SharedPointer<Node<K, V, P>, P>>,
     right: Option<SharedPointer<Node<K, V, P>, P>>,
+    /// Number of entries in the tree rooted at this node.
+    size: usize,
 }
 
 impl<K, V, P> Clone for Node<K, V, P>
@@ -143,6 +302,7 @@ where
             color: self.color,
             left: self.left.clone(),
             right: self.right.clone(),
+            size: self.size,
         }
     }
 }
@@ -152,18 +312,17 @@ where
     K: Ord,
     P: SharedPointerKind,
 {
-    fn new_red(entry: Entry<K, V>) -> Node<K, V, P> {
-        Node { entry: SharedPointer::new(entry), color: Color::Red, left: None, right: None }
-    }
-
-    fn new_black(entry: Entry<K, V>) -> Node<K, V, P> {
-        Node { entry: SharedPointer::new(entry), color: Color::Black, left: None, right: None }
-    }
-
     fn borrow(node: &Option<SharedPointer<Node<K, V, P>, P>>) -> Option<&Node<K, V, P>> {
         node.as_ref().map(|n| n.borrow())
     }
 
+    /// Returns `true` if `self` and `other` are the same node.  The nodes of maps with different
+    /// pointer kinds have different types, so we compare the address of their size instead, which
+    /// is part of the node and has the same type in both.
+    fn is_same_node<PO: SharedPointerKind>(&self, other: &Node<K, V, PO>) -> bool {
+        crate::utils::same_object(&self.size, &other.size)
+    }
+
     fn left_color(&self) -> Option<Color> {
         self.left.as_ref().map(|l| l.color)
     }
@@ -172,26 +331,61 @@ where
         self.right.as_ref().map(|r| r.color)
     }
 
-    fn get<Q: ?Sized>(&self, key: &Q) -> Option<&Entry<K, V>>
+    fn left_size(&self) -> usize {
+        self.left.as_ref().map_or(0, |l| l.size)
+    }
+
+    fn right_size(&self) -> usize {
+        self.right.as_ref().map_or(0, |r| r.size)
+    }
+
+    /// Recomputes the size of this node from the size of its children.  This must be called
+    /// whenever the children change, bottom-up.
+    fn update_size(&mut self) {
+        self.size = 1 + self.left_size() + self.right_size();
+    }
+
+    /// Returns the entry at position `index` in the order of the keys.
+    fn nth(&self, index: usize) -> Option<&KeyValue<K, V>> {
+        let size_left = self.left_size();
+
+        match index.cmp(&size_left) {
+            Ordering::Less => self.left.as_ref().and_then(|l| l.nth(index)),
+            Ordering::Equal => Some(&self.entry),
+            Ordering::Greater => self.right.as_ref().and_then(|r| r.nth(index - size_left - 1)),
+        }
+    }
+
+    fn get<Q: ?Sized>(&self, key: &Q) -> Option<&KeyValue<K, V>>
     where
-        K: Borrow<Q>,
-        Q: Ord,
+        Q: Comparable<K>,
     {
-        match key.cmp(self.entry.key.borrow()) {
+        match key.compare(&self.entry.key) {
             Ordering::Less => self.left.as_ref().and_then(|l| l.get(key)),
             Ordering::Equal => Some(&self.entry),
             Ordering::Greater => self.right.as_ref().and_then(|r| r.get(key)),
         }
     }
 
-    fn first(&self) -> &Entry<K, V> {
+    /// Returns the node at the end of `path`.
+    fn follow(&self, mut path: Path) -> &Node<K, V, P> {
+        let child = match path.next() {
+            Ordering::Less => &self.left,
+            Ordering::Equal => return self,
+            Ordering::Greater => &self.right,
+        };
+
+        child.as_ref().expect("path must exist in the tree").follow(path)
+    }
+
+    fn first(&self) -> &KeyValue<K, V> {
         match self.left {
             Some(ref l) => l.first(),
             None => &self.entry,
         }
     }
 
-    fn last(&self) -> &Entry<K, V> {
+    fn last(&self) -> &KeyValue<K, V> {
         match self.right {
             Some(ref r) => r.last(),
             None => &self.entry,
@@ -270,12 +464,14 @@ where
     ///                   ╱ ╲
     ///                  c   d
     /// ```
-    fn balance(&mut self) {
+    ///
+    /// Returns the rotation done, if any.
+    fn balance(&mut self) -> Option<Rotation> {
         use core::mem::swap;
         use Color::Black as B;
         use Color::Red as R;
 
-        match self.color {
+        let rotation = match self.color {
             B => {
                 let color_l: Option<Color> = self.left_color();
                 let color_l_l: Option<Color> = self.left.as_ref().and_then(|l| l.left_color());
@@ -301,8 +497,12 @@ where
                         swap(&mut node_l.left, &mut node_l.right);
                         swap(&mut self.right, &mut node_l.right);
 
+                        node_l.update_size();
+
                         self.left = Some(node_l_l_ptr);
                         self.right = Some(node_l_ptr);
+
+                        Some(Rotation::LeftLeft)
                     }
 
                     // Case 2
@@ -322,8 +522,13 @@ where
                         swap(&mut node_l.right, &mut node_l_r.right);
                         swap(&mut self.right, &mut node_l_r.right);
 
+                        node_l.update_size();
+                        node_l_r.update_size();
+
                         self.right = Some(node_l_r_ptr);
                         self.left = Some(node_l_ptr);
+
+                        Some(Rotation::LeftRight)
                     }
 
                     // Case 3
@@ -343,8 +548,13 @@ where
                         swap(&mut node_r_l.left, &mut node_r_l.right);
                         swap(&mut self.left, &mut node_r_l.left);
 
+                        node_r.update_size();
+                        node_r_l.update_size();
+
                         self.left = Some(node_r_l_ptr);
                         self.right = Some(node_r_ptr);
+
+                        Some(Rotation::RightLeft)
                     }
 
                     // Case 4
@@ -363,54 +573,89 @@ where
                         swap(&mut node_r.left, &mut node_r.right);
                         swap(&mut self.left, &mut node_r.left);
 
+                        node_r.update_size();
+
                         self.right = Some(node_r_r_ptr);
                         self.left = Some(node_r_ptr);
+
+                        Some(Rotation::RightRight)
                     }
 
-                    _ => (),
+                    _ => None,
                 }
             }
-            R => (),
-        }
+            R => None,
+        };
+
+        self.update_size();
+
+        rotation
     }
 
-    /// Inserts the entry and returns whether the key is new.
-    fn insert(root: &mut Option<SharedPointer<Node<K, V, P>, P>>, key: K, value: V) -> bool {
-        fn ins<K: Ord, V, P: SharedPointerKind>(
+    /// Inserts the entry and returns the entry it replaced, or `None` if the key is new.
+    fn insert(
+        root: &mut Option<SharedPointer<Node<K, V, P>, P>>,
+        key: K,
+        value: V,
+    ) -> Option<SharedPointer<KeyValue<K, V>, P>> {
+        let entry = SharedPointer::new(KeyValue::new(key, value));
+
+        Node::insert_by(root, entry, K::cmp).0
+    }
+
+    /// Same as `insert()` but the way down the tree is given by `direction(key, node_key)`
+    /// instead of comparing the keys.  Also returns the path to the entry once the tree is
+    /// rebalanced.
+    fn insert_by<D>(
+        root: &mut Option<SharedPointer<Node<K, V, P>, P>>,
+        entry: SharedPointer<KeyValue<K, V>, P>,
+        mut direction: D,
+    ) -> Insertion<K, V, P>
+    where
+        D: FnMut(&K, &K) -> Ordering,
+    {
+        fn ins<K: Ord, V, P: SharedPointerKind, D: FnMut(&K, &K) -> Ordering>(
             node: &mut Option<SharedPointer<Node<K, V, P>, P>>,
-            k: K,
-            v: V,
+            entry: SharedPointer<KeyValue<K, V>, P>,
             is_root: bool,
-        ) -> bool {
+            direction: &mut D,
+        ) -> Insertion<K, V, P> {
             match node {
                 Some(n) => {
                     let node = SharedPointer::make_mut(n);
 
-                    let ret = match k.cmp(&node.entry.key) {
+                    let ret = match direction(&entry.key, &node.entry.key) {
                         Ordering::Less => {
-                            let is_new_key = ins(&mut node.left, k, v, false);
+                            let (replaced, mut path) = ins(&mut node.left, entry, false, direction);
+
+                            path.push_front(Ordering::Less);
 
                             // Small optimization: avoid unnecessary calls to balance.
-                            if is_new_key {
-                                node.balance();
+                            if replaced.is_none() {
+                                if let Some(rotation) = node.balance() {
+                                    path.rotate(rotation);
+                                }
                             }
 
-                            is_new_key
+                            (replaced, path)
                         }
                         Ordering::Equal => {
-                            node.entry = SharedPointer::new(Entry::new(k, v));
-
-                            false
+                            (Some(core::mem::replace(&mut node.entry, entry)), Path::new())
                         }
                         Ordering::Greater => {
-                            let is_new_key = ins(&mut node.right, k, v, false);
+                            let (replaced, mut path) =
+                                ins(&mut node.right, entry, false, direction);
+
+                            path.push_front(Ordering::Greater);
 
                             // Small optimization: avoid unnecessary calls to balance.
-                            if is_new_key {
-                                node.balance();
+                            if replaced.is_none() {
+                                if let Some(rotation) = node.balance() {
+                                    path.rotate(rotation);
+                                }
                             }
 
-                            is_new_key
+                            (replaced, path)
                         }
                     };
 
@@ -421,18 +666,22 @@ where
                     ret
                 }
                 None => {
-                    *node = if is_root {
-                        Some(SharedPointer::new(Node::new_black(Entry::new(k, v))))
-                    } else {
-                        Some(SharedPointer::new(Node::new_red(Entry::new(k, v))))
-                    };
+                    let color = if is_root { Color::Black } else { Color::Red };
+
+                    *node = Some(SharedPointer::new(Node {
+                        entry,
+                        color,
+                        left: None,
+                        right: None,
+                        size: 1,
+                    }));
 
-                    true
+                    (None, Path::new())
                 }
             }
         }
 
-        ins(root, key, value, true)
+        ins(root, entry, true, &mut direction)
     }
 
     /// Returns `false` if node has no children to merge.
@@ -468,6 +717,7 @@ where
                         swap(node, r);
 
                         node.left = Some(r_ptr);
+                        node.update_size();
                     }
                     (R, B) => {
                         let l = SharedPointer::make_mut(&mut l_ptr);
@@ -479,6 +729,7 @@ where
                         swap(node, l);
 
                         node.right = Some(l_ptr);
+                        node.update_size();
                     }
                     (R, R) => {
                         let r = SharedPointer::make_mut(&mut r_ptr);
@@ -495,9 +746,12 @@ where
 
                                 l.right = fl;
                                 r.left = fr;
+                                l.update_size();
+                                r.update_size();
 
                                 node.left = Some(l_ptr);
                                 node.right = Some(r_ptr);
+                                node.update_size();
                             }
                             _ => {
                                 swap(l, node);
@@ -506,7 +760,10 @@ where
                                     r.left = Some(l_ptr);
                                 }
 
+                                r.update_size();
+
                                 node.right = Some(r_ptr);
+                                node.update_size();
                             }
                         }
                     }
@@ -525,9 +782,12 @@ where
 
                                 l.right = fl;
                                 r.left = fr;
+                                l.update_size();
+                                r.update_size();
 
                                 node.left = Some(l_ptr);
                                 node.right = Some(r_ptr);
+                                node.update_size();
                             }
                             _ => {
                                 swap(l, node);
@@ -536,6 +796,8 @@ where
                                     r.left = Some(l_ptr);
                                 }
 
+                                r.update_size();
+
                                 node.color = Color::Red;
                                 node.right = Some(r_ptr);
 
@@ -565,6 +827,8 @@ where
                 self.balance();
             }
         }
+
+        self.update_size();
     }
 
     fn remove_balance_left(&mut self) {
@@ -612,10 +876,14 @@ where
 
                 swap(&mut self.entry, &mut self_r_l.entry);
 
+                self_r_l.update_size();
+
                 self.left = Some(self_r_l_ptr);
             }
             _ => unreachable!(),
         }
+
+        self.update_size();
     }
 
     fn remove_balance_right(&mut self) {
@@ -663,28 +931,49 @@ where
 
                 swap(&mut self.entry, &mut self_l_r.entry);
 
+                self_l_r.update_size();
+
                 self.right = Some(self_l_r_ptr);
             }
             _ => unreachable!(),
         }
+
+        self.update_size();
     }
 
-    /// Returns `true` if the key was present.
+    /// Returns the removed entry, or `None` if the key was not present.
     ///
     /// If the node becomes empty `*root` will be set to `None`.
-    fn remove<Q: ?Sized>(root: &mut Option<SharedPointer<Node<K, V, P>, P>>, key: &Q) -> bool
+    fn remove<Q: ?Sized>(
+        root: &mut Option<SharedPointer<Node<K, V, P>, P>>,
+        key: &Q,
+    ) -> Option<SharedPointer<KeyValue<K, V>, P>>
     where
-        K: Borrow<Q>,
-        Q: Ord,
+        Q: Comparable<K>,
+    {
+        Node::remove_by(root, |node_key| key.compare(node_key))
+    }
+
+    /// Same as `remove()` but the way down the tree is given by `direction(node_key)` instead of
+    /// comparing the keys.
+    fn remove_by<D>(
+        root: &mut Option<SharedPointer<Node<K, V, P>, P>>,
+        mut direction: D,
+    ) -> Option<SharedPointer<KeyValue<K, V>, P>>
+    where
+        D: FnMut(&K) -> Ordering,
     {
-        fn del_left<K, V, Q: ?Sized, P>(node: &mut Node<K, V, P>, k: &Q) -> bool
+        fn del_left<K, V, P, D>(
+            node: &mut Node<K, V, P>,
+            direction: &mut D,
+        ) -> Option<SharedPointer<KeyValue<K, V>, P>>
         where
-            K: Borrow<Q> + Ord,
-            Q: Ord,
+            K: Ord,
             P: SharedPointerKind,
+            D: FnMut(&K) -> Ordering,
         {
             let original_left_color = node.left_color();
-            let removed = del(&mut node.left, k, false);
+            let removed = del(&mut node.left, false, direction);
 
             node.color = Color::Red; // In case of rebalance the color does not matter.
 
@@ -692,18 +981,23 @@ where
                 node.remove_balance_left();
             }
 
+            node.update_size();
+
             removed
         }
 
-        fn del_right<K, V, Q: ?Sized, P>(node: &mut Node<K, V, P>, k: &Q) -> bool
+        fn del_right<K, V, P, D>(
+            node: &mut Node<K, V, P>,
+            direction: &mut D,
+        ) -> Option<SharedPointer<KeyValue<K, V>, P>>
         where
-            K: Borrow<Q> + Ord,
-            Q: Ord,
+            K: Ord,
             P: SharedPointerKind,
+            D: FnMut(&K) -> Ordering,
         {
             let original_right_color = node.right_color();
 
-            let removed = del(&mut node.right, k, false);
+            let removed = del(&mut node.right, false, direction);
 
             node.color = Color::Red; // In case of rebalance the color does not matter.
 
@@ -711,34 +1005,37 @@ where
                 node.remove_balance_right();
             }
 
+            node.update_size();
+
             removed
         }
 
-        fn del<K, V, Q: ?Sized, P>(
+        fn del<K, V, P, D>(
             node: &mut Option<SharedPointer<Node<K, V, P>, P>>,
-            k: &Q,
             is_root: bool,
-        ) -> bool
+            direction: &mut D,
+        ) -> Option<SharedPointer<KeyValue<K, V>, P>>
         where
-            K: Borrow<Q> + Ord,
-            Q: Ord,
+            K: Ord,
             P: SharedPointerKind,
+            D: FnMut(&K) -> Ordering,
         {
             let (removed, make_node_none) = match *node {
                 Some(ref mut node_ptr) => {
                     let node = SharedPointer::make_mut(node_ptr);
 
-                    let ret = match k.cmp(node.entry.key.borrow()) {
-                        Ordering::Less => (del_left(node, k), false),
+                    let ret = match direction(&node.entry.key) {
+                        Ordering::Less => (del_left(node, direction), false),
                         Ordering::Equal => {
+                            let removed = SharedPointer::clone(&node.entry);
                             let left = node.left.take();
                             let right = node.right.take();
 
                             let make_node_none = !Node::remove_fuse(node, left, right);
 
-                            (true, make_node_none)
+                            (Some(removed), make_node_none)
                         }
-                        Ordering::Greater => (del_right(node, k), false),
+                        Ordering::Greater => (del_right(node, direction), false),
                     };
 
                     if is_root {
@@ -747,7 +1044,7 @@ where
 
                     ret
                 }
-                None => (false, false),
+                None => (None, false),
             };
 
             if make_node_none {
@@ -757,7 +1054,7 @@ where
             removed
         }
 
-        del(root, key, true)
+        del(root, true, &mut direction)
     }
 }
 
@@ -767,7 +1064,7 @@ where
     V: Clone,
     P: SharedPointerKind,
 {
-    fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut Entry<K, V>>
+    fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut KeyValue<K, V>>
     where
         K: Borrow<Q>,
         Q: Ord,
@@ -782,6 +1079,19 @@ where
             }
         }
     }
+
+    /// Same as `follow()` but copies the nodes along the path as needed to get a mutable
+    /// reference.
+    fn follow_mut(&mut self, mut path: Path) -> &mut Node<K, V, P> {
+        let child = match path.next() {
+            Ordering::Less => &mut self.left,
+            Ordering::Equal => return self,
+            Ordering::Greater => &mut self.right,
+        };
+
+        SharedPointer::make_mut(child.as_mut().expect("path must exist in the tree"))
+            .follow_mut(path)
+    }
 }
 
 impl<K, V> RedBlackTreeMapSync<K, V>
@@ -819,6 +1129,16 @@ where
     where
         K: Borrow<Q>,
         Q: Ord,
+    {
+        self.get_equiv(key)
+    }
+
+    /// Same as [`get()`](RedBlackTreeMap::get()) but the key can be of any type
+    /// [comparable](Comparable) to `K`.
+    #[must_use]
+    pub fn get_equiv<Q: ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Comparable<K>,
     {
         self.root.as_ref().and_then(|r| r.get(key)).map(|e| &e.value)
     }
@@ -843,11 +1163,18 @@ where
     }
 
     pub fn insert_mut(&mut self, key: K, value: V) {
-        let is_new_key = Node::insert(&mut self.root, key, value);
+        self.insert_shared_mut(key, value);
+    }
+
+    /// Returns the entry that was replaced, if any.
+    fn insert_shared_mut(&mut self, key: K, value: V) -> Option<SharedPointer<KeyValue<K, V>, P>> {
+        let replaced = Node::insert(&mut self.root, key, value);
 
-        if is_new_key {
+        if replaced.is_none() {
             self.size += 1;
         }
+
+        replaced
     }
 
     #[must_use]
@@ -870,13 +1197,47 @@ where
     where
         K: Borrow<Q>,
         Q: Ord,
+    {
+        self.remove_shared_mut(key).is_some()
+    }
+
+    /// Same as [`remove()`](RedBlackTreeMap::remove()) but the key can be of any type
+    /// [comparable](Comparable) to `K`.
+    #[must_use]
+    pub fn remove_equiv<Q: ?Sized>(&self, key: &Q) -> RedBlackTreeMap<K, V, P>
+    where
+        Q: Comparable<K>,
+    {
+        let mut new_map = self.clone();
+
+        if new_map.remove_equiv_mut(key) {
+            new_map
+        } else {
+            // We want to keep maximum sharing so in case of no change we just `clone()` ourselves.
+            self.clone()
+        }
+    }
+
+    /// Same as [`remove_mut()`](RedBlackTreeMap::remove_mut()) but the key can be of any type
+    /// [comparable](Comparable) to `K`.
+    pub fn remove_equiv_mut<Q: ?Sized>(&mut self, key: &Q) -> bool
+    where
+        Q: Comparable<K>,
+    {
+        self.remove_shared_mut(key).is_some()
+    }
+
+    /// Returns the removed entry, if any.
+    fn remove_shared_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<SharedPointer<KeyValue<K, V>, P>>
+    where
+        Q: Comparable<K>,
     {
         let removed = Node::remove(&mut self.root, key);
 
         // Note that unfortunately, even if nothing was removed, we still might have cloned some
         // part of the tree unnecessarily.
 
-        if removed {
+        if removed.is_some() {
             self.size -= 1;
         }
 
@@ -892,6 +1253,16 @@ where
         self.get(key).is_some()
     }
 
+    /// Same as [`contains_key()`](RedBlackTreeMap::contains_key()) but the key can be of any type
+    /// [comparable](Comparable) to `K`.
+    #[must_use]
+    pub fn contains_key_equiv<Q: ?Sized>(&self, key: &Q) -> bool
+    where
+        Q: Comparable<K>,
+    {
+        self.get_equiv(key).is_some()
+    }
+
     #[must_use]
     #[inline]
     pub fn size(&self) -> usize {
@@ -904,6 +1275,26 @@ where
         self.size() == 0
     }
 
+    /// Returns the entry with the `index`-th smallest key, or `None` if `index` is out of bounds.
+    /// Since each node knows the size of its subtree, this takes *O(log(n))* time.
+    #[must_use]
+    pub fn nth(&self, index: usize) -> Option<(&K, &V)> {
+        self.root.as_ref().and_then(|r| r.nth(index)).map(|e| (&e.key, &e.value))
+    }
+
+    /// Returns an entry chosen uniformly at random, or `None` if the map is empty.  This takes
+    /// *O(log(n))* time.
+    ///
+    /// This requires the `rand` feature.
+    #[cfg(feature = "rand")]
+    #[must_use]
+    pub fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Option<(&K, &V)> {
+        match self.size {
+            0 => None,
+            size => self.nth(rng.gen_range(0..size)),
+        }
+    }
+
     #[must_use]
     pub fn iter(&self) -> Iter<'_, K, V, P> {
         self.iter_ptr().map(|e| (&e.key, &e.value))
@@ -924,6 +1315,27 @@ where
         self.iter().map(|(_, v)| v)
     }
 
+    /// Descends the tree looking for `key`.  Returns the path taken and whether the key was
+    /// found, in which case the path leads to its node.
+    fn path_to(&self, key: &K) -> (Path, bool) {
+        let mut path = Path::new();
+        let mut node = self.root.as_ref();
+
+        while let Some(n) = node {
+            let direction = key.cmp(&n.entry.key);
+
+            node = match direction {
+                Ordering::Less => n.left.as_ref(),
+                Ordering::Equal => return (path, true),
+                Ordering::Greater => n.right.as_ref(),
+            };
+
+            path.push(direction);
+        }
+
+        (path, false)
+    }
+
     #[must_use]
     pub fn range<Q, RB>(&self, range: RB) -> RangeIter<'_, K, V, RB, Q, P>
     where
@@ -967,6 +1379,301 @@ where
             .as_mut()
             .and_then(|r| SharedPointer::make_mut(r).get_mut(key).map(|e| &mut e.value))
     }
+
+    /// Same as `insert()` but also returns the value that was replaced, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = RedBlackTreeMap::new().insert("a", 1);
+    ///
+    /// let (new_map, previous) = map.replace("a", 2);
+    ///
+    /// assert_eq!(previous, Some(1));
+    /// assert_eq!(new_map.get("a"), Some(&2));
+    /// assert_eq!(map.get("a"), Some(&1));
+    /// ```
+    #[must_use]
+    pub fn replace(&self, key: K, value: V) -> (RedBlackTreeMap<K, V, P>, Option<V>) {
+        let mut new_map = self.clone();
+        let previous = new_map.replace_mut(key, value);
+
+        (new_map, previous)
+    }
+
+    /// Same as `insert_mut()` but also returns the value that was replaced, if any.
+    ///
+    /// The value is only cloned if it is still shared with another map.
+    pub fn replace_mut(&mut self, key: K, value: V) -> Option<V> {
+        self.replace_entry_mut(key, value).map(|(_, value)| value)
+    }
+
+    /// Inserts the entry and returns the key and value that were replaced, if any.
+    pub(crate) fn replace_entry_mut(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.insert_shared_mut(key, value).map(KeyValue::into_key_value)
+    }
+
+    /// Same as `remove()` but also returns the key and value that were removed, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let map = RedBlackTreeMap::new().insert("a", 1);
+    ///
+    /// let (new_map, removed) = map.remove_entry("a");
+    ///
+    /// assert_eq!(removed, Some(("a", 1)));
+    /// assert!(new_map.is_empty());
+    /// assert_eq!(map.remove_entry("b").1, None);
+    /// ```
+    #[must_use]
+    pub fn remove_entry<Q: ?Sized>(&self, key: &Q) -> (RedBlackTreeMap<K, V, P>, Option<(K, V)>)
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut new_map = self.clone();
+
+        match new_map.remove_entry_mut(key) {
+            Some(entry) => (new_map, Some(entry)),
+            // We want to keep maximum sharing so in case of no change we just `clone()` ourselves.
+            None => (self.clone(), None),
+        }
+    }
+
+    /// Same as `remove_mut()` but also returns the key and value that were removed, if any.
+    ///
+    /// The key and value are only cloned if they are still shared with another map.
+    pub fn remove_entry_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.remove_shared_mut(key).map(KeyValue::into_key_value)
+    }
+
+    /// Same as `remove()` but also returns the value that was removed, if any.
+    #[must_use]
+    pub fn take<Q: ?Sized>(&self, key: &Q) -> (RedBlackTreeMap<K, V, P>, Option<V>)
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let (new_map, removed) = self.remove_entry(key);
+
+        (new_map, removed.map(|(_, value)| value))
+    }
+
+    /// Same as `remove_mut()` but also returns the value that was removed, if any.
+    pub fn take_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.remove_entry_mut(key).map(|(_, value)| value)
+    }
+
+    fn remove_path_mut(&mut self, mut path: Path) {
+        let removed = Node::remove_by(&mut self.root, |_| path.next());
+
+        debug_assert!(removed.is_some(), "path must lead to an entry");
+
+        self.size -= 1;
+    }
+
+    /// Gets the entry of `key` in the map, to inspect or modify it in place.  The tree is only
+    /// descended once: modifying the entry follows the path that was found instead of comparing
+    /// keys again.  The map is only copied (as needed by the structural sharing) when the entry
+    /// is modified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let mut map = RedBlackTreeMap::new();
+    ///
+    /// for word in "the quick brown fox jumps over the lazy dog".split(' ') {
+    ///     map.entry(word).and_modify(|count| *count += 1).or_insert(1);
+    /// }
+    ///
+    /// assert_eq!(map.get("the"), Some(&2));
+    /// assert_eq!(map.get("fox"), Some(&1));
+    /// ```
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, P> {
+        match self.path_to(&key) {
+            (path, true) => Entry::Occupied(OccupiedEntry { map: self, path }),
+            (path, false) => Entry::Vacant(VacantEntry { map: self, key, path }),
+        }
+    }
+}
+
+/// A view into a single entry of a map, which may either be vacant or occupied.  This is
+/// obtained with `RedBlackTreeMap::entry()`.
+#[derive(Debug)]
+pub enum Entry<'a, K, V, P>
+where
+    P: SharedPointerKind,
+{
+    Occupied(OccupiedEntry<'a, K, V, P>),
+    Vacant(VacantEntry<'a, K, V, P>),
+}
+
+/// A view into an occupied entry of a map.
+#[derive(Debug)]
+pub struct OccupiedEntry<'a, K, V, P>
+where
+    P: SharedPointerKind,
+{
+    map: &'a mut RedBlackTreeMap<K, V, P>,
+    path: Path,
+}
+
+/// A view into a vacant entry of a map.
+#[derive(Debug)]
+pub struct VacantEntry<'a, K, V, P>
+where
+    P: SharedPointerKind,
+{
+    map: &'a mut RedBlackTreeMap<K, V, P>,
+    key: K,
+    path: Path,
+}
+
+impl<'a, K, V, P> Entry<'a, K, V, P>
+where
+    K: Ord + Clone,
+    V: Clone,
+    P: SharedPointerKind,
+{
+    #[must_use]
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, and returns a mutable reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Inserts the result of `default()` if the entry is vacant, and returns a mutable reference
+    /// to the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    #[must_use]
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Entry<'a, K, V, P> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K, V, P> OccupiedEntry<'a, K, V, P>
+where
+    K: Ord + Clone,
+    V: Clone,
+    P: SharedPointerKind,
+{
+    fn node(&self) -> &Node<K, V, P> {
+        self.map.root.as_ref().expect("occupied entry must be in the map").follow(self.path)
+    }
+
+    fn node_mut(&mut self) -> &mut Node<K, V, P> {
+        let root = self.map.root.as_mut().expect("occupied entry must be in the map");
+
+        SharedPointer::make_mut(root).follow_mut(self.path)
+    }
+
+    /// Returns the key as stored in the map.
+    #[must_use]
+    pub fn key(&self) -> &K {
+        &self.node().entry.key
+    }
+
+    #[must_use]
+    pub fn get(&self) -> &V {
+        &self.node().entry.value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut SharedPointer::make_mut(&mut self.node_mut().entry).value
+    }
+
+    /// Same as `get_mut()` but the reference lives as long as the map borrow.
+    #[must_use]
+    pub fn into_mut(self) -> &'a mut V {
+        let root = self.map.root.as_mut().expect("occupied entry must be in the map");
+        let node = SharedPointer::make_mut(root).follow_mut(self.path);
+
+        &mut SharedPointer::make_mut(&mut node.entry).value
+    }
+
+    /// Replaces the value, returning the previous one.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the map, returning its value.
+    pub fn remove(self) -> V {
+        let entry = SharedPointer::clone(&self.node().entry);
+
+        self.map.remove_path_mut(self.path);
+
+        // The value is only cloned if the entry is still shared with another map.
+        SharedPointer::try_unwrap(entry).map_or_else(|e| e.value.clone(), |e| e.value)
+    }
+}
+
+impl<'a, K, V, P> VacantEntry<'a, K, V, P>
+where
+    K: Ord + Clone,
+    V: Clone,
+    P: SharedPointerKind,
+{
+    #[must_use]
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    #[must_use]
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts the value in the map, returning a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let VacantEntry { map, key, mut path } = self;
+        let entry = SharedPointer::new(KeyValue::new(key, value));
+
+        // Inserting rebalances the tree, so the entry may no longer be at the end of `path`.
+        let (_, path) = Node::insert_by(&mut map.root, entry, |_, _| path.next());
+        map.size += 1;
+
+        let root = map.root.as_mut().expect("entry was just inserted");
+        let node = SharedPointer::make_mut(root).follow_mut(path);
+
+        &mut SharedPointer::make_mut(&mut node.entry).value
+    }
 }
 
 impl<'a, K, Q: ?Sized, V, P> Index<&'a Q> for RedBlackTreeMap<K, V, P>
@@ -1008,9 +1715,20 @@ where
     P: SharedPointerKind,
     PO: SharedPointerKind,
 {
+    /// Two maps that share their root are equal without their entries being compared, even if
+    /// their values are not equal to themselves (like `f64::NAN`).
     fn eq(&self, other: &RedBlackTreeMap<K, V, PO>) -> bool {
-        self.size() == other.size()
-            && self.iter().all(|(key, value)| other.get(key).map_or(false, |v| *value == *v))
+        if self.size() != other.size() {
+            return false;
+        }
+
+        if let (Some(root), Some(other_root)) = (&self.root, &other.root) {
+            if root.is_same_node(other_root) {
+                return true;
+            }
+        }
+
+        self.iter().all(|(key, value)| other.get(key).map_or(false, |v| *value == *v))
     }
 }
 
@@ -1113,7 +1831,7 @@ where
 }
 
 mod iter_utils {
-    use super::{Entry, Node, RedBlackTreeMap};
+    use super::{KeyValue, Node, RedBlackTreeMap};
     use alloc::vec::Vec;
     use archery::{SharedPointer, SharedPointerKind};
     use core::borrow::Borrow;
@@ -1194,7 +1912,7 @@ mod iter_utils {
         }
 
         #[inline]
-        pub fn current(&self) -> Option<&'a SharedPointer<Entry<K, V>, P>> {
+        pub fn current(&self) -> Option<&'a SharedPointer<KeyValue<K, V>, P>> {
             self.stack.last().map(|node| &node.entry)
         }
 
@@ -1316,9 +2034,9 @@ where
     K: Ord,
     P: SharedPointerKind,
 {
-    type Item = &'a SharedPointer<Entry<K, V>, P>;
+    type Item = &'a SharedPointer<KeyValue<K, V>, P>;
 
-    fn next(&mut self) -> Option<&'a SharedPointer<Entry<K, V>, P>> {
+    fn next(&mut self) -> Option<&'a SharedPointer<KeyValue<K, V>, P>> {
         if self.size > 0 {
             self.size -= 1;
             self.range_iter.next()
@@ -1337,7 +2055,7 @@ where
     K: Ord,
     P: SharedPointerKind,
 {
-    fn next_back(&mut self) -> Option<&'a SharedPointer<Entry<K, V>, P>> {
+    fn next_back(&mut self) -> Option<&'a SharedPointer<KeyValue<K, V>, P>> {
         if self.size > 0 {
             self.size -= 1;
             self.range_iter.next_back()
@@ -1402,7 +2120,7 @@ where
         }
     }
 
-    fn current_forward(&self) -> Option<&'a SharedPointer<Entry<K, V>, P>> {
+    fn current_forward(&self) -> Option<&'a SharedPointer<KeyValue<K, V>, P>> {
         match self.is_remaining_range_empty() {
             true => None,
             false => self.stack_forward.as_ref().unwrap().current(),
@@ -1416,7 +2134,7 @@ where
             .advance(self.range.start_bound(), self.range.end_bound());
     }
 
-    fn current_backward(&self) -> Option<&'a SharedPointer<Entry<K, V>, P>> {
+    fn current_backward(&self) -> Option<&'a SharedPointer<KeyValue<K, V>, P>> {
         match self.is_remaining_range_empty() {
             true => None,
             false => self.stack_backward.as_ref().unwrap().current(),
@@ -1438,7 +2156,7 @@ where
     RB: RangeBounds<Q>,
     P: SharedPointerKind,
 {
-    type Item = &'a SharedPointer<Entry<K, V>, P>;
+    type Item = &'a SharedPointer<KeyValue<K, V>, P>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.init_if_needed(false);
@@ -1458,7 +2176,7 @@ where
     RB: RangeBounds<Q>,
     P: SharedPointerKind,
 {
-    fn next_back(&mut self) -> Option<&'a SharedPointer<Entry<K, V>, P>> {
+    fn next_back(&mut self) -> Option<&'a SharedPointer<KeyValue<K, V>, P>> {
         self.init_if_needed(true);
 
         let current = self.current_backward();
@@ -4,6 +4,9 @@
  */
 
 use super::*;
+use crate::equivalent::Equivalent;
+use alloc::borrow::ToOwned;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use pretty_assertions::assert_eq;
 use static_assertions::assert_impl_all;
@@ -33,6 +36,20 @@ where
             + self.right.as_ref().map_or(0, |r| r.count())
     }
 
+    fn has_consistent_sizes(&self) -> bool {
+        fn size<K, V, P: SharedPointerKind>(node: &Node<K, V, P>) -> Result<usize, ()> {
+            let size_left = node.left.as_ref().map_or(Ok(0), |l| size(l))?;
+            let size_right = node.right.as_ref().map_or(Ok(0), |r| size(r))?;
+
+            match 1 + size_left + size_right {
+                s if s == node.size => Ok(s),
+                _ => Err(()),
+            }
+        }
+
+        size(self).is_ok()
+    }
+
     fn is_black_height_balanced(&self) -> bool {
         fn black_height<K, V, P: SharedPointerKind>(node: &Node<K, V, P>) -> Result<usize, ()> {
             let bheight_left = node.left.as_ref().map_or(Ok(0), |l| black_height(l))?;
@@ -88,6 +105,16 @@ where
         go(self, &mut last)
     }
 
+    fn new_black(entry: KeyValue<K, V>) -> Node<K, V, P> {
+        Node {
+            entry: SharedPointer::new(entry),
+            color: Color::Black,
+            left: None,
+            right: None,
+            size: 1,
+        }
+    }
+
     fn make_black(self) -> Node<K, V, P> {
         let mut node = self;
         node.color = Color::Black;
@@ -114,7 +141,9 @@ where
             Result::Err(InvariantViolation::BlackHeightBalanced)
         } else if !self.root.as_ref().map_or(true, |r| r.color == Color::Black) {
             Result::Err(InvariantViolation::BlackRoot)
-        } else if self.root.as_ref().map_or(0, |r| r.count()) != self.size() {
+        } else if self.root.as_ref().map_or(0, |r| r.count()) != self.size()
+            || !self.root.as_ref().map_or(true, |r| r.has_consistent_sizes())
+        {
             Result::Err(InvariantViolation::SizeConsistency)
         } else {
             Ok(())
@@ -133,6 +162,7 @@ where
             && self.color == other.color
             && self.left == other.left
             && self.right == other.right
+            && self.size == other.size
     }
 }
 
@@ -142,8 +172,8 @@ mod node {
     use super::*;
     use pretty_assertions::assert_eq;
 
-    fn dummy_entry<T: Clone>(v: T) -> Entry<T, T> {
-        Entry { key: v.clone(), value: v }
+    fn dummy_entry<T: Clone>(v: T) -> KeyValue<T, T> {
+        KeyValue { key: v.clone(), value: v }
     }
 
     fn dummy_node<T: Clone>(v: T) -> Node<T, T, RcK> {
@@ -152,6 +182,7 @@ mod node {
             color: Color::Red,
             left: None,
             right: None,
+            size: 1,
         }
     }
 
@@ -160,11 +191,14 @@ mod node {
         left: Option<Node<T, T, RcK>>,
         right: Option<Node<T, T, RcK>>,
     ) -> Node<T, T, RcK> {
+        let size = 1 + left.as_ref().map_or(0, |n| n.size) + right.as_ref().map_or(0, |n| n.size);
+
         Node {
             entry: SharedPointer::new(dummy_entry(v)),
             color: Color::Red,
             left: left.map(|n| SharedPointer::new(n)),
             right: right.map(|n| SharedPointer::new(n)),
+            size,
         }
     }
 
@@ -309,14 +343,14 @@ mod node {
         //                   ╱ ╲
         //                  c   d
 
-        let entry_x = SharedPointer::new(Entry::new('x', ()));
-        let entry_y = SharedPointer::new(Entry::new('y', ()));
-        let entry_z = SharedPointer::new(Entry::new('z', ()));
+        let entry_x = SharedPointer::new(KeyValue::new('x', ()));
+        let entry_y = SharedPointer::new(KeyValue::new('y', ()));
+        let entry_z = SharedPointer::new(KeyValue::new('z', ()));
 
-        let tree_a = SharedPointer::new(Node::new_black(Entry::new('a', ())));
-        let tree_b = SharedPointer::new(Node::new_black(Entry::new('b', ())));
-        let tree_c = SharedPointer::new(Node::new_black(Entry::new('c', ())));
-        let tree_d = SharedPointer::new(Node::new_black(Entry::new('d', ())));
+        let tree_a = SharedPointer::new(Node::new_black(KeyValue::new('a', ())));
+        let tree_b = SharedPointer::new(Node::new_black(KeyValue::new('b', ())));
+        let tree_c = SharedPointer::new(Node::new_black(KeyValue::new('c', ())));
+        let tree_d = SharedPointer::new(Node::new_black(KeyValue::new('d', ())));
 
         let mut tree_case_1: Node<_, _, RcK> = Node {
             entry: SharedPointer::clone(&entry_z),
@@ -329,10 +363,13 @@ mod node {
                     color: Color::Red,
                     left: Some(SharedPointer::clone(&tree_a)),
                     right: Some(SharedPointer::clone(&tree_b)),
+                    size: 3,
                 })),
                 right: Some(SharedPointer::clone(&tree_c)),
+                size: 5,
             })),
             right: Some(SharedPointer::clone(&tree_d)),
+            size: 7,
         };
 
         let mut tree_case_2: Node<_, _, RcK> = Node {
@@ -347,9 +384,12 @@ mod node {
                     color: Color::Red,
                     left: Some(SharedPointer::clone(&tree_b)),
                     right: Some(SharedPointer::clone(&tree_c)),
+                    size: 3,
                 })),
+                size: 5,
             })),
             right: Some(SharedPointer::clone(&tree_d)),
+            size: 7,
         };
 
         let mut tree_case_3: Node<_, _, RcK> = Node {
@@ -364,9 +404,12 @@ mod node {
                     color: Color::Red,
                     left: Some(SharedPointer::clone(&tree_b)),
                     right: Some(SharedPointer::clone(&tree_c)),
+                    size: 3,
                 })),
                 right: Some(SharedPointer::clone(&tree_d)),
+                size: 5,
             })),
+            size: 7,
         };
 
         let mut tree_case_4: Node<_, _, RcK> = Node {
@@ -382,8 +425,11 @@ mod node {
                     color: Color::Red,
                     left: Some(SharedPointer::clone(&tree_c)),
                     right: Some(SharedPointer::clone(&tree_d)),
+                    size: 3,
                 })),
+                size: 5,
             })),
+            size: 7,
         };
 
         let mut tree_none_of_the_above: Node<_, _, RcK> = Node {
@@ -397,10 +443,13 @@ mod node {
                     color: Color::Black,
                     left: Some(SharedPointer::clone(&tree_a)),
                     right: Some(SharedPointer::clone(&tree_b)),
+                    size: 3,
                 })),
                 right: Some(SharedPointer::clone(&tree_c)),
+                size: 5,
             })),
             right: Some(SharedPointer::clone(&tree_d)),
+            size: 7,
         };
 
         let mut tree_balanced: Node<_, _, RcK> = Node {
@@ -411,125 +460,181 @@ mod node {
                 color: Color::Black,
                 left: Some(SharedPointer::clone(&tree_a)),
                 right: Some(SharedPointer::clone(&tree_b)),
+                size: 3,
             })),
             right: Some(SharedPointer::new(Node {
                 entry: SharedPointer::clone(&entry_z),
                 color: Color::Black,
                 left: Some(SharedPointer::clone(&tree_c)),
                 right: Some(SharedPointer::clone(&tree_d)),
+                size: 3,
             })),
+            size: 7,
         };
 
-        tree_case_1.balance();
+        let tree_case_1_original = tree_case_1.clone();
+        assert_eq!(tree_case_1.balance(), Some(Rotation::LeftLeft));
         assert_eq!(tree_case_1, tree_balanced.clone());
+        assert_rotated_paths(&tree_case_1_original, &tree_case_1, Rotation::LeftLeft);
 
-        tree_case_2.balance();
+        let tree_case_2_original = tree_case_2.clone();
+        assert_eq!(tree_case_2.balance(), Some(Rotation::LeftRight));
         assert_eq!(tree_case_2, tree_balanced.clone());
+        assert_rotated_paths(&tree_case_2_original, &tree_case_2, Rotation::LeftRight);
 
-        tree_case_3.balance();
+        let tree_case_3_original = tree_case_3.clone();
+        assert_eq!(tree_case_3.balance(), Some(Rotation::RightLeft));
         assert_eq!(tree_case_3, tree_balanced.clone());
+        assert_rotated_paths(&tree_case_3_original, &tree_case_3, Rotation::RightLeft);
 
-        tree_case_4.balance();
+        let tree_case_4_original = tree_case_4.clone();
+        assert_eq!(tree_case_4.balance(), Some(Rotation::RightRight));
         assert_eq!(tree_case_4, tree_balanced.clone());
+        assert_rotated_paths(&tree_case_4_original, &tree_case_4, Rotation::RightRight);
 
         let tree_none_of_the_above_original = tree_none_of_the_above.clone();
-        tree_none_of_the_above.balance();
+        assert_eq!(tree_none_of_the_above.balance(), None);
         assert_eq!(tree_none_of_the_above, tree_none_of_the_above_original);
 
         let tree_balanced_original = tree_balanced.clone();
-        tree_balanced.balance();
+        assert_eq!(tree_balanced.balance(), None);
         assert_eq!(tree_balanced, tree_balanced_original);
     }
 
+    /// Checks that every entry of `original` is found in `balanced` by following its path
+    /// once rotated.
+    fn assert_rotated_paths(
+        original: &Node<char, (), RcK>,
+        balanced: &Node<char, (), RcK>,
+        rotation: Rotation,
+    ) {
+        fn paths(node: &Node<char, (), RcK>, path: Path, acc: &mut Vec<(Path, char)>) {
+            acc.push((path, node.entry.key));
+
+            for (child, direction) in
+                [(&node.left, Ordering::Less), (&node.right, Ordering::Greater)]
+            {
+                if let Some(child) = child {
+                    let mut child_path = path;
+
+                    child_path.push(direction);
+                    paths(child, child_path, acc);
+                }
+            }
+        }
+
+        let mut entries = Vec::new();
+
+        paths(original, Path::new(), &mut entries);
+
+        assert_eq!(entries.len(), 7);
+
+        for (mut path, key) in entries {
+            path.rotate(rotation);
+
+            assert_eq!(balanced.follow(path).entry.key, key);
+        }
+    }
+
     #[test]
     fn test_insert() {
         let mut node = None;
-        let is_new_key = Node::insert(&mut node, 0, 1);
-        let expected_node: Node<_, _, RcK> = Node::new_black(Entry::new(0, 1));
+        let replaced = Node::insert(&mut node, 0, 1);
+        let expected_node: Node<_, _, RcK> = Node::new_black(KeyValue::new(0, 1));
 
-        assert!(is_new_key);
+        assert!(replaced.is_none());
         assert_eq!(node.as_ref().map(|n| n.borrow()), Some(&expected_node));
 
-        let is_new_key = Node::insert(&mut node, 0, 2);
-        let expected_node: Node<_, _, RcK> = Node::new_black(Entry::new(0, 2));
+        let replaced = Node::insert(&mut node, 0, 2);
+        let expected_node: Node<_, _, RcK> = Node::new_black(KeyValue::new(0, 2));
 
-        assert!(!is_new_key);
+        assert!(replaced.is_some());
         assert_eq!(node.as_ref().map(|n| n.borrow()), Some(&expected_node));
 
-        let is_new_key = Node::insert(&mut node, 10, 3);
+        let replaced = Node::insert(&mut node, 10, 3);
         let expected_node: Node<_, _, RcK> = Node {
-            entry: SharedPointer::new(Entry::new(0, 2)),
+            entry: SharedPointer::new(KeyValue::new(0, 2)),
             color: Color::Black,
             left: None,
             right: Some(SharedPointer::new(Node {
-                entry: SharedPointer::new(Entry::new(10, 3)),
+                entry: SharedPointer::new(KeyValue::new(10, 3)),
                 color: Color::Red,
                 left: None,
                 right: None,
+                size: 1,
             })),
+            size: 2,
         };
 
-        assert!(is_new_key);
+        assert!(replaced.is_none());
         assert_eq!(node.as_ref().map(|n| n.borrow()), Some(&expected_node));
 
-        let is_new_key = Node::insert(&mut node, 10, 4);
+        let replaced = Node::insert(&mut node, 10, 4);
         let expected_node: Node<_, _, RcK> = Node {
-            entry: SharedPointer::new(Entry::new(0, 2)),
+            entry: SharedPointer::new(KeyValue::new(0, 2)),
             color: Color::Black,
             left: None,
             right: Some(SharedPointer::new(Node {
-                entry: SharedPointer::new(Entry::new(10, 4)),
+                entry: SharedPointer::new(KeyValue::new(10, 4)),
                 color: Color::Red,
                 left: None,
                 right: None,
+                size: 1,
             })),
+            size: 2,
         };
 
-        assert!(!is_new_key);
+        assert!(replaced.is_some());
         assert_eq!(node.as_ref().map(|n| n.borrow()), Some(&expected_node));
 
-        let is_new_key = Node::insert(&mut node, 5, 5);
+        let replaced = Node::insert(&mut node, 5, 5);
         // It is going to get rebalanced (by case 3).
         let expected_node: Node<_, _, RcK> = Node {
-            entry: SharedPointer::new(Entry::new(5, 5)),
+            entry: SharedPointer::new(KeyValue::new(5, 5)),
             color: Color::Black,
             left: Some(SharedPointer::new(Node {
-                entry: SharedPointer::new(Entry::new(0, 2)),
+                entry: SharedPointer::new(KeyValue::new(0, 2)),
                 color: Color::Black,
                 left: None,
                 right: None,
+                size: 1,
             })),
             right: Some(SharedPointer::new(Node {
-                entry: SharedPointer::new(Entry::new(10, 4)),
+                entry: SharedPointer::new(KeyValue::new(10, 4)),
                 color: Color::Black,
                 left: None,
                 right: None,
+                size: 1,
             })),
+            size: 3,
         };
 
-        assert!(is_new_key);
+        assert!(replaced.is_none());
         assert_eq!(node.as_ref().map(|n| n.borrow()), Some(&expected_node));
 
-        let is_new_key = Node::insert(&mut node, 0, 1);
+        let replaced = Node::insert(&mut node, 0, 1);
         // It is going to get rebalanced (by case 3).
         let expected_node: Node<_, _, RcK> = Node {
-            entry: SharedPointer::new(Entry::new(5, 5)),
+            entry: SharedPointer::new(KeyValue::new(5, 5)),
             color: Color::Black,
             left: Some(SharedPointer::new(Node {
-                entry: SharedPointer::new(Entry::new(0, 1)),
+                entry: SharedPointer::new(KeyValue::new(0, 1)),
                 color: Color::Black,
                 left: None,
                 right: None,
+                size: 1,
             })),
             right: Some(SharedPointer::new(Node {
-                entry: SharedPointer::new(Entry::new(10, 4)),
+                entry: SharedPointer::new(KeyValue::new(10, 4)),
                 color: Color::Black,
                 left: None,
                 right: None,
+                size: 1,
             })),
+            size: 3,
         };
 
-        assert!(!is_new_key);
+        assert!(replaced.is_some());
         assert_eq!(node.as_ref().map(|n| n.borrow()), Some(&expected_node));
     }
 
@@ -556,18 +661,21 @@ mod node {
             color: Color::Black,
             left: None,
             right: None,
+            size: 1,
         };
         let right = Node {
             entry: SharedPointer::new(dummy_entry("x")),
             color: Color::Red,
             left: None,
             right: Some(SharedPointer::new(dummy_node("c"))),
+            size: 2,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("x")),
             color: Color::Red,
             left: Some(SharedPointer::new(left.clone())),
             right: Some(SharedPointer::new(dummy_node("c"))),
+            size: 3,
         };
 
         assert!(Node::remove_fuse(
@@ -582,18 +690,21 @@ mod node {
             color: Color::Red,
             left: Some(SharedPointer::new(dummy_node("a"))),
             right: None,
+            size: 2,
         };
         let right = Node {
             entry: SharedPointer::new(dummy_entry("c")),
             color: Color::Black,
             left: None,
             right: None,
+            size: 1,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("x")),
             color: Color::Red,
             left: Some(SharedPointer::new(dummy_node("a"))),
             right: Some(SharedPointer::new(right.clone())),
+            size: 3,
         };
 
         assert!(Node::remove_fuse(
@@ -608,12 +719,14 @@ mod node {
             color: Color::Red,
             left: Some(SharedPointer::new(dummy_node("a"))),
             right: None,
+            size: 2,
         };
         let right = Node {
             entry: SharedPointer::new(dummy_entry("y")),
             color: Color::Red,
             left: Some(SharedPointer::new(dummy_node("c").make_red())),
             right: Some(SharedPointer::new(dummy_node("d"))),
+            size: 3,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("c")),
@@ -623,13 +736,16 @@ mod node {
                 color: Color::Red,
                 left: Some(SharedPointer::new(dummy_node("a"))),
                 right: None,
+                size: 2,
             })),
             right: Some(SharedPointer::new(Node {
                 entry: SharedPointer::new(dummy_entry("y")),
                 color: Color::Red,
                 left: None,
                 right: Some(SharedPointer::new(dummy_node("d"))),
+                size: 2,
             })),
+            size: 5,
         };
 
         assert!(Node::remove_fuse(
@@ -644,12 +760,14 @@ mod node {
             color: Color::Red,
             left: Some(SharedPointer::new(dummy_node("a"))),
             right: None,
+            size: 2,
         };
         let right = Node {
             entry: SharedPointer::new(dummy_entry("y")),
             color: Color::Red,
             left: Some(SharedPointer::new(dummy_node("c").make_black())),
             right: Some(SharedPointer::new(dummy_node("d"))),
+            size: 3,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("x")),
@@ -660,7 +778,9 @@ mod node {
                 color: Color::Red,
                 left: Some(SharedPointer::new(dummy_node("c").make_black())),
                 right: Some(SharedPointer::new(dummy_node("d"))),
+                size: 3,
             })),
+            size: 5,
         };
 
         assert!(Node::remove_fuse(
@@ -675,12 +795,14 @@ mod node {
             color: Color::Black,
             left: Some(SharedPointer::new(dummy_node("a"))),
             right: None,
+            size: 2,
         };
         let right = Node {
             entry: SharedPointer::new(dummy_entry("y")),
             color: Color::Black,
             left: Some(SharedPointer::new(dummy_node("c").make_red())),
             right: Some(SharedPointer::new(dummy_node("d"))),
+            size: 3,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("c")),
@@ -690,13 +812,16 @@ mod node {
                 color: Color::Black,
                 left: Some(SharedPointer::new(dummy_node("a"))),
                 right: None,
+                size: 2,
             })),
             right: Some(SharedPointer::new(Node {
                 entry: SharedPointer::new(dummy_entry("y")),
                 color: Color::Black,
                 left: None,
                 right: Some(SharedPointer::new(dummy_node("d"))),
+                size: 2,
             })),
+            size: 5,
         };
 
         assert!(Node::remove_fuse(
@@ -711,12 +836,14 @@ mod node {
             color: Color::Black,
             left: Some(SharedPointer::new(dummy_node("a"))),
             right: None,
+            size: 2,
         };
         let right = Node {
             entry: SharedPointer::new(dummy_entry("y")),
             color: Color::Black,
             left: Some(SharedPointer::new(dummy_node("c").make_black())),
             right: Some(SharedPointer::new(dummy_node("d"))),
+            size: 3,
         };
         let expected_node = {
             let mut n = Node {
@@ -728,7 +855,9 @@ mod node {
                     color: Color::Black,
                     left: Some(SharedPointer::new(dummy_node("c").make_black())),
                     right: Some(SharedPointer::new(dummy_node("d"))),
+                    size: 3,
                 })),
+                size: 5,
             };
             n.remove_balance_left();
             n
@@ -752,13 +881,16 @@ mod node {
                 color: Color::Red,
                 left: Some(SharedPointer::new(dummy_node("a"))),
                 right: Some(SharedPointer::new(dummy_node("b"))),
+                size: 3,
             })),
             right: Some(SharedPointer::new(Node {
                 entry: SharedPointer::new(dummy_entry("z")),
                 color: Color::Red,
                 left: Some(SharedPointer::new(dummy_node("c"))),
                 right: Some(SharedPointer::new(dummy_node("d"))),
+                size: 3,
             })),
+            size: 7,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("y")),
@@ -768,13 +900,16 @@ mod node {
                 color: Color::Black,
                 left: Some(SharedPointer::new(dummy_node("a"))),
                 right: Some(SharedPointer::new(dummy_node("b"))),
+                size: 3,
             })),
             right: Some(SharedPointer::new(Node {
                 entry: SharedPointer::new(dummy_entry("z")),
                 color: Color::Black,
                 left: Some(SharedPointer::new(dummy_node("c"))),
                 right: Some(SharedPointer::new(dummy_node("d"))),
+                size: 3,
             })),
+            size: 7,
         };
 
         node.remove_balance();
@@ -788,6 +923,7 @@ mod node {
             color: Color::Black,
             left: None,
             right: None,
+            size: 1,
         };
 
         let mut node = Node {
@@ -798,8 +934,10 @@ mod node {
                 color: Color::Red,
                 left: Some(SharedPointer::new(dummy_node("a"))),
                 right: Some(SharedPointer::new(dummy_node("b"))),
+                size: 3,
             })),
             right: Some(SharedPointer::new(dummy_node("c"))),
+            size: 5,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("y")),
@@ -809,8 +947,10 @@ mod node {
                 color: Color::Black,
                 left: Some(SharedPointer::new(dummy_node("a"))),
                 right: Some(SharedPointer::new(dummy_node("b"))),
+                size: 3,
             })),
             right: Some(SharedPointer::new(dummy_node("c"))),
+            size: 5,
         };
 
         node.remove_balance_left();
@@ -825,7 +965,9 @@ mod node {
                 color: Color::Black,
                 left: Some(SharedPointer::new(dummy_node("a"))),
                 right: Some(SharedPointer::new(dummy_node("b"))),
+                size: 3,
             })),
+            size: 5,
         };
         let expected_node = {
             let mut n = Node {
@@ -837,7 +979,9 @@ mod node {
                     color: Color::Red,
                     left: Some(SharedPointer::new(dummy_node("a"))),
                     right: Some(SharedPointer::new(dummy_node("b"))),
+                    size: 3,
                 })),
+                size: 5,
             };
             n.remove_balance();
             n
@@ -858,9 +1002,12 @@ mod node {
                     color: Color::Black,
                     left: Some(SharedPointer::new(dummy_node("a"))),
                     right: Some(SharedPointer::new(dummy_node("b"))),
+                    size: 3,
                 })),
                 right: Some(SharedPointer::new(dummy_node("c").make_black())),
+                size: 5,
             })),
+            size: 7,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("y")),
@@ -870,6 +1017,7 @@ mod node {
                 color: Color::Black,
                 left: Some(SharedPointer::new(bl.clone())),
                 right: Some(SharedPointer::new(dummy_node("a"))),
+                size: 3,
             })),
             right: Some(SharedPointer::new({
                 let mut n = Node {
@@ -877,10 +1025,12 @@ mod node {
                     color: Color::Black,
                     left: Some(SharedPointer::new(dummy_node("b"))),
                     right: Some(SharedPointer::new(dummy_node("c").make_red())),
+                    size: 3,
                 };
                 n.remove_balance();
                 n
             })),
+            size: 7,
         };
 
         node.remove_balance_left();
@@ -894,6 +1044,7 @@ mod node {
             color: Color::Black,
             left: None,
             right: None,
+            size: 1,
         };
 
         let mut node = Node {
@@ -905,7 +1056,9 @@ mod node {
                 color: Color::Red,
                 left: Some(SharedPointer::new(dummy_node("b"))),
                 right: Some(SharedPointer::new(dummy_node("c"))),
+                size: 3,
             })),
+            size: 5,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("x")),
@@ -916,7 +1069,9 @@ mod node {
                 color: Color::Black,
                 left: Some(SharedPointer::new(dummy_node("b"))),
                 right: Some(SharedPointer::new(dummy_node("c"))),
+                size: 3,
             })),
+            size: 5,
         };
 
         node.remove_balance_right();
@@ -930,8 +1085,10 @@ mod node {
                 color: Color::Black,
                 left: Some(SharedPointer::new(dummy_node("a"))),
                 right: Some(SharedPointer::new(dummy_node("b"))),
+                size: 3,
             })),
             right: Some(SharedPointer::new(bl.clone())),
+            size: 5,
         };
         let expected_node = {
             let mut n = Node {
@@ -942,8 +1099,10 @@ mod node {
                     color: Color::Red,
                     left: Some(SharedPointer::new(dummy_node("a"))),
                     right: Some(SharedPointer::new(dummy_node("b"))),
+                    size: 3,
                 })),
                 right: Some(SharedPointer::new(bl.clone())),
+                size: 5,
             };
             n.remove_balance();
             n
@@ -964,9 +1123,12 @@ mod node {
                     color: Color::Black,
                     left: Some(SharedPointer::new(dummy_node("b"))),
                     right: Some(SharedPointer::new(dummy_node("c"))),
+                    size: 3,
                 })),
+                size: 5,
             })),
             right: Some(SharedPointer::new(bl.clone())),
+            size: 7,
         };
         let expected_node = Node {
             entry: SharedPointer::new(dummy_entry("y")),
@@ -977,6 +1139,7 @@ mod node {
                     color: Color::Black,
                     left: Some(SharedPointer::new(dummy_node("a").make_red())),
                     right: Some(SharedPointer::new(dummy_node("b"))),
+                    size: 3,
                 };
                 n.remove_balance();
                 n
@@ -986,7 +1149,9 @@ mod node {
                 color: Color::Black,
                 left: Some(SharedPointer::new(dummy_node("c"))),
                 right: Some(SharedPointer::new(bl.clone())),
+                size: 3,
             })),
+            size: 7,
         };
 
         node.remove_balance_right();
@@ -1020,6 +1185,55 @@ mod internal {
         }
     }
 
+    #[test]
+    fn test_path() {
+        let mut path = Path::new();
+
+        assert_eq!(path.next(), Ordering::Equal);
+
+        for i in 0..128 {
+            path.push(if i % 3 == 0 { Ordering::Greater } else { Ordering::Less });
+        }
+
+        for i in 0..128 {
+            let expected = if i % 3 == 0 { Ordering::Greater } else { Ordering::Less };
+
+            assert_eq!(path.next(), expected);
+        }
+
+        assert_eq!(path.next(), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_entry() {
+        let limit = 2_000;
+        let mut map = RedBlackTreeMap::new();
+        let mut expected = alloc::collections::BTreeMap::new();
+
+        for i in 0..limit {
+            let key = (i * 7919) % 500;
+
+            match map.entry(key) {
+                Entry::Occupied(entry) if i % 2 == 0 => {
+                    assert_eq!(entry.remove(), expected.remove(&key).unwrap());
+                }
+                Entry::Occupied(mut entry) => {
+                    assert_eq!(entry.insert(i), expected.insert(key, i).unwrap());
+                }
+                Entry::Vacant(entry) => {
+                    assert_eq!(*entry.insert(i), i);
+                    expected.insert(key, i);
+                }
+            }
+
+            if let Err(error) = map.check_consistent() {
+                panic!("Consistency error in red-black tree ({:?}) at step {}", error, i);
+            }
+
+            assert!(map.iter().map(|(k, v)| (*k, *v)).eq(expected.iter().map(|(k, v)| (*k, *v))));
+        }
+    }
+
     #[test]
     fn test_insert_sorted() {
         let vec: Vec<u32> = (0..4092).collect();
@@ -1649,6 +1863,182 @@ fn test_remove_mut() {
     }
 }
 
+#[test]
+fn test_replace() {
+    let map = RedBlackTreeMap::new().insert("a", 1).insert("b", 2);
+
+    let (new_map, previous) = map.replace("a", 10);
+
+    assert_eq!(previous, Some(1));
+    assert_eq!(new_map.get("a"), Some(&10));
+    assert_eq!(new_map.size(), 2);
+    assert_eq!(map.get("a"), Some(&1));
+
+    let (new_map, previous) = map.replace("c", 3);
+
+    assert_eq!(previous, None);
+    assert_eq!(new_map.get("c"), Some(&3));
+    assert_eq!(new_map.size(), 3);
+    assert_eq!(map.size(), 2);
+}
+
+#[test]
+fn test_replace_mut() {
+    let mut map = RedBlackTreeMap::new();
+    let limit = 1_000;
+
+    for i in 0..limit {
+        assert_eq!(map.replace_mut(i, -i), None);
+    }
+
+    let snapshot = map.clone();
+
+    for i in 0..limit {
+        assert_eq!(map.replace_mut(i, i), Some(-i));
+        assert_eq!(map.get(&i), Some(&i));
+    }
+
+    assert_eq!(map.size(), limit as usize);
+    assert_eq!(snapshot.get(&0), Some(&0));
+    assert_eq!(snapshot.get(&1), Some(&-1));
+}
+
+#[test]
+fn test_remove_entry() {
+    let map = RedBlackTreeMap::new().insert("a", 1).insert("b", 2);
+
+    let (new_map, removed) = map.remove_entry("a");
+
+    assert_eq!(removed, Some(("a", 1)));
+    assert_eq!(new_map.size(), 1);
+    assert!(!new_map.contains_key("a"));
+    assert_eq!(map.get("a"), Some(&1));
+
+    let (new_map, removed) = map.remove_entry("z");
+
+    assert_eq!(removed, None);
+    assert_eq!(new_map, map);
+}
+
+#[test]
+fn test_remove_entry_mut() {
+    let mut map = RedBlackTreeMap::new();
+    let limit = 1_000;
+
+    for i in 0..limit {
+        map.insert_mut(i, -i);
+    }
+
+    for i in 0..limit {
+        assert_eq!(map.remove_entry_mut(&i), Some((i, -i)));
+        assert_eq!(map.remove_entry_mut(&i), None);
+        assert_eq!(map.size(), (limit - i - 1) as usize);
+    }
+}
+
+#[test]
+fn test_take() {
+    let mut map = RedBlackTreeMap::new().insert(1, "one").insert(2, "two");
+
+    let (new_map, taken) = map.take(&1);
+
+    assert_eq!(taken, Some("one"));
+    assert_eq!(new_map.size(), 1);
+    assert_eq!(map.size(), 2);
+
+    assert_eq!(map.take_mut(&2), Some("two"));
+    assert_eq!(map.take_mut(&2), None);
+    assert_eq!(map.size(), 1);
+}
+
+#[test]
+fn test_entry_or_insert() {
+    let mut map = rbt_map![1 => 10];
+
+    assert_eq!(*map.entry(1).or_insert(20), 10);
+    assert_eq!(*map.entry(2).or_insert(20), 20);
+    assert_eq!(*map.entry(3).or_insert_with(|| 30), 30);
+
+    *map.entry(1).or_insert(0) += 1;
+
+    assert_eq!(map, rbt_map![1 => 11, 2 => 20, 3 => 30]);
+    assert_eq!(map.size(), 3);
+}
+
+#[test]
+fn test_entry_and_modify() {
+    let mut map = rbt_map!["a" => 1];
+
+    map.entry("a").and_modify(|v| *v += 1).or_insert(0);
+    map.entry("b").and_modify(|v| *v += 1).or_insert(0);
+
+    assert_eq!(map, rbt_map!["a" => 2, "b" => 0]);
+}
+
+#[test]
+fn test_entry_occupied() {
+    let mut map = rbt_map![1 => "one", 2 => "two", 3 => "three"];
+
+    match map.entry(2) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.key(), &2);
+            assert_eq!(entry.get(), &"two");
+
+            *entry.get_mut() = "dos";
+
+            assert_eq!(entry.insert("deux"), "dos");
+            assert_eq!(entry.remove(), "deux");
+        }
+        Entry::Vacant(_) => panic!("entry should be occupied"),
+    }
+
+    assert_eq!(map, rbt_map![1 => "one", 3 => "three"]);
+    assert_eq!(map.size(), 2);
+}
+
+#[test]
+fn test_entry_vacant() {
+    let mut map = rbt_map![1 => "one"];
+
+    match map.entry(2) {
+        Entry::Occupied(_) => panic!("entry should be vacant"),
+        Entry::Vacant(entry) => {
+            assert_eq!(entry.key(), &2);
+            assert_eq!(entry.insert("two"), &"two");
+        }
+    }
+
+    match map.entry(3) {
+        Entry::Occupied(_) => panic!("entry should be vacant"),
+        Entry::Vacant(entry) => assert_eq!(entry.into_key(), 3),
+    }
+
+    assert_eq!(map, rbt_map![1 => "one", 2 => "two"]);
+    assert_eq!(map.size(), 2);
+}
+
+#[test]
+fn test_entry_copy_on_write() {
+    let mut map: RedBlackTreeMap<i32, i32> = (0..100).map(|i| (i, i)).collect();
+    let original = map.clone();
+
+    assert_eq!(map.entry(50).key(), &50);
+    assert_eq!(map.entry(500).key(), &500);
+    assert!(SharedPointer::ptr_eq(map.root.as_ref().unwrap(), original.root.as_ref().unwrap()));
+
+    *map.entry(50).or_insert(0) += 1000;
+    map.entry(500).or_insert(500);
+
+    if let Entry::Occupied(entry) = map.entry(0) {
+        entry.remove();
+    }
+
+    assert_eq!(map.get(&50), Some(&1050));
+    assert_eq!(map.get(&500), Some(&500));
+    assert!(!map.contains_key(&0));
+    assert_eq!(original, (0..100).map(|i| (i, i)).collect::<RedBlackTreeMap<_, _>>());
+}
+
 #[test]
 fn test_first() {
     let map = rbt_map![5 => "hello", 12 => "there"];
@@ -1663,6 +2053,50 @@ fn test_last() {
     assert_eq!(map.last(), Some((&12, &"there")));
 }
 
+#[test]
+fn test_nth() {
+    let mut map: RedBlackTreeMap<i32, i32> = (0..1_000).map(|i| (i, -i)).collect();
+
+    for (i, k) in (0..1_000).enumerate() {
+        assert_eq!(map.nth(i), Some((&k, &-k)));
+    }
+
+    assert_eq!(map.nth(1_000), None);
+
+    for i in (0..1_000).step_by(3) {
+        map.remove_mut(&i);
+    }
+
+    for (i, entry) in map.iter().enumerate() {
+        assert_eq!(map.nth(i), Some(entry));
+    }
+
+    assert_eq!(map.nth(map.size()), None);
+    assert_eq!(RedBlackTreeMap::<i32, i32>::new().nth(0), None);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn test_sample() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let map: RedBlackTreeMap<u32, u32> = (0..100).map(|i| (i, i + 1)).collect();
+    let mut seen = [false; 100];
+
+    assert_eq!(RedBlackTreeMap::<u32, u32>::new().sample(&mut rng), None);
+
+    for _ in 0..10_000 {
+        let (k, v) = map.sample(&mut rng).unwrap();
+
+        assert_eq!(*v, *k + 1);
+        seen[*k as usize] = true;
+    }
+
+    assert!(seen.iter().all(|s| *s));
+}
+
 #[test]
 fn test_index() {
     let map = rbt_map![5 => "hello", 12 => "there"];
@@ -1729,6 +2163,15 @@ fn test_eq_pointer_kind_consistent() {
     assert!(map_b == map_b_sync);
 }
 
+#[test]
+fn test_eq_skips_shared_root() {
+    let map: RedBlackTreeMap<u32, f64> = (0..1000).map(|i| (i, f64::NAN)).collect();
+
+    // Values of maps with a shared root are not compared, so `NaN`s are equal to themselves.
+    assert!(map == map.clone());
+    assert!(map != map.insert(0, f64::NAN));
+}
+
 #[test]
 fn test_partial_ord() {
     let map_1 = rbt_map!["a" => 0xa];
@@ -1811,6 +2254,47 @@ fn test_clone() {
     assert_eq!(clone.get("there"), Some(&5));
 }
 
+/// A borrowed version of a `(String, u32)` key, which is ordered the same way.
+struct BorrowedKey<'a>(&'a str, u32);
+
+impl Equivalent<(String, u32)> for BorrowedKey<'_> {
+    fn equivalent(&self, key: &(String, u32)) -> bool {
+        self.0 == key.0 && self.1 == key.1
+    }
+}
+
+impl Comparable<(String, u32)> for BorrowedKey<'_> {
+    fn compare(&self, key: &(String, u32)) -> Ordering {
+        self.0.cmp(&key.0).then(self.1.cmp(&key.1))
+    }
+}
+
+#[test]
+fn test_comparable() {
+    let mut map = RedBlackTreeMap::new();
+
+    for i in 0..100 {
+        map.insert_mut((i.to_string(), i), i);
+    }
+
+    for i in 0..100 {
+        let key = i.to_string();
+
+        assert_eq!(map.get_equiv(&BorrowedKey(&key, i)), Some(&i));
+        assert!(map.contains_key_equiv(&BorrowedKey(&key, i)));
+        assert!(!map.contains_key_equiv(&BorrowedKey(&key, i + 1)));
+    }
+
+    let removed = map.remove_equiv(&BorrowedKey("42", 42));
+
+    assert_eq!(removed.size(), 99);
+    assert_eq!(removed, map.remove(&("42".to_owned(), 42)));
+
+    assert!(map.remove_equiv_mut(&BorrowedKey("7", 7)));
+    assert!(!map.remove_equiv_mut(&BorrowedKey("7", 7)));
+    assert_eq!(map.size(), 99);
+}
+
 #[cfg(feature = "serde")]
 #[test]
 fn test_serde() {
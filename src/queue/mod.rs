@@ -13,8 +13,6 @@ use core::hash::{Hash, Hasher};
 use core::iter::FromIterator;
 
 // TODO Use impl trait instead of this when available.
-type IterPtr<'a, T, P> =
-    core::iter::Chain<crate::list::IterPtr<'a, T, P>, LazilyReversedListIter<'a, T, P>>;
 pub type Iter<'a, T, P> = core::iter::Map<IterPtr<'a, T, P>, fn(&SharedPointer<T, P>) -> &T>;
 
 /// Creates a [`Queue`](queue/struct.Queue.html) containing the given arguments:
@@ -89,12 +87,19 @@ macro_rules! queue_sync {
 /// | `dequeue()`           |    Θ(1) |        Θ(n) |
 /// | `dequeue()` amortized |    Θ(1) |        Θ(1) |
 /// | `peek()`              |    Θ(1) |        Θ(1) |
+/// | `peek_back()`         |    Θ(1) |        Θ(1) |
+/// | `get()`               |    Θ(n) |        Θ(n) |
+/// | `append()`            |    Θ(m) |        Θ(m) |
+/// | `split_off()`         |    Θ(n) |        Θ(n) |
 /// | `len()`               |    Θ(1) |        Θ(1) |
 /// | `clone()`             |    Θ(1) |        Θ(1) |
 /// | iterator creation     |    Θ(1) |        Θ(1) |
 /// | iterator step         |    Θ(1) |        Θ(n) |
 /// | iterator full         |    Θ(n) |        Θ(n) |
 ///
+/// For `append()` *m* is the length of the queue being appended.  The iterator can be consumed
+/// from both ends, and the worst case of a step is only hit once per direction.
+///
 /// # Implementation details
 ///
 /// This queue is implemented as described in
@@ -142,6 +147,31 @@ where
         }
     }
 
+    /// Returns the last element of the queue, i.e. the element that was enqueued most recently.
+    #[must_use]
+    pub fn peek_back(&self) -> Option<&T> {
+        if !self.in_list.is_empty() {
+            self.in_list.first()
+        } else {
+            self.out_list.last()
+        }
+    }
+
+    /// Returns the element at the given position, where the element at position zero is the
+    /// one returned by [`peek()`](#method.peek).
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let out_len = self.out_list.len();
+
+        if index < out_len {
+            self.out_list.iter().nth(index)
+        } else if index < self.len() {
+            self.in_list.iter().nth(self.len() - 1 - index)
+        } else {
+            None
+        }
+    }
+
     #[must_use]
     pub fn dequeue(&self) -> Option<Queue<T, P>> {
         let mut new_queue = self.clone();
@@ -181,6 +211,79 @@ where
         self.in_list.push_front_mut(v);
     }
 
+    /// Returns the concatenation of `self` with `other`, i.e. `other` is enqueued, in order, after
+    /// the elements of `self`.
+    #[must_use]
+    pub fn append(&self, other: &Queue<T, P>) -> Queue<T, P> {
+        let mut new_queue = self.clone();
+
+        new_queue.append_mut(other);
+
+        new_queue
+    }
+
+    pub fn append_mut(&mut self, other: &Queue<T, P>) {
+        if self.is_empty() {
+            *self = other.clone();
+        } else {
+            for v in other.iter_ptr() {
+                self.in_list.push_front_ptr_mut(SharedPointer::clone(v));
+            }
+        }
+    }
+
+    /// Splits the queue in two at the given position.  The first queue contains the elements in
+    /// `[0, at)` and the second queue contains the elements in `[at, len)`.
+    ///
+    /// Returns `None` if `at` is greater than the length of the queue.
+    #[must_use]
+    pub fn split_off(&self, at: usize) -> Option<(Queue<T, P>, Queue<T, P>)> {
+        let mut front = self.clone();
+
+        front.split_off_mut(at).map(|back| (front, back))
+    }
+
+    /// Keeps the elements in `[0, at)` in `self` and returns a queue with the elements in
+    /// `[at, len)`.
+    ///
+    /// Returns `None`, and leaves `self` unchanged, if `at` is greater than the length of the
+    /// queue.
+    pub fn split_off_mut(&mut self, at: usize) -> Option<Queue<T, P>> {
+        let out_len = self.out_list.len();
+
+        if at > self.len() {
+            return None;
+        }
+
+        let back = if at <= out_len {
+            let mut back_out_list = self.out_list.clone();
+
+            for _ in 0..at {
+                back_out_list.drop_first_mut();
+            }
+
+            let back = Queue { in_list: self.in_list.clone(), out_list: back_out_list };
+
+            self.out_list = list_utils::prefix(&self.out_list, at);
+            self.in_list = List::new_with_ptr_kind();
+
+            back
+        } else {
+            // The elements of `in_list` are in reverse order, so the elements that stay in the
+            // front queue are at the end of that list.
+            let back_len = self.len() - at;
+            let back_in_list = list_utils::prefix(&self.in_list, back_len);
+
+            for _ in 0..back_len {
+                self.in_list.drop_first_mut();
+            }
+
+            Queue { in_list: back_in_list, out_list: List::new_with_ptr_kind() }
+        };
+
+        Some(back)
+    }
+
     #[must_use]
     #[inline]
     pub fn len(&self) -> usize {
@@ -200,7 +303,23 @@ where
 
     #[must_use]
     fn iter_ptr(&self) -> IterPtr<'_, T, P> {
-        self.out_list.iter_ptr().chain(LazilyReversedListIter::new(&self.in_list))
+        IterPtr::new(self)
+    }
+}
+
+mod list_utils {
+    use super::*;
+
+    /// Returns a list with the first `n` elements of `list`.
+    pub fn prefix<T, P: SharedPointerKind>(list: &List<T, P>, n: usize) -> List<T, P> {
+        let elements: Vec<&SharedPointer<T, P>> = list.iter_ptr().take(n).collect();
+        let mut prefix = List::new_with_ptr_kind();
+
+        for v in elements.into_iter().rev() {
+            prefix.push_front_ptr_mut(SharedPointer::clone(v));
+        }
+
+        prefix
     }
 }
 
@@ -311,6 +430,77 @@ where
     }
 }
 
+/// An iterator over the queue that can be consumed from both ends.
+///
+/// The front of the queue is the beginning of `out_list` followed by `in_list` reversed, and the
+/// back of the queue is the beginning of `in_list` followed by `out_list` reversed.  We only
+/// reverse a list if the iteration actually needs to reach it.
+#[derive(Debug)]
+pub struct IterPtr<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    out_list_forward: crate::list::IterPtr<'a, T, P>,
+    in_list_reversed: LazilyReversedListIter<'a, T, P>,
+    in_list_forward: crate::list::IterPtr<'a, T, P>,
+    out_list_reversed: LazilyReversedListIter<'a, T, P>,
+    remaining: usize,
+}
+
+impl<'a, T, P> IterPtr<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    fn new(queue: &Queue<T, P>) -> IterPtr<'_, T, P> {
+        IterPtr {
+            out_list_forward: queue.out_list.iter_ptr(),
+            in_list_reversed: LazilyReversedListIter::new(&queue.in_list),
+            in_list_forward: queue.in_list.iter_ptr(),
+            out_list_reversed: LazilyReversedListIter::new(&queue.out_list),
+            remaining: queue.len(),
+        }
+    }
+}
+
+impl<'a, T, P> Iterator for IterPtr<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    type Item = &'a SharedPointer<T, P>;
+
+    fn next(&mut self) -> Option<&'a SharedPointer<T, P>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+
+        self.out_list_forward.next().or_else(|| self.in_list_reversed.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, P> DoubleEndedIterator for IterPtr<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    fn next_back(&mut self) -> Option<&'a SharedPointer<T, P>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+
+        self.in_list_forward.next().or_else(|| self.out_list_reversed.next())
+    }
+}
+
+impl<'a, T, P> ExactSizeIterator for IterPtr<'a, T, P> where P: SharedPointerKind {}
+
+#[derive(Debug)]
 pub enum LazilyReversedListIter<'a, T: 'a, P>
 where
     P: SharedPointerKind,
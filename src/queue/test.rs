@@ -102,6 +102,65 @@ mod iter {
         assert_eq!(iterator.size_hint(), (0, Some(0)));
     }
 
+    #[test]
+    fn test_iter_backwards() {
+        let mut queue = Queue::new();
+        queue.enqueue_mut(0);
+        queue.enqueue_mut(1);
+        queue.dequeue_mut();
+        queue.enqueue_mut(2);
+        queue.enqueue_mut(3);
+        let mut iterator = queue.iter();
+
+        assert_eq!(iterator.next_back(), Some(&3));
+        assert_eq!(iterator.next_back(), Some(&2));
+        assert_eq!(iterator.next_back(), Some(&1));
+        assert_eq!(iterator.next_back(), None);
+    }
+
+    #[test]
+    fn test_iter_both_directions() {
+        let limit = 64;
+
+        for dequeued in 0..limit {
+            let mut queue = Queue::new();
+
+            for i in 0..limit {
+                queue.enqueue_mut(i);
+            }
+
+            for _ in 0..dequeued {
+                queue.dequeue_mut();
+            }
+
+            for i in limit..(2 * limit) {
+                queue.enqueue_mut(i);
+            }
+
+            let mut iterator = queue.iter();
+            let mut front = dequeued;
+            let mut back = 2 * limit;
+            let mut step = 0;
+
+            while front < back {
+                assert_eq!(iterator.size_hint(), (back - front, Some(back - front)));
+
+                if step % 3 == 0 {
+                    back -= 1;
+                    assert_eq!(iterator.next_back(), Some(&back));
+                } else {
+                    assert_eq!(iterator.next(), Some(&front));
+                    front += 1;
+                }
+
+                step += 1;
+            }
+
+            assert_eq!(iterator.next(), None);
+            assert_eq!(iterator.next_back(), None);
+        }
+    }
+
     #[test]
     fn test_into_iterator() {
         let mut queue = Queue::new();
@@ -211,6 +270,115 @@ fn test_peek() {
     assert_eq!(queue_3.peek(), Some(&1));
 }
 
+#[test]
+fn test_peek_back() {
+    let empty_queue: Queue<i32> = Queue::new();
+    let singleton_queue = queue!["hello"];
+    let queue = queue![0, 1, 2, 3];
+    let mut queue_2 = Queue::new();
+    queue_2.enqueue_mut(0);
+    queue_2.enqueue_mut(1);
+    queue_2.dequeue_mut();
+    queue_2.enqueue_mut(2);
+    queue_2.enqueue_mut(3);
+    let mut queue_3 = Queue::new();
+    queue_3.enqueue_mut(0);
+    queue_3.enqueue_mut(1);
+    queue_3.enqueue_mut(2);
+    queue_3.enqueue_mut(3);
+    queue_3.dequeue_mut();
+
+    assert_eq!(empty_queue.peek_back(), None);
+    assert_eq!(singleton_queue.peek_back(), Some(&"hello"));
+    assert_eq!(queue.peek_back(), Some(&3));
+    assert_eq!(queue_2.peek_back(), Some(&3));
+    assert_eq!(queue_3.peek_back(), Some(&3));
+}
+
+#[test]
+fn test_get() {
+    let mut queue = Queue::new();
+    queue.enqueue_mut(0);
+    queue.enqueue_mut(1);
+    queue.enqueue_mut(2);
+    queue.dequeue_mut();
+    queue.enqueue_mut(3);
+    queue.enqueue_mut(4);
+
+    assert_eq!(queue.get(0), Some(&1));
+    assert_eq!(queue.get(1), Some(&2));
+    assert_eq!(queue.get(2), Some(&3));
+    assert_eq!(queue.get(3), Some(&4));
+    assert_eq!(queue.get(4), None);
+    assert_eq!(Queue::<i32>::new().get(0), None);
+}
+
+#[test]
+fn test_append() {
+    let mut queue_a = Queue::new();
+    queue_a.enqueue_mut(0);
+    queue_a.enqueue_mut(1);
+    queue_a.dequeue_mut();
+    queue_a.enqueue_mut(2);
+    let queue_b = queue![3, 4].enqueue(5);
+    let empty_queue: Queue<i32> = Queue::new();
+
+    assert_eq!(queue_a.append(&queue_b), queue![1, 2, 3, 4, 5]);
+    assert_eq!(queue_a, queue![1, 2]);
+    assert_eq!(queue_b, queue![3, 4, 5]);
+    assert_eq!(empty_queue.append(&queue_b), queue_b);
+    assert_eq!(queue_b.append(&empty_queue), queue_b);
+    assert_eq!(queue_a.append(&queue_a), queue![1, 2, 1, 2]);
+}
+
+#[test]
+fn test_split_off() {
+    let limit = 10;
+
+    for dequeued in 0..limit {
+        let mut queue = Queue::new();
+
+        for i in 0..limit {
+            queue.enqueue_mut(i);
+        }
+
+        for _ in 0..dequeued {
+            queue.dequeue_mut();
+        }
+
+        for i in limit..(2 * limit) {
+            queue.enqueue_mut(i);
+        }
+
+        let len = queue.len();
+
+        for at in 0..=len {
+            let (front, back) = queue.split_off(at).unwrap();
+
+            assert_eq!(front.len(), at);
+            assert_eq!(back.len(), len - at);
+            assert!(front.iter().copied().eq(dequeued..(dequeued + at)));
+            assert!(back.iter().copied().eq((dequeued + at)..(2 * limit)));
+            assert_eq!(front.append(&back), queue);
+        }
+
+        assert!(queue.split_off(len + 1).is_none());
+    }
+}
+
+#[test]
+fn test_split_off_mut() {
+    let mut queue = queue![0, 1, 2, 3];
+
+    assert!(queue.split_off_mut(5).is_none());
+    assert_eq!(queue, queue![0, 1, 2, 3]);
+
+    let back = queue.split_off_mut(1).unwrap();
+
+    assert_eq!(queue, queue![0]);
+    assert_eq!(back, queue![1, 2, 3]);
+}
+
 #[test]
 fn test_dequeue_mut() {
     let mut empty_queue: Queue<i32> = Queue::new();
@@ -0,0 +1,659 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use crate::List;
+use alloc::vec::Vec;
+use archery::*;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::Display;
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+use core::ops::Index;
+
+// TODO Use impl trait instead of this when available.
+pub type Iter<'a, T, P> = core::iter::Map<IterPtr<'a, T, P>, fn(&SharedPointer<T, P>) -> &T>;
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! random_access_list_reverse {
+    ($ptr_kind:ty ; ; $($reversed:expr),*) => {
+         {
+            #[allow(unused_mut)]
+            let mut l: $crate::RandomAccessList<_, $ptr_kind> =
+                $crate::RandomAccessList::new_with_ptr_kind();
+            $(
+                l.push_front_mut($reversed);
+            )*
+            l
+        }
+    };
+    ($ptr_kind:ty ; $h:expr ; $($reversed:expr),*) => {
+        $crate::random_access_list_reverse!($ptr_kind ; ; $h, $($reversed),*)
+    };
+    ($ptr_kind:ty ; $h:expr, $($t:expr),+ ; $($reversed:expr),*) => {
+        $crate::random_access_list_reverse!($ptr_kind ; $($t),* ; $h, $($reversed),*)
+    };
+
+    // This is just to handle the cases where this macro is called with an extra comma in the
+    // reserve list, which can happen in a recursive call.
+    ($ptr_kind:ty ; $($t:expr),* ; $($reversed:expr),*,) => {
+        $crate::random_access_list_reverse!($ptr_kind ; $($t),* ; $($reversed),*)
+    };
+}
+
+/// Creates a [`RandomAccessList`](random_access_list/struct.RandomAccessList.html) containing the
+/// given arguments:
+///
+/// ```
+/// # use rpds::*;
+/// #
+/// let l = RandomAccessList::new()
+///     .push_front(3)
+///     .push_front(2)
+///     .push_front(1);
+///
+/// assert_eq!(random_access_list![1, 2, 3], l);
+/// ```
+#[macro_export]
+macro_rules! random_access_list {
+    ($($e:expr),*) => {
+        $crate::random_access_list_reverse!(::archery::RcK ; $($e),* ; )
+    };
+}
+
+/// Creates a [`RandomAccessList`](random_access_list/struct.RandomAccessList.html) that
+/// implements `Sync`, containing the given arguments:
+///
+/// ```
+/// # use rpds::*;
+/// #
+/// let l = RandomAccessList::new_sync()
+///     .push_front(3)
+///     .push_front(2)
+///     .push_front(1);
+///
+/// assert_eq!(random_access_list_sync![1, 2, 3], l);
+///
+/// fn is_sync() -> impl Sync {
+///     random_access_list_sync![0, 1, 1, 2, 3, 5, 8]
+/// }
+/// ```
+#[macro_export]
+macro_rules! random_access_list_sync {
+    ($($e:expr),*) => {
+        $crate::random_access_list_reverse!(::archery::ArcK ; $($e),* ; )
+    };
+}
+
+/// A persistent list with structural sharing and efficient random access.
+///
+/// Like a [`List`](../list/struct.List.html) it supports adding and removing elements at the
+/// front in constant time, but it can also access and update elements by index in logarithmic
+/// time.
+///
+/// # Complexity
+///
+/// Let *n* be the number of elements in the list.
+///
+/// ## Temporal complexity
+///
+/// | Operation         | Average   | Worst case  |
+/// |:----------------- | ---------:| -----------:|
+/// | `new()`           |      Θ(1) |        Θ(1) |
+/// | `push_front()`    |      Θ(1) |        Θ(1) |
+/// | `drop_first()`    |      Θ(1) |        Θ(1) |
+/// | `first()`         |      Θ(1) |        Θ(1) |
+/// | `get()`           | Θ(log(n)) |   Θ(log(n)) |
+/// | `set()`           | Θ(log(n)) |   Θ(log(n)) |
+/// | `len()`           |      Θ(1) |        Θ(1) |
+/// | `clone()`         |      Θ(1) |        Θ(1) |
+/// | iterator creation |      Θ(1) |        Θ(1) |
+/// | iterator step     |      Θ(1) |        Θ(1) |
+/// | iterator full     |      Θ(n) |        Θ(n) |
+///
+/// # Implementation details
+///
+/// This is a skew-binary random-access list as described in
+/// [Purely Functional Random-Access Lists](https://dl.acm.org/doi/10.1145/224164.224187) by
+/// Chris Okasaki.  The list is a sequence of complete binary trees whose sizes follow the skew
+/// binary representation of the length of the list.  The elements of each tree are stored in
+/// preorder.
+#[derive(Debug)]
+pub struct RandomAccessList<T, P = RcK>
+where
+    P: SharedPointerKind,
+{
+    /// Trees in increasing order of size.  Only the first two trees can have the same size.
+    trees: List<Tree<T, P>, P>,
+    length: usize,
+}
+
+pub type RandomAccessListSync<T> = RandomAccessList<T, ArcK>;
+
+#[derive(Debug)]
+struct Tree<T, P>
+where
+    P: SharedPointerKind,
+{
+    size: usize,
+    root: SharedPointer<Node<T, P>, P>,
+}
+
+impl<T, P> Clone for Tree<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> Tree<T, P> {
+        Tree { size: self.size, root: SharedPointer::clone(&self.root) }
+    }
+}
+
+#[derive(Debug)]
+enum Node<T, P>
+where
+    P: SharedPointerKind,
+{
+    Leaf(SharedPointer<T, P>),
+    Branch(SharedPointer<T, P>, SharedPointer<Node<T, P>, P>, SharedPointer<Node<T, P>, P>),
+}
+
+impl<T, P> Node<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn value(&self) -> &SharedPointer<T, P> {
+        match self {
+            Node::Leaf(v) | Node::Branch(v, _, _) => v,
+        }
+    }
+
+    /// Returns the element at position `index` of the tree rooted at this node, which has `size`
+    /// elements.
+    fn get(&self, size: usize, index: usize) -> &T {
+        let mut node = self;
+        let mut size = size;
+        let mut index = index;
+
+        loop {
+            match node {
+                Node::Branch(_, left, right) if index > 0 => {
+                    size /= 2;
+
+                    if index <= size {
+                        node = left;
+                        index -= 1;
+                    } else {
+                        node = right;
+                        index -= 1 + size;
+                    }
+                }
+                _ => {
+                    debug_assert_eq!(index, 0);
+
+                    return node.value();
+                }
+            }
+        }
+    }
+
+    /// Returns a copy of the tree rooted at this node, which has `size` elements, with the element
+    /// at position `index` replaced by `v`.
+    fn set(&self, size: usize, index: usize, v: SharedPointer<T, P>) -> Node<T, P> {
+        match self {
+            Node::Leaf(_) => {
+                debug_assert_eq!(index, 0);
+
+                Node::Leaf(v)
+            }
+            Node::Branch(_, left, right) if index == 0 => {
+                Node::Branch(v, SharedPointer::clone(left), SharedPointer::clone(right))
+            }
+            Node::Branch(value, left, right) => {
+                let half = size / 2;
+                let value = SharedPointer::clone(value);
+
+                if index <= half {
+                    let left = SharedPointer::new(left.set(half, index - 1, v));
+
+                    Node::Branch(value, left, SharedPointer::clone(right))
+                } else {
+                    let right = SharedPointer::new(right.set(half, index - 1 - half, v));
+
+                    Node::Branch(value, SharedPointer::clone(left), right)
+                }
+            }
+        }
+    }
+}
+
+impl<T> RandomAccessListSync<T> {
+    #[must_use]
+    pub fn new_sync() -> RandomAccessListSync<T> {
+        RandomAccessList::new_with_ptr_kind()
+    }
+}
+
+impl<T> RandomAccessList<T> {
+    #[must_use]
+    pub fn new() -> RandomAccessList<T> {
+        RandomAccessList::new_with_ptr_kind()
+    }
+}
+
+impl<T, P> RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+{
+    #[must_use]
+    pub fn new_with_ptr_kind() -> RandomAccessList<T, P> {
+        RandomAccessList { trees: List::new_with_ptr_kind(), length: 0 }
+    }
+
+    #[must_use]
+    pub fn first(&self) -> Option<&T> {
+        self.trees.first().map(|tree| tree.root.value().borrow())
+    }
+
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let mut index = index;
+
+        for tree in &self.trees {
+            if index < tree.size {
+                return Some(tree.root.get(tree.size, index));
+            }
+
+            index -= tree.size;
+        }
+
+        None
+    }
+
+    #[must_use]
+    pub fn set(&self, index: usize, v: T) -> Option<RandomAccessList<T, P>> {
+        let mut new_list = self.clone();
+
+        if new_list.set_mut(index, v) {
+            Some(new_list)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the operation was successful.
+    pub fn set_mut(&mut self, index: usize, v: T) -> bool {
+        if index >= self.length {
+            return false;
+        }
+
+        // There are only Θ(log(n)) trees, so we can afford to rebuild the ones before the tree
+        // that contains the element.
+        let mut index = index;
+        let mut prefix: Vec<Tree<T, P>> = Vec::new();
+
+        while let Some(tree) = self.trees.first().cloned() {
+            self.trees.drop_first_mut();
+
+            if index < tree.size {
+                let root = tree.root.set(tree.size, index, SharedPointer::new(v));
+
+                self.trees.push_front_mut(Tree { size: tree.size, root: SharedPointer::new(root) });
+
+                break;
+            }
+
+            index -= tree.size;
+            prefix.push(tree);
+        }
+
+        for tree in prefix.into_iter().rev() {
+            self.trees.push_front_mut(tree);
+        }
+
+        true
+    }
+
+    #[must_use]
+    pub fn push_front(&self, v: T) -> RandomAccessList<T, P> {
+        let mut new_list = self.clone();
+
+        new_list.push_front_mut(v);
+
+        new_list
+    }
+
+    pub fn push_front_mut(&mut self, v: T) {
+        let v = SharedPointer::new(v);
+        let mut first_two = self.trees.iter();
+        let tree = match (first_two.next(), first_two.next()) {
+            (Some(t1), Some(t2)) if t1.size == t2.size => {
+                let node =
+                    Node::Branch(v, SharedPointer::clone(&t1.root), SharedPointer::clone(&t2.root));
+                let size = 1 + t1.size + t2.size;
+
+                self.trees.drop_first_mut();
+                self.trees.drop_first_mut();
+
+                Tree { size, root: SharedPointer::new(node) }
+            }
+            _ => Tree { size: 1, root: SharedPointer::new(Node::Leaf(v)) },
+        };
+
+        self.trees.push_front_mut(tree);
+        self.length += 1;
+    }
+
+    #[must_use]
+    pub fn drop_first(&self) -> Option<RandomAccessList<T, P>> {
+        let mut new_list = self.clone();
+
+        if new_list.drop_first_mut() {
+            Some(new_list)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the operation was successful.
+    pub fn drop_first_mut(&mut self) -> bool {
+        let tree = match self.trees.first() {
+            Some(tree) => tree.clone(),
+            None => return false,
+        };
+
+        self.trees.drop_first_mut();
+
+        if let Node::Branch(_, left, right) = tree.root.borrow() {
+            let size = tree.size / 2;
+
+            self.trees.push_front_mut(Tree { size, root: SharedPointer::clone(right) });
+            self.trees.push_front_mut(Tree { size, root: SharedPointer::clone(left) });
+        }
+
+        self.length -= 1;
+
+        true
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        self.iter_ptr().map(|v| v.borrow())
+    }
+
+    #[must_use]
+    fn iter_ptr(&self) -> IterPtr<'_, T, P> {
+        IterPtr::new(self)
+    }
+}
+
+impl<T, P> Index<usize> for RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+{
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).unwrap_or_else(|| panic!("index out of bounds {}", index))
+    }
+}
+
+impl<T, P> Default for RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn default() -> RandomAccessList<T, P> {
+        RandomAccessList::new_with_ptr_kind()
+    }
+}
+
+impl<T: PartialEq, P, PO> PartialEq<RandomAccessList<T, PO>> for RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+    PO: SharedPointerKind,
+{
+    fn eq(&self, other: &RandomAccessList<T, PO>) -> bool {
+        self.length == other.length && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, P> Eq for RandomAccessList<T, P> where P: SharedPointerKind {}
+
+impl<T: PartialOrd<T>, P, PO> PartialOrd<RandomAccessList<T, PO>> for RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+    PO: SharedPointerKind,
+{
+    fn partial_cmp(&self, other: &RandomAccessList<T, PO>) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord, P> Ord for RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn cmp(&self, other: &RandomAccessList<T, P>) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Hash, P> Hash for RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Add the hash of length so that if two collections are added one after the other it doesn't
+        // hash to the same thing as a single collection with the same elements in the same order.
+        self.len().hash(state);
+
+        for e in self {
+            e.hash(state);
+        }
+    }
+}
+
+impl<T, P> Clone for RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn clone(&self) -> RandomAccessList<T, P> {
+        RandomAccessList { trees: self.trees.clone(), length: self.length }
+    }
+}
+
+impl<T: Display, P> Display for RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut first = true;
+
+        fmt.write_str("[")?;
+
+        for v in self.iter() {
+            if !first {
+                fmt.write_str(", ")?;
+            }
+            v.fmt(fmt)?;
+            first = false;
+        }
+
+        fmt.write_str("]")
+    }
+}
+
+impl<'a, T, P> IntoIterator for &'a RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, P> FromIterator<T> for RandomAccessList<T, P>
+where
+    P: SharedPointerKind,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(into_iter: I) -> RandomAccessList<T, P> {
+        let iter = into_iter.into_iter();
+        let (min_size, max_size_hint) = iter.size_hint();
+        let mut vec: Vec<T> = Vec::with_capacity(max_size_hint.unwrap_or(min_size));
+
+        for e in iter {
+            vec.push(e);
+        }
+
+        let mut list: RandomAccessList<T, P> = RandomAccessList::new_with_ptr_kind();
+
+        for e in vec.into_iter().rev() {
+            list.push_front_mut(e);
+        }
+
+        list
+    }
+}
+
+/// An iterator over the elements of the list that traverses each tree in preorder.
+#[derive(Debug)]
+pub struct IterPtr<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    trees: crate::list::Iter<'a, Tree<T, P>, P>,
+    /// Nodes still to visit in the current tree.  This never has more than Θ(log(n)) nodes.
+    stack: Vec<&'a Node<T, P>>,
+    length: usize,
+}
+
+impl<'a, T, P> IterPtr<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    fn new(list: &RandomAccessList<T, P>) -> IterPtr<'_, T, P> {
+        IterPtr { trees: list.trees.iter(), stack: Vec::new(), length: list.len() }
+    }
+}
+
+impl<'a, T, P> Iterator for IterPtr<'a, T, P>
+where
+    P: SharedPointerKind,
+{
+    type Item = &'a SharedPointer<T, P>;
+
+    fn next(&mut self) -> Option<&'a SharedPointer<T, P>> {
+        let node: &'a Node<T, P> = match self.stack.pop() {
+            Some(node) => node,
+            None => self.trees.next()?.root.borrow(),
+        };
+
+        if let Node::Branch(_, left, right) = node {
+            self.stack.push(right);
+            self.stack.push(left);
+        }
+
+        self.length -= 1;
+
+        Some(node.value())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<'a, T, P> ExactSizeIterator for IterPtr<'a, T, P> where P: SharedPointerKind {}
+
+#[cfg(feature = "serde")]
+pub mod serde {
+    use super::*;
+    use ::serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+    use ::serde::ser::{Serialize, Serializer};
+    use core::fmt;
+    use core::marker::PhantomData;
+
+    impl<T, P> Serialize for RandomAccessList<T, P>
+    where
+        T: Serialize,
+        P: SharedPointerKind,
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self)
+        }
+    }
+
+    impl<'de, T, P> Deserialize<'de> for RandomAccessList<T, P>
+    where
+        T: Deserialize<'de>,
+        P: SharedPointerKind,
+    {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<RandomAccessList<T, P>, D::Error> {
+            deserializer.deserialize_seq(RandomAccessListVisitor {
+                _phantom_t: PhantomData,
+                _phantom_p: PhantomData,
+            })
+        }
+    }
+
+    struct RandomAccessListVisitor<T, P> {
+        _phantom_t: PhantomData<T>,
+        _phantom_p: PhantomData<P>,
+    }
+
+    impl<'de, T, P> Visitor<'de> for RandomAccessListVisitor<T, P>
+    where
+        T: Deserialize<'de>,
+        P: SharedPointerKind,
+    {
+        type Value = RandomAccessList<T, P>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("a sequence")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<RandomAccessList<T, P>, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut vec: Vec<T> = if let Some(capacity) = seq.size_hint() {
+                Vec::with_capacity(capacity)
+            } else {
+                Vec::new()
+            };
+
+            while let Some(value) = seq.next_element()? {
+                vec.push(value);
+            }
+
+            let mut list: RandomAccessList<T, P> = RandomAccessList::new_with_ptr_kind();
+
+            for value in vec.into_iter().rev() {
+                list.push_front_mut(value);
+            }
+
+            Ok(list)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
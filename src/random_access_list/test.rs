@@ -0,0 +1,367 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::*;
+use pretty_assertions::assert_eq;
+use static_assertions::assert_impl_all;
+
+assert_impl_all!(RandomAccessListSync<i32>: Send, Sync);
+
+#[allow(dead_code)]
+fn compile_time_macro_random_access_list_sync_is_send_and_sync() -> impl Send + Sync {
+    random_access_list_sync!(0)
+}
+
+mod iter {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_iter() {
+        let limit = 1024;
+        let mut list = RandomAccessList::new();
+        let mut left = limit;
+
+        for i in 0..limit {
+            list.push_front_mut(i);
+        }
+
+        for v in &list {
+            left -= 1;
+            assert_eq!(*v, left);
+        }
+
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn test_iter_size_hint() {
+        let list = random_access_list![0, 1, 2];
+        let mut iterator = list.iter();
+
+        assert_eq!(iterator.size_hint(), (3, Some(3)));
+
+        iterator.next();
+
+        assert_eq!(iterator.size_hint(), (2, Some(2)));
+
+        iterator.next();
+
+        assert_eq!(iterator.size_hint(), (1, Some(1)));
+
+        iterator.next();
+
+        assert_eq!(iterator.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn test_into_iterator() {
+        let list = random_access_list![0, 1, 2, 3, 4];
+        let mut expected = 0;
+
+        for v in &list {
+            assert_eq!(*v, expected);
+            expected += 1;
+        }
+
+        assert_eq!(expected, 5);
+    }
+}
+
+mod internal {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn tree_size<T, P: SharedPointerKind>(node: &Node<T, P>) -> usize {
+        match node {
+            Node::Leaf(_) => 1,
+            Node::Branch(_, left, right) => {
+                let left_size = tree_size(left);
+
+                assert_eq!(left_size, tree_size(right));
+
+                1 + 2 * left_size
+            }
+        }
+    }
+
+    fn check<T, P: SharedPointerKind>(list: &RandomAccessList<T, P>) {
+        let sizes: Vec<usize> = list.trees.iter().map(|tree| tree.size).collect();
+
+        for tree in &list.trees {
+            assert_eq!(tree.size, tree_size(&tree.root));
+        }
+
+        for (i, w) in sizes.windows(2).enumerate() {
+            assert!(w[0] < w[1] || (i == 0 && w[0] == w[1]), "sizes: {:?}", sizes);
+        }
+
+        assert_eq!(sizes.iter().sum::<usize>(), list.len());
+    }
+
+    #[test]
+    fn test_invariants() {
+        let mut list = RandomAccessList::new();
+
+        for i in 0..300 {
+            list.push_front_mut(i);
+            check(&list);
+        }
+
+        for i in 0..300 {
+            assert!(list.set_mut(i, i * 2));
+            check(&list);
+        }
+
+        while list.drop_first_mut() {
+            check(&list);
+        }
+    }
+}
+
+#[test]
+fn test_new() {
+    let empty_list: RandomAccessList<i32> = RandomAccessList::new();
+
+    assert!(empty_list.trees.is_empty());
+    assert_eq!(empty_list.len(), 0);
+    assert!(empty_list.is_empty());
+}
+
+#[test]
+fn test_macro_random_access_list() {
+    let list_1 = RandomAccessList::new().push_front(1);
+    let list_1_2_3 = RandomAccessList::new().push_front(3).push_front(2).push_front(1);
+
+    assert_eq!(RandomAccessList::<u32>::new(), random_access_list![]);
+    assert_eq!(list_1, random_access_list![1]);
+    assert_eq!(list_1_2_3, random_access_list![1, 2, 3]);
+}
+
+#[test]
+fn test_first() {
+    let empty_list: RandomAccessList<i32> = RandomAccessList::new();
+    let singleton_list = random_access_list!["hello"];
+    let list = random_access_list![0, 1, 2, 3];
+
+    assert_eq!(empty_list.first(), None);
+    assert_eq!(singleton_list.first(), Some(&"hello"));
+    assert_eq!(list.first(), Some(&0));
+}
+
+#[test]
+fn test_get() {
+    let limit = 1000;
+    let list: RandomAccessList<usize> = (0..limit).collect();
+
+    for i in 0..limit {
+        assert_eq!(list.get(i), Some(&i));
+        assert_eq!(list[i], i);
+    }
+
+    assert_eq!(list.get(limit), None);
+    assert_eq!(RandomAccessList::<i32>::new().get(0), None);
+}
+
+#[test]
+#[should_panic(expected = "index out of bounds")]
+fn test_index_out_of_bounds() {
+    let list = random_access_list![0, 1, 2];
+    let _ = list[3];
+}
+
+#[test]
+fn test_set() {
+    let limit = 200;
+    let list: RandomAccessList<usize> = (0..limit).collect();
+
+    for i in 0..limit {
+        let new_list = list.set(i, 1000 + i).unwrap();
+
+        for j in 0..limit {
+            let expected = if i == j { 1000 + j } else { j };
+
+            assert_eq!(new_list.get(j), Some(&expected));
+            assert_eq!(list.get(j), Some(&j));
+        }
+
+        assert_eq!(new_list.len(), limit);
+    }
+
+    assert!(list.set(limit, 0).is_none());
+}
+
+#[test]
+fn test_set_mut() {
+    let mut list = random_access_list![0, 1, 2, 3];
+
+    assert!(list.set_mut(2, 20));
+    assert_eq!(list, random_access_list![0, 1, 20, 3]);
+    assert!(!list.set_mut(4, 40));
+    assert_eq!(list, random_access_list![0, 1, 20, 3]);
+}
+
+#[test]
+fn test_push_front() {
+    let list = random_access_list![1, 2];
+    let new_list = list.push_front(0);
+
+    assert_eq!(new_list, random_access_list![0, 1, 2]);
+    assert_eq!(list, random_access_list![1, 2]);
+}
+
+#[test]
+fn test_drop_first() {
+    let empty_list: RandomAccessList<i32> = RandomAccessList::new();
+    let singleton_list = random_access_list!["hello"];
+    let list = random_access_list![0, 1, 2, 3];
+
+    assert!(empty_list.drop_first().is_none());
+    assert_eq!(singleton_list.drop_first().unwrap().first(), None);
+    assert_eq!(list.drop_first().unwrap(), random_access_list![1, 2, 3]);
+    assert_eq!(list.len(), 4);
+    assert_eq!(list.drop_first().unwrap().len(), 3);
+}
+
+#[test]
+fn test_drop_first_mut() {
+    let limit = 500;
+    let mut list: RandomAccessList<usize> = (0..limit).collect();
+
+    for i in 0..limit {
+        assert_eq!(list.first(), Some(&i));
+        assert!(list.iter().copied().eq(i..limit));
+        assert!(list.drop_first_mut());
+        assert_eq!(list.len(), limit - i - 1);
+    }
+
+    assert!(!list.drop_first_mut());
+    assert!(list.is_empty());
+}
+
+#[test]
+fn test_from_iterator() {
+    let vec: Vec<u32> = vec![10, 11, 12, 13];
+    let list: RandomAccessList<u32> = vec.iter().copied().collect();
+
+    assert!(vec.iter().eq(list.iter()));
+}
+
+#[test]
+fn test_default() {
+    let list: RandomAccessList<i32> = RandomAccessList::default();
+
+    assert_eq!(list.first(), None);
+    assert_eq!(list.len(), 0);
+}
+
+#[test]
+fn test_display() {
+    let empty_list: RandomAccessList<i32> = RandomAccessList::new();
+    let singleton_list = random_access_list!["hello"];
+    let list = random_access_list![0, 1, 2, 3];
+
+    assert_eq!(format!("{}", empty_list), "[]");
+    assert_eq!(format!("{}", singleton_list), "[hello]");
+    assert_eq!(format!("{}", list), "[0, 1, 2, 3]");
+}
+
+#[test]
+fn test_eq() {
+    let list_1 = random_access_list!["a", "a"];
+    let list_1_prime = random_access_list!["a", "a"];
+    let list_2 = random_access_list!["a", "b"];
+
+    assert_ne!(list_1, list_2);
+    assert_eq!(list_1, list_1);
+    assert_eq!(list_1, list_1_prime);
+    assert_eq!(list_2, list_2);
+}
+
+#[test]
+fn test_eq_pointer_kind_consistent() {
+    let list_a = random_access_list!["a"];
+    let list_a_sync = random_access_list_sync!["a"];
+    let list_b = random_access_list!["b"];
+    let list_b_sync = random_access_list_sync!["b"];
+
+    assert!(list_a == list_a_sync);
+    assert!(list_a != list_b_sync);
+    assert!(list_b == list_b_sync);
+}
+
+#[test]
+fn test_partial_ord() {
+    let list_1 = random_access_list!["a"];
+    let list_1_prime = random_access_list!["a"];
+    let list_2 = random_access_list!["b"];
+    let list_3 = random_access_list![0.0];
+    let list_4 = random_access_list![core::f32::NAN];
+
+    assert_eq!(list_1.partial_cmp(&list_1_prime), Some(Ordering::Equal));
+    assert_eq!(list_1.partial_cmp(&list_2), Some(Ordering::Less));
+    assert_eq!(list_2.partial_cmp(&list_1), Some(Ordering::Greater));
+    assert_eq!(list_3.partial_cmp(&list_4), None);
+}
+
+#[test]
+fn test_ord() {
+    let list_1 = random_access_list!["a"];
+    let list_1_prime = random_access_list!["a"];
+    let list_2 = random_access_list!["b"];
+
+    assert_eq!(list_1.cmp(&list_1_prime), Ordering::Equal);
+    assert_eq!(list_1.cmp(&list_2), Ordering::Less);
+    assert_eq!(list_2.cmp(&list_1), Ordering::Greater);
+}
+
+fn hash<T: Hash, P: SharedPointerKind>(list: &RandomAccessList<T, P>) -> u64 {
+    #[allow(deprecated)]
+    let mut hasher = core::hash::SipHasher::new();
+
+    list.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[test]
+fn test_hash() {
+    let list_1 = random_access_list!["a"];
+    let list_1_prime = random_access_list!["a"];
+    let list_2 = random_access_list!["a", "b"];
+
+    assert_eq!(hash(&list_1), hash(&list_1));
+    assert_eq!(hash(&list_1), hash(&list_1_prime));
+    assert_ne!(hash(&list_1), hash(&list_2));
+}
+
+#[test]
+fn test_hash_pointer_kind_consistent() {
+    let list = random_access_list!["a"];
+    let list_sync = random_access_list_sync!["a"];
+
+    assert_eq!(hash(&list), hash(&list_sync));
+}
+
+#[test]
+fn test_clone() {
+    let list = random_access_list!["hello", "there"];
+    let clone = list.clone();
+
+    assert!(clone.iter().eq(list.iter()));
+    assert_eq!(clone.len(), list.len());
+    assert_eq!(clone.first(), list.first());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use bincode::{deserialize, serialize};
+    let list: RandomAccessList<i32> = random_access_list![5, 6, 7, 8];
+    let encoded = serialize(&list).unwrap();
+    let decoded: RandomAccessList<i32> = deserialize(&encoded).unwrap();
+
+    assert_eq!(list, decoded);
+}
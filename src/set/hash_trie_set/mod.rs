@@ -3,7 +3,9 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
+use crate::equivalent::Equivalent;
 use crate::map::hash_trie_map;
+use crate::map::hash_trie_map::TrieHash;
 use crate::utils::DefaultBuildHasher;
 use crate::HashTrieMap;
 use archery::{ArcK, RcK, SharedPointerKind};
@@ -11,10 +13,13 @@ use core::borrow::Borrow;
 use core::fmt::Display;
 use core::hash::BuildHasher;
 use core::hash::Hash;
+use core::hash::Hasher;
 use core::iter::FromIterator;
+#[cfg(feature = "rand")]
+use rand::RngCore;
 
 // TODO Use impl trait instead of this when available.
-pub type Iter<'a, T, P> = hash_trie_map::IterKeys<'a, T, (), P>;
+pub type Iter<'a, T, P, W = u64> = hash_trie_map::IterKeys<'a, T, (), P, W>;
 
 /// Creates a [`HashTrieSet`](set/hash_trie_set/struct.HashTrieSet.html) containing the given
 /// arguments:
@@ -82,29 +87,32 @@ macro_rules! ht_set_sync {
 /// | Operation         | Average | Worst case  |
 /// |:----------------- | -------:| -----------:|
 /// | `new()`           |    Θ(1) |        Θ(1) |
-/// | `insert()`        |    Θ(1) |        Θ(n) |
-/// | `remove()`        |    Θ(1) |        Θ(n) |
-/// | `contains()`      |    Θ(1) |        Θ(n) |
+/// | `insert()`        |    Θ(1) | Θ(log(n))\* |
+/// | `remove()`        |    Θ(1) | Θ(log(n))\* |
+/// | `contains()`      |    Θ(1) | Θ(log(n))\* |
 /// | `size()`          |    Θ(1) |        Θ(1) |
 /// | `clone()`         |    Θ(1) |        Θ(1) |
 /// | iterator creation |    Θ(1) |        Θ(1) |
-/// | iterator step     |    Θ(1) |        Θ(1) |
+/// | iterator step     |    Θ(1) | Θ(log(n))\* |
 /// | iterator full     |    Θ(n) |        Θ(n) |
 ///
+/// \* The worst case happens when many elements have the same hash.  See
+/// [`HashTrieMap`](../../map/hash_trie_map/struct.HashTrieMap.html) for details.
+///
 /// # Implementation details
 ///
 /// This is a thin wrapper around a [`HashTrieMap`](../../map/hash_trie_map/struct.HashTrieMap.html).
 #[derive(Debug)]
-pub struct HashTrieSet<T, P = RcK, H: BuildHasher = DefaultBuildHasher>
+pub struct HashTrieSet<T, P = RcK, H: BuildHasher = DefaultBuildHasher, W: TrieHash = u64>
 where
     T: Eq + Hash,
     H: Clone,
     P: SharedPointerKind,
 {
-    map: HashTrieMap<T, (), P, H>,
+    map: HashTrieMap<T, (), P, H, W>,
 }
 
-pub type HashTrieSetSync<T, H = DefaultBuildHasher> = HashTrieSet<T, ArcK, H>;
+pub type HashTrieSetSync<T, H = DefaultBuildHasher, W = u64> = HashTrieSet<T, ArcK, H, W>;
 
 impl<T> HashTrieSet<T, RcK, DefaultBuildHasher>
 where
@@ -140,14 +148,14 @@ where
     }
 }
 
-impl<T, P, H: BuildHasher> HashTrieSet<T, P, H>
+impl<T, P, H: BuildHasher, W: TrieHash> HashTrieSet<T, P, H, W>
 where
     T: Eq + Hash,
     H: Clone,
     P: SharedPointerKind,
 {
     #[must_use]
-    pub fn new_with_hasher_with_ptr_kind(hasher_builder: H) -> HashTrieSet<T, P, H> {
+    pub fn new_with_hasher_with_ptr_kind(hasher_builder: H) -> HashTrieSet<T, P, H, W> {
         HashTrieSet { map: HashTrieMap::new_with_hasher_and_ptr_kind(hasher_builder) }
     }
 
@@ -155,14 +163,14 @@ where
     pub fn new_with_hasher_and_degree_and_ptr_kind(
         hasher_builder: H,
         degree: u8,
-    ) -> HashTrieSet<T, P, H> {
+    ) -> HashTrieSet<T, P, H, W> {
         HashTrieSet {
             map: HashTrieMap::new_with_hasher_and_degree_and_ptr_kind(hasher_builder, degree),
         }
     }
 
     #[must_use]
-    pub fn insert(&self, v: T) -> HashTrieSet<T, P, H> {
+    pub fn insert(&self, v: T) -> HashTrieSet<T, P, H, W> {
         HashTrieSet { map: self.map.insert(v, ()) }
     }
 
@@ -171,7 +179,7 @@ where
     }
 
     #[must_use]
-    pub fn remove<V: ?Sized>(&self, v: &V) -> HashTrieSet<T, P, H>
+    pub fn remove<V: ?Sized>(&self, v: &V) -> HashTrieSet<T, P, H, W>
     where
         T: Borrow<V>,
         V: Hash + Eq,
@@ -196,18 +204,56 @@ where
         self.map.contains_key(v)
     }
 
+    /// Same as [`remove()`](HashTrieSet::remove()) but the value can be of any type
+    /// [equivalent](Equivalent) to `T`.
+    #[must_use]
+    pub fn remove_equiv<V: ?Sized>(&self, v: &V) -> HashTrieSet<T, P, H, W>
+    where
+        V: Hash + Equivalent<T>,
+    {
+        HashTrieSet { map: self.map.remove_equiv(v) }
+    }
+
+    /// Same as [`remove_mut()`](HashTrieSet::remove_mut()) but the value can be of any type
+    /// [equivalent](Equivalent) to `T`.
+    pub fn remove_equiv_mut<V: ?Sized>(&mut self, v: &V) -> bool
+    where
+        V: Hash + Equivalent<T>,
+    {
+        self.map.remove_equiv_mut(v)
+    }
+
+    /// Same as [`contains()`](HashTrieSet::contains()) but the value can be of any type
+    /// [equivalent](Equivalent) to `T`.
+    #[must_use]
+    pub fn contains_equiv<V: ?Sized>(&self, v: &V) -> bool
+    where
+        V: Hash + Equivalent<T>,
+    {
+        self.map.contains_key_equiv(v)
+    }
+
     #[must_use]
-    pub fn is_disjoint<I: BuildHasher + Clone>(&self, other: &HashTrieSet<T, P, I>) -> bool {
+    pub fn is_disjoint<I: BuildHasher + Clone, WI: TrieHash>(
+        &self,
+        other: &HashTrieSet<T, P, I, WI>,
+    ) -> bool {
         self.iter().all(|v| !other.contains(v))
     }
 
     #[must_use]
-    pub fn is_subset<I: BuildHasher + Clone>(&self, other: &HashTrieSet<T, P, I>) -> bool {
+    pub fn is_subset<I: BuildHasher + Clone, WI: TrieHash>(
+        &self,
+        other: &HashTrieSet<T, P, I, WI>,
+    ) -> bool {
         self.iter().all(|v| other.contains(v))
     }
 
     #[must_use]
-    pub fn is_superset<I: BuildHasher + Clone>(&self, other: &HashTrieSet<T, P, I>) -> bool {
+    pub fn is_superset<I: BuildHasher + Clone, WI: TrieHash>(
+        &self,
+        other: &HashTrieSet<T, P, I, WI>,
+    ) -> bool {
         other.is_subset(self)
     }
 
@@ -223,47 +269,133 @@ where
         self.size() == 0
     }
 
+    /// Returns the element at position `index` in the order of [`iter()`](HashTrieSet::iter()), or `None`
+    /// if `index` is out of bounds.  This takes *O(log(n))* time.
     #[must_use]
-    pub fn iter(&self) -> Iter<'_, T, P> {
+    pub fn nth(&self, index: usize) -> Option<&T> {
+        self.map.nth(index).map(|(k, _)| k)
+    }
+
+    /// Returns an element chosen uniformly at random, or `None` if the set is empty.  This takes
+    /// *O(log(n))* time.
+    ///
+    /// This requires the `rand` feature.
+    #[cfg(feature = "rand")]
+    #[must_use]
+    pub fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Option<&T> {
+        self.map.sample(rng).map(|(k, _)| k)
+    }
+
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, T, P, W> {
         self.map.keys()
     }
 }
 
-impl<T, P, H: BuildHasher> Clone for HashTrieSet<T, P, H>
+impl<T, P, H: BuildHasher, W: TrieHash> HashTrieSet<T, P, H, W>
+where
+    T: Eq + Hash + Clone,
+    H: Clone,
+    P: SharedPointerKind,
+{
+    /// Same as `insert()` but also returns the value that was replaced, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let set = HashTrieSet::new().insert(1);
+    ///
+    /// let (new_set, previous) = set.replace(1);
+    ///
+    /// assert_eq!(previous, Some(1));
+    /// assert_eq!(new_set.replace(2).1, None);
+    /// ```
+    #[must_use]
+    pub fn replace(&self, v: T) -> (HashTrieSet<T, P, H, W>, Option<T>) {
+        let mut new_set = self.clone();
+        let previous = new_set.replace_mut(v);
+
+        (new_set, previous)
+    }
+
+    /// Same as `insert_mut()` but also returns the value that was replaced, if any.
+    pub fn replace_mut(&mut self, v: T) -> Option<T> {
+        self.map.replace_entry_mut(v, ()).map(|(previous, ())| previous)
+    }
+
+    /// Same as `remove()` but also returns the value that was removed, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let set = HashTrieSet::new().insert("a");
+    ///
+    /// let (new_set, removed) = set.take("a");
+    ///
+    /// assert_eq!(removed, Some("a"));
+    /// assert!(new_set.is_empty());
+    /// ```
+    #[must_use]
+    pub fn take<V: ?Sized>(&self, v: &V) -> (HashTrieSet<T, P, H, W>, Option<T>)
+    where
+        T: Borrow<V>,
+        V: Hash + Eq,
+    {
+        let (map, removed) = self.map.remove_entry(v);
+
+        (HashTrieSet { map }, removed.map(|(v, ())| v))
+    }
+
+    /// Same as `remove_mut()` but also returns the value that was removed, if any.
+    pub fn take_mut<V: ?Sized>(&mut self, v: &V) -> Option<T>
+    where
+        T: Borrow<V>,
+        V: Hash + Eq,
+    {
+        self.map.remove_entry_mut(v).map(|(v, ())| v)
+    }
+}
+
+impl<T, P, H: BuildHasher, W: TrieHash> Clone for HashTrieSet<T, P, H, W>
 where
     T: Eq + Hash,
     H: Clone,
     P: SharedPointerKind,
 {
-    fn clone(&self) -> HashTrieSet<T, P, H> {
+    fn clone(&self) -> HashTrieSet<T, P, H, W> {
         HashTrieSet { map: self.map.clone() }
     }
 }
 
-impl<T, P, H: BuildHasher> Default for HashTrieSet<T, P, H>
+impl<T, P, H: BuildHasher, W: TrieHash> Default for HashTrieSet<T, P, H, W>
 where
     T: Eq + Hash,
     H: Default + Clone,
     P: SharedPointerKind,
 {
-    fn default() -> HashTrieSet<T, P, H> {
+    fn default() -> HashTrieSet<T, P, H, W> {
         HashTrieSet::new_with_hasher_with_ptr_kind(H::default())
     }
 }
 
-impl<T: Eq, P, PO, H: BuildHasher> PartialEq<HashTrieSet<T, PO, H>> for HashTrieSet<T, P, H>
+impl<T: Eq, P, PO, H: BuildHasher, W: TrieHash> PartialEq<HashTrieSet<T, PO, H, W>>
+    for HashTrieSet<T, P, H, W>
 where
     T: Hash,
     H: Clone,
     P: SharedPointerKind,
     PO: SharedPointerKind,
 {
-    fn eq(&self, other: &HashTrieSet<T, PO, H>) -> bool {
+    fn eq(&self, other: &HashTrieSet<T, PO, H, W>) -> bool {
         self.map.eq(&other.map)
     }
 }
 
-impl<T: Eq, P, H: BuildHasher> Eq for HashTrieSet<T, P, H>
+impl<T: Eq, P, H: BuildHasher, W: TrieHash> Eq for HashTrieSet<T, P, H, W>
 where
     T: Hash,
     H: Clone,
@@ -271,7 +403,19 @@ where
 {
 }
 
-impl<T, P, H: BuildHasher> Display for HashTrieSet<T, P, H>
+/// The hash of a set does not depend on its hasher or on the order in which the elements were
+/// inserted, so it is consistent with `Eq`.
+impl<T: Eq + Hash, P, H: BuildHasher, W: TrieHash> Hash for HashTrieSet<T, P, H, W>
+where
+    H: Clone,
+    P: SharedPointerKind,
+{
+    fn hash<HS: Hasher>(&self, state: &mut HS) {
+        self.map.hash(state);
+    }
+}
+
+impl<T, P, H: BuildHasher, W: TrieHash> Display for HashTrieSet<T, P, H, W>
 where
     T: Eq + Hash + Display,
     H: Clone,
@@ -294,34 +438,39 @@ where
     }
 }
 
-impl<'a, T, P, H: BuildHasher> IntoIterator for &'a HashTrieSet<T, P, H>
+impl<'a, T, P, H: BuildHasher, W: TrieHash> IntoIterator for &'a HashTrieSet<T, P, H, W>
 where
     T: Eq + Hash,
     H: Default + Clone,
     P: SharedPointerKind,
 {
     type Item = &'a T;
-    type IntoIter = Iter<'a, T, P>;
+    type IntoIter = Iter<'a, T, P, W>;
 
-    fn into_iter(self) -> Iter<'a, T, P> {
+    fn into_iter(self) -> Iter<'a, T, P, W> {
         self.iter()
     }
 }
 
-impl<T, P, H> FromIterator<T> for HashTrieSet<T, P, H>
+impl<T, P, H, W: TrieHash> FromIterator<T> for HashTrieSet<T, P, H, W>
 where
     T: Eq + Hash,
     H: BuildHasher + Clone + Default,
     P: SharedPointerKind,
 {
-    fn from_iter<I: IntoIterator<Item = T>>(into_iter: I) -> HashTrieSet<T, P, H> {
-        let mut set = HashTrieSet::new_with_hasher_with_ptr_kind(Default::default());
-
-        for v in into_iter {
-            set.insert_mut(v);
-        }
+    fn from_iter<I: IntoIterator<Item = T>>(into_iter: I) -> HashTrieSet<T, P, H, W> {
+        HashTrieSet { map: into_iter.into_iter().map(|v| (v, ())).collect() }
+    }
+}
 
-        set
+impl<T, P, H, W: TrieHash> Extend<T> for HashTrieSet<T, P, H, W>
+where
+    T: Eq + Hash,
+    H: BuildHasher + Clone,
+    P: SharedPointerKind,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.map.extend(iter.into_iter().map(|v| (v, ())));
     }
 }
 
@@ -333,7 +482,7 @@ pub mod serde {
     use core::fmt;
     use core::marker::PhantomData;
 
-    impl<T, P, H> Serialize for HashTrieSet<T, P, H>
+    impl<T, P, H, W: TrieHash> Serialize for HashTrieSet<T, P, H, W>
     where
         T: Eq + Hash + Serialize,
         H: BuildHasher + Clone + Default,
@@ -344,7 +493,7 @@ pub mod serde {
         }
     }
 
-    impl<'de, T, P, H> Deserialize<'de> for HashTrieSet<T, P, H>
+    impl<'de, T, P, H, W: TrieHash> Deserialize<'de> for HashTrieSet<T, P, H, W>
     where
         T: Eq + Hash + Deserialize<'de>,
         H: BuildHasher + Clone + Default,
@@ -352,34 +501,36 @@ pub mod serde {
     {
         fn deserialize<D: Deserializer<'de>>(
             deserializer: D,
-        ) -> Result<HashTrieSet<T, P, H>, D::Error> {
+        ) -> Result<HashTrieSet<T, P, H, W>, D::Error> {
             deserializer.deserialize_seq(HashTrieSetVisitor {
                 _phantom_t: PhantomData,
                 _phantom_h: PhantomData,
                 _phantom_p: PhantomData,
+                _phantom_w: PhantomData,
             })
         }
     }
 
-    struct HashTrieSetVisitor<T, P, H> {
+    struct HashTrieSetVisitor<T, P, H, W> {
         _phantom_t: PhantomData<T>,
         _phantom_h: PhantomData<H>,
         _phantom_p: PhantomData<P>,
+        _phantom_w: PhantomData<W>,
     }
 
-    impl<'de, T, P, H> Visitor<'de> for HashTrieSetVisitor<T, P, H>
+    impl<'de, T, P, H, W: TrieHash> Visitor<'de> for HashTrieSetVisitor<T, P, H, W>
     where
         T: Eq + Hash + Deserialize<'de>,
         H: BuildHasher + Clone + Default,
         P: SharedPointerKind,
     {
-        type Value = HashTrieSet<T, P, H>;
+        type Value = HashTrieSet<T, P, H, W>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
             formatter.write_str("a sequence")
         }
 
-        fn visit_seq<A>(self, mut seq: A) -> Result<HashTrieSet<T, P, H>, A::Error>
+        fn visit_seq<A>(self, mut seq: A) -> Result<HashTrieSet<T, P, H, W>, A::Error>
         where
             A: SeqAccess<'de>,
         {
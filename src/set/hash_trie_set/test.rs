@@ -4,6 +4,8 @@
  */
 
 use super::*;
+use alloc::borrow::ToOwned;
+use alloc::string::String;
 use alloc::vec::Vec;
 use pretty_assertions::assert_eq;
 use static_assertions::assert_impl_all;
@@ -224,6 +226,33 @@ fn test_remove_mut() {
     assert!(!set.contains("bar"));
 }
 
+#[test]
+fn test_nth() {
+    let set: HashTrieSet<i32> = (0..100).collect();
+
+    for (i, v) in set.iter().enumerate() {
+        assert_eq!(set.nth(i), Some(v));
+    }
+
+    assert_eq!(set.nth(100), None);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn test_sample() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let set: HashTrieSet<i32> = ht_set![1, 2, 3];
+
+    assert_eq!(HashTrieSet::<i32>::new().sample(&mut rng), None);
+
+    for _ in 0..100 {
+        assert!(set.contains(set.sample(&mut rng).unwrap()));
+    }
+}
+
 #[test]
 fn test_is_disjoint() {
     assert!(!HashTrieSet::is_disjoint(&ht_set![1, 2, 3], &ht_set![1, 2, 3]));
@@ -249,6 +278,26 @@ fn test_is_superset() {
     assert!(!HashTrieSet::is_superset(&ht_set![1, 2, 3], &ht_set![1, 2, 3, 5, 6]));
 }
 
+#[test]
+fn test_hash_width() {
+    let set: HashTrieSet<i32, RcK, DefaultBuildHasher, u32> = (0..100).fold(
+        HashTrieSet::new_with_hasher_with_ptr_kind(DefaultBuildHasher::default()),
+        |set, i| set.insert(i),
+    );
+    let wide: HashTrieSet<i32, RcK, DefaultBuildHasher, u128> = (0..50).collect();
+
+    assert_eq!(set.size(), 100);
+    assert!(set.contains(&99));
+    assert!(!set.remove(&99).contains(&99));
+    assert!(wide.is_subset(&set));
+    assert!(set.is_superset(&wide));
+    assert!(!set.is_disjoint(&wide));
+
+    let sync: HashTrieSetSync<i32, DefaultBuildHasher, u32> = (0..50).collect();
+
+    assert!(sync.iter().all(|v| set.contains(v)));
+}
+
 #[test]
 fn test_from_iterator() {
     let vec: Vec<&str> = vec![("two"), ("five")];
@@ -258,6 +307,21 @@ fn test_from_iterator() {
     assert_eq!(set, expected_set);
 }
 
+#[test]
+fn test_extend() {
+    let mut set = ht_set![1, 2];
+
+    set.extend(0..100);
+
+    assert_eq!(set.size(), 100);
+    assert!((0..100).all(|v| set.contains(&v)));
+
+    set.extend(vec![100, 1]);
+
+    assert_eq!(set.size(), 101);
+    assert!(set.contains(&100));
+}
+
 #[test]
 fn test_default() {
     let set: HashTrieSet<u32> = HashTrieSet::default();
@@ -305,6 +369,32 @@ fn test_eq_pointer_kind_consistent() {
     assert!(set_b == set_b_sync);
 }
 
+fn hash<T: Eq + Hash, P, H: BuildHasher + Clone>(set: &HashTrieSet<T, P, H>) -> u64
+where
+    P: SharedPointerKind,
+{
+    #[allow(deprecated)]
+    let mut hasher = core::hash::SipHasher::new();
+
+    set.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[test]
+fn test_hash() {
+    let set_1 = ht_set!["a", "b"];
+    let set_1_prime = ht_set!["b", "a"];
+    let set_1_sync = ht_set_sync!["a", "b"];
+    let set_2 = ht_set!["a", "c"];
+
+    assert_eq!(hash(&set_1), hash(&set_1));
+    assert_eq!(hash(&set_1), hash(&set_1_prime));
+    assert_eq!(hash(&set_1), hash(&set_1_sync));
+    assert_ne!(hash(&set_1), hash(&set_2));
+    assert_ne!(hash(&set_1), hash(&ht_set!["a"]));
+}
+
 #[test]
 fn test_clone() {
     let set = ht_set!["hello", "there"];
@@ -325,3 +415,75 @@ fn test_serde() {
 
     assert_eq!(set, decoded);
 }
+
+#[test]
+fn test_replace() {
+    let set = ht_set!["foo", "bar"];
+
+    let (new_set, previous) = set.replace("foo");
+
+    assert_eq!(previous, Some("foo"));
+    assert_eq!(new_set.size(), 2);
+
+    let (new_set, previous) = set.replace("baz");
+
+    assert_eq!(previous, None);
+    assert_eq!(new_set.size(), 3);
+    assert_eq!(set.size(), 2);
+
+    let mut set = set;
+
+    assert_eq!(set.replace_mut("bar"), Some("bar"));
+    assert_eq!(set.replace_mut("mumble"), None);
+    assert_eq!(set.size(), 3);
+}
+
+#[test]
+fn test_take() {
+    let set = ht_set!["foo", "bar"];
+
+    let (new_set, taken) = set.take("foo");
+
+    assert_eq!(taken, Some("foo"));
+    assert!(!new_set.contains("foo"));
+    assert!(set.contains("foo"));
+
+    let (new_set, taken) = set.take("not-there");
+
+    assert_eq!(taken, None);
+    assert_eq!(new_set, set);
+
+    let mut set = set;
+
+    assert_eq!(set.take_mut("bar"), Some("bar"));
+    assert_eq!(set.take_mut("bar"), None);
+    assert_eq!(set.size(), 1);
+}
+
+/// A borrowed version of a `(String, u32)` value, which hashes the same way.
+#[derive(Hash)]
+struct BorrowedValue<'a>(&'a str, u32);
+
+impl Equivalent<(String, u32)> for BorrowedValue<'_> {
+    fn equivalent(&self, value: &(String, u32)) -> bool {
+        self.0 == value.0 && self.1 == value.1
+    }
+}
+
+#[test]
+fn test_equivalent() {
+    let set = ht_set![("foo".to_owned(), 1), ("bar".to_owned(), 2)];
+
+    assert!(set.contains_equiv(&BorrowedValue("foo", 1)));
+    assert!(!set.contains_equiv(&BorrowedValue("foo", 2)));
+
+    let removed = set.remove_equiv(&BorrowedValue("foo", 1));
+
+    assert_eq!(removed, ht_set![("bar".to_owned(), 2)]);
+
+    let mut set = set;
+
+    assert!(set.remove_equiv_mut(&BorrowedValue("bar", 2)));
+    assert!(!set.remove_equiv_mut(&BorrowedValue("bar", 2)));
+    assert_eq!(set.size(), 1);
+}
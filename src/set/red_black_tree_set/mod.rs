@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
+use crate::equivalent::Comparable;
 use crate::map::red_black_tree_map;
 use crate::RedBlackTreeMap;
 use archery::{ArcK, RcK, SharedPointerKind};
@@ -11,6 +12,8 @@ use core::cmp::Ordering;
 use core::fmt::Display;
 use core::iter::FromIterator;
 use core::ops::RangeBounds;
+#[cfg(feature = "rand")]
+use rand::RngCore;
 
 // TODO Use impl trait instead of this when available.
 pub type Iter<'a, T, P> = red_black_tree_map::IterKeys<'a, T, (), P>;
@@ -172,6 +175,35 @@ where
         self.map.contains_key(v)
     }
 
+    /// Same as [`remove()`](RedBlackTreeSet::remove()) but the value can be of any type
+    /// [comparable](Comparable) to `T`.
+    #[must_use]
+    pub fn remove_equiv<V: ?Sized>(&self, v: &V) -> RedBlackTreeSet<T, P>
+    where
+        V: Comparable<T>,
+    {
+        RedBlackTreeSet { map: self.map.remove_equiv(v) }
+    }
+
+    /// Same as [`remove_mut()`](RedBlackTreeSet::remove_mut()) but the value can be of any type
+    /// [comparable](Comparable) to `T`.
+    pub fn remove_equiv_mut<V: ?Sized>(&mut self, v: &V) -> bool
+    where
+        V: Comparable<T>,
+    {
+        self.map.remove_equiv_mut(v)
+    }
+
+    /// Same as [`contains()`](RedBlackTreeSet::contains()) but the value can be of any type
+    /// [comparable](Comparable) to `T`.
+    #[must_use]
+    pub fn contains_equiv<V: ?Sized>(&self, v: &V) -> bool
+    where
+        V: Comparable<T>,
+    {
+        self.map.contains_key_equiv(v)
+    }
+
     #[must_use]
     pub fn first(&self) -> Option<&T> {
         self.map.first().map(|(k, _)| k)
@@ -247,6 +279,23 @@ where
         self.size() == 0
     }
 
+    /// Returns the element at position `index` in the order of [`iter()`](RedBlackTreeSet::iter()), or `None`
+    /// if `index` is out of bounds.  This takes *O(log(n))* time.
+    #[must_use]
+    pub fn nth(&self, index: usize) -> Option<&T> {
+        self.map.nth(index).map(|(k, _)| k)
+    }
+
+    /// Returns an element chosen uniformly at random, or `None` if the set is empty.  This takes
+    /// *O(log(n))* time.
+    ///
+    /// This requires the `rand` feature.
+    #[cfg(feature = "rand")]
+    #[must_use]
+    pub fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Option<&T> {
+        self.map.sample(rng).map(|(k, _)| k)
+    }
+
     #[must_use]
     pub fn iter(&self) -> Iter<'_, T, P> {
         self.map.keys()
@@ -263,6 +312,73 @@ where
     }
 }
 
+impl<T, P> RedBlackTreeSet<T, P>
+where
+    T: Ord + Clone,
+    P: SharedPointerKind,
+{
+    /// Same as `insert()` but also returns the value that was replaced, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let set = RedBlackTreeSet::new().insert(1);
+    ///
+    /// let (new_set, previous) = set.replace(1);
+    ///
+    /// assert_eq!(previous, Some(1));
+    /// assert_eq!(new_set.replace(2).1, None);
+    /// ```
+    #[must_use]
+    pub fn replace(&self, v: T) -> (RedBlackTreeSet<T, P>, Option<T>) {
+        let mut new_set = self.clone();
+        let previous = new_set.replace_mut(v);
+
+        (new_set, previous)
+    }
+
+    /// Same as `insert_mut()` but also returns the value that was replaced, if any.
+    pub fn replace_mut(&mut self, v: T) -> Option<T> {
+        self.map.replace_entry_mut(v, ()).map(|(previous, ())| previous)
+    }
+
+    /// Same as `remove()` but also returns the value that was removed, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rpds::*;
+    /// #
+    /// let set = RedBlackTreeSet::new().insert("a");
+    ///
+    /// let (new_set, removed) = set.take("a");
+    ///
+    /// assert_eq!(removed, Some("a"));
+    /// assert!(new_set.is_empty());
+    /// ```
+    #[must_use]
+    pub fn take<V: ?Sized>(&self, v: &V) -> (RedBlackTreeSet<T, P>, Option<T>)
+    where
+        T: Borrow<V>,
+        V: Ord,
+    {
+        let (map, removed) = self.map.remove_entry(v);
+
+        (RedBlackTreeSet { map }, removed.map(|(v, ())| v))
+    }
+
+    /// Same as `remove_mut()` but also returns the value that was removed, if any.
+    pub fn take_mut<V: ?Sized>(&mut self, v: &V) -> Option<T>
+    where
+        T: Borrow<V>,
+        V: Ord,
+    {
+        self.map.remove_entry_mut(v).map(|(v, ())| v)
+    }
+}
+
 impl<T, P> Clone for RedBlackTreeSet<T, P>
 where
     T: Ord,
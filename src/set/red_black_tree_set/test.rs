@@ -4,6 +4,9 @@
  */
 
 use super::*;
+use crate::equivalent::Equivalent;
+use alloc::borrow::ToOwned;
+use alloc::string::String;
 use alloc::vec::Vec;
 use pretty_assertions::assert_eq;
 use static_assertions::assert_impl_all;
@@ -188,6 +191,31 @@ fn test_last() {
     assert_eq!(set.last(), Some(&3));
 }
 
+#[test]
+fn test_nth() {
+    let set = rbt_set![3, 2, 1];
+
+    assert_eq!(set.nth(0), Some(&1));
+    assert_eq!(set.nth(2), Some(&3));
+    assert_eq!(set.nth(3), None);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn test_sample() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let set = rbt_set![3, 2, 1];
+
+    assert_eq!(RedBlackTreeSet::<i32>::new().sample(&mut rng), None);
+
+    for _ in 0..100 {
+        assert!(set.contains(set.sample(&mut rng).unwrap()));
+    }
+}
+
 #[test]
 fn test_remove() {
     let mut set = rbt_set!["foo", "bar", "mumble", "baz"];
@@ -406,3 +434,80 @@ fn test_serde() {
 
     assert_eq!(set, decoded);
 }
+
+#[test]
+fn test_replace() {
+    let set = rbt_set!["foo", "bar"];
+
+    let (new_set, previous) = set.replace("foo");
+
+    assert_eq!(previous, Some("foo"));
+    assert_eq!(new_set.size(), 2);
+
+    let (new_set, previous) = set.replace("baz");
+
+    assert_eq!(previous, None);
+    assert_eq!(new_set.size(), 3);
+    assert_eq!(set.size(), 2);
+
+    let mut set = set;
+
+    assert_eq!(set.replace_mut("bar"), Some("bar"));
+    assert_eq!(set.replace_mut("mumble"), None);
+    assert_eq!(set.size(), 3);
+}
+
+#[test]
+fn test_take() {
+    let set = rbt_set!["foo", "bar"];
+
+    let (new_set, taken) = set.take("foo");
+
+    assert_eq!(taken, Some("foo"));
+    assert!(!new_set.contains("foo"));
+    assert!(set.contains("foo"));
+
+    let (new_set, taken) = set.take("not-there");
+
+    assert_eq!(taken, None);
+    assert_eq!(new_set, set);
+
+    let mut set = set;
+
+    assert_eq!(set.take_mut("bar"), Some("bar"));
+    assert_eq!(set.take_mut("bar"), None);
+    assert_eq!(set.size(), 1);
+}
+
+/// A borrowed version of a `(String, u32)` value, which is ordered the same way.
+struct BorrowedValue<'a>(&'a str, u32);
+
+impl Equivalent<(String, u32)> for BorrowedValue<'_> {
+    fn equivalent(&self, value: &(String, u32)) -> bool {
+        self.0 == value.0 && self.1 == value.1
+    }
+}
+
+impl Comparable<(String, u32)> for BorrowedValue<'_> {
+    fn compare(&self, value: &(String, u32)) -> Ordering {
+        self.0.cmp(&value.0).then(self.1.cmp(&value.1))
+    }
+}
+
+#[test]
+fn test_comparable() {
+    let set = rbt_set![("foo".to_owned(), 1), ("bar".to_owned(), 2)];
+
+    assert!(set.contains_equiv(&BorrowedValue("foo", 1)));
+    assert!(!set.contains_equiv(&BorrowedValue("foo", 2)));
+
+    let removed = set.remove_equiv(&BorrowedValue("foo", 1));
+
+    assert_eq!(removed, rbt_set![("bar".to_owned(), 2)]);
+
+    let mut set = set;
+
+    assert!(set.remove_equiv_mut(&BorrowedValue("bar", 2)));
+    assert!(!set.remove_equiv_mut(&BorrowedValue("bar", 2)));
+    assert_eq!(set.size(), 1);
+}
@@ -21,5 +21,13 @@ where
     core::mem::swap(dest, SharedPointer::make_mut(&mut src));
 }
 
+/// Returns `true` if `a` and `b` are the same object in memory.  `T` must not be a zero-sized
+/// type, since distinct zero-sized objects can have the same address.
+pub fn same_object<T>(a: &T, b: &T) -> bool {
+    debug_assert_ne!(core::mem::size_of::<T>(), 0);
+
+    core::ptr::eq(a, b)
+}
+
 #[cfg(test)]
 mod test;
@@ -16,3 +16,15 @@ fn test_replace() {
 
     assert_eq!(dest, 3);
 }
+
+#[test]
+fn test_same_object() {
+    let a = [1, 2];
+    let b = [1, 2];
+
+    assert!(same_object(&a, &a));
+    assert!(!same_object(&a, &b));
+    assert!(same_object(&a[0], &a[0]));
+    assert!(!same_object(&a[0], &a[1]));
+    assert!(!same_object(&a[0], &b[0]));
+}
@@ -98,6 +98,19 @@ macro_rules! vector_sync {
 /// This implementation uses a bitmapped vector trie as described in
 /// [Understanding Persistent Vector Part 1](http://hypirion.com/musings/understanding-persistent-vector-pt-1)
 /// and [Understanding Persistent Vector Part 2](http://hypirion.com/musings/understanding-persistent-vector-pt-2).
+///
+/// # Equality
+///
+/// Two vectors with the same root and length are equal without their elements being compared, so
+/// a vector is equal to its clone even if it holds elements that are not equal to themselves, like
+/// `f64::NAN`:
+///
+/// ```rust
+/// # use rpds::*;
+/// let vector = vector![f64::NAN];
+///
+/// assert!(vector == vector.clone());
+/// ```
 #[derive(Debug)]
 pub struct Vector<T, P = RcK>
 where
@@ -123,6 +136,16 @@ impl<T, P> Node<T, P>
 where
     P: SharedPointerKind,
 {
+    /// Returns `true` if `self` and `other` are the same node.  Every node is the only value in
+    /// its allocation, so two nodes at the same address are the same node, even though their types
+    /// differ when the vectors have different element types or pointer kinds.
+    fn is_same_node<U, PO: SharedPointerKind>(&self, other: &Node<U, PO>) -> bool {
+        let address: *const Node<T, P> = self;
+        let other_address: *const Node<U, PO> = other;
+
+        core::ptr::eq(address.cast::<u8>(), other_address.cast::<u8>())
+    }
+
     fn new_empty_branch() -> Node<T, P> {
         Node::Branch(Vec::new())
     }
@@ -558,7 +581,12 @@ where
     PO: SharedPointerKind,
 {
     fn eq(&self, other: &Vector<U, PO>) -> bool {
-        self.length == other.length && self.iter().eq(other.iter())
+        if self.length != other.length {
+            return false;
+        }
+
+        // The same root with the same length holds the same elements.
+        self.root.is_same_node(&other.root) || self.iter().eq(other.iter())
     }
 }
 
@@ -634,6 +634,16 @@ fn test_eq_pointer_kind_consistent() {
     assert!(vector_b == vector_b_sync);
 }
 
+#[test]
+fn test_eq_skips_shared_root() {
+    let vector: Vector<f64> = (0..1000).map(|_| f64::NAN).collect();
+
+    // Elements of vectors with a shared root are not compared, so `NaN`s are equal to themselves.
+    assert!(vector == vector.clone());
+    assert!(vector != vector.set(0, f64::NAN).unwrap());
+    assert!(vector != vector.drop_last().unwrap());
+}
+
 #[test]
 fn test_partial_ord() {
     let vector_1 = vector!["a"];